@@ -0,0 +1,204 @@
+// The default web ui, it is served when no web_root is configured.
+// The api is requested relative to the page, which works behind a base_path.
+(function () {
+    const JOB_POLL_INTERVAL = 2000;
+
+    const element = (id) => document.getElementById(id);
+    let targets = [];
+    let stats = {targets: {}};
+
+    class AuthError extends Error {}
+
+    async function request(method, path, body) {
+        const options = {method, credentials: 'same-origin', headers: {}};
+        if (body !== undefined) {
+            options.headers['Content-Type'] = 'application/json';
+            options.body = JSON.stringify(body);
+        }
+        const response = await fetch('api/v1/' + path, options);
+        if (response.status === 401) {
+            throw new AuthError();
+        }
+        const text = await response.text();
+        const content = text ? JSON.parse(text) : null;
+        if (!response.ok) {
+            throw new Error((content && content.error) || response.statusText);
+        }
+        return content;
+    }
+
+    function cell(row, text) {
+        const td = document.createElement('td');
+        td.textContent = text;
+        row.appendChild(td);
+        return td;
+    }
+
+    function showStatus(text, error) {
+        const status = element('status');
+        status.textContent = text;
+        status.className = error ? 'error' : '';
+    }
+
+    function showLogin() {
+        element('content').classList.add('hidden');
+        element('login').classList.remove('hidden');
+    }
+
+    function handleError(err) {
+        if (err instanceof AuthError) {
+            showLogin();
+        } else {
+            showStatus(err.message, true);
+        }
+    }
+
+    function renderTargets() {
+        const body = element('targets');
+        body.replaceChildren();
+        targets.forEach((target) => {
+            const row = document.createElement('tr');
+            const target_stats = stats.targets[target.name];
+            cell(row, target.enabled ? target.name : target.name + ' (disabled)');
+            cell(row, target.output.map((output) => output.filename ? output.target + ' ' + output.filename : output.target).join(', '));
+            cell(row, target_stats ? target_stats.groups : '-');
+            cell(row, target_stats ? target_stats.channels : '-');
+            const button = document.createElement('button');
+            button.textContent = 'Run';
+            button.disabled = !target.enabled;
+            button.onclick = () => run(target.name);
+            cell(row, '').appendChild(button);
+            body.appendChild(row);
+        });
+
+        const select = element('target');
+        const selected = select.value;
+        select.replaceChildren();
+        targets.forEach((target) => select.appendChild(new Option(target.name, target.name)));
+        if (targets.some((target) => target.name === selected)) {
+            select.value = selected;
+        }
+        renderGroups();
+    }
+
+    function renderGroups() {
+        const list = element('groups');
+        list.replaceChildren();
+        const target_stats = stats.targets[element('target').value];
+        if (!target_stats) {
+            return;
+        }
+        const filter = element('group-filter').value.toLowerCase();
+        Object.entries(target_stats.group_channels)
+            .filter(([group]) => group.toLowerCase().includes(filter))
+            .forEach(([group, count]) => {
+                const item = document.createElement('li');
+                item.title = 'Search the group';
+                item.appendChild(document.createTextNode(group));
+                const counter = document.createElement('span');
+                counter.textContent = count;
+                item.appendChild(counter);
+                item.onclick = () => {
+                    element('query').value = group;
+                    search();
+                };
+                list.appendChild(item);
+            });
+    }
+
+    async function search() {
+        const query = element('query').value.trim();
+        const body = element('results');
+        body.replaceChildren();
+        if (!query) {
+            return;
+        }
+        try {
+            const params = new URLSearchParams({q: query, target: element('target').value, limit: '500'});
+            const entries = await request('GET', 'playlist/search?' + params);
+            entries.forEach((entry) => {
+                const row = document.createElement('tr');
+                cell(row, entry.group);
+                cell(row, entry.title);
+                cell(row, entry.type);
+                body.appendChild(row);
+            });
+            showStatus(entries.length + ' entries found');
+        } catch (err) {
+            handleError(err);
+        }
+    }
+
+    async function pollJob(id) {
+        const job_status = element('job');
+        try {
+            const job = await request('GET', 'jobs/' + encodeURIComponent(id));
+            job_status.textContent = 'Job ' + job.status + ': ' + job.targets.join(', ')
+                + ' (' + job.sources_processed + '/' + job.sources_total + ' sources)'
+                + (job.errors.length ? ' - ' + job.errors.join(', ') : '');
+            job_status.className = job.status === 'failed' ? 'error' : '';
+            if (job.status === 'queued' || job.status === 'running') {
+                setTimeout(() => pollJob(id), JOB_POLL_INTERVAL);
+            } else {
+                await load();
+            }
+        } catch (err) {
+            handleError(err);
+        }
+    }
+
+    async function run(target_name) {
+        try {
+            const query = target_name ? '?target=' + encodeURIComponent(target_name) : '';
+            const result = await request('POST', 'playlist/refresh' + query);
+            await pollJob(result.job_id);
+        } catch (err) {
+            handleError(err);
+        }
+    }
+
+    async function load() {
+        try {
+            const [source_targets, current_stats, auth] = await Promise.all([
+                request('GET', 'targets'), request('GET', 'stats'), request('GET', 'auth/status')]);
+            targets = source_targets.flat();
+            stats = current_stats;
+            element('login').classList.add('hidden');
+            element('content').classList.remove('hidden');
+            element('logout').classList.toggle('hidden', !(auth && auth.enabled));
+            renderTargets();
+            showStatus(stats.processing.last_run
+                ? 'Last run: ' + new Date(stats.processing.last_run * 1000).toLocaleString() : 'Not processed yet');
+        } catch (err) {
+            handleError(err);
+        }
+    }
+
+    element('login').onsubmit = async (event) => {
+        event.preventDefault();
+        try {
+            await request('POST', 'auth/login', {username: element('username').value, password: element('password').value});
+            element('login-error').textContent = '';
+            element('password').value = '';
+            await load();
+        } catch (err) {
+            element('login-error').textContent = err instanceof AuthError ? 'Invalid credentials' : err.message;
+        }
+    };
+    element('logout').onclick = async () => {
+        await request('POST', 'auth/logout').catch(() => null);
+        showLogin();
+    };
+    element('run-all').onclick = () => run();
+    element('target').onchange = () => {
+        renderGroups();
+        search();
+    };
+    element('group-filter').oninput = renderGroups;
+    element('search').onsubmit = (event) => {
+        event.preventDefault();
+        search();
+    };
+
+    load();
+})();
@@ -0,0 +1,48 @@
+//! Compares trying the regex of each mapper with the regex set prefilter of `MapperSet`
+//! in `src/model/mapping.rs`, the crate is a binary, so the strategy is measured with the regex crate directly.
+//! The set pays off from about `MAPPER_SET_MIN_SIZE` mappers on.
+//!
+//! `cargo bench --bench mapper_set`
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use regex::{Regex, RegexSet};
+use std::hint::black_box;
+
+const COUNTRIES: [&str; 8] = ["DE", "FR", "UK", "US", "IT", "ES", "NL", "PL"];
+
+fn create_patterns(count: usize) -> Vec<String> {
+    (0..count).map(|idx| format!("^{}[: ]+(?i).*(channel|tv){}.*$", COUNTRIES[idx % COUNTRIES.len()], idx)).collect()
+}
+
+fn create_channel_names() -> Vec<String> {
+    (0..2000).map(|idx| format!("{}: Channel{} HD", COUNTRIES[idx % COUNTRIES.len()], idx % 97)).collect()
+}
+
+fn match_each(regexps: &[Regex], names: &[String]) -> usize {
+    names.iter().map(|name| regexps.iter().filter(|re| re.is_match(name)).count()).sum()
+}
+
+/// Only the mappers selected by the set are tried, like `MapperSetMatcher::is_candidate`.
+fn match_with_set(set: &RegexSet, regexps: &[Regex], names: &[String]) -> usize {
+    names.iter().map(|name| {
+        let matches = set.matches(name);
+        regexps.iter().enumerate().filter(|(idx, re)| matches.matched(*idx) && re.is_match(name)).count()
+    }).sum()
+}
+
+fn bench_mapper_set(c: &mut Criterion) {
+    let names = create_channel_names();
+    let mut group = c.benchmark_group("mapper_set");
+    for count in [4, 8, 16, 64, 256] {
+        let patterns = create_patterns(count);
+        let regexps: Vec<Regex> = patterns.iter().map(|pattern| Regex::new(pattern).unwrap()).collect();
+        let set = RegexSet::new(&patterns).unwrap();
+        assert_eq!(match_each(&regexps, &names), match_with_set(&set, &regexps, &names));
+        group.bench_with_input(BenchmarkId::new("each", count), &count, |b, _| b.iter(|| match_each(black_box(&regexps), black_box(&names))));
+        group.bench_with_input(BenchmarkId::new("set", count), &count, |b, _| b.iter(|| match_with_set(black_box(&set), black_box(&regexps), black_box(&names))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_mapper_set);
+criterion_main!(benches);
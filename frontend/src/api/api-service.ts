@@ -6,6 +6,26 @@ const HEADER_CONTENT_TYPE = 'Content-Type';
 const HEADER_LANGUAGE = 'X-Language';
 const HEADER_ACCEPT = 'Accept';
 
+// with `api.auth` the server answers 401 until the session cookie is set
+let loginPending = false;
+axios.interceptors.response.use(undefined, (error) => {
+    const requestUrl: string = error?.config?.url ?? '';
+    if (error?.response?.status === 401 && !requestUrl.endsWith('auth/login') && !loginPending) {
+        loginPending = true;
+        const username = window.prompt('Username');
+        const password = username != null ? window.prompt('Password') : null;
+        if (username != null && password != null) {
+            axios.post(config.api.serverUrl + 'auth/login', {username, password})
+                .then(() => window.location.reload())
+                .catch(() => window.alert('Login failed'))
+                .finally(() => loginPending = false);
+        } else {
+            loginPending = false;
+        }
+    }
+    return Promise.reject(error);
+});
+
 export default interface ApiService {
     get<T>(query: string, url?: string): Observable<T>;
 
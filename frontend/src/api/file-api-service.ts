@@ -6,11 +6,18 @@ import {first} from "rxjs/operators";
 //const FILE_API_PATH = 'file';
 const FILE_DOWNLOAD_API_PATH = 'file/download';
 const FILE_DOWNLOAD_INFO_API_PATH = FILE_DOWNLOAD_API_PATH + '/info';
+const FILE_DOWNLOADS_API_PATH = 'file/downloads';
 
 export default interface FileApiService extends ApiService {
     download(req: FileDownloadRequest): Observable<FileDownloadInfo>;
 
     getDownloadInfo(): Observable<DownloadInfo>;
+
+    getDownloads(): Observable<FileDownloadInfo[]>;
+
+    removeDownload(uuid: string): Observable<FileDownloadInfo>;
+
+    retryDownload(uuid: string): Observable<void>;
 }
 
 export class DefaultFileApiService extends DefaultApiService implements FileApiService {
@@ -40,4 +47,16 @@ export class DefaultFileApiService extends DefaultApiService implements FileApiS
             fetch_info();
         });
     }
+
+    getDownloads(): Observable<FileDownloadInfo[]> {
+        return this.get<FileDownloadInfo[]>(FILE_DOWNLOADS_API_PATH);
+    }
+
+    removeDownload(uuid: string): Observable<FileDownloadInfo> {
+        return this.delete<FileDownloadInfo>(FILE_DOWNLOADS_API_PATH + '/' + encodeURIComponent(uuid));
+    }
+
+    retryDownload(uuid: string): Observable<void> {
+        return this.post<void>(FILE_DOWNLOADS_API_PATH + '/' + encodeURIComponent(uuid) + '/retry', {});
+    }
 }
@@ -2,14 +2,18 @@ import ApiService, {DefaultApiService} from "./api-service";
 import {PlaylistGroup} from "../model/playlist";
 import {Observable, throwError} from "rxjs";
 import {PlaylistRequest} from "../model/playlist-request";
+import {VideoSearchResult} from "../model/video-search";
 
 const PLAYLIST_API_PATH = 'playlist';
 const TARGET_UPDATE_API_PATH = 'playlist/update';
+const VIDEO_SEARCH_API_PATH = 'video/search';
 
 export default interface PlaylistApiService extends ApiService {
     getPlaylist(req: PlaylistRequest): Observable<PlaylistGroup[]>;
 
     updateTargets(targets: string[]): Observable<any>;
+
+    searchVideo(title: string): Observable<VideoSearchResult[]>;
 }
 
 export class DefaultPlaylistApiService extends DefaultApiService implements PlaylistApiService {
@@ -23,4 +27,8 @@ export class DefaultPlaylistApiService extends DefaultApiService implements Play
     updateTargets(targets: string[]): Observable<any> {
         return this.post(TARGET_UPDATE_API_PATH, targets);
     }
+
+    searchVideo(title: string): Observable<VideoSearchResult[]> {
+        return this.get<VideoSearchResult[]>(VIDEO_SEARCH_API_PATH + '?q=' + encodeURIComponent(title));
+    }
 }
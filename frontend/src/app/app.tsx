@@ -98,8 +98,8 @@ export default function App(props: AppProps) {
         }
     }, [clipboardChannel, serverConfig]);
 
-    const handleOnWebSearch = useCallback((playlistItem: PlaylistItem): void => {
-        if (playlistItem) {
+    const openWebSearch = useCallback((playlistItem: PlaylistItem): void => {
+        if (playlistItem && serverConfig.video.web_search) {
             let title = playlistItem.header.title;
             let pattern = serverConfig.video.download?.episode_pattern;
             if (pattern) {
@@ -120,6 +120,27 @@ export default function App(props: AppProps) {
         }
     }, [serverConfig]);
 
+    const handleOnWebSearch = useCallback((playlistItem: PlaylistItem): void => {
+        if (playlistItem && serverConfig.video.search) {
+            services.playlist().searchVideo(playlistItem.header.title).pipe(first()).subscribe({
+                next: (results) => {
+                    const url = results.find(result => result.url)?.url;
+                    if (url) {
+                        window.open(url, "imdb");
+                    } else {
+                        openWebSearch(playlistItem);
+                    }
+                },
+                error: () => {
+                    enqueueSnackbar('Video search failed!', {variant: 'error'});
+                    openWebSearch(playlistItem);
+                },
+            });
+        } else {
+            openWebSearch(playlistItem);
+        }
+    }, [serverConfig, services, enqueueSnackbar, openWebSearch]);
+
     useEffect(() => {
         services.config().getServerConfig().pipe(first()).subscribe({
             next: (cfg: ServerConfig) => {
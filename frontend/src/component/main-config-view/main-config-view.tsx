@@ -88,6 +88,7 @@ export default function MainConfigView(props: MainConfigViewProps) {
     const handleSave = useCallback(() => {
         if (mainConfig && apiConfig) {
             const cfgMessaging: MessagingConfig = {
+                ...messagingConfig,
                 notify_on: messagingConfig.notify_on,
                 telegram: telegramConfig
             };
@@ -101,6 +102,7 @@ export default function MainConfigView(props: MainConfigViewProps) {
                 api: apiConfig,
                 working_dir: mainConfig.working_dir,
                 backup_dir: mainConfig.backup_dir,
+                backup: mainConfig.backup,
                 schedule: mainConfig.schedule,
                 threads: mainConfig.threads,
                 messaging: cfgMessaging,
@@ -133,7 +133,7 @@ export default function PlaylistGallery(props: PlaylistGalleryProps) {
                         <div className={'tool-button'} data-item={playlistItem.id} onClick={handleDownloadUrl}>
                             {getIconByName('Download')}
                         </div>
-                        {serverConfig.video?.web_search &&
+                        {(serverConfig.video?.web_search || serverConfig.video?.search) &&
                             <div className={'tool-button'} data-item={playlistItem.id} onClick={handleWebSearch}>
                                 {getIconByName('WebSearch')}
                             </div>
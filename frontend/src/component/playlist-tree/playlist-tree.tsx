@@ -131,7 +131,7 @@ export default function PlaylistTree(props: PlaylistTreeProps) {
                         <div className={'tool-button'} data-item={entry.id} onClick={handleDownloadUrl}>
                             {getIconByName('Download')}
                         </div>
-                        {serverConfig.video?.web_search &&
+                        {(serverConfig.video?.web_search || serverConfig.video?.search) &&
                             <div className={'tool-button'} data-item={entry.id} onClick={handleWebSearch}>
                                 {getIconByName('WebSearch')}
                             </div>
@@ -14,7 +14,8 @@ const prod = {
         version: process.env.REACT_APP_VERSION,
     },
     api: {
-        serverUrl: location.origin + '/api/v1/',
+        // relative to the page, the ui can be served below a base path
+        serverUrl: new URL('api/v1/', location.origin + location.pathname).href,
     },
 };
 
@@ -8,8 +8,10 @@ export interface FileDownloadInfo {
     ts_created?: number;
     ts_modified?: number;
     filename?: string;
+    state?: 'queued' | 'downloading' | 'failed' | 'done';
     finished?: boolean;
     filesize?: number;
+    total_size?: number;
     error?: string;
 }
 
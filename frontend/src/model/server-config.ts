@@ -11,7 +11,10 @@ export enum SortOrder {
 export enum TargetType {
     m3u = "m3u",
     xtream = "xtream",
-    strm = "strm"
+    strm = "strm",
+    kodi = "kodi",
+    json = "json",
+    csv = "csv"
 }
 
 export enum ProcessingOrder {
@@ -37,34 +40,56 @@ export interface InputConfig {
 export interface TargetConfig {
     enabled: boolean,
     name: string,
+    extends?: string,
+    condition?: {
+        time?: string[],
+        inputs_up?: string[],
+        inputs_down?: string[],
+    },
     options: {
         ignore_logo: boolean,
         underscore_whitespace: boolean,
         cleanup: boolean,
         kodi_style: boolean,
+        strm_layout: 'flat' | 'kodi' | 'jellyfin',
         xtream_skip_live_direct_source: boolean,
         xtream_skip_video_direct_source: boolean,
         xtream_resolve_series: boolean,
+        xtream_resolve_series_cache: boolean,
+        xtream_resolve_series_parallel: number,
+        xtream_resolve_series_rate?: number,
+        xtream_keep_versions: number,
+        xtream_virtual_ids: boolean,
+        tmdb_enrich: boolean,
+        skip_unchanged: boolean,
+        m3u_attributes?: string[],
+        history: number,
     },
     sort: {
         match_as_ascii: boolean,
+        natural: boolean,
         groups: {
-            order: SortOrder
+            order: SortOrder,
+            sequence?: string[]
         },
         channels:
             {
                 field: string,
                 group_pattern: string,
-                order: SortOrder
+                order: SortOrder,
+                sequence?: string[]
             }[]
     },
     filter: string,
+    include_groups?: string[],
+    exclude_groups?: string[],
     output: [
         {
             target: TargetType,
             filename: string
         }
     ],
+    output_dir: string,
     rename: [
         {
             field: string,
@@ -74,7 +99,64 @@ export interface TargetConfig {
     ],
     mapping: string[],
     processing_order: ProcessingOrder,
-    watch: string[]
+    watch: string[],
+    dedup: {
+        key: 'url' | 'name' | 'stream_id',
+        priority: string
+    },
+    merge: {
+        strategy: 'concat' | 'prefer_first' | 'interleave',
+        duplicate_epg_id: 'keep' | 'drop' | 'clear'
+    },
+    quality_preference: {
+        mode: 'best' | 'order',
+        order: ('uhd' | 'fhd' | 'hd' | 'sd')[],
+        ffprobe: string
+    },
+    numbering: {
+        start: number,
+        ranges: {
+            group: string,
+            start: number,
+            end: number
+        }[]
+    },
+    health_check: {
+        sample: number,
+        timeout: number,
+        parallel: number,
+        action: 'mark' | 'remove',
+        mark_prefix: string,
+        ffprobe: string
+    },
+    hooks: {
+        after_download: HookConfig,
+        after_filter: HookConfig,
+        before_write: HookConfig
+    },
+    exec: HookConfig,
+    custom_channels: CustomChannelConfig[],
+    radio: RadioConfig
+}
+
+export interface RadioConfig {
+    filter: string,
+    group: string,
+    m3u_attribute: boolean
+}
+
+export interface CustomChannelConfig {
+    name: string,
+    url: string,
+    group: string,
+    logo: string,
+    epg_channel_id: string,
+    radio: boolean
+}
+
+export interface HookConfig {
+    command: string,
+    args: string[]
 }
 
 export interface SourceConfig {
@@ -82,17 +164,34 @@ export interface SourceConfig {
     targets: TargetConfig[];
 }
 
+export interface VideoPostProcessingConfig {
+    remux?: 'mkv' | 'mp4';
+    ffmpeg: string;
+    rename?: string;
+    library_dir?: string;
+}
+
 export interface VideoDownloadConfig {
     headers: Record<string, string>,
     directory: string;
     organize_into_directories: boolean;
     episode_pattern: string;
+    parallel?: number;
+    post_processing?: VideoPostProcessingConfig;
+}
+
+export interface VideoSearchConfig {
+    provider: 'tmdb' | 'imdb' | 'custom';
+    api_key?: string;
+    url?: string;
+    language?: string;
 }
 
 export interface VideoConfig {
     extensions: string[];
     download?: VideoDownloadConfig,
     web_search?: string;
+    search?: VideoSearchConfig;
 }
 
 export interface TelegramConfig {
@@ -100,9 +199,38 @@ export interface TelegramConfig {
     chat_ids: string[];
 }
 
+export interface DiscordConfig {
+    url: string;
+}
+
+export interface WebhookConfig {
+    url: string;
+    headers?: Record<string, string>;
+    body?: string;
+}
+
+export interface PushoverConfig {
+    url?: string;
+    token: string;
+    user: string;
+    priority?: number;
+}
+
+export interface NtfyConfig {
+    url?: string;
+    topic: string;
+    priority?: number;
+    token?: string;
+}
+
 export interface MessagingConfig {
     notify_on?: string[];
     telegram?: TelegramConfig;
+    discord?: DiscordConfig;
+    webhook?: WebhookConfig;
+    pushover?: PushoverConfig;
+    ntfy?: NtfyConfig;
+    watch_template?: string;
 }
 
 export interface Credentials {
@@ -136,17 +264,103 @@ export interface ApiProxyConfig {
 export interface ServerApiConfig {
     host: string;
     port: number;
-    web_root: string
+    web_root: string,
+    base_path?: string,
+    shutdown_timeout?: number,
+    tls?: ServerApiTlsConfig,
+    security?: ServerApiSecurityConfig,
+    auth?: ServerApiAuthConfig,
+    access_log?: ServerApiAccessLogConfig,
+    listen?: ServerApiListenConfig[],
+    scope?: 'all' | 'streaming' | 'management';
+}
+
+export interface ServerApiListenConfig {
+    host: string;
+    port: number;
+    scope?: 'all' | 'streaming' | 'management';
+    tls?: boolean;
+    ipv6_only?: boolean;
+}
+
+export interface ServerApiAccessLogConfig {
+    storage?: 'file' | 'sqlite';
+    max_size?: number;
+    max_files?: number;
+    max_days?: number;
+}
+
+export interface ServerApiAuthConfig {
+    token?: string;
+    username?: string;
+    password?: string;
+    session_timeout?: number;
+    users?: ServerApiUserConfig[];
+}
+
+export interface ServerApiUserConfig {
+    username: string;
+    password: string;
+    role: 'admin' | 'operator' | 'viewer';
+    targets?: string[];
+}
+
+export interface ServerApiSecurityConfig {
+    rate_limit?: number;
+    max_failed_logins?: number;
+    failed_login_window?: number;
+    ban_duration?: number;
+    trusted_proxies?: string[];
+}
+
+export interface ServerApiTlsConfig {
+    cert: string;
+    key: string;
+    self_signed?: boolean;
+    port?: number;
+}
+
+export interface BackupConfig {
+    max_count?: number;
+    max_age?: number;
+}
+
+export interface AccountCheckConfig {
+    schedule?: string;
+    expiry_days?: number;
+}
+
+export interface TmdbConfig {
+    api_key: string;
+    api_url?: string;
+    language?: string;
+    rate?: number;
+    cache_days?: number;
+}
+
+export interface TenantConfig {
+    name: string;
+    config_path: string;
+    hosts?: string[];
+    path?: string;
 }
 
 export interface ServerMainConfig {
     api: ServerApiConfig;
     threads: number;
+    max_parallel_jobs?: number;
+    storage?: string;
     working_dir: string;
     backup_dir: string;
+    backup?: BackupConfig;
     schedule: string;
+    sources_dir?: string;
     messaging?: MessagingConfig;
+    account_check?: AccountCheckConfig;
+    tmdb?: TmdbConfig;
     video?: VideoConfig;
+    proxy?: string;
+    tenants?: TenantConfig[];
 }
 
 export default interface ServerConfig extends ServerMainConfig {
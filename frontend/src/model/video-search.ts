@@ -0,0 +1,6 @@
+export interface VideoSearchResult {
+    title: string;
+    year?: number;
+    poster?: string;
+    url?: string;
+}
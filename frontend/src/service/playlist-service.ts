@@ -3,6 +3,7 @@ import PlaylistApiService, {DefaultPlaylistApiService} from "../api/playlist-api
 import {PlaylistGroup} from "../model/playlist";
 import {first} from "rxjs/operators";
 import {PlaylistRequest} from "../model/playlist-request";
+import {VideoSearchResult} from "../model/video-search";
 
 export default class PlaylistService {
 
@@ -32,4 +33,8 @@ export default class PlaylistService {
     update(targets: string[]): Observable<any> {
         return this.playlistApiService.updateTargets(targets);
     }
+
+    searchVideo(title: string): Observable<VideoSearchResult[]> {
+        return this.playlistApiService.searchVideo(title);
+    }
 }
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::ServiceResponse;
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde_json::json;
+
+use crate::api::api_model::{ApiError, AppState};
+use crate::api::{api_utils, auth_api};
+use crate::model::config::Config;
+use crate::utils::access_log::{self, AccessLogEntry, AccessLogQuery};
+
+const STREAM_CONTEXTS: [&str; 4] = ["live", "movie", "series", "timeshift"];
+
+/// Counts the sent bytes and writes the entry when the body is dropped,
+/// which is after the last chunk or when the client disconnects.
+struct AccessLogBody {
+    body: BoxBody,
+    config: Arc<Config>,
+    entry: AccessLogEntry,
+    started: Instant,
+}
+
+impl MessageBody for AccessLogBody {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.body).poll_next(cx);
+        if let Poll::Ready(Some(Ok(bytes))) = &result {
+            this.entry.bytes_sent += bytes.len() as u64;
+        }
+        result
+    }
+}
+
+impl Drop for AccessLogBody {
+    fn drop(&mut self) {
+        self.entry.duration = self.started.elapsed().as_millis() as u64;
+        access_log::record(Arc::clone(&self.config), self.entry.clone());
+    }
+}
+
+/// The literal path segment in front of the username, or the last one if the username is a query parameter.
+/// `get.php` is logged as `m3u`, `player_api.php` with its `action`, like `player_api:get_live_streams`.
+fn get_action(pattern: &str, query: &HashMap<String, String>, is_stream: bool) -> String {
+    let segments: Vec<&str> = pattern.split('/').filter(|segment| !segment.is_empty()).collect();
+    let segment = match segments.iter().position(|segment| *segment == "{username}") {
+        Some(index) => index.checked_sub(1).map(|index| segments[index]),
+        None => segments.iter().rev().find(|segment| !segment.starts_with('{')).copied(),
+    }.unwrap_or_default();
+    let action = segment.trim_end_matches(".php");
+    if is_stream {
+        // streams without a context are live streams
+        return String::from(if STREAM_CONTEXTS.contains(&action) { action } else { "live" });
+    }
+    match action {
+        "get" | "apiget" => String::from("m3u"),
+        "epg" => String::from("xmltv"),
+        "player_api" | "xtream" => match query.get("action") {
+            Some(api_action) => format!("player_api:{}", api_action),
+            None => String::from("player_api"),
+        },
+        _ => action.to_string(),
+    }
+}
+
+/// Requests with a username, like the player api, playlist, epg and stream requests, are logged.
+/// The password is not part of the entry.
+fn get_entry(req: &HttpRequest, config: &Config, status: u16) -> Option<AccessLogEntry> {
+    let pattern = req.match_pattern()?;
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string()).map(web::Query::into_inner).unwrap_or_default();
+    let username = req.match_info().get("username").map(String::from)
+        .or_else(|| query.get("username").cloned())
+        .filter(|username| !username.trim().is_empty())?;
+    let path_stream_id = req.match_info().get("stream_id")
+        .map(|stream_id| stream_id.split('.').next().unwrap_or_default().to_string());
+    let stream_id = path_stream_id.clone().or_else(|| query.get("stream").or_else(|| query.get("stream_id")).cloned());
+    Some(AccessLogEntry {
+        timestamp: chrono::Local::now().timestamp(),
+        username,
+        ip: api_utils::get_client_ip(req, config.api.security.as_ref()),
+        action: get_action(&pattern, &query, path_stream_id.is_some()),
+        stream_id,
+        status,
+        bytes_sent: 0,
+        duration: 0,
+    })
+}
+
+/// Wraps the body of the response to log the request when `api.access_log` is configured.
+/// The app state of a tenant is only known after its scope matched, so the response is inspected.
+pub(crate) fn log_response<B: MessageBody + 'static>(res: ServiceResponse<B>, started: Instant) -> ServiceResponse<BoxBody> {
+    let config = match res.request().app_data::<web::Data<AppState>>() {
+        Some(app_state) => app_state.get_config(),
+        None => return res.map_into_boxed_body(),
+    };
+    if config.api.access_log.is_none() {
+        return res.map_into_boxed_body();
+    }
+    match get_entry(res.request(), &config, res.status().as_u16()) {
+        Some(entry) => res.map_body(|_, body| BoxBody::new(AccessLogBody { body: BoxBody::new(body), config, entry, started })),
+        None => res.map_into_boxed_body(),
+    }
+}
+
+/// Queries the access log, an operator or viewer with targets only gets the entries of their users.
+#[utoipa::path(get, path = "/api/v1/access-log", tag = "users", params(AccessLogQuery),
+    responses((status = 200, body = [AccessLogEntry]), (status = 400, body = ApiError), (status = 500, body = ApiError)))]
+pub(crate) async fn access_log(
+    req: HttpRequest,
+    query: web::Query<AccessLogQuery>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    let mut query = query.into_inner();
+    if let Some(principal) = auth_api::get_request_principal(&req).filter(|principal| principal.targets.is_some()) {
+        query.usernames = Some(config._api_proxy.read().unwrap().as_ref().map_or(vec![], |api_proxy| api_proxy.user.iter()
+            .filter(|target_user| principal.has_target(&target_user.target))
+            .flat_map(|target_user| target_user.credentials.iter().map(|credentials| credentials.username.to_string()))
+            .collect()));
+    }
+    match web::block(move || access_log::query(&config, &query)).await {
+        Ok(Ok(entries)) => HttpResponse::Ok().json(entries),
+        Ok(Err(err)) => HttpResponse::BadRequest().json(json!({"error": err})),
+        Err(err) => HttpResponse::InternalServerError().json(json!({"error": err.to_string()})),
+    }
+}
@@ -1,16 +1,32 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, RwLock};
 use actix_web::web;
+use log::error;
 use serde::{Deserialize, Serialize};
 use unidecode::unidecode;
 use crate::model::api_proxy::{ApiProxyConfig};
-use crate::model::config::{Config, ConfigTargetOptions, ConfigRename, ConfigSort, InputType, ProcessTargets, TargetOutput, VideoConfig, VideoDownloadConfig, ConfigApi, MessagingConfig};
-use crate::model::model_config::{default_as_empty_str, ProcessingOrder};
+use crate::create_m3u_filter_error_result;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{validate_targets, Config, ConfigDedup, ConfigNumbering, ConfigHealthCheck, ConfigHook, ConfigHooks, ConfigQualityPreference, ConfigTargetOptions, ConfigRename, ConfigSort, InputType, ProcessTargets, TargetOutput, VideoConfig, VideoDownloadConfig, ConfigApi, MessagingConfig, ScheduleConfig, HdHomeRunConfig, BackupConfig, StorageType, LogConfig, ConfigTenant, AccountCheckConfig, TmdbConfig};
+use crate::model::model_config::{ApiRole, default_as_empty_str, ProcessingOrder};
+use crate::utils::{file_utils, video_postprocess};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub(crate) enum DownloadState {
+    #[serde(rename = "queued")]
+    Queued,
+    #[serde(rename = "downloading")]
+    Downloading,
+    #[serde(rename = "failed")]
+    Failed,
+    #[serde(rename = "done")]
+    Done,
+}
 
 /// File-Download information.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct FileDownload {
     /// uuid of the download for identification.
     pub uuid: String,
@@ -21,11 +37,14 @@ pub(crate) struct FileDownload {
     /// filename is the filename.
     pub filename: String,
     /// url is the download url.
-    pub url: reqwest::Url,
+    pub url: String,
+    pub state: DownloadState,
     /// finished is true, if download is finished, otherweise false
     pub finished: bool,
     /// the filesize.
     pub size: u64,
+    /// the size reported by the server, unknown until the download has started
+    pub total_size: Option<u64>,
     /// Optional error if something goes wrong during downloading.
     pub error: Option<String>,
 }
@@ -41,19 +60,8 @@ pub(crate) struct FileDownload {
 ///
 fn get_download_directory(download_cfg: &VideoDownloadConfig, filestem: &str) -> PathBuf {
     if download_cfg.organize_into_directories {
-        let mut file_stem = filestem;
-        if let Some(re) = &download_cfg._re_episode_pattern {
-            if let Some(captures) = re.captures(file_stem) {
-                if let Some(episode) = captures.name("episode") {
-                    if !episode.as_str().is_empty() {
-                        file_stem = &file_stem[..episode.start()];
-                    }
-                }
-            }
-        }
-        let re_ending = download_cfg._re_remove_filename_ending.as_ref().unwrap();
-        let dir_name = re_ending.replace(file_stem, "");
-        let file_dir: PathBuf = [download_cfg.directory.as_ref().unwrap(), dir_name.as_ref()].iter().collect();
+        let dir_name = video_postprocess::get_download_title(download_cfg, filestem);
+        let file_dir: PathBuf = [download_cfg.directory.as_ref().unwrap(), dir_name.as_str()].iter().collect();
         file_dir
     } else {
         PathBuf::from(download_cfg.directory.as_ref().unwrap())
@@ -101,9 +109,11 @@ impl FileDownload {
                     file_dir,
                     file_path,
                     filename,
-                    url,
+                    url: url.to_string(),
+                    state: DownloadState::Queued,
                     finished: false,
                     size: 0,
+                    total_size: None,
                     error: None,
                 })
             }
@@ -140,17 +150,203 @@ impl SharedLocks {
 }
 
 
+const DOWNLOAD_QUEUE_FILE: &str = "download_queue.json";
+
+/// The downloads are kept in `download_queue.json` of the working dir until they are removed,
+/// interrupted downloads are queued again on startup.
 pub(crate) struct DownloadQueue {
-    pub queue: Arc<Mutex<VecDeque<FileDownload>>>,
-    pub active: Arc<RwLock<Option<FileDownload>>>,
-    pub finished: Arc<RwLock<Vec<FileDownload>>>,
+    /// all downloads in the order they were queued
+    pub downloads: Mutex<Vec<FileDownload>>,
+    /// downloads finished since the last `file/download/info` request
+    pub finished: Mutex<Vec<FileDownload>>,
+    file_path: Option<PathBuf>,
+}
+
+impl DownloadQueue {
+    pub(crate) fn new(working_dir: &String) -> Self {
+        let file_path = file_utils::get_file_path(working_dir, Some(PathBuf::from(DOWNLOAD_QUEUE_FILE)));
+        let mut downloads: Vec<FileDownload> = file_path.as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        downloads.iter_mut().filter(|fd| fd.state == DownloadState::Downloading)
+            .for_each(|fd| fd.state = DownloadState::Queued);
+        DownloadQueue {
+            downloads: Mutex::new(downloads),
+            finished: Mutex::new(Vec::new()),
+            file_path,
+        }
+    }
+
+    pub(crate) fn save(&self, downloads: &[FileDownload]) {
+        if let Some(path) = &self.file_path {
+            match serde_json::to_string(downloads) {
+                Ok(content) => if let Err(err) = std::fs::write(path, content) {
+                    error!("cant write download queue {}: {}", path.to_str().unwrap_or("?"), err);
+                },
+                Err(err) => error!("cant serialize download queue: {}", err),
+            }
+        }
+    }
+}
+
+/// Counts the active reverse proxy streams per user.
+pub(crate) struct ActiveUserConnections {
+    connections: Mutex<HashMap<String, u8>>,
+}
+
+impl ActiveUserConnections {
+    pub(crate) fn new() -> Self {
+        ActiveUserConnections {
+            connections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn get_connection_count(&self, username: &str) -> u8 {
+        self.connections.lock().unwrap().get(username).copied().unwrap_or(0)
+    }
+
+    /// Registers a new connection for the user, returns `None` if `max_connections` is reached.
+    /// The connection is released when the returned guard is dropped.
+    pub(crate) fn add_connection(self: &Arc<Self>, username: &str, max_connections: u8) -> Option<UserConnectionGuard> {
+        let mut connections = self.connections.lock().unwrap();
+        let count = connections.entry(username.to_string()).or_insert(0);
+        if max_connections > 0 && *count >= max_connections {
+            return None;
+        }
+        *count += 1;
+        Some(UserConnectionGuard {
+            connections: Arc::clone(self),
+            username: username.to_string(),
+        })
+    }
+
+    pub(crate) fn get_total_connections(&self) -> usize {
+        self.connections.lock().unwrap().values().map(|count| *count as usize).sum()
+    }
+
+    fn remove_connection(&self, username: &str) {
+        let mut connections = self.connections.lock().unwrap();
+        if let Some(count) = connections.get_mut(username) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                connections.remove(username);
+            }
+        }
+    }
+}
+
+pub(crate) struct UserConnectionGuard {
+    connections: Arc<ActiveUserConnections>,
+    username: String,
+}
+
+impl Drop for UserConnectionGuard {
+    fn drop(&mut self) {
+        self.connections.remove_connection(&self.username);
+    }
+}
+
+/// The user of a management api request, its role is taken from the current config.
+#[derive(Debug, Clone)]
+pub(crate) struct ApiPrincipal {
+    pub username: String,
+    pub role: ApiRole,
+    /// targets of an operator or viewer, `None` for all targets
+    pub targets: Option<Vec<String>>,
+}
+
+impl ApiPrincipal {
+    pub(crate) fn has_target(&self, target: &str) -> bool {
+        self.targets.as_ref().is_none_or(|targets| targets.iter().any(|name| name == target))
+    }
+}
+
+/// Login sessions of the management api, the value is the expiry timestamp and the username.
+pub(crate) struct AdminSessions {
+    sessions: Mutex<HashMap<String, (i64, String)>>,
+}
+
+impl AdminSessions {
+    pub(crate) fn new() -> Self {
+        AdminSessions {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the new session id and its expiry timestamp.
+    pub(crate) fn create_session(&self, username: &str, timeout: u64) -> (String, i64) {
+        let session_id = uuid::Uuid::new_v4().simple().to_string();
+        let expires = chrono::Local::now().timestamp().saturating_add_unsigned(timeout);
+        self.sessions.lock().unwrap().insert(session_id.clone(), (expires, username.to_string()));
+        (session_id, expires)
+    }
+
+    /// Returns the username of a valid session, expired sessions are removed.
+    pub(crate) fn get_session_user(&self, session_id: &str) -> Option<String> {
+        let now = chrono::Local::now().timestamp();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, (expires, _)| *expires > now);
+        sessions.get(session_id).map(|(_, username)| username.to_string())
+    }
+
+    pub(crate) fn remove_session(&self, session_id: &str) {
+        self.sessions.lock().unwrap().remove(session_id);
+    }
 }
 
 pub(crate) struct AppState {
-    pub config: Arc<Config>,
-    pub targets: Arc<ProcessTargets>,
+    config: RwLock<Arc<Config>>,
+    targets: RwLock<Arc<ProcessTargets>>,
     pub downloads: Arc<DownloadQueue>,
     pub shared_locks: Arc<SharedLocks>,
+    pub active_users: Arc<ActiveUserConnections>,
+    pub sessions: Arc<AdminSessions>,
+    /// `api.base_path` of the server, tenants use the one of the main config
+    pub base_path: String,
+    /// path of a tenant below the base path
+    pub tenant_path: String,
+}
+
+impl AppState {
+    pub(crate) fn new(config: Arc<Config>, targets: Arc<ProcessTargets>) -> Self {
+        let downloads = Arc::new(DownloadQueue::new(&config.working_dir));
+        AppState {
+            base_path: config.api.base_path.clone().unwrap_or_default(),
+            tenant_path: String::new(),
+            config: RwLock::new(config),
+            targets: RwLock::new(targets),
+            downloads,
+            shared_locks: Arc::new(SharedLocks::new()),
+            active_users: Arc::new(ActiveUserConnections::new()),
+            sessions: Arc::new(AdminSessions::new()),
+        }
+    }
+
+    /// Returns the currently active config.
+    /// Requests keep the returned config even when it is replaced by a reload.
+    pub(crate) fn get_config(&self) -> Arc<Config> {
+        Arc::clone(&self.config.read().unwrap())
+    }
+
+    /// The targets given at startup, processed by the schedules without targets.
+    pub(crate) fn get_targets(&self) -> Arc<ProcessTargets> {
+        Arc::clone(&self.targets.read().unwrap())
+    }
+
+    /// Replaces the config, the targets given at startup are resolved again with the new sources.
+    /// The schedulers are started once, a config with changed schedules is rejected and needs a restart.
+    pub(crate) fn set_config(&self, config: Config) -> Result<(), M3uFilterError> {
+        if self.get_config().get_schedules() != config.get_schedules() {
+            return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "The schedules have changed, they are applied after a restart");
+        }
+        let current = self.get_targets();
+        let mut targets = validate_targets(&current.names, &config.sources)?;
+        targets.dry_run = current.dry_run;
+        *self.targets.write().unwrap() = Arc::new(targets);
+        *self.config.write().unwrap() = Arc::new(config);
+        Ok(())
+    }
 }
 
 #[derive(Serialize)]
@@ -193,7 +389,8 @@ pub(crate) struct XtreamAuthorizationResponse {
 }
 
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, serde::Serialize, serde::Deserialize, utoipa::ToSchema, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 pub(crate) struct UserApiRequest {
     #[serde(default = "default_as_empty_str")]
     pub username: String,
@@ -213,9 +410,21 @@ pub(crate) struct UserApiRequest {
     pub category_id: String,
     #[serde(default = "default_as_empty_str")]
     pub limit: String,
+    #[serde(default = "default_as_empty_str")]
+    pub stream: String,
+    #[serde(default = "default_as_empty_str")]
+    pub start: String,
+    #[serde(default = "default_as_empty_str")]
+    pub duration: String,
+    /// `m3u` or `m3u_plus` for the playlist of `get.php`
+    #[serde(default = "default_as_empty_str", rename = "type")]
+    pub playlist_type: String,
+    /// stream format of the `get.php` playlist, `ts` or `m3u8`
+    #[serde(default = "default_as_empty_str")]
+    pub output: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, utoipa::ToSchema)]
 pub(crate) struct ServerInputConfig {
     pub id: u16,
     pub input_type: InputType,
@@ -227,7 +436,7 @@ pub(crate) struct ServerInputConfig {
     pub enabled: bool,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, utoipa::ToSchema)]
 pub(crate) struct ServerTargetConfig {
     pub id: u16,
     pub enabled: bool,
@@ -241,31 +450,60 @@ pub(crate) struct ServerTargetConfig {
     pub mapping: Option<Vec<String>>,
     pub processing_order: ProcessingOrder,
     pub watch: Option<Vec<String>>,
+    pub dedup: Option<ConfigDedup>,
+    pub quality_preference: Option<ConfigQualityPreference>,
+    pub numbering: Option<ConfigNumbering>,
+    pub health_check: Option<ConfigHealthCheck>,
+    pub hooks: Option<ConfigHooks>,
+    pub exec: Option<ConfigHook>,
 }
 
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, utoipa::ToSchema)]
 pub(crate) struct ServerSourceConfig {
     pub inputs: Vec<ServerInputConfig>,
     pub targets: Vec<ServerTargetConfig>,
 }
 
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, utoipa::ToSchema)]
 pub(crate) struct ServerConfig {
     pub api: ConfigApi,
     pub threads: u8,
+    pub max_parallel_jobs: u8,
+    pub storage: StorageType,
     pub working_dir: String,
     pub backup_dir: Option<String>,
+    pub backup: Option<BackupConfig>,
     pub schedule: Option<String>,
+    pub schedules: Option<Vec<ScheduleConfig>>,
     pub sources: Vec<ServerSourceConfig>,
+    pub sources_dir: Option<String>,
     pub messaging: Option<MessagingConfig>,
+    pub account_check: Option<AccountCheckConfig>,
+    pub tmdb: Option<TmdbConfig>,
     pub video: Option<VideoConfig>,
+    pub hdhomerun: Option<HdHomeRunConfig>,
+    pub log: Option<LogConfig>,
+    pub proxy: Option<String>,
+    pub tenants: Option<Vec<ConfigTenant>>,
     pub api_proxy: Option<ApiProxyConfig>,
 }
 
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+/// The body of the error responses of the api.
+#[derive(Serialize, Debug, utoipa::ToSchema)]
+pub(crate) struct ApiError {
+    pub error: String,
+}
+
+/// The body of the restore responses, the id of the restored snapshot or the restored config file.
+#[derive(Serialize, Debug, utoipa::ToSchema)]
+pub(crate) struct RestoreResponse {
+    pub restored: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, utoipa::ToSchema)]
 pub(crate) struct PlaylistRequest {
     pub url: Option<String>,
     pub input_id: Option<u16>,
@@ -277,7 +515,7 @@ impl From<web::Json<PlaylistRequest>> for PlaylistRequest {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, utoipa::ToSchema)]
 pub(crate) struct FileDownloadRequest {
     pub url: String,
     pub filename: String,
@@ -1,39 +1,336 @@
-use std::path::{Path};
-use actix_web::http::header::{CACHE_CONTROL, HeaderValue};
-use actix_web::{HttpRequest, HttpResponse, web};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use actix_web::http::header::{ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, ContentEncoding, ETAG, HeaderValue, IF_NONE_MATCH, RETRY_AFTER, VARY};
+use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder, web};
+use actix_web::web::Bytes;
+use log::{error, info};
+use serde_json::Value;
 use crate::api::api_model::{AppState, UserApiRequest};
-use crate::model::api_proxy::{UserCredentials};
-use crate::model::config::ConfigTarget;
+use crate::model::api_proxy::{ApiProxyServerInfo, UserCredentials};
+use crate::model::config::{Config, ConfigApiSecurity, ConfigTarget, normalize_base_path};
+use crate::model::model_playlist::XtreamCluster;
+use crate::utils::{ban_list, compression_utils, geoip, string_utils, user_stats};
+use crate::utils::compression_utils::{Encoding, StreamCompressor};
+use crate::utils::ban_list::ClientRejection;
 
-pub(crate) async fn serve_file(file_path: &Path, req: &HttpRequest) -> HttpResponse {
+fn get_request_encoding(req: &HttpRequest) -> Option<Encoding> {
+    req.headers().get(ACCEPT_ENCODING).and_then(|value| value.to_str().ok())
+        .and_then(compression_utils::get_accepted_encoding)
+}
+
+/// Returns the compressed copy of the file if the client accepts an encoding and the file is large enough.
+async fn get_encoded_file(cfg: &Config, file_path: &Path, req: &HttpRequest) -> Option<(PathBuf, Encoding)> {
+    let encoding = get_request_encoding(req)?;
+    if std::fs::metadata(file_path).ok()?.len() < compression_utils::MIN_COMPRESS_SIZE {
+        return None;
+    }
+    let cache_dir = compression_utils::get_cache_dir(&cfg.working_dir)?;
+    let path = file_path.to_path_buf();
+    match actix_rt::task::spawn_blocking(move || compression_utils::get_compressed_file(&cache_dir, &path, encoding)).await {
+        Ok(Ok(compressed_path)) => Some((compressed_path, encoding)),
+        Ok(Err(err)) => {
+            error!("Could not compress {}: {}", file_path.to_str().unwrap_or("?"), err);
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+/// Serves the file with its etag, compressed if the client accepts `br` or `gzip`.
+pub(crate) async fn serve_file(cfg: &Config, file_path: &Path, req: &HttpRequest) -> HttpResponse {
     if file_path.exists() {
-        if let Ok(file) = actix_files::NamedFile::open_async(file_path).await {
-            let mut result = file.set_content_type(mime::APPLICATION_JSON)
-                .disable_content_disposition().into_response(req);
+        let (path, encoding) = match get_encoded_file(cfg, file_path, req).await {
+            Some((compressed_path, encoding)) => (compressed_path, Some(encoding)),
+            None => (file_path.to_path_buf(), None),
+        };
+        if let Ok(file) = actix_files::NamedFile::open_async(&path).await {
+            let mut file = file.set_content_type(mime::APPLICATION_JSON).disable_content_disposition();
+            if let Some(encoding) = encoding {
+                file = file.set_content_encoding(get_content_encoding(encoding));
+            }
+            let mut result = file.into_response(req);
             let headers = result.headers_mut();
             headers.insert(CACHE_CONTROL, HeaderValue::from_bytes("no-cache".as_bytes()).unwrap());
+            headers.insert(VARY, HeaderValue::from_static("accept-encoding"));
             return result;
         }
     }
     HttpResponse::NoContent().finish()
 }
 
-pub(crate) fn get_user_target_by_credentials<'a>(username: &str, password: &str, api_req: &'a UserApiRequest,
-                                                 app_state: &'a web::Data<AppState>) -> Option<(UserCredentials, &'a ConfigTarget)> {
-    if !username.is_empty() && !password.is_empty() {
-        app_state.config.get_target_for_user(username, password)
+fn get_content_encoding(encoding: Encoding) -> ContentEncoding {
+    match encoding {
+        Encoding::Brotli => ContentEncoding::Brotli,
+        Encoding::Gzip => ContentEncoding::Gzip,
+    }
+}
+
+pub(crate) fn is_none_match(req: &HttpRequest, etag: &str) -> bool {
+    req.headers().get(IF_NONE_MATCH).and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').map(str::trim)
+            .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag))
+}
+
+/// Serves generated content like a playlist of a user. The etag is the hash of the content,
+/// a client which has the content gets `304 Not Modified`.
+/// The content is compressed on a blocking thread while it is sent.
+pub(crate) fn serve_content(req: &HttpRequest, content_type: &str, content: String) -> HttpResponse {
+    let encoding = get_request_encoding(req).filter(|_| content.len() as u64 >= compression_utils::MIN_COMPRESS_SIZE);
+    let etag = format!("\"{}{}\"", string_utils::get_stable_hash(&[&content]), encoding.map_or(String::new(), |encoding| format!("-{}", encoding.name())));
+    if is_none_match(req, &etag) {
+        return HttpResponse::NotModified().insert_header((ETAG, etag)).insert_header((VARY, "accept-encoding")).finish();
+    }
+    let mut response = HttpResponse::Ok();
+    response.content_type(content_type)
+        .insert_header((ETAG, etag))
+        .insert_header((CACHE_CONTROL, "no-cache"))
+        .insert_header((VARY, "accept-encoding"));
+    match encoding {
+        Some(encoding) => stream_chunks(response, Some(encoding), move || std::iter::once(Ok(content))),
+        None => response.body(content),
+    }
+}
+
+/// Chunks which are produced ahead of the response.
+const CONTENT_STREAM_BUFFER: usize = 4;
+
+/// Compresses the chunks with the encoding, an error of a chunk ends the content.
+fn encode_chunks<I>(mut chunks: I, encoding: Option<Encoding>) -> Box<dyn Iterator<Item=std::io::Result<Bytes>>>
+    where I: Iterator<Item=std::io::Result<String>> + 'static {
+    match encoding {
+        Some(encoding) => {
+            let mut compressor = Some(StreamCompressor::new(encoding));
+            Box::new(std::iter::from_fn(move || {
+                let current = compressor.as_mut()?;
+                for chunk in chunks.by_ref() {
+                    let chunk = match chunk {
+                        Ok(chunk) => chunk,
+                        Err(err) => {
+                            compressor = None;
+                            return Some(Err(err));
+                        }
+                    };
+                    match current.compress(chunk.as_bytes()) {
+                        Ok(bytes) if bytes.is_empty() => {}
+                        result => return Some(result.map(Bytes::from)),
+                    }
+                }
+                compressor.take().map(|finished| finished.finish().map(Bytes::from))
+            }))
+        }
+        None => Box::new(chunks.map(|chunk| chunk.map(Bytes::from))),
+    }
+}
+
+/// Serves content which is sent while it is generated, compressed if the client accepts it.
+/// The content is not known in advance, an etag can only be given if it is derived from the source of the content.
+/// The chunks are created and compressed on a blocking thread, which waits while the client is slower.
+/// An error of a chunk aborts the response, the client doesn't get incomplete content as complete.
+pub(crate) fn serve_content_stream<F, I>(req: &HttpRequest, content_type: &str, etag: Option<String>, create_chunks: F) -> HttpResponse
+    where F: FnOnce() -> I + Send + 'static,
+          I: Iterator<Item=std::io::Result<String>> + 'static {
+    let encoding = get_request_encoding(req);
+    let etag = etag.map(|etag| format!("\"{}{}\"", etag, encoding.map_or(String::new(), |encoding| format!("-{}", encoding.name()))));
+    if let Some(etag) = etag.as_ref().filter(|etag| is_none_match(req, etag)) {
+        return HttpResponse::NotModified().insert_header((ETAG, etag.as_str())).insert_header((VARY, "accept-encoding")).finish();
+    }
+    let mut response = HttpResponse::Ok();
+    response.content_type(content_type)
+        .insert_header((CACHE_CONTROL, "no-cache"))
+        .insert_header((VARY, "accept-encoding"));
+    if let Some(etag) = etag {
+        response.insert_header((ETAG, etag));
+    }
+    stream_chunks(response, encoding, create_chunks)
+}
+
+/// Sends the chunks with the encoding, they are created and compressed on a blocking thread.
+fn stream_chunks<F, I>(mut response: HttpResponseBuilder, encoding: Option<Encoding>, create_chunks: F) -> HttpResponse
+    where F: FnOnce() -> I + Send + 'static,
+          I: Iterator<Item=std::io::Result<String>> + 'static {
+    if let Some(encoding) = encoding {
+        response.insert_header((CONTENT_ENCODING, encoding.name()));
+    }
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(CONTENT_STREAM_BUFFER);
+    actix_rt::task::spawn_blocking(move || {
+        for chunk in encode_chunks(create_chunks(), encoding) {
+            // the client disconnected
+            if sender.blocking_send(chunk).is_err() {
+                break;
+            }
+        }
+    });
+    response.streaming(futures::stream::poll_fn(move |cx| receiver.poll_recv(cx)))
+}
+
+pub(crate) fn get_user_target_by_credentials<'a>(req: &HttpRequest, username: &str, password: &str, api_req: &'a UserApiRequest,
+                                                 config: &'a Config) -> Option<(UserCredentials, &'a ConfigTarget)> {
+    let token = api_req.token.as_str().trim();
+    let result = if !username.is_empty() && !password.is_empty() {
+        config.get_target_for_user(username, password)
+    } else if !token.is_empty() {
+        config.get_target_for_user_by_token(token)
     } else {
-        let token = api_req.token.as_str().trim();
-        if !token.is_empty() {
-            app_state.config.get_target_for_user_by_token(token)
-        } else {
-            None
+        None
+    };
+    match &result {
+        Some((user, _)) => {
+            if !is_allowed_client(req, config, user) {
+                return None;
+            }
+            user_stats::record_request(&config.working_dir, &user.username);
+        }
+        None => if !username.is_empty() || !token.is_empty() {
+            record_failed_login(req, config);
         }
     }
+    result
 }
 
-pub(crate) fn get_user_target<'a>(api_req: &'a UserApiRequest, app_state: &'a web::Data<AppState>) -> Option<(UserCredentials, &'a ConfigTarget)> {
+pub(crate) fn get_user_target<'a>(req: &HttpRequest, api_req: &'a UserApiRequest, config: &'a Config) -> Option<(UserCredentials, &'a ConfigTarget)> {
     let username = api_req.username.as_str().trim();
     let password = api_req.password.as_str().trim();
-    get_user_target_by_credentials(username, password, api_req, app_state)
-}
\ No newline at end of file
+    get_user_target_by_credentials(req, username, password, api_req, config)
+}
+
+fn get_peer_ip(req: &HttpRequest) -> String {
+    req.peer_addr().map(|addr| addr.ip().to_canonical().to_string()).unwrap_or_default()
+}
+
+/// Forwarded headers are only honoured from the `trusted_proxies`, any other client could set them.
+fn is_trusted_proxy(req: &HttpRequest) -> bool {
+    req.app_data::<web::Data<AppState>>().is_some_and(|app_state| app_state.get_config().api.security.as_ref()
+        .is_some_and(|security| security.trusted_proxies.contains(&get_peer_ip(req))))
+}
+
+/// The peer address, for requests of a trusted proxy the client address added by the proxy.
+pub(crate) fn get_client_ip(req: &HttpRequest, security: Option<&ConfigApiSecurity>) -> String {
+    let peer_ip = get_peer_ip(req);
+    if security.is_some_and(|security| security.trusted_proxies.contains(&peer_ip)) {
+        // the proxy appends the address, the addresses before can be set by the client
+        if let Some(ip) = get_header(req, "x-forwarded-for").and_then(|value| value.rsplit(',').next()).map(str::trim) {
+            if !ip.is_empty() {
+                return ip.to_string();
+            }
+        }
+    }
+    peer_ip
+}
+
+/// Returns the response for a banned client and, if `count_request` is set, for a client which exceeds the rate limit.
+pub(crate) fn check_client(req: &HttpRequest, config: &Config, count_request: bool) -> Option<HttpResponse> {
+    let security = config.api.security.as_ref()?;
+    match ban_list::check_client(&config.working_dir, security, &get_client_ip(req, Some(security)), count_request) {
+        Ok(()) => None,
+        Err(ClientRejection::Banned) => Some(HttpResponse::Forbidden().finish()),
+        Err(ClientRejection::RateLimited) => Some(HttpResponse::TooManyRequests().insert_header((RETRY_AFTER, "60")).finish()),
+    }
+}
+
+pub(crate) fn record_failed_login(req: &HttpRequest, config: &Config) {
+    if let Some(security) = &config.api.security {
+        ban_list::record_failed_login(&config.working_dir, security, &get_client_ip(req, Some(security)));
+    }
+}
+
+fn get_client_country(config: &Config, ip: IpAddr) -> Option<String> {
+    let geoip_db = config._api_proxy.read().unwrap().as_ref().and_then(|api_proxy| api_proxy.geoip_db.clone())?;
+    geoip::lookup_country(&geoip_db, ip)
+}
+
+/// Checks the client ip against the `allowed_networks` and the country restrictions of the user.
+pub(crate) fn is_allowed_client(req: &HttpRequest, config: &Config, user: &UserCredentials) -> bool {
+    if !user.has_client_restriction() {
+        return true;
+    }
+    let client_ip = get_client_ip(req, config.api.security.as_ref());
+    let allowed = match client_ip.parse::<IpAddr>() {
+        Ok(ip) if user.is_allowed_network(&ip) => !user.has_country_restriction()
+            || user.is_allowed_country(get_client_country(config, ip).as_deref()),
+        _ => false,
+    };
+    if !allowed {
+        info!("Denied access for user {} from {}", &user.username, client_ip);
+    }
+    allowed
+}
+
+fn get_header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|value| value.to_str().ok())
+}
+
+/// A `X-Forwarded-Prefix` header from a trusted proxy takes precedence over `api.base_path`,
+/// the path of a tenant is appended.
+pub(crate) fn get_base_path(req: &HttpRequest) -> String {
+    let app_state = req.app_data::<web::Data<AppState>>();
+    let base_path = match get_header(req, "x-forwarded-prefix").filter(|_| is_trusted_proxy(req)) {
+        Some(prefix) => normalize_base_path(prefix).unwrap_or_default(),
+        None => app_state.map(|app_state| app_state.base_path.clone()).unwrap_or_default(),
+    };
+    match app_state {
+        Some(app_state) => format!("{}{}", base_path, app_state.tenant_path),
+        None => base_path,
+    }
+}
+
+/// Scheme and host of the request without the forwarded headers.
+fn get_request_scheme_host(req: &HttpRequest) -> (&'static str, String) {
+    let scheme = if req.app_config().secure() { "https" } else { "http" };
+    let host = req.uri().authority().map(ToString::to_string)
+        .or_else(|| get_header(req, "host").map(String::from))
+        .unwrap_or_else(|| req.app_config().host().to_string());
+    (scheme, host)
+}
+
+/// Returns the url under which the client reached the server, without a trailing slash.
+/// For requests of a trusted proxy scheme and host are taken from the `Forwarded` or `X-Forwarded-Proto`/`X-Forwarded-Host` headers if present.
+pub(crate) fn get_base_url(req: &HttpRequest) -> String {
+    if is_trusted_proxy(req) {
+        let info = req.connection_info();
+        return format!("{}://{}{}", info.scheme(), info.host(), get_base_path(req));
+    }
+    let (scheme, host) = get_request_scheme_host(req);
+    format!("{}://{}{}", scheme, host, get_base_path(req))
+}
+
+/// Url of a stream document of the xtream repository on m3u-filter, like `http://host/live/user/password/1.ts`.
+/// Series have no stream url, their episodes are only known after `get_series_info`.
+pub(crate) fn get_user_stream_url(base_url: &str, user: &UserCredentials, cluster: &XtreamCluster,
+                                  stream: &Value, live_extension: &str) -> Option<String> {
+    let stream_id = stream.get("stream_id").and_then(Value::as_i64)?;
+    let (context, extension) = match cluster {
+        XtreamCluster::Live => ("live", live_extension),
+        XtreamCluster::Video => ("movie", stream.get("container_extension").and_then(Value::as_str)
+            .filter(|extension| !extension.is_empty()).unwrap_or("mp4")),
+        XtreamCluster::Series => return None,
+    };
+    Some(format!("{}/{}/{}/{}/{}.{}", base_url, context, user.username, user.password, stream_id, extension))
+}
+
+fn is_forwarded(req: &HttpRequest) -> bool {
+    ["forwarded", "x-forwarded-host", "x-forwarded-proto"].iter().any(|name| req.headers().contains_key(*name))
+}
+
+/// Behind a trusted reverse proxy the server info for xtream clients is taken from the forwarded headers,
+/// otherwise the configured server info is returned.
+pub(crate) fn get_server_info(req: &HttpRequest, server_info: &ApiProxyServerInfo) -> ApiProxyServerInfo {
+    let mut result = server_info.clone();
+    if is_forwarded(req) && is_trusted_proxy(req) {
+        let info = req.connection_info();
+        let scheme = info.scheme();
+        let host = info.host();
+        // without a port in the host the default port of the scheme is used
+        let (host, port) = match host.rsplit_once(':') {
+            Some((name, port)) if port.parse::<u16>().is_ok() => (name, port),
+            _ => (host, if scheme == "https" { "443" } else { "80" }),
+        };
+        result.protocol = scheme.to_string();
+        result.host = host.to_string();
+        if scheme == "https" {
+            result.https_port = port.to_string();
+        } else {
+            result.http_port = port.to_string();
+        }
+    }
+    result
+}
@@ -0,0 +1,190 @@
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::http::Method;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, web};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::api_model::{ApiError, ApiPrincipal, AppState};
+use crate::api::api_utils;
+use crate::model::config::{ConfigApiAuth, secure_eq};
+use crate::model::model_config::ApiRole;
+
+pub(crate) const SESSION_COOKIE: &str = "m3u_filter_session";
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub(crate) struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct LoginResponse {
+    pub token: String,
+    /// unix timestamp of the session expiry
+    pub expires: i64,
+    pub role: Option<ApiRole>,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct AuthStatus {
+    pub enabled: bool,
+    pub authenticated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<ApiRole>,
+}
+
+fn get_bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers().get("authorization").and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer ")).map(str::trim)
+}
+
+fn get_session_id(req: &HttpRequest) -> Option<String> {
+    req.cookie(SESSION_COOKIE).map(|cookie| cookie.value().to_string())
+        .or_else(|| get_bearer_token(req).map(String::from))
+}
+
+/// The static token is an admin, the role of a session user is taken from the current config.
+fn get_principal(req: &HttpRequest, app_state: &AppState, auth: &ConfigApiAuth) -> Option<ApiPrincipal> {
+    if let (Some(token), Some(expected)) = (get_bearer_token(req), &auth.token) {
+        if secure_eq(token, expected) {
+            return Some(ApiPrincipal { username: String::from("token"), role: ApiRole::Admin, targets: None });
+        }
+    }
+    let username = get_session_id(req).and_then(|session_id| app_state.sessions.get_session_user(&session_id))?;
+    auth.get_user_role(&username).map(|(role, targets)| ApiPrincipal { username, role, targets: targets.cloned() })
+}
+
+/// The path within the `/api/v1` scope, the base path in front of the scope is removed.
+fn get_api_path(path: &str) -> &str {
+    path.split_once("/api/v1").map_or(path, |(_, api_path)| api_path)
+}
+
+/// Operators and viewers can read the stats, jobs, access log and api-proxy users, operators can also save the users of their targets.
+fn is_permitted(role: ApiRole, method: &Method, path: &str) -> bool {
+    let path = get_api_path(path);
+    let readable = *method == Method::GET && (["/stats", "/users", "/inputs/status", "/jobs", "/access-log"].contains(&path)
+        || path.starts_with("/jobs/") || (path.starts_with("/users/") && path.ends_with("/stats")));
+    match role {
+        ApiRole::Admin => true,
+        ApiRole::Operator => readable || (*method == Method::POST && path == "/config/user"),
+        ApiRole::Viewer => readable,
+    }
+}
+
+/// The endpoints which are needed before the login.
+pub(crate) fn is_public_path(path: &str) -> bool {
+    ["/auth/login", "/auth/logout", "/auth/status", "/openapi.json"].contains(&get_api_path(path))
+}
+
+/// Requests are allowed without `api.auth`, the login, status and OpenAPI endpoints are always allowed.
+/// The principal of an allowed request is added to its extensions.
+pub(crate) fn authorize(req: &HttpRequest) -> Result<(), HttpResponse> {
+    let app_state = match req.app_data::<web::Data<AppState>>() {
+        Some(app_state) => app_state,
+        None => return Err(HttpResponse::Unauthorized().finish()),
+    };
+    let config = app_state.get_config();
+    let auth = match &config.api.auth {
+        Some(auth) => auth,
+        None => return Ok(()),
+    };
+    let path = req.path();
+    if is_public_path(path) {
+        return Ok(());
+    }
+    match get_principal(req, app_state, auth) {
+        Some(principal) if is_permitted(principal.role, req.method(), path) => {
+            req.extensions_mut().insert(principal);
+            Ok(())
+        }
+        Some(principal) => {
+            debug!("Denied {} {} for {}", req.method(), path, &principal.username);
+            Err(HttpResponse::Forbidden().json(json!({"error": "Forbidden"})))
+        }
+        None => Err(HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))),
+    }
+}
+
+/// Returns the principal of the request, `None` if `api.auth` is not configured.
+pub(crate) fn get_request_principal(req: &HttpRequest) -> Option<ApiPrincipal> {
+    req.extensions().get::<ApiPrincipal>().cloned()
+}
+
+fn create_cookie(req: &HttpRequest, value: &str) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE, value.to_string())
+        .path(format!("{}/", api_utils::get_base_path(req)))
+        .http_only(true)
+        .secure(req.connection_info().scheme() == "https")
+        .same_site(SameSite::Strict)
+        .finish()
+}
+
+/// Creates a session for the web ui, other clients can send the returned token as bearer token.
+#[utoipa::path(post, path = "/api/v1/auth/login", tag = "auth", request_body = LoginRequest,
+    responses((status = 200, description = "The session token, it is also set as cookie", body = LoginResponse),
+              (status = 400, description = "Authentication is not enabled", body = ApiError),
+              (status = 401, description = "Invalid credentials", body = ApiError)))]
+pub(crate) async fn login(
+    req: HttpRequest,
+    login_req: web::Json<LoginRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    if let Some(response) = api_utils::check_client(&req, &config, false) {
+        return response;
+    }
+    let auth = match &config.api.auth {
+        Some(auth) => auth,
+        None => return HttpResponse::BadRequest().json(json!({"error": "Authentication is not enabled"})),
+    };
+    let username = match auth.find_user(login_req.username.trim(), login_req.password.trim()) {
+        Some(username) => username,
+        None => {
+            info!("Failed api login for {}", login_req.username.trim());
+            api_utils::record_failed_login(&req, &config);
+            return HttpResponse::Unauthorized().json(json!({"error": "Invalid credentials"}));
+        }
+    };
+    let (session_id, expires) = _app_state.sessions.create_session(&username, auth.session_timeout);
+    let mut cookie = create_cookie(&req, &session_id);
+    cookie.set_max_age(actix_web::cookie::time::Duration::seconds(0i64.saturating_add_unsigned(auth.session_timeout)));
+    let role = auth.get_user_role(&username).map(|(role, _)| role);
+    HttpResponse::Ok().cookie(cookie).json(LoginResponse { token: session_id, expires, role })
+}
+
+#[utoipa::path(post, path = "/api/v1/auth/logout", tag = "auth", responses((status = 200, description = "The session is removed")))]
+pub(crate) async fn logout(
+    req: HttpRequest,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Some(session_id) = get_session_id(&req) {
+        _app_state.sessions.remove_session(&session_id);
+    }
+    let mut cookie = create_cookie(&req, "");
+    cookie.make_removal();
+    HttpResponse::Ok().cookie(cookie).finish()
+}
+
+/// Tells the web ui whether a login is needed and the role of the logged in user.
+#[utoipa::path(get, path = "/api/v1/auth/status", tag = "auth", responses((status = 200, description = "The login state", body = AuthStatus)))]
+pub(crate) async fn status(
+    req: HttpRequest,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    match &config.api.auth {
+        Some(auth) => {
+            let principal = get_principal(&req, &_app_state, auth);
+            HttpResponse::Ok().json(AuthStatus {
+                enabled: true,
+                authenticated: principal.is_some(),
+                role: principal.as_ref().map(|principal| principal.role),
+                username: principal.map(|principal| principal.username),
+            })
+        }
+        None => HttpResponse::Ok().json(AuthStatus { enabled: false, authenticated: true, username: None, role: None }),
+    }
+}
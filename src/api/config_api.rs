@@ -0,0 +1,194 @@
+use actix_web::{HttpResponse, web};
+use log::{error, info};
+use serde_json::json;
+
+use crate::api::api_model::{ApiError, AppState, RestoreResponse};
+use crate::m3u_filter_error::M3uFilterError;
+use crate::model::config::{Config, ConfigDto, ConfigInput, ConfigTarget, SourcesConfigDto};
+use crate::utils::{backup_utils, config_reader};
+use crate::utils::backup_utils::BackupEntry;
+
+fn error_response(err: &M3uFilterError) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({"error": err.to_string()}))
+}
+
+fn save_sources(app_state: &AppState, sources: &SourcesConfigDto) -> HttpResponse {
+    let config = app_state.get_config();
+    // the programs of the targets can't be changed through the api, only in the sources file
+    let current = match read_sources(app_state) {
+        Ok(current) => current,
+        Err(response) => return response,
+    };
+    if let Err(err) = config_reader::check_executables(&current.get_executables(), &sources.get_executables()) {
+        return HttpResponse::Forbidden().json(json!({"error": err.to_string()}));
+    }
+    let main_config = match config_reader::read_main_config_file(config._config_file_path.as_str()) {
+        Ok(cfg) => cfg,
+        Err(err) => return error_response(&err),
+    };
+    if let Err(err) = config_reader::validate_config(&config, &main_config, sources) {
+        return error_response(&err);
+    }
+    let file_path = config._sources_file_path.as_str();
+    match config_reader::save_sources_config(file_path, &config, sources) {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(err) => {
+            error!("Failed to save source.yml {}", err.to_string());
+            HttpResponse::InternalServerError().json(json!({"error": err.to_string()}))
+        }
+    }
+}
+
+/// The programs of the main config can't be changed through the api, only in the config file.
+pub(crate) fn check_main_config_executables(config: &Config, cfg: &ConfigDto) -> Result<(), HttpResponse> {
+    let current = config_reader::read_main_config_file(config._config_file_path.as_str())
+        .map_err(|err| HttpResponse::InternalServerError().json(json!({"error": err.to_string()})))?;
+    config_reader::check_executables(&current.get_executables(), &cfg.get_executables())
+        .map_err(|err| HttpResponse::Forbidden().json(json!({"error": err.to_string()})))
+}
+
+fn read_sources(app_state: &AppState) -> Result<SourcesConfigDto, HttpResponse> {
+    config_reader::read_sources_file(app_state.get_config()._sources_file_path.as_str()).map_err(|err| {
+        error!("Failed to read source.yml {}", err.to_string());
+        HttpResponse::InternalServerError().json(json!({"error": err.to_string()}))
+    })
+}
+
+/// Replaces the main config after validating it with the current sources.
+#[utoipa::path(put, path = "/api/v1/config", tag = "config", request_body = ConfigDto,
+    responses((status = 200, description = "The config is saved"), (status = 400, description = "Invalid config", body = ApiError),
+        (status = 403, description = "A program is changed", body = ApiError)))]
+pub(crate) async fn update_config(
+    req: web::Json<ConfigDto>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let cfg = req.0;
+    if !cfg.is_valid() {
+        return HttpResponse::BadRequest().json(json!({"error": "Invalid content"}));
+    }
+    let sources = match read_sources(&_app_state) {
+        Ok(sources) => sources,
+        Err(response) => return response,
+    };
+    let config = _app_state.get_config();
+    if let Err(response) = check_main_config_executables(&config, &cfg) {
+        return response;
+    }
+    if let Err(err) = config_reader::validate_config(&config, &cfg, &sources) {
+        return error_response(&err);
+    }
+    let file_path = config._config_file_path.as_str();
+    match config_reader::save_main_config(file_path, &config, &cfg) {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(err) => {
+            error!("Failed to save config.yml {}", err.to_string());
+            HttpResponse::InternalServerError().json(json!({"error": err.to_string()}))
+        }
+    }
+}
+
+/// Returns the targets of each source, one list per source.
+#[utoipa::path(get, path = "/api/v1/targets", tag = "targets",
+    responses((status = 200, description = "The targets of each source", body = Vec<Vec<ConfigTarget>>), (status = 500, body = ApiError)))]
+pub(crate) async fn targets(
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    match read_sources(&_app_state) {
+        Ok(sources) => HttpResponse::Ok().json(sources.sources.iter().map(|s| &s.targets).collect::<Vec<&Vec<ConfigTarget>>>()),
+        Err(response) => response,
+    }
+}
+
+/// Replaces the targets of each source, the list needs one entry per source.
+#[utoipa::path(put, path = "/api/v1/targets", tag = "targets", request_body = Vec<Vec<ConfigTarget>>,
+    responses((status = 200, description = "The targets are saved"), (status = 400, body = ApiError), (status = 403, body = ApiError)))]
+pub(crate) async fn update_targets(
+    req: web::Json<Vec<Vec<ConfigTarget>>>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let mut sources = match read_sources(&_app_state) {
+        Ok(sources) => sources,
+        Err(response) => return response,
+    };
+    let targets = req.0;
+    if targets.len() != sources.sources.len() {
+        return HttpResponse::BadRequest().json(json!({"error": format!("Expected targets for {} sources", sources.sources.len())}));
+    }
+    sources.sources.iter_mut().zip(targets).for_each(|(source, source_targets)| source.targets = source_targets);
+    save_sources(&_app_state, &sources)
+}
+
+/// Returns the inputs of each source, one list per source.
+#[utoipa::path(get, path = "/api/v1/inputs", tag = "inputs",
+    responses((status = 200, description = "The inputs of each source", body = Vec<Vec<ConfigInput>>), (status = 500, body = ApiError)))]
+pub(crate) async fn inputs(
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    match read_sources(&_app_state) {
+        Ok(sources) => HttpResponse::Ok().json(sources.sources.iter().map(|s| &s.inputs).collect::<Vec<&Vec<ConfigInput>>>()),
+        Err(response) => response,
+    }
+}
+
+/// Replaces the inputs of each source, the list needs one entry per source.
+#[utoipa::path(put, path = "/api/v1/inputs", tag = "inputs", request_body = Vec<Vec<ConfigInput>>,
+    responses((status = 200, description = "The inputs are saved"), (status = 400, body = ApiError)))]
+pub(crate) async fn update_inputs(
+    req: web::Json<Vec<Vec<ConfigInput>>>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let mut sources = match read_sources(&_app_state) {
+        Ok(sources) => sources,
+        Err(response) => return response,
+    };
+    let inputs = req.0;
+    if inputs.len() != sources.sources.len() {
+        return HttpResponse::BadRequest().json(json!({"error": format!("Expected inputs for {} sources", sources.sources.len())}));
+    }
+    sources.sources.iter_mut().zip(inputs).for_each(|(source, source_inputs)| source.inputs = source_inputs);
+    save_sources(&_app_state, &sources)
+}
+
+/// Reloads config, sources, mappings and api-proxy config from disk without restarting the server.
+/// Running requests keep the config they started with, changed schedules need a restart.
+#[utoipa::path(post, path = "/api/v1/config/reload", tag = "config",
+    responses((status = 200, description = "The config is reloaded"), (status = 400, body = ApiError)))]
+pub(crate) async fn reload_config(
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    match config_reader::reload_config(&_app_state.get_config()).and_then(|cfg| _app_state.set_config(cfg)) {
+        Ok(()) => {
+            info!("Config reloaded");
+            HttpResponse::Ok().finish()
+        }
+        Err(err) => {
+            error!("Failed to reload config {}", err.to_string());
+            error_response(&err)
+        }
+    }
+}
+
+/// Lists the config backups, newest first.
+#[utoipa::path(get, path = "/api/v1/backups", tag = "config", responses((status = 200, body = [BackupEntry])))]
+pub(crate) async fn backups(
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    HttpResponse::Ok().json(backup_utils::list_backups(config.backup_dir.as_ref().unwrap()))
+}
+
+/// Restores a config file from a backup, the config is applied with the next reload.
+#[utoipa::path(post, path = "/api/v1/backups/{id}/restore", tag = "config", params(("id" = String, Path, description = "Id of the backup")),
+    responses((status = 200, body = RestoreResponse), (status = 400, body = ApiError)))]
+pub(crate) async fn restore_backup(
+    path: web::Path<String>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    match backup_utils::restore_backup(&_app_state.get_config(), path.as_str()) {
+        Ok(file) => HttpResponse::Ok().json(RestoreResponse { restored: file }),
+        Err(err) => {
+            error!("Failed to restore backup {}", err.to_string());
+            error_response(&err)
+        }
+    }
+}
@@ -1,135 +1,242 @@
-use std::fs::File;
+use std::fs::OpenOptions;
 use std::{fs, io};
-use std::io::{ErrorKind, Write};
-use std::ops::Deref;
-use std::sync::{Arc, RwLock};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use actix_web::{HttpResponse, web};
-use serde_json::{json, Value};
-use crate::api::api_model::{AppState, DownloadQueue, FileDownload, FileDownloadRequest};
-use crate::model::config::{VideoDownloadConfig};
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::json;
+use crate::api::api_model::{ApiError, AppState, DownloadQueue, DownloadState, FileDownload, FileDownloadRequest};
+use crate::model::config::{Config, VideoDownloadConfig};
 use futures::stream::TryStreamExt;
-use log::{info};
-use crate::utils::{request_utils};
-
-async fn download_file(active: Arc<RwLock<Option<FileDownload>>>, client: &reqwest::Client) -> Result<(), String> {
-    let file_download = { active.read().unwrap().as_ref().unwrap().clone() };
-    match client.get(file_download.url.clone()).send().await {
-        Ok(response) => {
-            match fs::create_dir_all(&file_download.file_dir) {
-                Ok(_) => {
-                    if let Some(file_path_str) = file_download.file_path.to_str() {
-                        info!("Downloading {}", file_path_str);
-                        match File::create(&file_download.file_path) {
-                            Ok(mut file) => {
-                                let mut downloaded: u64 = 0;
-                                let mut stream = response.bytes_stream().map_err(|err| io::Error::new(ErrorKind::Other, err));
-                                loop {
-                                    match stream.try_next().await {
-                                        Ok(item) => {
-                                            match item {
-                                                Some(chunk) => {
-                                                    match file.write_all(&chunk) {
-                                                        Ok(_) => {
-                                                            downloaded += chunk.len() as u64;
-                                                            active.write().unwrap().as_mut().unwrap().size = downloaded;
-                                                        }
-                                                        Err(err) => return Err(format!("Error while writing to file: {} {}", file_path_str, err))
-                                                    }
-                                                }
-                                                None => {
-                                                    let megabytes = request_utils::bytes_to_megabytes(downloaded);
-                                                    info!("Downloaded {}, filesize: {}MB", file_path_str, megabytes);
-                                                    active.write().unwrap().as_mut().unwrap().size = downloaded;
-                                                    return Ok(());
-                                                }
-                                            }
-                                        }
-                                        Err(err) => return Err(format!("Error while writing to file: {} {}", file_path_str, err))
-                                    }
-                                }
-                            }
-                            Err(err) => Err(format!("Error while writing to file: {} {}", file_path_str, err))
-                        }
-                    } else {
-                        Err("Error file-download file-path unknown".to_string())
-                    }
+use log::{error, info};
+use crate::utils::{request_utils, video_postprocess};
+
+/// The file is downloaded into `<filename>.part` and renamed when it is complete.
+fn get_part_path(file_path: &Path) -> PathBuf {
+    let mut part_path = file_path.as_os_str().to_os_string();
+    part_path.push(".part");
+    PathBuf::from(part_path)
+}
+
+/// Applies `func` to the download, returns `false` if the download was removed from the queue.
+fn update_download<F>(download_queue: &DownloadQueue, uuid: &str, func: F) -> bool
+    where F: FnOnce(&mut FileDownload) {
+    match download_queue.downloads.lock().unwrap().iter_mut().find(|fd| fd.uuid == uuid) {
+        Some(file_download) => {
+            func(file_download);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Continues a partial download with a range request. If the server ignores the range the file is downloaded again.
+async fn download_file(download_queue: &DownloadQueue, file_download: &FileDownload, client: &reqwest::Client) -> Result<(), String> {
+    let file_path_str = file_download.file_path.to_str().ok_or_else(|| "Error file-download file-path unknown".to_string())?;
+    if let Err(err) = fs::create_dir_all(&file_download.file_dir) {
+        return Err(format!("Error while creating directory for file: {} {}", &file_download.file_dir.to_str().unwrap_or("?"), err));
+    }
+    let part_path = get_part_path(&file_download.file_path);
+    let offset = fs::metadata(&part_path).map_or(0, |meta| meta.len());
+    let mut request = client.get(&file_download.url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    }
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(err) => return Err(format!("Error while opening url: {} {}", &file_download.url, err)),
+    };
+    // the partial file is already complete
+    if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        return fs::rename(&part_path, &file_download.file_path).map_err(|err| format!("Error while writing to file: {} {}", file_path_str, err));
+    }
+    if !response.status().is_success() {
+        return Err(format!("Error while opening url: {} status {}", &file_download.url, response.status()));
+    }
+    let resumed = response.status() == StatusCode::PARTIAL_CONTENT;
+    let mut downloaded = if resumed { offset } else { 0 };
+    let total_size = response.content_length().map(|length| length + downloaded);
+    if resumed {
+        info!("Resuming download {} at {}MB", file_path_str, request_utils::bytes_to_megabytes(offset));
+    } else {
+        info!("Downloading {}", file_path_str);
+    }
+    update_download(download_queue, &file_download.uuid, |fd| {
+        fd.size = downloaded;
+        fd.total_size = total_size;
+    });
+    let mut file = match OpenOptions::new().create(true).write(true).append(resumed).truncate(!resumed).open(&part_path) {
+        Ok(file) => file,
+        Err(err) => return Err(format!("Error while writing to file: {} {}", file_path_str, err)),
+    };
+    let mut stream = response.bytes_stream().map_err(io::Error::other);
+    loop {
+        match stream.try_next().await {
+            Ok(Some(chunk)) => {
+                if let Err(err) = file.write_all(&chunk) {
+                    return Err(format!("Error while writing to file: {} {}", file_path_str, err));
                 }
-                Err(err) => Err(format!("Error while creating directory for file: {} {}", &file_download.file_dir.to_str().unwrap_or("?"), err))
+                downloaded += chunk.len() as u64;
+                if !update_download(download_queue, &file_download.uuid, |fd| fd.size = downloaded) {
+                    info!("Download cancelled {}", file_path_str);
+                    drop(file);
+                    let _ = fs::remove_file(&part_path);
+                    return Ok(());
+                }
+            }
+            Ok(None) => {
+                drop(file);
+                info!("Downloaded {}, filesize: {}MB", file_path_str, request_utils::bytes_to_megabytes(downloaded));
+                return fs::rename(&part_path, &file_download.file_path).map_err(|err| format!("Error while writing to file: {} {}", file_path_str, err));
             }
+            // the partial file is kept, a retry continues the download
+            Err(err) => return Err(format!("Error while writing to file: {} {}", file_path_str, err)),
         }
-        Err(err) => Err(format!("Error while opening url: {} {}", &file_download.url, err))
     }
 }
 
-fn run_download_queue(download_cfg: &VideoDownloadConfig, download_queue: Arc<DownloadQueue>) -> Result<(), String> {
-    let next_download = {
-        download_queue.as_ref().queue.lock().unwrap().pop_front()
+/// A failed post processing keeps the download `done`, the error is reported with the download.
+async fn post_process_download(download_cfg: &VideoDownloadConfig, download_queue: &DownloadQueue, file_download: &FileDownload) {
+    let post_processing = match &download_cfg.post_processing {
+        Some(post_processing) => post_processing,
+        None => return,
     };
-    if next_download.is_some() {
-        { *download_queue.as_ref().active.write().unwrap() = next_download; }
-        let headers = request_utils::get_request_headers(&download_cfg.headers, None);
-        let dq = Arc::clone(&download_queue);
-        match reqwest::Client::builder().default_headers(headers).build() {
-            Ok(client) => {
-                actix_rt::spawn(async move {
-                    loop {
-                        if dq.active.read().unwrap().deref().is_some() {
-                            match download_file(Arc::clone(&dq.active), &client).await {
-                                Ok(_) => {
-                                    if let Some(fd) = &mut *dq.active.write().unwrap() {
-                                        fd.finished = true;
-                                        dq.finished.write().unwrap().push(fd.clone());
-                                    }
-                                }
-                                Err(err) => {
-                                    if let Some(fd) = &mut *dq.active.write().unwrap() {
-                                        fd.finished = true;
-                                        fd.error = Some(err);
-                                        dq.finished.write().unwrap().push(fd.clone());
-                                    }
-                                }
-                            }
-                            *dq.active.write().unwrap() = dq.queue.lock().unwrap().pop_front();
-                        } else {
-                            break;
-                        }
-                    }
-                });
+    // cancelled downloads have no file
+    if !update_download(download_queue, &file_download.uuid, |_| {}) {
+        return;
+    }
+    let result = video_postprocess::post_process(download_cfg, post_processing, &file_download.file_path).await;
+    update_download(download_queue, &file_download.uuid, |fd| match result {
+        Ok(file_path) => {
+            fd.filename = file_path.file_name().and_then(|name| name.to_str()).unwrap_or_default().to_string();
+            fd.file_dir = file_path.parent().map(Path::to_path_buf).unwrap_or_default();
+            fd.file_path = file_path;
+        }
+        Err(err) => {
+            error!("Post processing failed for {}: {}", &fd.filename, err);
+            fd.error = Some(format!("Post processing failed: {}", err));
+        }
+    });
+}
+
+fn finish_download(download_queue: &DownloadQueue, uuid: &str, result: Result<(), String>) {
+    let mut downloads = download_queue.downloads.lock().unwrap();
+    if let Some(fd) = downloads.iter_mut().find(|fd| fd.uuid == uuid) {
+        fd.finished = true;
+        match result {
+            Ok(_) => fd.state = DownloadState::Done,
+            Err(err) => {
+                error!("{}", err);
+                fd.state = DownloadState::Failed;
+                fd.error = Some(err);
             }
-            Err(_) => return Err("Failed to build http client".to_string()),
         }
+        download_queue.finished.lock().unwrap().push(fd.clone());
     }
+    download_queue.save(&downloads);
+}
+
+/// Starts queued downloads until `parallel` downloads are running, each finished download starts the next one.
+/// The downloads go through the global `proxy`.
+fn run_download_queue(download_cfg: &VideoDownloadConfig, proxy: Option<&str>, download_queue: &Arc<DownloadQueue>) -> Result<(), String> {
+    let headers = request_utils::get_request_headers(&download_cfg.headers, None);
+    let client = request_utils::build_client(reqwest::Client::builder().default_headers(headers), proxy)?;
+    let mut downloads = download_queue.downloads.lock().unwrap();
+    let running = downloads.iter().filter(|fd| fd.state == DownloadState::Downloading).count();
+    let available = usize::from(download_cfg.parallel.max(1)).saturating_sub(running);
+    for fd in downloads.iter_mut().filter(|fd| fd.state == DownloadState::Queued).take(available) {
+        fd.state = DownloadState::Downloading;
+        let file_download = fd.clone();
+        let dq = Arc::clone(download_queue);
+        let client = client.clone();
+        let cfg = download_cfg.clone();
+        let proxy = proxy.map(String::from);
+        actix_rt::spawn(async move {
+            let result = download_file(&dq, &file_download, &client).await;
+            if result.is_ok() {
+                post_process_download(&cfg, &dq, &file_download).await;
+            }
+            finish_download(&dq, &file_download.uuid, result);
+            if let Err(err) = run_download_queue(&cfg, proxy.as_deref(), &dq) {
+                error!("{}", err);
+            }
+        });
+    }
+    download_queue.save(&downloads);
     Ok(())
 }
 
+fn get_download_config(config: &Config) -> Option<&VideoDownloadConfig> {
+    config.video.as_ref().and_then(|video| video.download.as_ref())
+}
+
+/// Continues the downloads which were queued or interrupted when the server stopped.
+pub(crate) fn resume_downloads(app_state: &AppState) {
+    let config = app_state.get_config();
+    if let Some(download_cfg) = get_download_config(&config) {
+        if let Err(err) = run_download_queue(download_cfg, config.proxy.as_deref(), &app_state.downloads) {
+            error!("{}", err);
+        }
+    }
+}
+
+/// The state of a download as it is returned by the api, the paths of the file are not exposed.
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct DownloadInfo {
+    pub uuid: String,
+    pub filename: String,
+    pub filesize: u64,
+    pub total_size: Option<u64>,
+    pub state: DownloadState,
+    pub finished: bool,
+    pub error: Option<String>,
+}
 
-macro_rules! download_info {
-    ($file_download:expr) => {
-       json!({"uuid": $file_download.uuid, "filename":  $file_download.filename,
-       "filesize": $file_download.size, "finished": $file_download.finished,
-       "error": $file_download.error})
+impl From<&FileDownload> for DownloadInfo {
+    fn from(file_download: &FileDownload) -> Self {
+        Self {
+            uuid: file_download.uuid.to_string(),
+            filename: file_download.filename.to_string(),
+            filesize: file_download.size,
+            total_size: file_download.total_size,
+            state: file_download.state,
+            finished: file_download.finished,
+            error: file_download.error.clone(),
+        }
     }
 }
 
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct DownloadsInfo {
+    pub completed: bool,
+    /// the downloads finished since the last request
+    pub downloads: Vec<DownloadInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<DownloadInfo>,
+}
+
+#[utoipa::path(post, path = "/api/v1/file/download", tag = "downloads", request_body = FileDownloadRequest,
+    responses((status = 200, description = "The queued download", body = DownloadInfo), (status = 400, body = ApiError)))]
 pub(crate) async fn queue_download_file(
     req: web::Json<FileDownloadRequest>,
     _app_state: web::Data<AppState>,
 ) -> HttpResponse {
-    if let Some(download_cfg) = &_app_state.config.video.as_ref().unwrap().download {
+    let config = _app_state.get_config();
+    if let Some(download_cfg) = get_download_config(&config) {
         if download_cfg.directory.is_none() {
             return HttpResponse::BadRequest().json(json!({"error": "Server config missing video.download.directory configuration"}));
         }
         match FileDownload::new(req.url.as_str(), req.filename.as_str(), download_cfg) {
             Some(file_download) => {
-                let response = HttpResponse::Ok().json(download_info!(file_download));
-                _app_state.downloads.queue.lock().unwrap().push_back(file_download);
-                if _app_state.downloads.active.read().unwrap().is_none() {
-                    match run_download_queue(download_cfg, Arc::clone(&_app_state.downloads)) {
-                        Ok(_) => {}
-                        Err(err) => return HttpResponse::InternalServerError().json(json!({"error": err})),
-                    }
+                let response = HttpResponse::Ok().json(DownloadInfo::from(&file_download));
+                _app_state.downloads.downloads.lock().unwrap().push(file_download);
+                match run_download_queue(download_cfg, config.proxy.as_deref(), &_app_state.downloads) {
+                    Ok(_) => response,
+                    Err(err) => HttpResponse::InternalServerError().json(json!({"error": err})),
                 }
-                response
             }
             None => HttpResponse::BadRequest().json(json!({"error": "Invalid Arguments"})),
         }
@@ -138,19 +245,90 @@ pub(crate) async fn queue_download_file(
     }
 }
 
+/// Returns the downloads finished since the last request and the first running download.
+#[utoipa::path(get, path = "/api/v1/file/download/info", tag = "downloads", responses((status = 200, body = DownloadsInfo)))]
 pub(crate) async fn download_file_info(
     _app_state: web::Data<AppState>,
 ) -> HttpResponse {
-    let finished_list: &[Value] = &_app_state.downloads.finished.write().unwrap().drain(..)
-        .map(|fd| download_info!(fd)).collect::<Vec<Value>>();
-
-    match &*_app_state.downloads.active.read().unwrap() {
-        None => HttpResponse::Ok().json(json!({
-            "completed": true, "downloads": finished_list
-        })),
-        Some(file_download) =>
-            HttpResponse::Ok().json(json!({
-            "completed": false, "downloads": finished_list, "active": download_info!(file_download)
-        }))
-    }
-}
\ No newline at end of file
+    let finished_list: Vec<DownloadInfo> = _app_state.downloads.finished.lock().unwrap().drain(..)
+        .map(|fd| DownloadInfo::from(&fd)).collect();
+
+    let downloads = _app_state.downloads.downloads.lock().unwrap();
+    let completed = !downloads.iter().any(|fd| fd.state == DownloadState::Queued || fd.state == DownloadState::Downloading);
+    HttpResponse::Ok().json(DownloadsInfo {
+        completed,
+        downloads: finished_list,
+        active: downloads.iter().find(|fd| fd.state == DownloadState::Downloading).map(DownloadInfo::from),
+    })
+}
+
+/// Lists all downloads of the queue with their state.
+#[utoipa::path(get, path = "/api/v1/file/downloads", tag = "downloads", responses((status = 200, body = [DownloadInfo])))]
+pub(crate) async fn download_list(
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let downloads: Vec<DownloadInfo> = _app_state.downloads.downloads.lock().unwrap().iter()
+        .map(DownloadInfo::from).collect();
+    HttpResponse::Ok().json(downloads)
+}
+
+/// Removes the download from the queue, a running download is cancelled and its partial file is deleted.
+/// The file of a finished download is kept.
+#[utoipa::path(delete, path = "/api/v1/file/downloads/{uuid}", tag = "downloads", params(("uuid" = String, Path, description = "Id of the download")),
+    responses((status = 200, description = "The removed download", body = DownloadInfo), (status = 404)))]
+pub(crate) async fn remove_download(
+    path: web::Path<String>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let uuid = path.into_inner();
+    let removed = {
+        let mut downloads = _app_state.downloads.downloads.lock().unwrap();
+        let removed = downloads.iter().position(|fd| fd.uuid == uuid).map(|index| downloads.remove(index));
+        if removed.is_some() {
+            _app_state.downloads.save(&downloads);
+        }
+        removed
+    };
+    match removed {
+        Some(file_download) => {
+            // the running download removes its partial file itself
+            if file_download.state == DownloadState::Queued || file_download.state == DownloadState::Failed {
+                let _ = fs::remove_file(get_part_path(&file_download.file_path));
+            }
+            HttpResponse::Ok().json(DownloadInfo::from(&file_download))
+        }
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Queues a failed download again, it continues with the partial file.
+#[utoipa::path(post, path = "/api/v1/file/downloads/{uuid}/retry", tag = "downloads", params(("uuid" = String, Path, description = "Id of the download")),
+    responses((status = 200), (status = 400, body = ApiError), (status = 404)))]
+pub(crate) async fn retry_download(
+    path: web::Path<String>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    let download_cfg = match get_download_config(&config) {
+        Some(download_cfg) => download_cfg,
+        None => return HttpResponse::BadRequest().json(json!({"error": "Server config missing video.download configuration"})),
+    };
+    let uuid = path.into_inner();
+    let mut retried = false;
+    let found = update_download(&_app_state.downloads, &uuid, |fd| if fd.state == DownloadState::Failed {
+        fd.state = DownloadState::Queued;
+        fd.finished = false;
+        fd.error = None;
+        retried = true;
+    });
+    if !found {
+        return HttpResponse::NotFound().finish();
+    }
+    if !retried {
+        return HttpResponse::BadRequest().json(json!({"error": "Only failed downloads can be retried"}));
+    }
+    match run_download_queue(download_cfg, config.proxy.as_deref(), &_app_state.downloads) {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(err) => HttpResponse::InternalServerError().json(json!({"error": err})),
+    }
+}
@@ -0,0 +1,141 @@
+use actix_web::{HttpRequest, HttpResponse, Resource, web};
+use serde::{Deserialize, Serialize};
+
+use crate::api::api_model::AppState;
+use crate::api::api_utils::get_base_url;
+use crate::model::config::{Config, HdHomeRunConfig};
+use crate::model::model_playlist::PlaylistItemType;
+use crate::repository::m3u_repository;
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "PascalCase")]
+struct HdHomeRunDevice {
+    friendly_name: String,
+    manufacturer: String,
+    #[serde(rename = "ModelNumber")]
+    model_number: String,
+    firmware_name: String,
+    tuner_count: u8,
+    firmware_version: String,
+    #[serde(rename = "DeviceID")]
+    device_id: String,
+    device_auth: String,
+    #[serde(rename = "BaseURL")]
+    base_url: String,
+    #[serde(rename = "LineupURL")]
+    lineup_url: String,
+}
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "PascalCase")]
+struct HdHomeRunLineupStatus {
+    scan_in_progress: u8,
+    scan_possible: u8,
+    source: String,
+    source_list: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "PascalCase")]
+struct HdHomeRunLineupEntry {
+    guide_number: String,
+    guide_name: String,
+    #[serde(rename = "URL")]
+    url: String,
+}
+
+fn get_hdhomerun_config(config: &Config) -> Option<&HdHomeRunConfig> {
+    config.hdhomerun.as_ref().filter(|hdhr| hdhr.enabled)
+}
+
+#[utoipa::path(get, path = "/discover.json", tag = "hdhomerun", responses((status = 200, body = HdHomeRunDevice), (status = 404)))]
+pub(crate) async fn discover(
+    req: HttpRequest,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    match get_hdhomerun_config(&config) {
+        Some(hdhr) => {
+            let base_url = get_base_url(&req);
+            let device = HdHomeRunDevice {
+                friendly_name: hdhr.name.to_string(),
+                manufacturer: String::from("Silicondust"),
+                model_number: String::from("HDTC-2US"),
+                firmware_name: String::from("hdhomeruntc_atsc"),
+                tuner_count: hdhr.tuner_count,
+                firmware_version: String::from("20150826"),
+                device_id: hdhr.device_id.as_ref().map_or_else(String::new, |id| id.to_string()),
+                device_auth: String::from("m3u-filter"),
+                lineup_url: format!("{}/lineup.json", &base_url),
+                base_url,
+            };
+            HttpResponse::Ok().json(device)
+        }
+        None => HttpResponse::NotFound().finish()
+    }
+}
+
+#[utoipa::path(get, path = "/lineup_status.json", tag = "hdhomerun", responses((status = 200, body = HdHomeRunLineupStatus), (status = 404)))]
+pub(crate) async fn lineup_status(
+    _req: HttpRequest,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    match get_hdhomerun_config(&config) {
+        Some(_) => HttpResponse::Ok().json(HdHomeRunLineupStatus {
+            scan_in_progress: 0,
+            scan_possible: 1,
+            source: String::from("Cable"),
+            source_list: vec![String::from("Cable")],
+        }),
+        None => HttpResponse::NotFound().finish()
+    }
+}
+
+#[utoipa::path(get, path = "/lineup.json", tag = "hdhomerun", responses((status = 200, body = [HdHomeRunLineupEntry]), (status = 404)))]
+pub(crate) async fn lineup(
+    _req: HttpRequest,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    if let Some(hdhr) = get_hdhomerun_config(&config) {
+        if let Some(target) = config.get_target_by_name(&hdhr.target) {
+            let entries: Vec<HdHomeRunLineupEntry> = m3u_repository::load_m3u_playlist(&config, target)
+                .unwrap_or_default().iter()
+                .flat_map(|pg| &pg.channels)
+                .filter(|pli| pli.header.borrow().item_type == PlaylistItemType::Live)
+                .enumerate()
+                .map(|(idx, pli)| {
+                    let header = pli.header.borrow();
+                    HdHomeRunLineupEntry {
+                        guide_number: (idx + 1).to_string(),
+                        guide_name: header.title.to_string(),
+                        url: header.url.to_string(),
+                    }
+                }).collect();
+            return HttpResponse::Ok().json(entries);
+        }
+    }
+    HttpResponse::NotFound().finish()
+}
+
+#[utoipa::path(post, path = "/lineup.post", tag = "hdhomerun", responses((status = 200), (status = 404)))]
+pub(crate) async fn lineup_post(
+    _req: HttpRequest,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    match get_hdhomerun_config(&config) {
+        Some(_) => HttpResponse::Ok().finish(),
+        None => HttpResponse::NotFound().finish()
+    }
+}
+
+pub(crate) fn hdhr_api_register() -> Vec<Resource> {
+    vec![
+        web::resource("/discover.json").route(web::get().to(discover)),
+        web::resource("/lineup_status.json").route(web::get().to(lineup_status)),
+        web::resource("/lineup.json").route(web::get().to(lineup)),
+        web::resource("/lineup.post").route(web::post().to(lineup_post)),
+    ]
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use actix_web::{HttpRequest, HttpResponse, Resource, web};
+use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce};
+use aes_gcm::aead::{Aead, AeadCore, OsRng};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use log::{debug, error};
+use url::Url;
+
+use crate::api::api_model::AppState;
+use crate::api::api_utils::{check_client, get_base_url, is_allowed_client, record_failed_login};
+use crate::model::api_proxy::UserCredentials;
+use crate::model::config::{Config, ConfigInput, ConfigTarget, InputType};
+use crate::utils::{request_utils, shutdown, stream_reconnect, throttle};
+
+const HLS_PROXY_PATH: &str = "hlsproxy";
+const NONCE_SIZE: usize = 12;
+
+/// Segment ids are encrypted with a key generated at startup,
+/// clients can neither read the provider urls and credentials nor forge other urls.
+fn get_segment_key() -> &'static Key<Aes256Gcm> {
+    static KEY: OnceLock<Key<Aes256Gcm>> = OnceLock::new();
+    KEY.get_or_init(|| Aes256Gcm::generate_key(OsRng))
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct HlsSegment {
+    pub username: String,
+    pub password: String,
+    pub url: String,
+}
+
+fn encode_segment_id(segment: &HlsSegment) -> Option<String> {
+    let nonce = Aes256Gcm::generate_nonce(OsRng);
+    let plain = serde_json::to_vec(segment).ok()?;
+    let ciphertext = Aes256Gcm::new(get_segment_key()).encrypt(&nonce, plain.as_slice()).ok()?;
+    Some(URL_SAFE_NO_PAD.encode([nonce.as_slice(), &ciphertext].concat()))
+}
+
+pub(crate) fn decode_segment_id(segment_id: &str) -> Option<HlsSegment> {
+    let data = URL_SAFE_NO_PAD.decode(segment_id).ok()?;
+    if data.len() <= NONCE_SIZE {
+        return None;
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_SIZE);
+    let plain = Aes256Gcm::new(get_segment_key()).decrypt(Nonce::from_slice(nonce), ciphertext).ok()?;
+    serde_json::from_slice(&plain).ok()
+}
+
+pub(crate) fn is_hls_response(response: &reqwest::Response) -> bool {
+    let is_hls_content_type = response.headers().get(reqwest::header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .is_some_and(|ct| ct.to_lowercase().contains("mpegurl"));
+    is_hls_content_type || response.url().path().to_lowercase().ends_with(".m3u8")
+}
+
+fn rewrite_hls_url(url: &str, base_url: &Url, proxy_url: &str, user: &UserCredentials) -> String {
+    match base_url.join(url) {
+        Ok(absolute_url) => {
+            let segment = HlsSegment { username: user.username.clone(), password: user.password.clone(), url: absolute_url.to_string() };
+            match encode_segment_id(&segment) {
+                Some(segment_id) => format!("{}/{}", proxy_url, segment_id),
+                None => url.to_string()
+            }
+        }
+        Err(_) => url.to_string()
+    }
+}
+
+fn rewrite_hls_uri_attribute(line: &str, base_url: &Url, proxy_url: &str, user: &UserCredentials) -> String {
+    if let Some(start) = line.find("URI=\"") {
+        let value_start = start + 5;
+        if let Some(len) = line[value_start..].find('"') {
+            let uri = &line[value_start..value_start + len];
+            return format!("{}{}{}", &line[..value_start], rewrite_hls_url(uri, base_url, proxy_url, user), &line[value_start + len..]);
+        }
+    }
+    line.to_string()
+}
+
+/// Rewrites all segment, variant and key urls of the playlist to go through the proxy.
+/// The urls are replaced by opaque segment ids, they contain neither the provider url nor the user credentials.
+pub(crate) fn rewrite_hls_playlist(content: &str, base_url: &Url, proxy_url: &str, user: &UserCredentials) -> String {
+    content.lines().map(|line| {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            line.to_string()
+        } else if trimmed.starts_with('#') {
+            rewrite_hls_uri_attribute(line, base_url, proxy_url, user)
+        } else {
+            rewrite_hls_url(trimmed, base_url, proxy_url, user)
+        }
+    }).collect::<Vec<String>>().join("\n")
+}
+
+/// Serves a hls response, playlists are rewritten, segments are streamed.
+/// A segment is reopened with the `stream_reconnect` options of the input, when the provider drops it.
+pub(crate) async fn serve_hls_response(req: &HttpRequest, user: &UserCredentials, input: &ConfigInput,
+                                       request: Option<reqwest::RequestBuilder>, response: reqwest::Response) -> HttpResponse {
+    if is_hls_response(&response) {
+        let base_url = response.url().clone();
+        match response.text().await {
+            Ok(content) => {
+                let proxy_url = format!("{}/{}", get_base_url(req), HLS_PROXY_PATH);
+                let playlist = rewrite_hls_playlist(&content, &base_url, &proxy_url, user);
+                HttpResponse::Ok().content_type("application/vnd.apple.mpegurl").body(playlist)
+            }
+            Err(err) => {
+                error!("Failed to read hls playlist {}: {}", &base_url, err);
+                HttpResponse::BadRequest().finish()
+            }
+        }
+    } else {
+        let mut response_builder = HttpResponse::Ok();
+        response.headers().iter().for_each(|(k, v)| {
+            response_builder.insert_header((k, v));
+        });
+        let client_range = req.headers().get(actix_web::http::header::RANGE).and_then(|range| range.to_str().ok());
+        let stream = stream_reconnect::get_stream(input, request, response, false, client_range);
+        response_builder.body(actix_web::body::BodyStream::new(shutdown::until_shutdown(throttle::throttle(stream, user.max_bitrate_kbps))))
+    }
+}
+
+fn get_target_input<'a>(config: &'a Config, target: &ConfigTarget) -> Option<&'a ConfigInput> {
+    match config.get_input_for_target(&target.name, &InputType::Xtream) {
+        None => config.get_input_for_target(&target.name, &InputType::M3u),
+        Some(inp) => Some(inp)
+    }
+}
+
+#[utoipa::path(get, path = "/hlsproxy/{segment_id}", tag = "player",
+    params(("segment_id" = String, Path, description = "Segment id from a rewritten hls playlist")),
+    responses((status = 200, description = "The hls playlist or segment"), (status = 400), (status = 403)))]
+pub(crate) async fn hls_segment(
+    req: HttpRequest,
+    path: web::Path<String>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    // segments are requested every few seconds, they are not counted for the rate limit
+    if let Some(response) = check_client(&req, &config, false) {
+        return response;
+    }
+    let segment = match decode_segment_id(&path.into_inner()) {
+        Some(segment) => segment,
+        None => {
+            debug!("Invalid hls segment id");
+            record_failed_login(&req, &config);
+            return HttpResponse::BadRequest().finish();
+        }
+    };
+    if let Some((user, target)) = config.get_target_for_user(&segment.username, &segment.password) {
+        if !is_allowed_client(&req, &config, &user) {
+            return HttpResponse::Forbidden().finish();
+        }
+        if let Some(target_input) = get_target_input(&config, target) {
+            if let Ok(url) = Url::parse(&segment.url) {
+                let req_headers: HashMap<&str, &[u8]> = req.headers().iter().map(|(k, v)| (k.as_str(), v.as_bytes())).collect();
+                let client = match request_utils::get_client_request(target_input, url, Some(&req_headers)) {
+                    Ok(client) => client,
+                    Err(err) => {
+                        error!("{}", err);
+                        return HttpResponse::BadGateway().finish();
+                    }
+                };
+                let reconnect_request = client.try_clone();
+                match client.send().await {
+                    Ok(response) => {
+                        if response.status().is_success() {
+                            return serve_hls_response(&req, &user, target_input, reconnect_request, response).await;
+                        }
+                        debug!("Failed to open hls segment got status {} for {}", response.status(), &segment.url);
+                    }
+                    Err(err) => error!("Received failure from server {}:  {}", &segment.url, err)
+                }
+            } else {
+                error!("Url is malformed {}", &segment.url)
+            }
+        }
+    } else {
+        // the credentials of the user changed since the playlist was served
+        debug!("Could not find any user {}", segment.username);
+    }
+    HttpResponse::BadRequest().finish()
+}
+
+pub(crate) fn hls_api_register() -> Vec<Resource> {
+    vec![
+        web::resource(format!("/{}/{{segment_id}}", HLS_PROXY_PATH)).route(web::get().to(hls_segment)),
+    ]
+}
@@ -1,21 +1,170 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
 use actix_web::{HttpRequest, HttpResponse, Resource, web};
+use openssl::sha::Sha256;
+use serde_json::Value;
 
-use crate::api::api_utils::{get_user_target, serve_file};
+use crate::api::api_utils::{check_client, get_base_url, get_user_stream_url, get_user_target, serve_content_stream, serve_file};
 use crate::api::api_model::{AppState, UserApiRequest};
+use crate::model::api_proxy::UserCredentials;
+use crate::model::config::{Config, ConfigTarget};
+use crate::model::model_config::TargetType;
+use crate::model::model_playlist::XtreamCluster;
 use crate::repository::m3u_repository::get_m3u_file_path;
+use crate::repository::xtream_repository;
+
+fn get_str<'a>(document: &'a Value, field: &str) -> &'a str {
+    document.get(field).and_then(Value::as_str).unwrap_or("")
+}
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The streams of a cluster with the names of their categories.
+struct ClusterStreams {
+    cluster: XtreamCluster,
+    category_names: HashMap<String, String>,
+    streams: Box<dyn Iterator<Item=Value>>,
+}
+
+/// Creates the playlist like the `get.php` of a xtream server from the xtream repository of the target,
+/// with the streams of the users bouquet and stream urls of m3u-filter.
+/// The playlist is created in chunks while it is sent, the streams are read one after another.
+struct UserM3u {
+    config: Arc<Config>,
+    target_name: String,
+    user: UserCredentials,
+    base_url: String,
+    m3u_plus: bool,
+    live_extension: &'static str,
+    clusters: Vec<XtreamCluster>,
+    current: Option<ClusterStreams>,
+    started: bool,
+}
+
+impl UserM3u {
+    fn new(config: Arc<Config>, target_name: String, user: UserCredentials, base_url: String, m3u_plus: bool, live_extension: &'static str) -> Self {
+        UserM3u {
+            config,
+            target_name,
+            user,
+            base_url,
+            m3u_plus,
+            live_extension,
+            clusters: vec![XtreamCluster::Video, XtreamCluster::Live],
+            current: None,
+            started: false,
+        }
+    }
+
+    /// Opens the streams of the next cluster, `false` if all clusters are written.
+    fn next_cluster(&mut self) -> bool {
+        match self.clusters.pop() {
+            Some(cluster) => {
+                let (categories, streams) = xtream_repository::xtream_iter_user_streams(&self.config, &self.target_name, &cluster, &self.user);
+                let category_names = categories.iter()
+                    .filter_map(|category| category.get("category_id").and_then(xtream_repository::get_category_id)
+                        .map(|id| (id, get_str(category, "category_name").to_string()))).collect();
+                self.current = Some(ClusterStreams { cluster, category_names, streams });
+                true
+            }
+            None => {
+                self.current = None;
+                false
+            }
+        }
+    }
+
+    fn write_stream(&self, content: &mut String, current: &ClusterStreams, stream: &Value) {
+        if let Some(url) = get_user_stream_url(&self.base_url, &self.user, &current.cluster, stream, self.live_extension) {
+            let name = get_str(stream, "name");
+            if self.m3u_plus {
+                let group = stream.get("category_id").and_then(xtream_repository::get_category_id)
+                    .and_then(|id| current.category_names.get(&id)).map_or("", String::as_str);
+                let _ = writeln!(content, "#EXTINF:-1 tvg-id=\"{}\" tvg-name=\"{}\" tvg-logo=\"{}\" group-title=\"{}\",{}",
+                                 get_str(stream, "epg_channel_id"), name, get_str(stream, "stream_icon"), group, name);
+            } else {
+                let _ = writeln!(content, "#EXTINF:-1,{}", name);
+            }
+            let _ = writeln!(content, "{}", url);
+        }
+    }
+}
+
+impl Iterator for UserM3u {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let mut content = String::with_capacity(CHUNK_SIZE + 1024);
+        if !self.started {
+            self.started = true;
+            content.push_str("#EXTM3U\n");
+        }
+        while content.len() < CHUNK_SIZE {
+            if self.current.is_none() && !self.next_cluster() {
+                break;
+            }
+            let next = self.current.as_mut().and_then(|current| current.streams.next());
+            match next {
+                Some(stream) => if let Some(current) = &self.current {
+                    self.write_stream(&mut content, current, &stream);
+                },
+                None => { self.next_cluster(); }
+            }
+        }
+        if content.is_empty() { None } else { Some(content) }
+    }
+}
 
+/// The etag depends on the collection version of the target and on the request, the playlist is the same until the next processing run.
+fn get_user_m3u_etag(config: &Config, target_name: &str, user: &UserCredentials, base_url: &str, m3u_plus: bool, live_extension: &str) -> Option<String> {
+    let version = xtream_repository::xtream_get_collection_version(config, target_name)?;
+    let groups = user.groups.as_ref().map_or_else(String::new, |groups| groups.join("\0"));
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}\0{}\0{}\0{}\0{}\0{}\0{}\0{}", target_name, version, user.username, user.password,
+                          groups, base_url, m3u_plus, live_extension).as_bytes());
+    Some(hasher.finish()[..16].iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Serves the playlist of the user created from the xtream repository, a client with the current playlist gets `304 Not Modified`.
+pub(crate) fn serve_user_m3u(req: &HttpRequest, api_req: &UserApiRequest, config: Arc<Config>, target: &ConfigTarget, user: UserCredentials) -> HttpResponse {
+    let base_url = get_base_url(req);
+    let m3u_plus = api_req.playlist_type != "m3u";
+    let live_extension = if api_req.output == "m3u8" || api_req.output == "hls" { "m3u8" } else { "ts" };
+    let etag = get_user_m3u_etag(&config, &target.name, &user, &base_url, m3u_plus, live_extension);
+    let target_name = target.name.to_string();
+    serve_content_stream(req, "audio/x-mpegurl", etag,
+                         move || UserM3u::new(config, target_name, user, base_url, m3u_plus, live_extension).map(Ok))
+}
 
-async fn m3u_api(
+#[utoipa::path(get, path = "/get.php", tag = "player", params(UserApiRequest),
+    responses((status = 200, description = "The m3u playlist of the user", body = String, content_type = "audio/x-mpegurl"), (status = 204), (status = 400),
+        (status = 403, description = "The user has a bouquet and the target has no xtream output")))]
+pub(crate) async fn m3u_api(
     api_req: web::Query<UserApiRequest>,
     req: HttpRequest,
     _app_state: web::Data<AppState>,
 ) -> HttpResponse {
-    match get_user_target(&api_req, &_app_state) {
-        Some((_, target)) => {
+    let config = _app_state.get_config();
+    if let Some(response) = check_client(&req, &config, true) {
+        return response;
+    }
+    match get_user_target(&req, &api_req, &config) {
+        Some((user, target)) => {
             let filename = target.get_m3u_filename();
+            // xtream clients request the playlist with a type, users with a bouquet get only their groups
+            if target.has_output(&TargetType::Xtream)
+                && (!api_req.playlist_type.is_empty() || filename.is_none() || user.groups.is_some()) {
+                return serve_user_m3u(&req, &api_req, Arc::clone(&config), target, user);
+            }
+            // the playlist file contains all groups, it can't be served to a user with a bouquet
+            if user.groups.is_some() {
+                return HttpResponse::Forbidden().finish();
+            }
             if filename.is_some() {
-                if let Some(file_path) = get_m3u_file_path(&_app_state.config, &filename) {
-                    return serve_file(&file_path, &req).await;
+                if let Some(file_path) = get_m3u_file_path(&config, target, &filename) {
+                    return serve_file(&config, &file_path, &req).await;
                 }
             }
             HttpResponse::NoContent().finish()
@@ -33,4 +182,4 @@ pub(crate) fn m3u_api_register() -> Vec<Resource> {
         web::resource("/apiget").route(web::get().to(m3u_api)),
         web::resource("/m3u").route(web::get().to(m3u_api))
     ]
-}
\ No newline at end of file
+}
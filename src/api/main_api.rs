@@ -1,65 +1,119 @@
-use std::collections::VecDeque;
 use std::io::ErrorKind;
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex, RwLock};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use actix_cors::Cors;
 use actix_files::NamedFile;
-use actix_web::{App, get, HttpRequest, HttpServer, web};
+use actix_web::{App, guard, HttpResponse, HttpServer, Scope, web};
+use actix_web::http::header::LOCATION;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Server, ServerHandle, Service, ServiceFactory, ServiceRequest, ServiceResponse};
 use actix_web::middleware::Logger;
+use futures::TryFutureExt;
+use log::{info, warn};
+use socket2::{Domain, Protocol, Socket, Type};
 use crate::api::m3u_api::{m3u_api_register};
+use crate::api::panel_api::{panel_api_register};
 
-use crate::api::api_model::{AppState, DownloadQueue, SharedLocks};
-use crate::api::scheduler::start_scheduler;
+use crate::api::api_model::AppState;
+use crate::api::hdhr_api::{hdhr_api_register};
+use crate::api::hls_api::{hls_api_register};
+use crate::api::{access_log_api, download_api};
+use crate::api::scheduler::{start_account_check, start_schedulers, start_sources_dir_watch};
+use crate::api::stats_api::{stats_api_register};
 use crate::api::v1_api::{v1_api_register};
+use crate::api::web_ui_api::{web_ui_register};
 use crate::api::xmltv_api::{xmltv_api_register};
 use crate::api::xtream_api::{xtream_api_register};
-use crate::model::config::{Config,ProcessTargets};
-
-#[get("/")]
-async fn index(
-    _req: HttpRequest,
-    _app_state: web::Data<AppState>,
-) -> std::io::Result<NamedFile> {
-    let path: PathBuf = [&_app_state.config.api.web_root, "index.html"].iter().collect();
-    NamedFile::open(path)
-}
+use crate::model::config::{Config, ConfigApiListen, ConfigTenant, ProcessTargets};
+use crate::model::model_config::ListenScope;
+use crate::utils::{jobs, shutdown, tls_utils, user_stats};
 
 
-#[actix_web::main]
-pub(crate) async fn start_server(cfg: Arc<Config>, targets: Arc<ProcessTargets>) -> futures::io::Result<()> {
-    let host = cfg.api.host.to_string();
-    let port = cfg.api.port;
-    let web_dir = cfg.api.web_root.to_string();
-    let web_dir_path = PathBuf::from(&web_dir);
-    if !&web_dir_path.exists() || !&web_dir_path.is_dir() {
-        return Err(std::io::Error::new(ErrorKind::NotFound,
-                                       format!("web_root does not exists or is not an directory: {:?}", &web_dir_path)));
+/// The web ui loads its files relative to the page, `/base_path` is redirected to `/base_path/`.
+fn configure_base_path_redirect(cfg: &mut web::ServiceConfig, base_path: &str) {
+    if !base_path.is_empty() {
+        let location = format!("{}/", base_path);
+        cfg.route(base_path, web::get().to(move || {
+            let location = location.clone();
+            async move { HttpResponse::PermanentRedirect().insert_header((LOCATION, location)).finish() }
+        }));
     }
+}
 
-    let schedule = cfg.schedule.clone();
-
-    let shared_data = web::Data::new(AppState {
-        config: cfg,
-        targets,
-        downloads: Arc::from(DownloadQueue {
-            queue: Arc::from(Mutex::new(VecDeque::new())),
-            active: Arc::from(RwLock::new(None)),
-            finished: Arc::from(RwLock::new(Vec::new())),
-        }),
-        shared_locks: Arc::new(SharedLocks::new()),
-    });
+/// The player endpoints and the Web-UI with its rest api are only served on the listen addresses of their scope.
+/// Without a web dir the embedded web ui is served.
+fn create_api_scope(path: &str, web_dir_path: Option<&Path>, listen_scope: ListenScope) -> Scope {
+    let mut scope = web::scope(path);
+    if listen_scope.serves_management() {
+        // this is necessary because of the xtream api route without specific prefix
+        if let Some(web_dir_path) = web_dir_path {
+            scope = scope.service(actix_files::Files::new("/static", web_dir_path.join("static")));
+        }
+        scope = scope.service(v1_api_register());
+    }
+    if listen_scope.serves_streaming() {
+        scope = scope.service(xtream_api_register())
+            .service(m3u_api_register())
+            .service(panel_api_register())
+            .service(xmltv_api_register())
+            .service(hdhr_api_register())
+            .service(hls_api_register());
+    }
+    if listen_scope.serves_management() {
+        scope = scope.service(stats_api_register());
+        scope = match web_dir_path {
+            Some(web_dir_path) => {
+                let index_path = web_dir_path.join("index.html");
+                scope.route("/", web::get().to(move || {
+                    let index_path = index_path.clone();
+                    async move { NamedFile::open(index_path) }
+                }))
+                    .service(actix_files::Files::new("/", web_dir_path))
+            }
+            None => scope.service(web_ui_register()),
+        };
+    }
+    scope
+}
 
-    // Scheduler
-    if let Some(expression) = schedule {
-        let cloned_data = shared_data.clone();
-        actix_rt::spawn(async move {
-            start_scheduler(&expression, cloned_data).await
-        });
+/// The scope of a tenant has the app state of the tenant, requests to other hosts are passed to the next scope.
+fn create_tenant_scope(tenant: &ConfigTenant, data: &web::Data<AppState>, web_dir_path: Option<&Path>, listen_scope: ListenScope) -> Scope {
+    let scope = create_api_scope(&format!("{}{}", data.base_path, data.tenant_path), web_dir_path, listen_scope).app_data(data.clone());
+    match tenant.hosts.split_first() {
+        Some((host, others)) => scope.guard(others.iter()
+            .fold(guard::Any(guard::Host(host)), |hosts, other| hosts.or(guard::Host(other)))),
+        None => scope,
     }
+}
+
+fn create_tenant_data(tenant: &ConfigTenant, cfg: Config, base_path: &str) -> web::Data<AppState> {
+    // schedules without targets process all targets of the tenant
+    let targets = ProcessTargets { enabled: false, inputs: vec![], targets: vec![], dry_run: false, names: None, conditions: false };
+    let schedules = cfg.get_schedules();
+    let mut app_state = AppState::new(Arc::new(cfg), Arc::new(targets));
+    app_state.base_path = base_path.to_string();
+    app_state.tenant_path = tenant.path.clone().unwrap_or_default();
+    let data = web::Data::new(app_state);
+    info!("Tenant {}: hosts: {} path: {}{}/", &tenant.name,
+        if tenant.hosts.is_empty() { String::from("all") } else { tenant.hosts.join(", ") }, base_path, &data.tenant_path);
+    start_schedulers(&schedules, &data);
+    start_account_check(&data);
+    start_sources_dir_watch(&data);
+    download_api::resume_downloads(&data);
+    data
+}
 
-    // Web Server
-    HttpServer::new(move || App::new()
+pub(crate) fn create_app(shared_data: &web::Data<AppState>, tenant_data: &[(ConfigTenant, web::Data<AppState>)], base_path: &str,
+              web_dir_path: Option<&Path>, listen_scope: ListenScope)
+              -> App<impl ServiceFactory<ServiceRequest, Config=(), Response=ServiceResponse<impl MessageBody>, Error=actix_web::Error, InitError=()>> {
+    let mut app = App::new()
+        .wrap_fn(|req, srv| {
+            let started = Instant::now();
+            srv.call(req).map_ok(move |res| access_log_api::log_response(res, started))
+        })
         .wrap(Logger::default())
         .wrap(Cors::default()
             .supports_credentials()
@@ -69,18 +123,111 @@ pub(crate) async fn start_server(cfg: Arc<Config>, targets: Arc<ProcessTargets>)
             .max_age(3600)
         )
         .app_data(shared_data.clone())
-        // this is necessary because of the xtream api route without specific prefix
-        .service(actix_files::Files::new("/static", web_dir_path.join("static")))
-        .service(v1_api_register())
-        .service(xtream_api_register())
-        .service(m3u_api_register())
-        .service(xmltv_api_register())
-        .service(index)
-        .service(actix_files::Files::new("/", &web_dir_path))
-    )
-        .bind(format!("{}:{}", host, port))?
-        .run().await
+        .configure(|cfg| configure_base_path_redirect(cfg, base_path));
+    // the tenants are matched before the main config, which serves all other requests
+    for (tenant, data) in tenant_data {
+        app = app.configure(|cfg| configure_base_path_redirect(cfg, &format!("{}{}", base_path, data.tenant_path)))
+            .service(create_tenant_scope(tenant, data, web_dir_path, listen_scope));
+    }
+    app.service(create_api_scope(base_path, web_dir_path, listen_scope))
+}
+
+/// Binds the ipv6 address without accepting ipv4 connections, the system default would block the port for ipv4.
+fn create_ipv6_only_listener(listen: &ConfigApiListen) -> std::io::Result<std::net::TcpListener> {
+    let ip = listen.get_ipv6_address()
+        .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, format!("no ipv6 address: {}", &listen.host)))?;
+    let socket = Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_only_v6(true)?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::new(IpAddr::V6(ip), listen.port).into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
+#[actix_web::main]
+pub(crate) async fn start_server(cfg: Arc<Config>, targets: Arc<ProcessTargets>, tenants: Vec<(ConfigTenant, Config)>) -> futures::io::Result<()> {
+    let host = cfg.api.host.to_string();
+    let port = cfg.api.port;
+    // an empty web_root serves the embedded web ui
+    let web_dir_path = Some(&cfg.api.web_root).filter(|web_dir| !web_dir.is_empty()).map(PathBuf::from);
+    if let Some(web_dir_path) = &web_dir_path {
+        if !web_dir_path.exists() || !web_dir_path.is_dir() {
+            return Err(std::io::Error::new(ErrorKind::NotFound,
+                                           format!("web_root does not exists or is not an directory: {:?}", web_dir_path)));
+        }
+    }
+
+    let schedules = cfg.get_schedules();
+    let shutdown_timeout = cfg.api.shutdown_timeout;
+    let tls = cfg.api.tls.clone();
+    let listen = cfg.api.listen.clone();
+    let api_scope = cfg.api.scope;
+    let base_path = cfg.api.base_path.clone().unwrap_or_default();
+
+    let shared_data = web::Data::new(AppState::new(cfg, targets));
+
+    // Scheduler
+    start_schedulers(&schedules, &shared_data);
+    start_account_check(&shared_data);
+    start_sources_dir_watch(&shared_data);
+    download_api::resume_downloads(&shared_data);
+    user_stats::start_flush_task();
+    let tenant_data: Vec<(ConfigTenant, web::Data<AppState>)> = tenants.into_iter()
+        .map(|(tenant, tenant_cfg)| {
+            let data = create_tenant_data(&tenant, tenant_cfg, &base_path);
+            (tenant, data)
+        }).collect();
+
+    // Web Server, the listen addresses of a scope share one server
+    let mut servers = vec![];
+    for listen_scope in [ListenScope::All, ListenScope::Streaming, ListenScope::Management] {
+        let scope_listen: Vec<&ConfigApiListen> = listen.iter().filter(|listen| listen.scope == listen_scope).collect();
+        if listen_scope != api_scope && scope_listen.is_empty() {
+            continue;
+        }
+        let (shared_data, tenant_data, base_path, web_dir_path) = (shared_data.clone(), tenant_data.clone(), base_path.clone(), web_dir_path.clone());
+        let mut server = HttpServer::new(move || create_app(&shared_data, &tenant_data, &base_path, web_dir_path.as_deref(), listen_scope))
+            .disable_signals();
+        if listen_scope == api_scope {
+            server = match &tls {
+                Some(tls) => {
+                    let acceptor = tls_utils::create_ssl_acceptor(tls, &host)?;
+                    match tls.port {
+                        Some(tls_port) => server.bind(format!("{}:{}", host, port))?.bind_openssl(format!("{}:{}", host, tls_port), acceptor)?,
+                        None => server.bind_openssl(format!("{}:{}", host, port), acceptor)?,
+                    }
+                }
+                None => server.bind(format!("{}:{}", host, port))?,
+            };
+        }
+        for listen in scope_listen {
+            let acceptor = match tls.as_ref().filter(|_| listen.tls) {
+                Some(tls) => Some(tls_utils::create_ssl_acceptor(tls, &host)?),
+                None => None,
+            };
+            server = match (acceptor, listen.ipv6_only) {
+                (Some(acceptor), true) => server.listen_openssl(create_ipv6_only_listener(listen)?, acceptor)?,
+                (Some(acceptor), false) => server.bind_openssl(listen.get_address(), acceptor)?,
+                (None, true) => server.listen(create_ipv6_only_listener(listen)?)?,
+                (None, false) => server.bind(listen.get_address())?,
+            };
+        }
+        servers.push(server.run());
+    }
+    // the jobs are spawned on the worker threads, they are stopped with the server
+    let server_handles: Vec<ServerHandle> = servers.iter().map(Server::handle).collect();
+    actix_rt::spawn(async move {
+        shutdown::wait_for_signal().await;
+        shutdown::begin_shutdown();
+        shutdown::notify_systemd("STOPPING=1");
+        if !jobs::wait_for_running_jobs(Duration::from_secs(u64::from(shutdown_timeout))).await {
+            warn!("Processing jobs are still running after {} seconds, stopping anyway", shutdown_timeout);
+        }
+        user_stats::flush();
+        futures::future::join_all(server_handles.iter().map(|server_handle| server_handle.stop(true))).await;
+    });
+    shutdown::notify_systemd("READY=1");
+    futures::future::try_join_all(servers).await.map(|_| ())
     //
     // .service(actix_files::Files::new("/static", ".").show_files_listing())
 }
-
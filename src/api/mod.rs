@@ -2,8 +2,18 @@ pub(crate) mod api_utils;
 pub(crate) mod api_model;
 pub(crate) mod main_api;
 mod download_api;
-mod v1_api;
-mod xtream_api;
-mod m3u_api;
+mod config_api;
+pub(crate) mod v1_api;
+pub(crate) mod xtream_api;
+pub(crate) mod m3u_api;
 mod xmltv_api;
+mod hdhr_api;
+pub(crate) mod hls_api;
+mod stats_api;
+mod security_api;
+pub(crate) mod auth_api;
+mod access_log_api;
+mod panel_api;
+mod web_ui_api;
+pub(crate) mod openapi_api;
 mod scheduler;
@@ -0,0 +1,96 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Deserialize;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme};
+use utoipa::openapi::server::Server;
+use utoipa::{Modify, OpenApi};
+
+use crate::api::api_model::ApiError;
+use crate::api::api_utils::get_base_path;
+use crate::api::{access_log_api, auth_api, config_api, download_api, hdhr_api, hls_api, m3u_api, panel_api, security_api, stats_api, v1_api, xmltv_api, xtream_api};
+
+const BEARER_SECURITY: &str = "bearer";
+const SESSION_SECURITY: &str = "session";
+
+/// Adds the credentials of `api.auth` to the endpoints which need them.
+struct AuthSecurity;
+
+impl Modify for AuthSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(BEARER_SECURITY, SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()));
+        components.add_security_scheme(SESSION_SECURITY, SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(auth_api::SESSION_COOKIE))));
+        openapi.paths.paths.iter_mut()
+            .filter(|(path, _)| path.starts_with("/api/v1/") && !auth_api::is_public_path(path))
+            .flat_map(|(_, item)| [&mut item.get, &mut item.put, &mut item.post, &mut item.delete])
+            .flatten()
+            .for_each(|operation| operation.security = Some(vec![
+                SecurityRequirement::new(BEARER_SECURITY, Vec::<String>::new()),
+                SecurityRequirement::new(SESSION_SECURITY, Vec::<String>::new()),
+            ]));
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(description = "The rest api of the Web-UI. If `api.auth` is configured, the endpoints need a bearer token or the session cookie of `auth/login`."),
+    paths(
+        openapi,
+        auth_api::login, auth_api::logout, auth_api::status,
+        v1_api::config, config_api::update_config, v1_api::save_config_main, v1_api::save_config_api_proxy_config, config_api::reload_config,
+        config_api::backups, config_api::restore_backup,
+        config_api::targets, config_api::update_targets, v1_api::target_history, v1_api::target_snapshot, v1_api::restore_target_snapshot,
+        config_api::inputs, config_api::update_inputs, v1_api::input_status, v1_api::input_raw_groups, v1_api::input_raw_channels,
+        v1_api::overrides, v1_api::put_override, v1_api::remove_override,
+        v1_api::list_custom_channels, v1_api::put_custom_channel, v1_api::remove_custom_channel,
+        v1_api::playlist, v1_api::playlist_update, v1_api::playlist_refresh, v1_api::playlist_preview, v1_api::playlist_search,
+        v1_api::playlist_health, v1_api::filter_test, v1_api::video_search,
+        v1_api::job_list, v1_api::job,
+        stats_api::stats, stats_api::prometheus_metrics,
+        v1_api::proxy_users, v1_api::save_config_api_proxy_user, stats_api::user_stats, access_log_api::access_log,
+        security_api::bans, security_api::add_ban, security_api::remove_ban,
+        download_api::queue_download_file, download_api::download_file_info, download_api::download_list,
+        download_api::remove_download, download_api::retry_download,
+    ),
+    components(schemas(ApiError)),
+    modifiers(&AuthSecurity),
+)]
+pub(crate) struct ManagementApiDoc;
+
+/// The endpoints of the players, they authenticate with the credentials of the api-proxy users.
+#[derive(OpenApi)]
+#[openapi(paths(
+    xtream_api::xtream_player_api_get, xtream_api::xtream_player_api_post,
+    xtream_api::xtream_player_api_live_stream, xtream_api::xtream_player_api_live_stream_alt,
+    xtream_api::xtream_player_api_movie_stream, xtream_api::xtream_player_api_series_stream,
+    xtream_api::xtream_player_api_timeshift_stream, xtream_api::xtream_player_api_timeshift_php,
+    m3u_api::m3u_api, xmltv_api::xmltv_api, hls_api::hls_segment,
+    panel_api::panel_api, panel_api::enigma2_api, panel_api::enigma2_bouquet,
+    hdhr_api::discover, hdhr_api::lineup_status, hdhr_api::lineup, hdhr_api::lineup_post,
+))]
+struct PlayerApiDoc;
+
+#[derive(Debug, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct OpenApiRequest {
+    /// include the player api endpoints
+    #[serde(default)]
+    pub player: bool,
+}
+
+/// The OpenAPI document of the api, the server url is the base path of the request.
+#[utoipa::path(get, path = "/api/v1/openapi.json", tag = "api", params(OpenApiRequest),
+    responses((status = 200, description = "The OpenAPI document", body = Object)))]
+pub(crate) async fn openapi(
+    req: HttpRequest,
+    query: web::Query<OpenApiRequest>,
+) -> HttpResponse {
+    let mut doc = ManagementApiDoc::openapi();
+    if query.player {
+        doc.merge(PlayerApiDoc::openapi());
+    }
+    let base_path = get_base_path(&req);
+    if !base_path.is_empty() {
+        doc.servers = Some(vec![Server::new(base_path)]);
+    }
+    HttpResponse::Ok().json(doc)
+}
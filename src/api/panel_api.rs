@@ -0,0 +1,225 @@
+use std::fmt::Write;
+
+use actix_web::{HttpRequest, HttpResponse, Resource, web};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use quick_xml::escape::escape;
+use serde_json::{json, Map, Value};
+
+use crate::api::api_model::{AppState, UserApiRequest};
+use crate::api::api_utils::{check_client, get_base_url, get_user_stream_url, get_user_target};
+use crate::api::xtream_api;
+use crate::model::api_proxy::UserCredentials;
+use crate::model::config::{Config, ConfigTarget};
+use crate::model::model_config::TargetType;
+use crate::model::model_playlist::XtreamCluster;
+use crate::repository::m3u_repository::sanitize_for_filename;
+use crate::repository::xtream_repository;
+
+const ENIGMA2_PATH: &str = "enigma2.php";
+
+fn get_str<'a>(document: &'a Value, field: &str) -> &'a str {
+    document.get(field).and_then(Value::as_str).unwrap_or("")
+}
+
+fn get_category_id(document: &Value) -> String {
+    document.get("category_id").and_then(xtream_repository::get_category_id).unwrap_or_default()
+}
+
+fn get_cluster(playlist_type: &str) -> XtreamCluster {
+    if playlist_type.contains("vod") || playlist_type == "movie" { XtreamCluster::Video } else { XtreamCluster::Live }
+}
+
+/// Authenticates the user of a request to a target with xtream output.
+fn get_xtream_user<'a>(req: &HttpRequest, api_req: &'a UserApiRequest, config: &'a Config) -> Result<(UserCredentials, &'a ConfigTarget), HttpResponse> {
+    if let Some(response) = check_client(req, config, true) {
+        return Err(response);
+    }
+    match get_user_target(req, api_req, config) {
+        Some((user, target)) if target.has_output(&TargetType::Xtream) => Ok((user, target)),
+        Some(_) => Err(HttpResponse::NoContent().finish()),
+        None => Err(HttpResponse::Unauthorized().finish()),
+    }
+}
+
+/// The panel response of xtream servers, the user and server info with the categories
+/// and the live and movie streams by stream id.
+fn create_panel(req: &HttpRequest, config: &Config, target: &ConfigTarget, user: &UserCredentials, app_state: &AppState) -> Value {
+    let mut categories = Map::new();
+    let mut available_channels = Map::new();
+    for (cluster, name) in [(XtreamCluster::Live, "live"), (XtreamCluster::Video, "movie"), (XtreamCluster::Series, "series")] {
+        let (cluster_categories, streams) = xtream_repository::xtream_get_user_streams(config, &target.name, &cluster, user);
+        categories.insert(name.to_string(), Value::Array(cluster_categories));
+        if cluster != XtreamCluster::Series {
+            for mut stream in streams {
+                if let Some(stream_id) = stream.get("stream_id").map(Value::to_string) {
+                    if let Some(document) = stream.as_object_mut() {
+                        document.insert("stream_type".to_string(), Value::String(name.to_string()));
+                    }
+                    available_channels.insert(stream_id, stream);
+                }
+            }
+        }
+    }
+    let mut panel = serde_json::to_value(xtream_api::get_user_info(req, user, app_state)).unwrap_or_else(|_| json!({}));
+    if let Some(document) = panel.as_object_mut() {
+        document.insert("categories".to_string(), Value::Object(categories));
+        document.insert("available_channels".to_string(), Value::Object(available_channels));
+    }
+    panel
+}
+
+#[utoipa::path(get, path = "/panel_api.php", tag = "player", params(UserApiRequest),
+    responses((status = 200, description = "The user and server info with the categories and streams of the provider", body = Object),
+        (status = 400), (status = 401)))]
+pub(crate) async fn panel_api(
+    req: HttpRequest,
+    api_req: web::Query<UserApiRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    // actions are answered like the player api
+    if !api_req.action.trim().is_empty() {
+        return xtream_api::xtream_player_api(&req, api_req.into_inner(), &_app_state).await;
+    }
+    let config = _app_state.get_config();
+    match get_xtream_user(&req, &api_req, &config) {
+        Ok((user, target)) => HttpResponse::Ok().json(create_panel(&req, &config, target, &user, &_app_state)),
+        Err(response) => response,
+    }
+}
+
+fn add_enigma2_element(xml: &mut String, tag: &str, value: &str) {
+    let _ = writeln!(xml, "    <{}>{}</{}>", tag, escape(value), tag);
+}
+
+fn add_enigma2_playlist_name(xml: &mut String, name: &str) {
+    let _ = writeln!(xml, "  <playlist_name>{}</playlist_name>", escape(name));
+}
+
+fn get_enigma2_url(base_url: &str, user: &UserCredentials, playlist_type: &str, category_id: Option<&str>) -> String {
+    let mut url = format!("{}/{}?username={}&password={}&type={}", base_url, ENIGMA2_PATH, user.username, user.password, playlist_type);
+    if let Some(category_id) = category_id {
+        let _ = write!(url, "&category_id={}", category_id);
+    }
+    url
+}
+
+/// The enigma2 api of xtream servers, a menu with the live and vod categories, the categories
+/// and the streams of a category. Titles and descriptions are base64 encoded.
+fn create_enigma2_playlist(req: &HttpRequest, api_req: &UserApiRequest, config: &Config, target: &ConfigTarget, user: &UserCredentials) -> String {
+    let base_url = get_base_url(req);
+    let playlist_type = api_req.playlist_type.as_str();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" ?>\n<items>\n");
+    match playlist_type {
+        "get_live_categories" | "get_vod_categories" => {
+            let cluster = get_cluster(playlist_type);
+            let (categories, _) = xtream_repository::xtream_get_user_streams(config, &target.name, &cluster, user);
+            let streams_type = if cluster == XtreamCluster::Live { "get_live_streams" } else { "get_vod_streams" };
+            add_enigma2_playlist_name(&mut xml, if cluster == XtreamCluster::Live { "Live [ All ]" } else { "Vod [ All ]" });
+            for category in &categories {
+                let category_id = get_category_id(category);
+                xml.push_str("  <category>\n");
+                add_enigma2_element(&mut xml, "category_id", &category_id);
+                add_enigma2_element(&mut xml, "category_title", &STANDARD.encode(get_str(category, "category_name")));
+                add_enigma2_element(&mut xml, "playlist_url", &get_enigma2_url(&base_url, user, streams_type, Some(&category_id)));
+                xml.push_str("  </category>\n");
+            }
+        }
+        "get_live_streams" | "get_vod_streams" => {
+            let cluster = get_cluster(playlist_type);
+            let (categories, streams) = xtream_repository::xtream_get_user_streams(config, &target.name, &cluster, user);
+            let category_id = api_req.category_id.trim();
+            let category_name = categories.iter().find(|category| get_category_id(category) == category_id)
+                .map_or("", |category| get_str(category, "category_name"));
+            add_enigma2_playlist_name(&mut xml, category_name);
+            for stream in streams.iter().filter(|stream| category_id.is_empty() || get_category_id(stream) == category_id) {
+                if let Some(url) = get_user_stream_url(&base_url, user, &cluster, stream, "ts") {
+                    let description = if cluster == XtreamCluster::Live { get_str(stream, "epg_channel_id") } else { get_str(stream, "plot") };
+                    xml.push_str("  <channel>\n");
+                    add_enigma2_element(&mut xml, "title", &STANDARD.encode(get_str(stream, "name")));
+                    add_enigma2_element(&mut xml, "description", &STANDARD.encode(description));
+                    add_enigma2_element(&mut xml, "desc_image", get_str(stream, "stream_icon"));
+                    add_enigma2_element(&mut xml, "category_id", &get_category_id(stream));
+                    add_enigma2_element(&mut xml, "stream_url", &url);
+                    xml.push_str("  </channel>\n");
+                }
+            }
+        }
+        _ => {
+            add_enigma2_playlist_name(&mut xml, &target.name);
+            for (category_id, title, categories_type) in [("1", "Live Streams", "get_live_categories"), ("2", "Vod", "get_vod_categories")] {
+                xml.push_str("  <category>\n");
+                add_enigma2_element(&mut xml, "category_id", category_id);
+                add_enigma2_element(&mut xml, "category_title", &STANDARD.encode(title));
+                add_enigma2_element(&mut xml, "playlist_url", &get_enigma2_url(&base_url, user, categories_type, None));
+                xml.push_str("  </category>\n");
+            }
+        }
+    }
+    xml.push_str("</items>\n");
+    xml
+}
+
+#[utoipa::path(get, path = "/enigma2.php", tag = "player", params(UserApiRequest),
+    responses((status = 200, body = String, content_type = "application/xml"), (status = 400)))]
+pub(crate) async fn enigma2_api(
+    req: HttpRequest,
+    api_req: web::Query<UserApiRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    match get_xtream_user(&req, &api_req, &config) {
+        Ok((user, target)) => HttpResponse::Ok().content_type(mime::TEXT_XML)
+            .body(create_enigma2_playlist(&req, &api_req, &config, target, &user)),
+        Err(response) => response,
+    }
+}
+
+/// Userbouquet file for enigma2 receivers with a marker for each category, `:` in the urls is encoded.
+fn create_enigma2_bouquet(req: &HttpRequest, api_req: &UserApiRequest, config: &Config, target: &ConfigTarget, user: &UserCredentials) -> String {
+    let base_url = get_base_url(req);
+    let cluster = get_cluster(api_req.playlist_type.as_str());
+    let (categories, streams) = xtream_repository::xtream_get_user_streams(config, &target.name, &cluster, user);
+    let mut bouquet = format!("#NAME {}\n", target.name);
+    for (marker_id, category) in categories.iter().enumerate() {
+        let category_id = get_category_id(category);
+        let category_name = get_str(category, "category_name");
+        let _ = writeln!(bouquet, "#SERVICE 1:64:{:X}:0:0:0:0:0:0:0::{}\n#DESCRIPTION {}", marker_id + 1, category_name, category_name);
+        for stream in streams.iter().filter(|stream| get_category_id(stream) == category_id) {
+            let stream_id = stream.get("stream_id").and_then(Value::as_i64).unwrap_or_default();
+            if let Some(url) = get_user_stream_url(&base_url, user, &cluster, stream, "ts") {
+                let name = get_str(stream, "name");
+                let _ = writeln!(bouquet, "#SERVICE 4097:0:1:{:X}:0:0:0:0:0:0:{}:{}\n#DESCRIPTION {}", stream_id, url.replace(':', "%3a"), name, name);
+            }
+        }
+    }
+    bouquet
+}
+
+#[utoipa::path(get, path = "/enigma2/bouquet", tag = "player", params(UserApiRequest),
+    responses((status = 200, body = String, content_type = "text/plain"), (status = 400)))]
+pub(crate) async fn enigma2_bouquet(
+    req: HttpRequest,
+    api_req: web::Query<UserApiRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    match get_xtream_user(&req, &api_req, &config) {
+        Ok((user, target)) => {
+            let filename = format!("userbouquet.{}.tv", sanitize_for_filename(&target.name, true));
+            HttpResponse::Ok().content_type(mime::TEXT_PLAIN_UTF_8)
+                .insert_header(ContentDisposition { disposition: DispositionType::Attachment, parameters: vec![DispositionParam::Filename(filename)] })
+                .body(create_enigma2_bouquet(&req, &api_req, &config, target, &user))
+        }
+        Err(response) => response,
+    }
+}
+
+pub(crate) fn panel_api_register() -> Vec<Resource> {
+    vec![
+        web::resource("/panel_api.php").route(web::get().to(panel_api)).route(web::post().to(panel_api)),
+        web::resource(format!("/{}", ENIGMA2_PATH)).route(web::get().to(enigma2_api)),
+        web::resource("/enigma2/bouquet").route(web::get().to(enigma2_bouquet)),
+    ]
+}
@@ -1,14 +1,65 @@
+use std::future::Future;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 use actix_web::web::Data;
 use chrono::Local;
 use cron::Schedule;
-use log::error;
+use log::{error, info};
 use crate::api::api_model::AppState;
 use crate::exit;
-use crate::processing::playlist_processor::exec_processing;
+use crate::m3u_filter_error::M3uFilterError;
+use crate::model::config::{ProcessTargets, ScheduleConfig, validate_targets};
+use crate::processing::playlist_processor::{exec_processing, get_scheduled_targets};
+use crate::utils::{account_status, config_reader};
 
-pub(crate) async fn start_scheduler(expression: &str, data: Data<AppState>) -> ! {
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Runs the task and starts it again if it panics, a failing processing doesn't stop the schedule.
+fn spawn_supervised<F, T>(name: String, task: F)
+    where F: Fn() -> T + 'static, T: Future<Output=()> + 'static {
+    actix_rt::spawn(async move {
+        loop {
+            match actix_rt::spawn(task()).await {
+                Ok(()) => break,
+                Err(err) => {
+                    error!("{} stopped: {}, restarting in {}s", name, err, RESTART_DELAY.as_secs());
+                    actix_rt::time::sleep(RESTART_DELAY).await;
+                }
+            }
+        }
+    });
+}
+
+/// Returns the targets of a schedule, they are resolved with the current config, a reload can change the target ids.
+/// Entries without targets process the targets given at startup.
+fn get_schedule_targets(data: &AppState, target_names: &Option<Vec<String>>) -> Result<Arc<ProcessTargets>, M3uFilterError> {
+    match target_names {
+        None => Ok(data.get_targets()),
+        Some(_) => validate_targets(target_names, &data.get_config().sources).map(Arc::new),
+    }
+}
+
+/// Starts a scheduler for each configured schedule entry.
+pub(crate) fn start_schedulers(schedules: &[ScheduleConfig], data: &Data<AppState>) {
+    for schedule in schedules {
+        if let Err(err) = get_schedule_targets(data, &schedule.targets) {
+            error!("Failed to start scheduler {}: {}", &schedule.schedule, err);
+            continue;
+        }
+        info!("Schedule: {} targets: {}", &schedule.schedule,
+            schedule.targets.as_ref().map_or("all".to_string(), |t| t.join(", ")));
+        let expression = schedule.schedule.to_string();
+        let target_names = schedule.targets.clone();
+        let cloned_data = data.clone();
+        spawn_supervised(format!("Scheduler {}", &expression), move || {
+            let (expression, data, target_names) = (expression.clone(), cloned_data.clone(), target_names.clone());
+            async move { start_scheduler(&expression, data, target_names).await }
+        });
+    }
+}
+
+pub(crate) async fn start_scheduler(expression: &str, data: Data<AppState>, target_names: Option<Vec<String>>) -> ! {
     match Schedule::from_str(expression) {
         Ok(schedule) => {
             let offset = *Local::now().offset();
@@ -19,11 +70,83 @@ pub(crate) async fn start_scheduler(expression: &str, data: Data<AppState>) -> !
 
                 if let Some(datetime) = upcoming.next() {
                     if datetime.timestamp() <= local.timestamp() {
-                        exec_processing(data.config.clone(), data.targets.clone()).await;
+                        let config = data.get_config();
+                        match get_schedule_targets(&data, &target_names) {
+                            Ok(targets) => match get_scheduled_targets(&config, &targets, Local::now().time()) {
+                                Some(scheduled_targets) => exec_processing(config, scheduled_targets, None).await,
+                                None => info!("Schedule {} skipped, the conditions of its targets are not met", expression),
+                            },
+                            Err(err) => error!("Schedule {} skipped: {}", expression, err),
+                        }
                     }
                 }
             }
         }
         Err(err) => exit!("Failed to start scheduler: {}", err)
     }
-}
\ No newline at end of file
+}
+
+/// Checks the provider accounts at startup and then with the schedule of `account_check`.
+pub(crate) fn start_account_check(data: &Data<AppState>) {
+    let expression = match &data.get_config().account_check {
+        Some(account_check) => account_check.schedule.to_string(),
+        None => return,
+    };
+    info!("Account check schedule: {}", &expression);
+    let cloned_data = data.clone();
+    spawn_supervised(String::from("Account check"), move || {
+        let (expression, data) = (expression.clone(), cloned_data.clone());
+        async move { run_account_check(&expression, data).await }
+    });
+}
+
+async fn run_account_check(expression: &str, data: Data<AppState>) {
+    match Schedule::from_str(expression) {
+        Ok(schedule) => {
+            account_status::check_accounts(&data.get_config()).await;
+            let offset = *Local::now().offset();
+            loop {
+                let mut upcoming = schedule.upcoming(offset).take(1);
+                actix_rt::time::sleep(Duration::from_millis(500)).await;
+                if let Some(datetime) = upcoming.next() {
+                    if datetime.timestamp() <= Local::now().timestamp() {
+                        account_status::check_accounts(&data.get_config()).await;
+                    }
+                }
+            }
+        }
+        Err(err) => error!("Failed to start account check: {}", err),
+    }
+}
+
+const SOURCES_DIR_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Reloads the config when a file of the `sources_dir` is added, changed or removed.
+/// A config which fails to load is logged and the running config is kept until the next change.
+pub(crate) fn start_sources_dir_watch(data: &Data<AppState>) {
+    let config = data.get_config();
+    match &config.sources_dir {
+        Some(sources_dir) => info!("Watching sources dir: {}", sources_dir),
+        None => return,
+    }
+    let mut state = config_reader::get_sources_dir_state(&config);
+    let cloned_data = data.clone();
+    actix_rt::spawn(async move {
+        loop {
+            actix_rt::time::sleep(SOURCES_DIR_CHECK_INTERVAL).await;
+            let config = cloned_data.get_config();
+            let current_state = config_reader::get_sources_dir_state(&config);
+            if current_state == state {
+                continue;
+            }
+            state = current_state;
+            match config_reader::reload_config(&config).and_then(|cfg| {
+                let source_count = cfg.sources.len();
+                cloned_data.set_config(cfg).map(|()| source_count)
+            }) {
+                Ok(source_count) => info!("Sources dir changed, config reloaded with {} sources", source_count),
+                Err(err) => error!("Sources dir changed, failed to reload config: {}", err),
+            }
+        }
+    });
+}
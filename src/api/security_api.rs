@@ -0,0 +1,55 @@
+use std::net::IpAddr;
+
+use actix_web::{HttpResponse, web};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::api::api_model::{ApiError, AppState};
+use crate::utils::ban_list;
+use crate::utils::ban_list::Ban;
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub(crate) struct BanRequest {
+    pub ip: String,
+    /// seconds, 0 bans until the ban is removed
+    #[serde(default)]
+    pub duration: u64,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Active bans of the player api endpoints.
+#[utoipa::path(get, path = "/api/v1/security/bans", tag = "security", responses((status = 200, body = [Ban])))]
+pub(crate) async fn bans(
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(ban_list::get_bans(&_app_state.get_config().working_dir))
+}
+
+#[utoipa::path(post, path = "/api/v1/security/bans", tag = "security", request_body = BanRequest,
+    responses((status = 200, description = "The ban", body = Ban), (status = 400, body = ApiError)))]
+pub(crate) async fn add_ban(
+    req: web::Json<BanRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let ban_req = req.into_inner();
+    let ip = ban_req.ip.trim();
+    if ip.parse::<IpAddr>().is_err() {
+        return HttpResponse::BadRequest().json(json!({"error": format!("invalid ip address: {}", ip)}));
+    }
+    let reason = ban_req.reason.as_deref().unwrap_or("manual");
+    HttpResponse::Ok().json(ban_list::add_ban(&_app_state.get_config().working_dir, ip, reason, ban_req.duration))
+}
+
+#[utoipa::path(delete, path = "/api/v1/security/bans/{ip}", tag = "security", params(("ip" = String, Path, description = "Banned ip address")),
+    responses((status = 200), (status = 404)))]
+pub(crate) async fn remove_ban(
+    path: web::Path<String>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    if ban_list::remove_ban(&_app_state.get_config().working_dir, path.into_inner().trim()) {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+
+use actix_web::{HttpRequest, HttpResponse, Resource, web};
+use serde::Serialize;
+
+use crate::api::api_model::AppState;
+use crate::api::auth_api;
+use crate::utils::{metrics, user_stats};
+use crate::utils::metrics::{ProcessingStats, TargetStats};
+use crate::utils::user_stats::UserStats;
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct CacheStatsResponse {
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct StatsResponse {
+    pub processing: ProcessingStats,
+    pub targets: BTreeMap<String, TargetStats>,
+    pub download_failures: BTreeMap<String, u64>,
+    pub partial_failures: BTreeMap<String, Vec<String>>,
+    pub cache: CacheStatsResponse,
+    pub active_streams: usize,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct UserStatsResponse {
+    pub username: String,
+    #[serde(flatten)]
+    pub stats: UserStats,
+    pub active_cons: u8,
+}
+
+/// Processing, download and cache statistics for the web ui.
+#[utoipa::path(get, path = "/api/v1/stats", tag = "stats", responses((status = 200, body = StatsResponse)))]
+pub(crate) async fn stats(
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let stats = metrics::get_metrics(&_app_state.get_config().working_dir);
+    HttpResponse::Ok().json(StatsResponse {
+        processing: stats.processing,
+        targets: stats.targets,
+        download_failures: stats.download_failures,
+        partial_failures: stats.partial_failures,
+        cache: CacheStatsResponse { hits: stats.cache.hits, misses: stats.cache.misses, hit_rate: stats.cache.hit_rate() },
+        active_streams: _app_state.active_users.get_total_connections(),
+    })
+}
+
+/// The metrics are outside of the `/api/v1` scope, they need the same authentication.
+#[utoipa::path(get, path = "/metrics", tag = "stats",
+    responses((status = 200, description = "Metrics in the prometheus text format", body = String, content_type = "text/plain"),
+              (status = 401), (status = 403)))]
+pub(crate) async fn prometheus_metrics(
+    req: HttpRequest,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = auth_api::authorize(&req) {
+        return response;
+    }
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render_prometheus(&_app_state.get_config().working_dir, _app_state.active_users.get_total_connections()))
+}
+
+/// Usage of an api-proxy user, unknown users and users of targets the operator or viewer can't see are not found.
+#[utoipa::path(get, path = "/api/v1/users/{id}/stats", tag = "users", params(("id" = String, Path, description = "Username of the api-proxy user")),
+    responses((status = 200, body = UserStatsResponse), (status = 404)))]
+pub(crate) async fn user_stats(
+    req: HttpRequest,
+    path: web::Path<String>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let username = path.into_inner();
+    let principal = auth_api::get_request_principal(&req);
+    let config = _app_state.get_config();
+    let known_user = config._api_proxy.read().unwrap().as_ref()
+        .is_some_and(|api_proxy| api_proxy.user.iter()
+            .filter(|t| principal.as_ref().is_none_or(|principal| principal.has_target(&t.target)))
+            .flat_map(|t| &t.credentials).any(|c| c.username == username));
+    if !known_user {
+        return HttpResponse::NotFound().finish();
+    }
+    let stats = user_stats::get_user_stats(&config.working_dir, &username);
+    let active_cons = _app_state.active_users.get_connection_count(&username);
+    HttpResponse::Ok().json(UserStatsResponse { username, stats, active_cons })
+}
+
+pub(crate) fn stats_api_register() -> Vec<Resource> {
+    vec![
+        web::resource("/metrics").route(web::get().to(prometheus_metrics)),
+    ]
+}
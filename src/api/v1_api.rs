@@ -1,28 +1,87 @@
 use std::sync::{Arc};
-use actix_web::{HttpResponse, Scope, web};
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web::dev::{HttpServiceFactory, Service, ServiceResponse};
+use futures::future::{Either, ready};
+use futures::TryFutureExt;
 use serde_json::{json};
-use crate::api::api_model::{AppState, PlaylistRequest, ServerConfig, ServerInputConfig, ServerSourceConfig, ServerTargetConfig};
-use crate::model::config::{Config, ConfigDto, ConfigInput, ConfigInputOptions, ConfigSource, ConfigTarget, InputType, validate_targets};
+use crate::api::api_model::{ApiError, AppState, PlaylistRequest, RestoreResponse, ServerConfig, ServerInputConfig, ServerSourceConfig, ServerTargetConfig};
+use crate::model::config::{Config, ConfigCustomChannel, ConfigDto, ConfigInput, ConfigSource, ConfigTarget, InputType, validate_targets};
 use log::{error};
-use crate::api::download_api;
+use crate::api::{access_log_api, auth_api, config_api, download_api, openapi_api, security_api, stats_api};
 use crate::m3u_filter_error::M3uFilterError;
 use crate::model::api_proxy::{ApiProxyConfig, ApiProxyServerInfo, TargetUser};
-use crate::processing::playlist_processor;
-use crate::utils::{config_reader, download};
+use crate::model::model_config::ApiRole;
+use crate::model::model_playlist::{PlaylistGroup, PlaylistItemType};
+use crate::processing::{custom_channels, filter_test, playlist_health, playlist_overrides, playlist_preview, playlist_processor};
+use crate::processing::custom_channels::CustomChannelEntry;
+use crate::processing::filter_test::FilterTestResult;
+use crate::processing::playlist_health::HealthReport;
+use crate::processing::playlist_overrides::ChannelOverride;
+use crate::processing::playlist_preview::PlaylistPreview;
+use crate::repository::{history_repository, raw_repository, search_repository};
+use crate::repository::history_repository::SnapshotEntry;
+use crate::repository::raw_repository::{RawChannelPage, RawGroupInfo};
+use crate::repository::search_repository::SearchEntry;
+use crate::utils::{account_status, config_reader, download, jobs, video_search};
+use crate::utils::account_status::InputAccountStatus;
+use crate::utils::jobs::Job;
+use crate::utils::video_search::VideoSearchResult;
 
-fn _save_config_api_proxy(backup_dir: &str, api_proxy: &mut ApiProxyConfig) -> Option<M3uFilterError> {
-    match config_reader::save_api_proxy(api_proxy._file_path.as_str(), backup_dir, api_proxy) {
-        Ok(_) => {}
-        Err(err) => {
-            error!("Failed to save api_proxy.yml {}", err.to_string());
-            return Some(err);
+/// The api-proxy file is changed in its unresolved form, so placeholders and `!secret` references are kept.
+/// The running config gets the resolved result.
+fn _save_config_api_proxy<F>(cfg: &Config, update: F) -> Option<M3uFilterError>
+    where
+        F: FnOnce(&mut ApiProxyConfig) {
+    let mut guard = cfg._api_proxy.write().unwrap();
+    if let Some(api_proxy) = guard.as_mut() {
+        let file_path = api_proxy._file_path.to_string();
+        let result = config_reader::read_api_proxy_file(&file_path).and_then(|mut file_config| {
+            update(&mut file_config);
+            config_reader::save_api_proxy(&file_path, cfg, &file_config)
+        });
+        match result {
+            Ok(resolved) => *api_proxy = resolved,
+            Err(err) => {
+                error!("Failed to save api_proxy.yml {}", err.to_string());
+                return Some(err);
+            }
         }
     }
     None
 }
 
-fn _save_config_main(file_path: &str, backup_dir: &str, cfg: &ConfigDto) -> Option<M3uFilterError> {
-    match config_reader::save_main_config(file_path, backup_dir, cfg) {
+const REDACTED_CREDENTIAL: &str = "********";
+
+fn redact_credentials(target_user: &TargetUser) -> TargetUser {
+    let mut result = target_user.clone();
+    for credentials in &mut result.credentials {
+        credentials.password = REDACTED_CREDENTIAL.to_string();
+        credentials.token = credentials.token.as_ref().map(|_| REDACTED_CREDENTIAL.to_string());
+    }
+    result
+}
+
+/// Redacted passwords and tokens of saved users are replaced with the current ones of the same user of the same target,
+/// a username can be used by several targets with other credentials.
+pub(crate) fn restore_redacted_credentials(target_users: &mut [TargetUser], current: &[TargetUser]) {
+    for target_user in target_users.iter_mut() {
+        let current_credentials = current.iter().filter(|current_user| current_user.target == target_user.target)
+            .flat_map(|current_user| &current_user.credentials).collect::<Vec<_>>();
+        for credentials in &mut target_user.credentials {
+            if let Some(existing) = current_credentials.iter().find(|c| c.username == credentials.username) {
+                if credentials.password == REDACTED_CREDENTIAL {
+                    credentials.password = existing.password.to_string();
+                }
+                if credentials.token.as_deref() == Some(REDACTED_CREDENTIAL) {
+                    credentials.token = existing.token.clone();
+                }
+            }
+        }
+    }
+}
+
+fn _save_config_main(file_path: &str, config: &Config, cfg: &ConfigDto) -> Option<M3uFilterError> {
+    match config_reader::save_main_config(file_path, config, cfg) {
         Ok(_) => {}
         Err(err) => {
             error!("Failed to save config.yml {}", err.to_string());
@@ -32,30 +91,76 @@ fn _save_config_main(file_path: &str, backup_dir: &str, cfg: &ConfigDto) -> Opti
     None
 }
 
+/// The api-proxy users as they are written in the file, an operator or viewer gets the users of its targets
+/// without passwords and tokens.
+#[utoipa::path(get, path = "/api/v1/users", tag = "users", responses((status = 200, body = [TargetUser]), (status = 500, body = ApiError)))]
+pub(crate) async fn proxy_users(
+    http_req: HttpRequest,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let principal = auth_api::get_request_principal(&http_req);
+    let config = _app_state.get_config();
+    let file_path = config._api_proxy.read().unwrap().as_ref().map(|api_proxy| api_proxy._file_path.to_string());
+    let api_proxy = match file_path.map(|file_path| config_reader::read_api_proxy_file(&file_path)).transpose() {
+        Ok(api_proxy) => api_proxy,
+        Err(err) => return HttpResponse::InternalServerError().json(json!({"error": err.to_string()})),
+    };
+    let is_admin = principal.as_ref().is_none_or(|principal| principal.role == ApiRole::Admin);
+    let users: Vec<TargetUser> = api_proxy.map_or(vec![], |api_proxy| api_proxy.user.iter()
+        .filter(|target_user| principal.as_ref().is_none_or(|principal| principal.has_target(&target_user.target)))
+        .map(|target_user| if is_admin { target_user.clone() } else { redact_credentials(target_user) })
+        .collect());
+    HttpResponse::Ok().json(users)
+}
+
+/// An operator with targets can only save the users of its targets, the users of the other targets are kept.
+/// Redacted passwords and tokens are kept unchanged.
+#[utoipa::path(post, path = "/api/v1/config/user", tag = "users", request_body = Vec<TargetUser>,
+    responses((status = 200), (status = 403, body = ApiError), (status = 500, body = ApiError)))]
 pub(crate) async fn save_config_api_proxy_user(
+    http_req: HttpRequest,
     mut req: web::Json<Vec<TargetUser>>,
     mut _app_state: web::Data<AppState>,
 ) -> HttpResponse {
     req.0.iter_mut().flat_map(|t| &mut t.credentials).for_each(|c| c.trim());
-    if let Some(api_proxy) = _app_state.config._api_proxy.write().unwrap().as_mut() {
-        api_proxy.user = req.0;
-        let backup_dir = _app_state.config.backup_dir.as_ref().unwrap().as_str();
-        if let Some(err) = _save_config_api_proxy(backup_dir, api_proxy) {
-            return HttpResponse::InternalServerError().json(json!({"error": err.to_string()}));
+    let principal = auth_api::get_request_principal(&http_req).filter(|principal| principal.targets.is_some());
+    if let Some(principal) = &principal {
+        if let Some(target_user) = req.0.iter().find(|target_user| !principal.has_target(&target_user.target)) {
+            return HttpResponse::Forbidden().json(json!({"error": format!("No access to target {}", &target_user.target)}));
+        }
+    }
+    let config = _app_state.get_config();
+    let mut target_users = req.0;
+    let update = |api_proxy: &mut ApiProxyConfig| {
+        restore_redacted_credentials(&mut target_users, &api_proxy.user);
+        match &principal {
+            Some(principal) => {
+                api_proxy.user.retain(|target_user| !principal.has_target(&target_user.target));
+                api_proxy.user.extend(target_users);
+            }
+            None => api_proxy.user = target_users,
         }
+    };
+    if let Some(err) = _save_config_api_proxy(&config, update) {
+        return HttpResponse::InternalServerError().json(json!({"error": err.to_string()}));
     }
     HttpResponse::Ok().finish()
 }
 
+#[utoipa::path(post, path = "/api/v1/config/main", tag = "config", request_body = ConfigDto,
+    responses((status = 200), (status = 400, body = ApiError), (status = 403, body = ApiError), (status = 500, body = ApiError)))]
 pub(crate) async fn save_config_main(
     req: web::Json<ConfigDto>,
     mut _app_state: web::Data<AppState>,
 ) -> HttpResponse {
     let cfg = req.0;
     if cfg.is_valid() {
-        let file_path = _app_state.config._config_file_path.as_str();
-        let backup_dir = _app_state.config.backup_dir.as_ref().unwrap().as_str();
-        if let Some(err) = _save_config_main(file_path, backup_dir, &cfg) {
+        let config = _app_state.get_config();
+        if let Err(response) = config_api::check_main_config_executables(&config, &cfg) {
+            return response;
+        }
+        let file_path = config._config_file_path.as_str();
+        if let Some(err) = _save_config_main(file_path, &config, &cfg) {
             return HttpResponse::InternalServerError().json(json!({"error": err.to_string()}));
         }
         HttpResponse::Ok().finish()
@@ -65,6 +170,8 @@ pub(crate) async fn save_config_main(
 }
 
 
+#[utoipa::path(post, path = "/api/v1/config/apiproxy", tag = "config", request_body = Vec<ApiProxyServerInfo>,
+    responses((status = 200), (status = 400, body = ApiError), (status = 500, body = ApiError)))]
 pub(crate) async fn save_config_api_proxy_config(
     req: web::Json<Vec<ApiProxyServerInfo>>,
     mut _app_state: web::Data<AppState>,
@@ -75,26 +182,26 @@ pub(crate) async fn save_config_api_proxy_config(
             return HttpResponse::BadRequest().json(json!({"error": "Invalid content"}));
         }
     }
-    if let Some(api_proxy) = _app_state.config._api_proxy.write().unwrap().as_mut() {
-        api_proxy.server = req_api_proxy;
-        let backup_dir = _app_state.config.backup_dir.as_ref().unwrap().as_str();
-        if let Some(err) = _save_config_api_proxy(backup_dir, api_proxy) {
-            return HttpResponse::InternalServerError().json(json!({"error": err.to_string()}));
-        }
+    let config = _app_state.get_config();
+    if let Some(err) = _save_config_api_proxy(&config, |api_proxy| api_proxy.server = req_api_proxy) {
+        return HttpResponse::InternalServerError().json(json!({"error": err.to_string()}));
     }
     HttpResponse::Ok().finish()
 }
 
+#[utoipa::path(post, path = "/api/v1/playlist/update", tag = "playlist", request_body(content = Vec<String>, description = "Target names, all targets if empty"),
+    responses((status = 200, description = "The processing is started"), (status = 400, body = ApiError)))]
 pub(crate) async fn playlist_update(
     req: web::Json<Vec<String>>,
     _app_state: web::Data<AppState>,
 ) -> HttpResponse {
     let targets = req.0;
     let user_targets = if targets.is_empty() { None } else { Some(targets) };
-    let process_targets = validate_targets(&user_targets, &_app_state.config.sources);
+    let config = _app_state.get_config();
+    let process_targets = validate_targets(&user_targets, &config.sources);
     match process_targets {
         Ok(valid_targets) => {
-            actix_rt::spawn(playlist_processor::exec_processing(Arc::clone(&_app_state.config), Arc::new(valid_targets)));
+            actix_rt::spawn(playlist_processor::exec_processing(config, Arc::new(valid_targets), None));
             HttpResponse::Ok().finish()
         }
         Err(err) => {
@@ -104,45 +211,418 @@ pub(crate) async fn playlist_update(
     }
 }
 
-fn create_config_input_for_url(url: &str) -> ConfigInput {
-    ConfigInput {
-        id: 0,
-        headers: Default::default(),
-        input_type: InputType::M3u,
-        url: String::from(url),
-        epg_url: None,
-        username: None,
-        password: None,
-        persist: None,
-        prefix: None,
-        suffix: None,
-        name: None,
-        enabled: true,
-        options: Some(ConfigInputOptions {
-            xtream_info_cache: false,
-        }),
+#[derive(Debug, Clone, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct RefreshRequest {
+    /// comma separated target names, all targets if not given
+    pub target: Option<String>,
+}
+
+/// Starts processing of the requested targets and returns the job id.
+#[utoipa::path(post, path = "/api/v1/playlist/refresh", tag = "playlist", params(RefreshRequest),
+    responses((status = 202, description = "The id of the processing job", body = JobResponse), (status = 400, body = ApiError)))]
+pub(crate) async fn playlist_refresh(
+    req: web::Query<RefreshRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let target_names: Vec<String> = req.target.as_deref().unwrap_or("").split(',')
+        .map(str::trim).filter(|name| !name.is_empty()).map(String::from).collect();
+    let config = _app_state.get_config();
+    let user_targets = if target_names.is_empty() { None } else { Some(target_names) };
+    match validate_targets(&user_targets, &config.sources) {
+        Ok(valid_targets) => {
+            let target_names = playlist_processor::get_process_target_names(&config, &valid_targets);
+            let job_id = jobs::create_job(&config.working_dir, target_names, config.sources.len());
+            actix_rt::spawn(playlist_processor::exec_processing(config, Arc::new(valid_targets), Some(job_id.clone())));
+            HttpResponse::Accepted().json(JobResponse { job_id })
+        }
+        Err(err) => HttpResponse::BadRequest().json(json!({"error": err.to_string()}))
+    }
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub(crate) struct JobResponse {
+    pub job_id: String,
+}
+
+fn default_as_search_limit() -> usize { 100 }
+
+#[derive(Debug, Clone, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct SearchRequest {
+    pub q: String,
+    pub target: Option<String>,
+    #[serde(default = "default_as_search_limit")]
+    pub limit: usize,
+}
+
+/// Searches names, titles, groups and the plot and cast of the processed playlists.
+#[utoipa::path(get, path = "/api/v1/playlist/search", tag = "playlist", params(SearchRequest), responses((status = 200, body = [SearchEntry])))]
+pub(crate) async fn playlist_search(
+    req: web::Query<SearchRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    HttpResponse::Ok().json(search_repository::search(&config, &req.q, req.target.as_deref(), req.limit))
+}
+
+#[derive(Debug, Clone, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct VideoSearchRequest {
+    pub q: String,
+}
+
+/// Looks up a video title with the `video.search` provider, the episode and year suffix are removed from the query.
+#[utoipa::path(get, path = "/api/v1/video/search", tag = "video", params(VideoSearchRequest),
+    responses((status = 200, body = [VideoSearchResult]), (status = 400, body = ApiError), (status = 502, body = ApiError)))]
+pub(crate) async fn video_search(
+    req: web::Query<VideoSearchRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    let (video, search) = match config.video.as_ref().and_then(|video| video.search.as_ref().map(|search| (video, search))) {
+        Some(video_search) => video_search,
+        None => return HttpResponse::BadRequest().json(json!({"error": "video search is not configured"})),
+    };
+    let (query, year) = video_search::get_search_query(video, &req.q);
+    if query.is_empty() {
+        return HttpResponse::BadRequest().json(json!({"error": "query is empty"}));
+    }
+    match video_search::search(search, &query, year).await {
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(err) => HttpResponse::BadGateway().json(json!({"error": err})),
+    }
+}
+
+/// Returns the provider account state of the xtream inputs from the last `account_check`.
+#[utoipa::path(get, path = "/api/v1/inputs/status", tag = "inputs", responses((status = 200, body = [InputAccountStatus])))]
+pub(crate) async fn input_status(
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(account_status::get_account_status(&_app_state.get_config()))
+}
+
+/// Returns the groups of the unfiltered playlist of a persisted input.
+#[utoipa::path(get, path = "/api/v1/inputs/{id}/raw/groups", tag = "inputs", params(("id" = u16, Path, description = "Id of the input")),
+    responses((status = 200, body = [RawGroupInfo]), (status = 404)))]
+pub(crate) async fn input_raw_groups(
+    path: web::Path<u16>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    match raw_repository::get_raw_groups(&config, path.into_inner()) {
+        Some(groups) => HttpResponse::Ok().json(groups),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+fn default_as_raw_channel_limit() -> usize { 100 }
+
+#[derive(Debug, Clone, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct RawChannelsRequest {
+    pub group: Option<u32>,
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default = "default_as_raw_channel_limit")]
+    pub limit: usize,
+}
+
+/// Returns a page of the channels of the unfiltered playlist of a persisted input.
+#[utoipa::path(get, path = "/api/v1/inputs/{id}/raw/channels", tag = "inputs", params(("id" = u16, Path, description = "Id of the input"), RawChannelsRequest),
+    responses((status = 200, body = RawChannelPage), (status = 404)))]
+pub(crate) async fn input_raw_channels(
+    path: web::Path<u16>,
+    req: web::Query<RawChannelsRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    match raw_repository::get_raw_channels(&config, path.into_inner(), req.group, req.offset, req.limit) {
+        Some(page) => HttpResponse::Ok().json(page),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct HealthRequest {
+    pub target: Option<String>,
+}
+
+/// Returns the channel health check results of the last processing run.
+#[utoipa::path(get, path = "/api/v1/playlist/health", tag = "playlist", params(HealthRequest), responses((status = 200, body = [HealthReport])))]
+pub(crate) async fn playlist_health(
+    req: web::Query<HealthRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    HttpResponse::Ok().json(playlist_health::get_health_reports(&config, req.target.as_deref()))
+}
+
+/// Lists the snapshots kept with the `history` option of the target.
+#[utoipa::path(get, path = "/api/v1/targets/{name}/history", tag = "targets", params(("name" = String, Path, description = "Name of the target")),
+    responses((status = 200, body = [SnapshotEntry]), (status = 404)))]
+pub(crate) async fn target_history(
+    path: web::Path<String>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    let target_name = path.into_inner();
+    match config.get_target_by_name(&target_name) {
+        Some(target) => HttpResponse::Ok().json(history_repository::list_snapshots(&config, &target.name)),
+        None => HttpResponse::NotFound().finish(),
     }
 }
 
+/// Returns a snapshot of the target as m3u playlist.
+#[utoipa::path(get, path = "/api/v1/targets/{name}/history/{id}", tag = "targets",
+    params(("name" = String, Path, description = "Name of the target"), ("id" = String, Path, description = "Id of the snapshot")),
+    responses((status = 200, body = String, content_type = "audio/x-mpegurl"), (status = 404)))]
+pub(crate) async fn target_snapshot(
+    path: web::Path<(String, String)>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    let (target_name, id) = path.into_inner();
+    let target = match config.get_target_by_name(&target_name) {
+        Some(target) => target,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    match history_repository::load_snapshot(&config, &target.name, &id) {
+        Some(playlist) => {
+            let mut content = String::from("#EXTM3U\n");
+            playlist.iter().flat_map(|group| &group.channels)
+                .filter(|item| item.header.borrow().item_type != PlaylistItemType::SeriesInfo)
+                .for_each(|item| {
+                    content.push_str(&item.to_m3u(&target.options));
+                    content.push('\n');
+                });
+            HttpResponse::Ok().content_type("audio/x-mpegurl").body(content)
+        }
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Writes the outputs of the target from a snapshot, the next processing run replaces them again.
+#[utoipa::path(post, path = "/api/v1/targets/{name}/history/{id}/restore", tag = "targets",
+    params(("name" = String, Path, description = "Name of the target"), ("id" = String, Path, description = "Id of the snapshot")),
+    responses((status = 200, body = RestoreResponse), (status = 404), (status = 503, description = "The target is processed"), (status = 500, body = ApiError)))]
+pub(crate) async fn restore_target_snapshot(
+    path: web::Path<(String, String)>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    let (target_name, id) = path.into_inner();
+    let target = match config.get_target_by_name(&target_name) {
+        Some(target) => target,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    // a running job of the target would overwrite the restored playlist
+    let _permit = match jobs::acquire(&config.working_dir, vec![target.name.to_string()], config.max_parallel_jobs).await {
+        Some(permit) => permit,
+        None => return HttpResponse::ServiceUnavailable().finish(),
+    };
+    match playlist_processor::restore_snapshot(&config, target, &id) {
+        Ok(true) => HttpResponse::Ok().json(RestoreResponse { restored: id }),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(errors) => {
+            let messages: Vec<String> = errors.iter().map(|err| err.message.to_string()).collect();
+            error!("Failed to restore snapshot {} of target {}: {}", id, target.name, messages.join(", "));
+            HttpResponse::InternalServerError().json(json!({"error": messages.join(", ")}))
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct OverrideRequest {
+    pub target: Option<String>,
+    pub cluster: Option<String>,
+}
+
+/// Lists the channel overrides, with a target only the ones applied to it.
+#[utoipa::path(get, path = "/api/v1/overrides", tag = "overrides", params(OverrideRequest), responses((status = 200, body = [ChannelOverride])))]
+pub(crate) async fn overrides(
+    req: web::Query<OverrideRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    HttpResponse::Ok().json(playlist_overrides::get_overrides(&config, req.target.as_deref()))
+}
+
+/// Adds or replaces the override of a channel, it is applied with the next processing run.
+#[utoipa::path(post, path = "/api/v1/overrides", tag = "overrides", request_body = ChannelOverride,
+    responses((status = 200, body = ChannelOverride), (status = 400, body = ApiError)))]
+pub(crate) async fn put_override(
+    req: web::Json<ChannelOverride>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    match playlist_overrides::put_override(&config, req.into_inner()) {
+        Ok(item) => HttpResponse::Ok().json(item),
+        Err(err) => HttpResponse::BadRequest().json(json!({"error": err.message})),
+    }
+}
+
+/// Removes the overrides of a stream id, the query can restrict it to a target and cluster.
+#[utoipa::path(delete, path = "/api/v1/overrides/{id}", tag = "overrides", params(("id" = String, Path, description = "Stream id of the channel"), OverrideRequest),
+    responses((status = 200), (status = 404), (status = 500, body = ApiError)))]
+pub(crate) async fn remove_override(
+    path: web::Path<String>,
+    req: web::Query<OverrideRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    match playlist_overrides::remove_overrides(&config, path.into_inner().trim(), req.cluster.as_deref(), req.target.as_deref()) {
+        Ok(0) => HttpResponse::NotFound().finish(),
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(err) => HttpResponse::InternalServerError().json(json!({"error": err.message})),
+    }
+}
+
+/// Lists the custom channels added through the api, the ones of the config are listed with the targets.
+#[utoipa::path(get, path = "/api/v1/custom-channels", tag = "custom-channels", responses((status = 200, body = [CustomChannelEntry])))]
+pub(crate) async fn list_custom_channels(
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    HttpResponse::Ok().json(custom_channels::get_custom_channels(&config))
+}
+
+/// Adds or replaces a custom channel, it is added to the targets with the next processing run.
+#[utoipa::path(post, path = "/api/v1/custom-channels", tag = "custom-channels", request_body = ConfigCustomChannel,
+    responses((status = 200, body = CustomChannelEntry), (status = 400, body = ApiError)))]
+pub(crate) async fn put_custom_channel(
+    req: web::Json<ConfigCustomChannel>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    match custom_channels::put_custom_channel(&config, req.into_inner()) {
+        Ok(entry) => HttpResponse::Ok().json(entry),
+        Err(err) => HttpResponse::BadRequest().json(json!({"error": err.message})),
+    }
+}
+
+/// Removes the custom channels with the stream id, the channels of the config can't be removed.
+#[utoipa::path(delete, path = "/api/v1/custom-channels/{id}", tag = "custom-channels", params(("id" = i32, Path, description = "Stream id of the channel")),
+    responses((status = 200), (status = 404), (status = 500, body = ApiError)))]
+pub(crate) async fn remove_custom_channel(
+    path: web::Path<i32>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    match custom_channels::remove_custom_channel(&config, path.into_inner()) {
+        Ok(0) => HttpResponse::NotFound().finish(),
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(err) => HttpResponse::InternalServerError().json(json!({"error": err.message})),
+    }
+}
+
+fn default_as_filter_samples() -> usize { 10 }
+
+#[derive(Debug, Clone, serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct FilterTestRequest {
+    pub filter: String,
+    pub url: Option<String>,
+    pub input_id: Option<u16>,
+    #[serde(default = "default_as_filter_samples")]
+    pub samples: usize,
+}
+
+/// Evaluates a filter against the playlist of a configured input or an url.
+#[utoipa::path(post, path = "/api/v1/filter/test", tag = "playlist", request_body = FilterTestRequest,
+    responses((status = 200, body = FilterTestResult), (status = 400, body = ApiError)))]
+pub(crate) async fn filter_test(
+    req: web::Json<FilterTestRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    let input = match (&req.input_id, &req.url) {
+        (Some(input_id), _) => config.get_input_by_id(input_id),
+        (None, Some(url)) => Some(download::create_config_input_for_url(url)),
+        (None, None) => None,
+    };
+    match input {
+        None => HttpResponse::BadRequest().json(json!({"error": "Invalid Arguments"})),
+        Some(input) => match filter_test::test_filter(&config, &req.filter, &input, req.samples).await {
+            Ok(result) => HttpResponse::Ok().json(result),
+            Err(err) => HttpResponse::BadRequest().json(json!({"error": err.to_string()})),
+        }
+    }
+}
+
+fn default_as_preview_limit() -> usize { 1000 }
+
+#[derive(Debug, Clone, serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct PreviewRequest {
+    pub target: ConfigTarget,
+    #[serde(default)]
+    pub input_ids: Vec<u16>,
+    #[serde(default = "default_as_preview_limit")]
+    pub limit: usize,
+}
+
+/// Processes the raw playlists of persisted inputs with the target of the request, without writing files.
+#[utoipa::path(post, path = "/api/v1/playlist/preview", tag = "playlist", request_body = PreviewRequest,
+    responses((status = 200, body = PlaylistPreview), (status = 400, body = ApiError), (status = 500)))]
+pub(crate) async fn playlist_preview(
+    req: web::Json<PreviewRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let config = _app_state.get_config();
+    let PreviewRequest { target, input_ids, limit } = req.into_inner();
+    // the processing of the raw playlists would block the worker
+    match web::block(move || playlist_preview::preview_target(&config, target, &input_ids, limit)).await {
+        Ok(Ok(preview)) => HttpResponse::Ok().json(preview),
+        Ok(Err(err)) => HttpResponse::BadRequest().json(json!({"error": err.to_string()})),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[utoipa::path(get, path = "/api/v1/jobs", tag = "jobs", responses((status = 200, body = [Job])))]
+pub(crate) async fn job_list(
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(jobs::get_jobs(&_app_state.get_config().working_dir))
+}
+
+#[utoipa::path(get, path = "/api/v1/jobs/{id}", tag = "jobs", params(("id" = String, Path, description = "Id of the job")),
+    responses((status = 200, body = Job), (status = 404)))]
+pub(crate) async fn job(
+    path: web::Path<String>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    match jobs::get_job(&_app_state.get_config().working_dir, &path.into_inner()) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[utoipa::path(post, path = "/api/v1/playlist", tag = "playlist", request_body = PlaylistRequest,
+    responses((status = 200, description = "The downloaded playlist", body = [PlaylistGroup]), (status = 400, body = ApiError)))]
 pub(crate) async fn playlist(
     req: web::Json<PlaylistRequest>,
     _app_state: web::Data<AppState>,
 ) -> HttpResponse {
+    let config = _app_state.get_config();
     match match &req.input_id {
         Some(input_id) => {
-            _app_state.config.get_input_by_id(input_id)
+            config.get_input_by_id(input_id)
         }
         None => {
             let url = req.url.as_deref().unwrap_or("");
-            Some(create_config_input_for_url(url))
+            Some(download::create_config_input_for_url(url))
         }
     } {
         None => HttpResponse::BadRequest().json(json!({"error": "Invalid Arguments"})),
         Some(input) => {
             let (result, errors) =
                 match input.input_type {
-                    InputType::M3u => download::get_m3u_playlist(&_app_state.config, &input, &_app_state.config.working_dir).await,
-                    InputType::Xtream => download::get_xtream_playlist(&input, &_app_state.config.working_dir).await,
+                    InputType::M3u => download::get_m3u_playlist(&config, &input, &config.working_dir, false).await,
+                    InputType::Xtream => {
+                        let (playlist, errors, _) = download::get_xtream_playlist(&input, &config.working_dir, false).await;
+                        (playlist, errors)
+                    }
                 };
             if result.is_empty() {
                 let error_strings: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
@@ -154,6 +634,7 @@ pub(crate) async fn playlist(
     }
 }
 
+#[utoipa::path(get, path = "/api/v1/config", tag = "config", responses((status = 200, body = ServerConfig)))]
 pub(crate) async fn config(
     _app_state: web::Data<AppState>,
 ) -> HttpResponse {
@@ -180,6 +661,12 @@ pub(crate) async fn config(
         mapping: t.mapping.clone(),
         processing_order: t.processing_order.clone(),
         watch: t.watch.clone(),
+        dedup: t.dedup.clone(),
+        quality_preference: t.quality_preference.clone(),
+        numbering: t.numbering.clone(),
+        health_check: t.health_check.clone(),
+        hooks: t.hooks.clone(),
+        exec: t.exec.clone(),
     };
 
     let map_source = |s: &ConfigSource| ServerSourceConfig {
@@ -190,41 +677,114 @@ pub(crate) async fn config(
     let map_config = |config: &Config| ServerConfig {
         api: config.api.clone(),
         threads: config.threads,
+        max_parallel_jobs: config.max_parallel_jobs,
+        storage: config.storage,
         working_dir: config.working_dir.to_owned(),
         backup_dir: config.backup_dir.to_owned(),
+        backup: config.backup.clone(),
         schedule: config.schedule.clone(),
+        schedules: config.schedules.clone(),
         messaging: config.messaging.clone(),
+        account_check: config.account_check.clone(),
+        tmdb: config.tmdb.clone(),
         video: config.video.clone(),
+        hdhomerun: config.hdhomerun.clone(),
+        log: config.log.clone(),
+        proxy: config.proxy.clone(),
+        tenants: config.tenants.clone(),
         sources: config.sources.iter().map(map_source).collect(),
-        api_proxy: config._api_proxy.read().unwrap().clone(),
+        sources_dir: config.sources_dir.clone(),
+        api_proxy: None,
     };
 
-    let mut result = match config_reader::read_config(_app_state.config._config_path.as_str(),
-                                       _app_state.config._config_file_path.as_str(),
-                                       _app_state.config._sources_file_path.as_str()) {
-        Ok(mut cfg) => {
-            let _ = cfg.prepare();
-            map_config(&cfg)
-        }
-        Err(_) => map_config(&_app_state.config)
+    // the files are returned unresolved, so that saving them keeps placeholders and secret references
+    let app_config = _app_state.get_config();
+    let mut result = match config_reader::read_unresolved_config(app_config._config_path.as_str(),
+                                       app_config._config_file_path.as_str(),
+                                       app_config._sources_file_path.as_str()) {
+        Ok(cfg) => map_config(&cfg),
+        Err(err) => return HttpResponse::InternalServerError().json(json!({"error": err.to_string()})),
     };
-
-    // if we didn't read it from file then we should use it from app_state
-    if result.api_proxy.is_none() {
-        result.api_proxy = _app_state.config._api_proxy.read().unwrap().clone();
+    let api_proxy_file = app_config._api_proxy.read().unwrap().as_ref().map(|api_proxy| api_proxy._file_path.to_string());
+    if let Some(file_path) = api_proxy_file {
+        match config_reader::read_api_proxy_file(&file_path) {
+            Ok(api_proxy) => result.api_proxy = Some(api_proxy),
+            Err(err) => return HttpResponse::InternalServerError().json(json!({"error": err.to_string()})),
+        }
     }
 
     HttpResponse::Ok().json(result)
 }
 
-pub(crate) fn v1_api_register() -> Scope {
-    web::scope("/api/v1")
-        .route("/config", web::get().to(config))
-        .route("/config/main", web::post().to(save_config_main))
-        .route("/config/user", web::post().to(save_config_api_proxy_user))
-        .route("/config/apiproxy", web::post().to(save_config_api_proxy_config))
-        .route("/playlist", web::post().to(playlist))
-        .route("/playlist/update", web::post().to(playlist_update))
-        .route("/file/download", web::post().to(download_api::queue_download_file))
-        .route("/file/download/info", web::get().to(download_api::download_file_info))
+/// Registers the routes of the api and lists them as `(method, path)` to check them against the OpenAPI document.
+macro_rules! v1_api_routes {
+    ($($method:ident $path:literal => $handler:path,)*) => {
+        #[cfg(test)]
+        pub(crate) const V1_API_ROUTES: &[(&str, &str)] = &[$((stringify!($method), $path)),*];
+
+        /// The endpoints need the credentials of `api.auth` if it is configured.
+        pub(crate) fn v1_api_register() -> impl HttpServiceFactory {
+            web::scope("/api/v1")
+                .wrap_fn(|req, srv| {
+                    match auth_api::authorize(req.request()) {
+                        Ok(()) => Either::Left(srv.call(req).map_ok(ServiceResponse::map_into_boxed_body)),
+                        Err(response) => Either::Right(ready(Ok(req.into_response(response)))),
+                    }
+                })
+                $(.route($path, web::$method().to($handler)))*
+        }
+    }
+}
+
+v1_api_routes! {
+    post "/auth/login" => auth_api::login,
+    post "/auth/logout" => auth_api::logout,
+    get "/auth/status" => auth_api::status,
+    get "/openapi.json" => openapi_api::openapi,
+    get "/config" => config,
+    put "/config" => config_api::update_config,
+    post "/config/main" => save_config_main,
+    post "/config/user" => save_config_api_proxy_user,
+    post "/config/apiproxy" => save_config_api_proxy_config,
+    post "/config/reload" => config_api::reload_config,
+    get "/targets" => config_api::targets,
+    put "/targets" => config_api::update_targets,
+    get "/targets/{name}/history" => target_history,
+    get "/targets/{name}/history/{id}" => target_snapshot,
+    post "/targets/{name}/history/{id}/restore" => restore_target_snapshot,
+    get "/overrides" => overrides,
+    post "/overrides" => put_override,
+    delete "/overrides/{id}" => remove_override,
+    get "/custom-channels" => list_custom_channels,
+    post "/custom-channels" => put_custom_channel,
+    delete "/custom-channels/{id}" => remove_custom_channel,
+    get "/inputs" => config_api::inputs,
+    put "/inputs" => config_api::update_inputs,
+    get "/inputs/status" => input_status,
+    get "/inputs/{id}/raw/groups" => input_raw_groups,
+    get "/inputs/{id}/raw/channels" => input_raw_channels,
+    get "/stats" => stats_api::stats,
+    get "/users" => proxy_users,
+    get "/access-log" => access_log_api::access_log,
+    get "/users/{id}/stats" => stats_api::user_stats,
+    get "/security/bans" => security_api::bans,
+    post "/security/bans" => security_api::add_ban,
+    delete "/security/bans/{ip}" => security_api::remove_ban,
+    get "/backups" => config_api::backups,
+    post "/backups/{id}/restore" => config_api::restore_backup,
+    post "/playlist" => playlist,
+    post "/playlist/update" => playlist_update,
+    post "/playlist/refresh" => playlist_refresh,
+    post "/playlist/preview" => playlist_preview,
+    get "/playlist/search" => playlist_search,
+    get "/playlist/health" => playlist_health,
+    get "/video/search" => video_search,
+    post "/filter/test" => filter_test,
+    get "/jobs" => job_list,
+    get "/jobs/{id}" => job,
+    post "/file/download" => download_api::queue_download_file,
+    get "/file/download/info" => download_api::download_file_info,
+    get "/file/downloads" => download_api::download_list,
+    delete "/file/downloads/{uuid}" => download_api::remove_download,
+    post "/file/downloads/{uuid}/retry" => download_api::retry_download,
 }
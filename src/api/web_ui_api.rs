@@ -0,0 +1,34 @@
+use actix_web::{HttpRequest, HttpResponse, Resource, web};
+use rust_embed::RustEmbed;
+
+use crate::api::api_utils::serve_content;
+
+/// The default web ui which is compiled into the binary, it is served if no `web_root` is configured.
+#[derive(RustEmbed)]
+#[folder = "assets/web/"]
+struct WebAssets;
+
+fn get_content_type(path: &str) -> &'static str {
+    match path.rsplit('.').next() {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        _ => "text/plain; charset=utf-8",
+    }
+}
+
+async fn serve_asset(req: HttpRequest) -> HttpResponse {
+    let path = match req.match_info().query("path") {
+        "" => "index.html",
+        path => path,
+    };
+    match WebAssets::get(path) {
+        Some(asset) => serve_content(&req, get_content_type(path), String::from_utf8_lossy(&asset.data).into_owned()),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+pub(crate) fn web_ui_register() -> Resource {
+    web::resource("/{path:.*}").route(web::get().to(serve_asset))
+}
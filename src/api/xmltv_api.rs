@@ -1,9 +1,9 @@
 use std::path::{PathBuf};
 use actix_web::{HttpRequest, HttpResponse, Resource, web};
-use log::{debug, info};
+use log::{debug, error, info};
 use url::Url;
 
-use crate::api::api_utils::{get_user_target, serve_file};
+use crate::api::api_utils::{check_client, get_user_target, serve_file};
 use crate::api::api_model::{AppState, UserApiRequest};
 use crate::model::api_proxy::ProxyType;
 use crate::model::config::{Config, ConfigTarget, InputType};
@@ -17,7 +17,7 @@ fn get_epg_path_for_target(config: &Config, target: &ConfigTarget) -> Option<Pat
     for output in &target.output {
         match output.target {
             TargetType::M3u => {
-                if let Some(epg_path) = get_m3u_epg_file_path(config, &target.get_m3u_filename()) {
+                if let Some(epg_path) = get_m3u_epg_file_path(config, target, &target.get_m3u_filename()) {
                     if file_utils::path_exists(&epg_path) {
                         return Some(epg_path);
                     } else {
@@ -35,26 +35,32 @@ fn get_epg_path_for_target(config: &Config, target: &ConfigTarget) -> Option<Pat
                     }
                 }
             }
-            TargetType::Strm => {}
+            TargetType::Strm | TargetType::Kodi | TargetType::Json | TargetType::Csv => {}
         }
     }
     None
 }
 
-async fn xmltv_api(
+#[utoipa::path(get, path = "/xmltv.php", tag = "player", params(UserApiRequest),
+    responses((status = 200, description = "The epg of the user", body = String, content_type = "application/xml"), (status = 204), (status = 400)))]
+pub(crate) async fn xmltv_api(
     api_req: web::Query<UserApiRequest>,
     req: HttpRequest,
     _app_state: web::Data<AppState>,
 ) -> HttpResponse {
-    if let Some((user, target)) = get_user_target(&api_req, &_app_state) {
-        match get_epg_path_for_target(&_app_state.config, target) {
+    let config = _app_state.get_config();
+    if let Some(response) = check_client(&req, &config, true) {
+        return response;
+    }
+    if let Some((user, target)) = get_user_target(&req, &api_req, &config) {
+        match get_epg_path_for_target(&config, target) {
             None => {
                 // If no epg_url is provided for input, we did not process the xmltv for our channels.
                 // We are now delivering the original untouched xmltv.
                 // If you want to use xmltv then provide the url in the config to filter unnecessary content.
                 // If you have multiple xtream sources, the first one will be used for epg
                 let target_name = &target.name;
-                if let Some(input) = _app_state.config.get_input_for_target(target_name, &InputType::Xtream) {
+                if let Some(input) = config.get_input_for_target(target_name, &InputType::Xtream) {
                     let epg_url = input.epg_url.as_ref().map_or("".to_string(), |s| s.to_owned());
                     let api_url = if  epg_url.is_empty() {
                         format!("{}/xmltv.php?username={}&password={}",
@@ -68,7 +74,13 @@ async fn xmltv_api(
                             debug!("Redirecting epg request to {}", api_url);
                             return HttpResponse::Found().insert_header(("Location", api_url)).finish();
                         }
-                        let client = request_utils::get_client_request(input, url, None);
+                        let client = match request_utils::get_client_request(input, url, None) {
+                            Ok(client) => client,
+                            Err(err) => {
+                                error!("{}", err);
+                                return HttpResponse::BadGateway().finish();
+                            }
+                        };
                         if let Ok(response) = client.send().await {
                             if response.status().is_success() {
                                 if let Ok(content) = response.text().await {
@@ -79,7 +91,7 @@ async fn xmltv_api(
                     }
                 }
             }
-            Some(epg_path) => return serve_file(&epg_path, &req).await
+            Some(epg_path) => return serve_file(&config, &epg_path, &req).await
         }
     }
     HttpResponse::Ok().content_type(mime::TEXT_XML).body(
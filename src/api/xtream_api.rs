@@ -1,26 +1,101 @@
 // https://github.com/tellytv/go.xtream-codes/blob/master/structs.go
 
-use std::collections::HashMap;
-use std::io::{Error};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Cursor, Error};
 use std::path::Path;
 use std::str::FromStr;
 use actix_web::{HttpRequest, HttpResponse, web, Resource};
+use actix_web::web::Bytes;
 use chrono::{Duration, Local};
-use log::{debug, error};
+use futures::{Stream, StreamExt};
+use log::{debug, error, info};
+use memmap2::Mmap;
+use openssl::sha::Sha256;
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value};
 use url::{Url};
 
-use crate::api::api_utils::{get_user_target, get_user_target_by_credentials, serve_file};
-use crate::api::api_model::{AppState, UserApiRequest, XtreamAuthorizationResponse, XtreamServerInfo, XtreamUserInfo};
+use crate::api::hls_api;
+use crate::api::api_utils::{check_client, get_server_info, get_user_target, get_user_target_by_credentials, serve_content, serve_content_stream, serve_file};
+use crate::api::api_model::{AppState, UserApiRequest, UserConnectionGuard, XtreamAuthorizationResponse, XtreamServerInfo, XtreamUserInfo};
 use crate::model::api_proxy::{ProxyType, UserCredentials};
-use crate::model::config::{Config, ConfigInput, InputType};
+use crate::model::config::{Config, ConfigInput, ConfigTarget, InputType, StorageType};
 use crate::model::model_config::{TargetType};
 use crate::model::model_playlist::XtreamCluster;
-use crate::repository::xtream_repository;
-use crate::utils::{json_utils, request_utils};
+use crate::processing::custom_channels;
+use crate::repository::{sqlite_repository, xtream_repository};
+use crate::repository::id_mapping_repository::VirtualIdKind;
+use crate::utils::{json_utils, request_utils, shared_stream, shutdown, stream_reconnect, throttle, user_stats};
 
-pub(crate) async fn serve_query(file_path: &Path, filter: &HashMap<&str, &str>) -> HttpResponse {
-    let filtered = json_utils::filter_json_file(file_path, filter);
-    HttpResponse::Ok().json(filtered)
+/// Size of the chunks of filtered collections, the entries are read from the file while the response is sent.
+const COLLECTION_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The etag of a filtered collection depends on the collection file and the filter,
+/// a client gets `304 Not Modified` without reading the file. The etag stays the same across restarts.
+fn get_collection_etag(file_path: &Path, filter: &str) -> Option<String> {
+    let metadata = std::fs::metadata(file_path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?;
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}\0{}\0{}\0{}", file_path.to_string_lossy(), metadata.len(), modified.as_nanos(), filter).as_bytes());
+    Some(hasher.finish()[..16].iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Serves the entries of the given categories, optionally restricted to a single requested category.
+/// The collection file is memory mapped, concurrent requests share the pages of the file instead of each reading it.
+/// The entries are parsed and filtered on a blocking thread, the worker only sends the chunks.
+/// A broken collection file aborts the response, the client doesn't get a truncated list.
+pub(crate) fn serve_user_query(req: &HttpRequest, file_path: &Path, category_ids: Option<HashSet<String>>, category_id: &str) -> HttpResponse {
+    // SAFETY: collection files are never changed in place, a new version is written into a new directory
+    // and old versions are removed, an unlinked file stays mapped.
+    let mmap = match File::open(file_path).and_then(|file| unsafe { Mmap::map(&file) }) {
+        Ok(mmap) => mmap,
+        Err(_) => return HttpResponse::NoContent().finish(),
+    };
+    let mut filter = category_ids.as_ref().map_or_else(Vec::new, |ids| ids.iter().map(String::as_str).collect::<Vec<_>>());
+    filter.sort_unstable();
+    let etag = get_collection_etag(file_path, &format!("{}/{}", filter.join(","), category_id));
+    let category_id = category_id.to_string();
+    serve_content_stream(req, mime::APPLICATION_JSON.as_ref(), etag, move || {
+        let entries = json_utils::iter_json_array::<Value, Cursor<Mmap>>(Cursor::new(mmap))
+            .filter(move |entry| entry.as_ref().map_or(true, |entry| entry.get("category_id").and_then(xtream_repository::get_category_id)
+                .is_some_and(|id| category_ids.as_ref().is_none_or(|ids| ids.contains(&id)) && (category_id.is_empty() || id.eq(&category_id)))));
+        json_utils::to_json_array_chunks(entries, COLLECTION_CHUNK_SIZE)
+    })
+}
+
+/// Serves the collections of the sqlite storage, the entries are selected by category in the database.
+fn serve_sqlite_query(req: &HttpRequest, cfg: &Config, target_name: &str, action: &str, user: &UserCredentials, category_id: &str) -> HttpResponse {
+    let collection = match action {
+        "get_live_categories" => xtream_repository::COL_CAT_LIVE,
+        "get_vod_categories" => xtream_repository::COL_CAT_VOD,
+        "get_series_categories" => xtream_repository::COL_CAT_SERIES,
+        "get_live_streams" => xtream_repository::COL_LIVE,
+        "get_vod_streams" => xtream_repository::COL_VOD,
+        "get_series" => xtream_repository::COL_SERIES,
+        _ => {
+            debug!("Cant find action: {}/{}", target_name, action);
+            return HttpResponse::NoContent().finish();
+        }
+    };
+    let category_ids = xtream_repository::xtream_get_user_category_ids(cfg, target_name, get_category_collection(action), user);
+    match xtream_repository::get_xtream_storage_path(cfg, target_name)
+        .map(|path| sqlite_repository::query_collection(&path, collection, category_ids.as_ref(), category_id)) {
+        Some(Ok(content)) => serve_content(req, mime::APPLICATION_JSON.as_ref(), content),
+        Some(Err(err)) => {
+            debug!("Could not query collection for xtream target: {} {}", target_name, err);
+            HttpResponse::NoContent().finish()
+        }
+        None => HttpResponse::NoContent().finish(),
+    }
+}
+
+fn get_category_collection(action: &str) -> &'static str {
+    match action {
+        "get_live_categories" | "get_live_streams" => xtream_repository::COL_CAT_LIVE,
+        "get_vod_categories" | "get_vod_streams" => xtream_repository::COL_CAT_VOD,
+        _ => xtream_repository::COL_CAT_SERIES,
+    }
 }
 
 fn get_xtream_player_api_action_url(input: &ConfigInput, action: &str) -> Option<String> {
@@ -60,28 +135,65 @@ fn get_xtream_player_api_stream_url(input: &ConfigInput, context: &str, action_p
     }
 }
 
+/// Replaces the stream id in the last segment of the action path with the provider id, the extension is kept.
+fn get_provider_action_path(config: &Config, target: &ConfigTarget, context: &str, action_path: &str) -> Option<String> {
+    let (prefix, file) = action_path.rsplit_once('/').map_or(("", action_path), |(prefix, file)| (prefix, file));
+    let (stream_id, extension) = file.split_once('.').map_or((file, ""), |(stream_id, extension)| (stream_id, extension));
+    let kinds: &[VirtualIdKind] = match context {
+        "movie" => &[VirtualIdKind::Video],
+        "series" => &[VirtualIdKind::Episode],
+        "" => &[VirtualIdKind::Live, VirtualIdKind::Video],
+        _ => &[VirtualIdKind::Live],
+    };
+    let provider_id = match stream_id.parse::<i32>() {
+        Ok(id) => xtream_repository::xtream_get_provider_id(config, target, id, kinds)?,
+        // not a stream id, the request is forwarded as it is
+        Err(_) => return Some(action_path.to_string()),
+    };
+    let provider_file = if extension.is_empty() { provider_id.to_string() } else { format!("{}.{}", provider_id, extension) };
+    Some(if prefix.is_empty() { provider_file } else { format!("{}/{}", prefix, provider_file) })
+}
+
+/// The stream id of the last path segment, without the file extension.
+fn get_action_stream_id(action_path: &str) -> Option<i32> {
+    action_path.rsplit('/').next().and_then(|file| file.split('.').next()).and_then(|id| id.parse::<i32>().ok())
+}
+
+/// Users with a bouquet can only play the streams of their categories.
+fn is_user_stream(config: &Config, target_name: &str, user: &UserCredentials, context: &str, action_path: &str) -> bool {
+    let clusters: &[XtreamCluster] = match context {
+        "movie" => &[XtreamCluster::Video],
+        "series" => return true,
+        "" => &[XtreamCluster::Live, XtreamCluster::Video],
+        _ => &[XtreamCluster::Live],
+    };
+    match get_action_stream_id(action_path) {
+        Some(stream_id) => xtream_repository::xtream_is_user_stream(config, target_name, clusters, stream_id, user),
+        None => user.groups.is_none(),
+    }
+}
 
-fn get_user_info(user: &UserCredentials, cfg: &Config) -> XtreamAuthorizationResponse {
-    let server_info_list = cfg._api_proxy.read().unwrap().as_ref().unwrap().server.clone();
+pub(crate) fn get_user_info(req: &HttpRequest, user: &UserCredentials, app_state: &AppState) -> XtreamAuthorizationResponse {
+    let server_info_list = app_state.get_config()._api_proxy.read().unwrap().as_ref().unwrap().server.clone();
     let server_info_name = match &user.server {
         Some(server_name) => server_name.as_str(),
         None => "default"
     };
-    let server_info = match server_info_list.iter().find(|c| c.name.eq(server_info_name)) {
+    let server_info = get_server_info(req, match server_info_list.iter().find(|c| c.name.eq(server_info_name)) {
         Some(info) => info,
         None => server_info_list.first().unwrap(),
-    };
+    });
 
     let now = Local::now();
     XtreamAuthorizationResponse {
         user_info: XtreamUserInfo {
-            active_cons: "0".to_string(),
+            active_cons: app_state.active_users.get_connection_count(&user.username).to_string(),
             allowed_output_formats: Vec::from(["ts".to_string()]),
             auth: 1,
             created_at: (now - Duration::days(365)).timestamp(), // fake
             exp_date: (now + Duration::days(365)).timestamp(),// fake
             is_trial: "0".to_string(),
-            max_connections: "1".to_string(),
+            max_connections: if user.max_connections > 0 { user.max_connections.to_string() } else { "1".to_string() },
             message: server_info.message.to_string(),
             password: user.password.to_string(),
             username: user.username.to_string(),
@@ -100,6 +212,26 @@ fn get_user_info(user: &UserCredentials, cfg: &Config) -> XtreamAuthorizationRes
     }
 }
 
+/// Streams the provider response to the client with the limits and stats of the user.
+/// The guard lives as long as the stream, the connection is released when the client disconnects.
+fn serve_stream<S, E>(headers: &HeaderMap, stream: S, connection_guard: UserConnectionGuard, user: UserCredentials, working_dir: String) -> HttpResponse
+    where S: Stream<Item=Result<Bytes, E>> + 'static,
+          E: Into<Box<dyn std::error::Error>> + 'static {
+    let mut response_builder = HttpResponse::Ok();
+    headers.iter().for_each(|(k, v)| {
+        response_builder.insert_header((k, v));
+    });
+    let mut streamed_bytes = user_stats::StreamedBytes::new(working_dir, user.username);
+    let stream = shutdown::until_shutdown(throttle::throttle(stream, user.max_bitrate_kbps)).map(move |chunk| {
+        let _ = &connection_guard;
+        if let Ok(bytes) = &chunk {
+            streamed_bytes.add(bytes.len() as u64);
+        }
+        chunk
+    });
+    response_builder.body(actix_web::body::BodyStream::new(stream))
+}
+
 async fn xtream_player_api_stream(
     req: &HttpRequest,
     api_req: &web::Query<UserApiRequest>,
@@ -109,31 +241,75 @@ async fn xtream_player_api_stream(
     password: &str,
     action_path: &str,
 ) -> HttpResponse {
-    if let Some((user, target)) = get_user_target_by_credentials(username, password, api_req, _app_state) {
+    let config = _app_state.get_config();
+    if let Some(response) = check_client(req, &config, true) {
+        return response;
+    }
+    if let Some((user, target)) = get_user_target_by_credentials(req, username, password, api_req, &config) {
         let target_name = &target.name;
         if target.has_output(&TargetType::Xtream) {
-            if let Some(target_input) = match _app_state.config.get_input_for_target(target_name, &InputType::Xtream) {
-                None => _app_state.config.get_input_for_target(target_name, &InputType::M3u),
+            if !is_user_stream(&config, target_name, &user, context, action_path) {
+                debug!("Stream {} is not in the bouquet of user {}", action_path, &user.username);
+                return HttpResponse::Forbidden().finish();
+            }
+            if let Some(target_input) = match config.get_input_for_target(target_name, &InputType::Xtream) {
+                None => config.get_input_for_target(target_name, &InputType::M3u),
                 Some(inp) => Some(inp)
             } {
-                if let Some(stream_url) = get_xtream_player_api_stream_url(target_input, context, action_path) {
+                if let Some(stream_url) = get_provider_action_path(&config, target, context, action_path)
+                    .and_then(|provider_action_path| custom_channels::get_stream_url(&config, target, &provider_action_path)
+                        .or_else(|| get_xtream_player_api_stream_url(target_input, context, &provider_action_path))) {
                     if user.proxy == ProxyType::Redirect {
                         debug!("Redirecting stream request to {}", stream_url);
                         return HttpResponse::Found().insert_header(("Location", stream_url)).finish();
                     }
 
+                    let connection_guard = match _app_state.active_users.add_connection(&user.username, user.max_connections) {
+                        Some(guard) => guard,
+                        None => {
+                            info!("User {} exceeded max connections {}", &user.username, user.max_connections);
+                            return HttpResponse::Forbidden().finish();
+                        }
+                    };
+
+                    // live channels with a shared stream join the running provider connection
+                    let shared_buffer_size = if context == "live" { shared_stream::get_buffer_size(target_input) } else { None };
+                    if let Some(shared_client) = shared_buffer_size.and_then(|_| shared_stream::subscribe(&stream_url)) {
+                        debug!("Joined shared stream {}", &stream_url);
+                        let headers = shared_client.get_headers().clone();
+                        return serve_stream(&headers, shared_client, connection_guard, user, config.working_dir.clone());
+                    }
+
                     let req_headers: HashMap<&str, &[u8]> = req.headers().iter().map(|(k, v)| (k.as_str(), v.as_bytes())).collect();
                     debug!("Try to open stream {}", &stream_url);
                     if let Ok(url) = Url::parse(&stream_url) {
-                        let client = request_utils::get_client_request(target_input, url, Some(&req_headers));
+                        let mut client = match request_utils::get_client_request(target_input, url, Some(&req_headers)) {
+                            Ok(client) => client,
+                            Err(err) => {
+                                error!("{}", err);
+                                return HttpResponse::BadGateway().finish();
+                            }
+                        };
+                        // channel headers from mappings replace the client and input headers
+                        if let Some(headers) = get_action_stream_id(action_path).and_then(|id| xtream_repository::xtream_get_stream_headers(&config, target_name, id)) {
+                            client = client.headers(request_utils::get_request_headers(&headers, None));
+                        }
+                        let reconnect_request = client.try_clone();
                         match client.send().await {
                             Ok(response) => {
                                 if response.status().is_success() {
-                                    let mut response_builder = HttpResponse::Ok();
-                                    response.headers().iter().for_each(|(k, v)| {
-                                        response_builder.insert_header((k, v));
-                                    });
-                                    return response_builder.body(actix_web::body::BodyStream::new(response.bytes_stream()));
+                                    if hls_api::is_hls_response(&response) {
+                                        return hls_api::serve_hls_response(req, &user, target_input, reconnect_request, response).await;
+                                    }
+                                    let headers = response.headers().clone();
+                                    let client_range = req.headers().get(actix_web::http::header::RANGE).and_then(|range| range.to_str().ok());
+                                    let provider_stream = stream_reconnect::get_stream(target_input, reconnect_request, response, context == "live", client_range);
+                                    if let Some(buffer_size) = shared_buffer_size {
+                                        let shared_client = shared_stream::share(&stream_url, buffer_size, headers, provider_stream);
+                                        let headers = shared_client.get_headers().clone();
+                                        return serve_stream(&headers, shared_client, connection_guard, user, config.working_dir.clone());
+                                    }
+                                    return serve_stream(&headers, provider_stream, connection_guard, user, config.working_dir.clone());
                                 } else {
                                     debug!("Failed to open stream got status {} for {}", response.status(), &stream_url)
                                 }
@@ -161,7 +337,12 @@ async fn xtream_player_api_stream(
     HttpResponse::BadRequest().finish()
 }
 
-async fn xtream_player_api_live_stream(
+#[utoipa::path(get, path = "/live/{username}/{password}/{stream_id}", tag = "player",
+    params(("username" = String, Path, description = "Username of the api-proxy user"),
+           ("password" = String, Path, description = "Password of the api-proxy user"),
+           ("stream_id" = String, Path, description = "Stream id with the file extension")),
+    responses((status = 200, description = "The live stream"), (status = 302, description = "Redirect to the provider"), (status = 400), (status = 403)))]
+pub(crate) async fn xtream_player_api_live_stream(
     req: HttpRequest,
     api_req: web::Query<UserApiRequest>,
     path: web::Path<(String, String, String)>,
@@ -171,7 +352,12 @@ async fn xtream_player_api_live_stream(
     xtream_player_api_stream(&req, &api_req, &_app_state, "live", &username, &password, &stream_id).await
 }
 
-async fn xtream_player_api_live_stream_alt(
+#[utoipa::path(get, path = "/{username}/{password}/{stream_id}", tag = "player",
+    params(("username" = String, Path, description = "Username of the api-proxy user"),
+           ("password" = String, Path, description = "Password of the api-proxy user"),
+           ("stream_id" = String, Path, description = "Stream id with the file extension")),
+    responses((status = 200, description = "The live stream"), (status = 302, description = "Redirect to the provider"), (status = 400), (status = 403)))]
+pub(crate) async fn xtream_player_api_live_stream_alt(
     req: HttpRequest,
     api_req: web::Query<UserApiRequest>,
     path: web::Path<(String, String, String)>,
@@ -181,7 +367,12 @@ async fn xtream_player_api_live_stream_alt(
     xtream_player_api_stream(&req, &api_req, &_app_state, "", &username, &password, &stream_id).await
 }
 
-async fn xtream_player_api_series_stream(
+#[utoipa::path(get, path = "/series/{username}/{password}/{stream_id}", tag = "player",
+    params(("username" = String, Path, description = "Username of the api-proxy user"),
+           ("password" = String, Path, description = "Password of the api-proxy user"),
+           ("stream_id" = String, Path, description = "Stream id with the file extension")),
+    responses((status = 200, description = "The episode stream"), (status = 302, description = "Redirect to the provider"), (status = 400), (status = 403)))]
+pub(crate) async fn xtream_player_api_series_stream(
     req: HttpRequest,
     api_req: web::Query<UserApiRequest>,
     path: web::Path<(String, String, String)>,
@@ -191,7 +382,12 @@ async fn xtream_player_api_series_stream(
     xtream_player_api_stream(&req, &api_req, &_app_state, "series", &username, &password, &stream_id).await
 }
 
-async fn xtream_player_api_movie_stream(
+#[utoipa::path(get, path = "/movie/{username}/{password}/{stream_id}", tag = "player",
+    params(("username" = String, Path, description = "Username of the api-proxy user"),
+           ("password" = String, Path, description = "Password of the api-proxy user"),
+           ("stream_id" = String, Path, description = "Stream id with the file extension")),
+    responses((status = 200, description = "The movie stream"), (status = 302, description = "Redirect to the provider"), (status = 400), (status = 403)))]
+pub(crate) async fn xtream_player_api_movie_stream(
     req: HttpRequest,
     api_req: web::Query<UserApiRequest>,
     path: web::Path<(String, String, String)>,
@@ -201,7 +397,14 @@ async fn xtream_player_api_movie_stream(
     xtream_player_api_stream(&req, &api_req, &_app_state, "movie", &username, &password, &stream_id).await
 }
 
-async fn xtream_player_api_timeshift_stream(
+#[utoipa::path(get, path = "/timeshift/{username}/{password}/{duration}/{start}/{stream_id}", tag = "player",
+    params(("username" = String, Path, description = "Username of the api-proxy user"),
+           ("password" = String, Path, description = "Password of the api-proxy user"),
+           ("duration" = String, Path, description = "Duration in minutes"),
+           ("start" = String, Path, description = "Start time"),
+           ("stream_id" = String, Path, description = "Stream id with the file extension")),
+    responses((status = 200, description = "The catchup stream"), (status = 400), (status = 403)))]
+pub(crate) async fn xtream_player_api_timeshift_stream(
     req: HttpRequest,
     api_req: web::Query<UserApiRequest>,
     path: web::Path<(String, String, String, String, String)>,
@@ -212,29 +415,46 @@ async fn xtream_player_api_timeshift_stream(
     xtream_player_api_stream(&req, &api_req, &_app_state, "timeshift", &username, &password, &action_path).await
 }
 
+/// Catchup with query parameters, the request is forwarded to the provider in path style
+/// `timeshift/{username}/{password}/{duration}/{start}/{stream_id}.ts`.
+#[utoipa::path(get, path = "/streaming/timeshift.php", tag = "player", params(UserApiRequest),
+    responses((status = 200, description = "The catchup stream"), (status = 400), (status = 403)))]
+pub(crate) async fn xtream_player_api_timeshift_php(
+    req: HttpRequest,
+    api_req: web::Query<UserApiRequest>,
+    _app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let stream = api_req.stream.trim();
+    let duration = api_req.duration.trim();
+    let start = api_req.start.trim();
+    if stream.is_empty() || duration.is_empty() || start.is_empty() {
+        return HttpResponse::BadRequest().finish();
+    }
+    let stream_id = if stream.contains('.') { stream.to_string() } else { format!("{}.ts", stream) };
+    let action_path = format!("{}/{}/{}", duration, start, stream_id);
+    let username = api_req.username.trim().to_string();
+    let password = api_req.password.trim().to_string();
+    xtream_player_api_stream(&req, &api_req, &_app_state, "timeshift", &username, &password, &action_path).await
+}
+
 async fn xtream_get_stream_info(app_state: &AppState, target_name: &str, stream_id: i32,
                                 cluster: &XtreamCluster) -> Result<String, Error> {
-    if let Some(target_input) = app_state.config.get_input_for_target(target_name, &InputType::Xtream) {
+    let config = app_state.get_config();
+    if let Some(target_input) = config.get_input_for_target(target_name, &InputType::Xtream) {
         if let Ok(content) = xtream_repository::xtream_get_stored_stream_info(app_state, target_name, stream_id, cluster, target_input).await {
             return Ok(content);
         }
 
         if let Some(info_url) = get_xtream_player_api_info_url(target_input, cluster, stream_id) {
             if let Ok(url) = Url::parse(&info_url) {
-                let client = request_utils::get_client_request(target_input, url, None);
-                if let Ok(response) = client.send().await {
-                    debug!("{}", response.status());
-                    if response.status().is_success() {
-                        match response.text().await {
-                            Ok(content) => {
-                                // TODO we are not replacing direct_source, we should add an option to do this.
-                                xtream_repository::xtream_persist_stream_info(app_state, target_name, stream_id, cluster,
-                                                                              target_input, content.as_str()).await;
-                                return Ok(content);
-                            }
-                            Err(err) => { error!("Failed to download info {}", err.to_string()); }
-                        }
+                match request_utils::get_api_text_content(target_input, &config.working_dir, url).await {
+                    Ok(content) => {
+                        // TODO we are not replacing direct_source, we should add an option to do this.
+                        xtream_repository::xtream_persist_stream_info(app_state, target_name, stream_id, cluster,
+                                                                      target_input, content.as_str()).await;
+                        return Ok(content);
                     }
+                    Err(err) => { error!("Failed to download info {}", err); }
                 }
             }
         }
@@ -243,12 +463,28 @@ async fn xtream_get_stream_info(app_state: &AppState, target_name: &str, stream_
 }
 
 async fn xtream_get_stream_info_response(app_state: &AppState, user: &UserCredentials,
-                                         target_name: &str, stream_id: &str,
+                                         target: &ConfigTarget, stream_id: &str,
                                          cluster: &XtreamCluster) -> HttpResponse {
+    let target_name = &target.name;
     match FromStr::from_str(stream_id) {
         Ok(xtream_stream_id) => {
+            // a user with a bouquet gets only the infos of the series and movies in its categories
+            if !xtream_repository::xtream_is_user_stream(&app_state.get_config(), target_name, std::slice::from_ref(cluster), xtream_stream_id, user) {
+                return HttpResponse::Forbidden().finish();
+            }
+            // the generated series info is served for all users of the series, its episodes are streamed through the proxy
+            if *cluster == XtreamCluster::Series {
+                if let Some(content) = xtream_repository::xtream_get_series_info(&app_state.get_config(), target_name, xtream_stream_id) {
+                    return HttpResponse::Ok().content_type(mime::APPLICATION_JSON).body(content);
+                }
+            }
+            let xtream_stream_id = match xtream_repository::xtream_get_provider_id(&app_state.get_config(), target, xtream_stream_id,
+                                                                                   &[VirtualIdKind::from_cluster(cluster)]) {
+                Some(provider_id) => provider_id,
+                None => return HttpResponse::Ok().content_type(mime::APPLICATION_JSON).body("{info:[]}"),
+            };
             if user.proxy == ProxyType::Redirect {
-                if let Some(target_input) = app_state.config.get_input_for_target(target_name, &InputType::Xtream) {
+                if let Some(target_input) = app_state.get_config().get_input_for_target(target_name, &InputType::Xtream) {
                     if let Some(info_url) = get_xtream_player_api_info_url(target_input, cluster, xtream_stream_id) {
                         return HttpResponse::Found().insert_header(("Location", info_url)).finish();
                     }
@@ -264,9 +500,18 @@ async fn xtream_get_stream_info_response(app_state: &AppState, user: &UserCreden
     }
 }
 
-async fn xtream_get_short_epg(app_state: &AppState, user: &UserCredentials, target_name: &str, stream_id: &str, limit: &str) -> HttpResponse {
-    if let Some(target_input) = app_state.config.get_input_for_target(target_name, &InputType::Xtream) {
-        if let Some(action_url) = get_xtream_player_api_action_url(target_input, "get_short_epg") {
+/// Serves `get_short_epg` and `get_simple_data_table` from the provider.
+/// The responses are cached like the stream infos when the input has a `cache` option.
+async fn xtream_get_epg(app_state: &AppState, user: &UserCredentials, target: &ConfigTarget, action: &str,
+                        stream_id: &str, limit: &str) -> HttpResponse {
+    let config = app_state.get_config();
+    let target_name = &target.name;
+    let provider_id = match stream_id.parse::<i32>() {
+        Ok(id) => xtream_repository::xtream_get_provider_id(&config, target, id, &[VirtualIdKind::Live]).map(|id| id.to_string()),
+        Err(_) => Some(stream_id.to_string()),
+    };
+    if let (Some(target_input), Some(stream_id)) = (config.get_input_for_target(target_name, &InputType::Xtream), provider_id) {
+        if let Some(action_url) = get_xtream_player_api_action_url(target_input, action) {
             let mut info_url = format!("{}&stream_id={}", action_url, stream_id);
             if !(limit.is_empty() || limit.eq("0")) {
                 info_url = format!("{}&limit={}", info_url, limit);
@@ -276,78 +521,82 @@ async fn xtream_get_short_epg(app_state: &AppState, user: &UserCredentials, targ
                     return HttpResponse::Found().insert_header(("Location", info_url)).finish();
                 }
 
-                let client = request_utils::get_client_request(target_input, url, None);
-                if let Ok(response) = client.send().await {
-                    if response.status().is_success() {
-                        return match response.text().await {
-                            Ok(content) => {
-                                HttpResponse::Ok().content_type(mime::APPLICATION_JSON).body(content)
-                            }
-                            Err(err) => {
-                                error!("Failed to download epg {}", err.to_string());
-                                HttpResponse::NoContent().finish()
-                            }
-                        };
-                    }
+                match request_utils::get_api_text_content(target_input, &config.working_dir, url).await {
+                    Ok(content) => return HttpResponse::Ok().content_type(mime::APPLICATION_JSON).body(content),
+                    Err(err) => error!("Failed to download epg {}", err),
                 }
             }
         }
     }
-    error!("Cant find short epg with id: {}/{}", target_name, stream_id);
-    HttpResponse::NoContent().finish()
+    error!("Cant find epg with id: {}/{}", target_name, stream_id);
+    // clients expect a listing, an empty response is treated as an error.
+    HttpResponse::Ok().json(json!({"epg_listings": []}))
 }
 
-async fn xtream_player_api(
+pub(crate) async fn xtream_player_api(
     req: &HttpRequest,
     api_req: UserApiRequest,
     _app_state: &web::Data<AppState>,
 ) -> HttpResponse {
-    match get_user_target(&api_req, _app_state) {
+    let config = _app_state.get_config();
+    if let Some(response) = check_client(req, &config, true) {
+        return response;
+    }
+    match get_user_target(req, &api_req, &config) {
         Some((user, target)) => {
             let action = api_req.action.trim();
             let target_name = &target.name;
             if target.has_output(&TargetType::Xtream) {
                 if action.is_empty() {
-                    return HttpResponse::Ok().json(get_user_info(&user, &_app_state.config));
+                    return HttpResponse::Ok().json(get_user_info(req, &user, _app_state));
                 }
 
                 match action {
                     "get_series_info" => {
-                        xtream_get_stream_info_response(_app_state, &user, target_name,
+                        xtream_get_stream_info_response(_app_state, &user, target,
                                                         api_req.series_id.trim(),
                                                         &XtreamCluster::Series).await
                     }
                     "get_vod_info" => {
-                        xtream_get_stream_info_response(_app_state, &user, target_name,
+                        xtream_get_stream_info_response(_app_state, &user, target,
                                                         api_req.vod_id.trim(),
                                                         &XtreamCluster::Video).await
                     }
                     "get_epg" |
                     "get_short_epg" => {
-                        xtream_get_short_epg(_app_state, &user, target_name,
-                                             api_req.stream_id.trim(),
-                                             api_req.limit.trim()).await
+                        xtream_get_epg(_app_state, &user, target, "get_short_epg",
+                                       api_req.stream_id.trim(),
+                                       api_req.limit.trim()).await
+                    }
+                    "get_simple_data_table" => {
+                        xtream_get_epg(_app_state, &user, target, "get_simple_data_table",
+                                       api_req.stream_id.trim(), "").await
+                    }
+                    _ if config.storage == StorageType::Sqlite => {
+                        serve_sqlite_query(req, &config, target_name, action, &user, api_req.category_id.trim())
                     }
                     _ => {
                         match match action {
-                            "get_live_categories" => xtream_repository::xtream_get_collection_path(&_app_state.config, target_name, xtream_repository::COL_CAT_LIVE),
-                            "get_vod_categories" => xtream_repository::xtream_get_collection_path(&_app_state.config, target_name, xtream_repository::COL_CAT_VOD),
-                            "get_series_categories" => xtream_repository::xtream_get_collection_path(&_app_state.config, target_name, xtream_repository::COL_CAT_SERIES),
-                            "get_live_streams" => xtream_repository::xtream_get_collection_path(&_app_state.config, target_name, xtream_repository::COL_LIVE),
-                            "get_vod_streams" => xtream_repository::xtream_get_collection_path(&_app_state.config, target_name, xtream_repository::COL_VOD),
-                            "get_series" => xtream_repository::xtream_get_collection_path(&_app_state.config, target_name, xtream_repository::COL_SERIES),
+                            "get_live_categories" => xtream_repository::xtream_get_collection_path(&config, target_name, xtream_repository::COL_CAT_LIVE),
+                            "get_vod_categories" => xtream_repository::xtream_get_collection_path(&config, target_name, xtream_repository::COL_CAT_VOD),
+                            "get_series_categories" => xtream_repository::xtream_get_collection_path(&config, target_name, xtream_repository::COL_CAT_SERIES),
+                            "get_live_streams" => xtream_repository::xtream_get_collection_path(&config, target_name, xtream_repository::COL_LIVE),
+                            "get_vod_streams" => xtream_repository::xtream_get_collection_path(&config, target_name, xtream_repository::COL_VOD),
+                            "get_series" => xtream_repository::xtream_get_collection_path(&config, target_name, xtream_repository::COL_SERIES),
                             _ => Err(Error::new(std::io::ErrorKind::Unsupported, format!("Cant find action: {}/{}", target_name, action))),
                         } {
                             Ok((path, content)) => {
                                 if let Some(file_path) = path {
                                     let category_id = api_req.category_id.trim();
-                                    if !category_id.is_empty() {
-                                        serve_query(&file_path, &HashMap::from([("category_id", category_id)])).await
+                                    let category_ids = xtream_repository::xtream_get_user_category_ids(
+                                        &config, target_name, get_category_collection(action), &user);
+                                    if category_ids.is_some() || !category_id.is_empty() {
+                                        serve_user_query(req, &file_path, category_ids, category_id)
                                     } else {
-                                        serve_file(&file_path, req).await
+                                        serve_file(&config, &file_path, req).await
                                     }
                                 } else if let Some(payload) = content {
-                                    HttpResponse::Ok().body(payload)
+                                    serve_content(req, mime::APPLICATION_JSON.as_ref(), payload)
                                 } else {
                                     HttpResponse::NoContent().finish()
                                 }
@@ -360,7 +609,7 @@ async fn xtream_player_api(
                     }
                 }
             } else {
-                HttpResponse::Ok().json(get_user_info(&user, &_app_state.config))
+                HttpResponse::Ok().json(get_user_info(req, &user, _app_state))
             }
         }
         _ => {
@@ -376,16 +625,20 @@ async fn xtream_player_api(
 }
 
 
-async fn xtream_player_api_get(req: HttpRequest,
-                               api_req: web::Query<UserApiRequest>,
-                               _app_state: web::Data<AppState>,
+#[utoipa::path(get, path = "/player_api.php", tag = "player", params(UserApiRequest),
+    responses((status = 200, description = "The user info or the result of the action, the documents of the provider", body = Object), (status = 400), (status = 401)))]
+pub(crate) async fn xtream_player_api_get(req: HttpRequest,
+                                          api_req: web::Query<UserApiRequest>,
+                                          _app_state: web::Data<AppState>,
 ) -> HttpResponse {
     xtream_player_api(&req, api_req.into_inner(), &_app_state).await
 }
 
-async fn xtream_player_api_post(req: HttpRequest,
-                                api_req: web::Form<UserApiRequest>,
-                                _app_state: web::Data<AppState>,
+#[utoipa::path(post, path = "/xtream", tag = "player", request_body(content = UserApiRequest, content_type = "application/x-www-form-urlencoded"),
+    responses((status = 200, description = "The user info or the result of the action, the documents of the provider", body = Object), (status = 400), (status = 401)))]
+pub(crate) async fn xtream_player_api_post(req: HttpRequest,
+                                           api_req: web::Form<UserApiRequest>,
+                                           _app_state: web::Data<AppState>,
 ) -> HttpResponse {
     xtream_player_api(&req, api_req.into_inner(), &_app_state).await
 }
@@ -393,13 +646,13 @@ async fn xtream_player_api_post(req: HttpRequest,
 pub(crate) fn xtream_api_register() -> Vec<Resource> {
     vec![
         web::resource("/player_api.php").route(web::get().to(xtream_player_api_get)).route(web::post().to(xtream_player_api_get)),
-        web::resource("/panel_api.php").route(web::get().to(xtream_player_api_get)).route(web::post().to(xtream_player_api_get)),
         web::resource("/xtream").route(web::get().to(xtream_player_api_get)).route(web::post().to(xtream_player_api_post)),
         web::resource("/{username}/{password}/{stream_id}").route(web::get().to(xtream_player_api_live_stream_alt)),
         web::resource("/live/{username}/{password}/{stream_id}").route(web::get().to(xtream_player_api_live_stream)),
         web::resource("/movie/{username}/{password}/{stream_id}").route(web::get().to(xtream_player_api_movie_stream)),
         web::resource("/series/{username}/{password}/{stream_id}").route(web::get().to(xtream_player_api_series_stream)),
-        web::resource("/timeshift/{username}/{password}/{duration}/{start}{stream_id}").route(web::get().to(xtream_player_api_timeshift_stream)),
+        web::resource("/timeshift/{username}/{password}/{duration}/{start}/{stream_id}").route(web::get().to(xtream_player_api_timeshift_stream)),
+        web::resource("/streaming/timeshift.php").route(web::get().to(xtream_player_api_timeshift_php)),
         /* TODO
         web::resource("/hlsr/{token}/{username}/{password}/{channel}/{hash}/{chunk}").route(web::get().to(xtream_player_api_hlsr_stream))
         web::resource("/hls/{token}/{chunk}").route(web::get().to(xtream_player_api_hls_stream))
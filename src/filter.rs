@@ -1,7 +1,8 @@
 use std::cell::RefCell;
+use std::cmp::Ordering;
 use enum_iterator::all;
 use std::collections::{HashMap};
-use std::rc::Rc;
+use std::sync::Arc;
 use log::{debug, error, Level, log_enabled};
 use pest::iterators::Pair;
 use pest::Parser;
@@ -13,7 +14,7 @@ use crate::{create_m3u_filter_error_result};
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
 
 
-pub(crate) fn get_field_value(pli: &PlaylistItem, field: &ItemField) -> Rc<String> {
+pub(crate) fn get_field_value(pli: &PlaylistItem, field: &ItemField) -> Arc<str> {
     let header = pli.header.borrow();
     let value = match field {
         ItemField::Group => &header.group,
@@ -21,10 +22,10 @@ pub(crate) fn get_field_value(pli: &PlaylistItem, field: &ItemField) -> Rc<Strin
         ItemField::Title => &header.title,
         ItemField::Url => &header.url,
     };
-    Rc::clone(value)
+    Arc::clone(value)
 }
 
-pub(crate) fn set_field_value(pli: &mut PlaylistItem, field: &ItemField, value: Rc<String>) {
+pub(crate) fn set_field_value(pli: &mut PlaylistItem, field: &ItemField, value: Arc<str>) {
     let header = &mut pli.header.borrow_mut();
     match field {
         ItemField::Group => header.group = value,
@@ -39,10 +40,23 @@ pub(crate) struct ValueProvider<'a> {
 }
 
 impl<'a> ValueProvider<'a> {
-    fn call(&self, field: &ItemField) -> Rc<String> {
+    fn call(&self, field: &ItemField) -> Arc<str> {
         let pli = *self.pli.borrow();
         get_field_value(pli, field)
     }
+
+    fn get_property(&self, name: &str) -> Option<String> {
+        let pli = *self.pli.borrow();
+        let header = pli.header.borrow();
+        header.additional_properties.as_ref()
+            .and_then(|props| props.iter().find(|(key, _)| key.eq(name)))
+            .and_then(|(_, value)| match value {
+                serde_json::Value::String(text) => Some(text.to_string()),
+                serde_json::Value::Number(num) => Some(num.to_string()),
+                serde_json::Value::Bool(flag) => Some(if *flag { "1" } else { "0" }.to_string()),
+                _ => None,
+            })
+    }
 }
 
 pub(crate) trait ValueProcessor {
@@ -57,10 +71,14 @@ impl ValueProcessor for MockValueProcessor {
     }
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 pub(crate) struct PatternTemplate {
     pub name: String,
+    #[serde(default)]
     pub value: String,
+    /// regular expressions of a list template, used with `IN template!{name}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone)]
@@ -82,10 +100,26 @@ not = { ^"not" }
 regexp = @{ "\"" ~ ( "\\\"" | (!"\"" ~ ANY) )* ~ "\"" }
 comparison_value = _{ regexp }
 comparison = { field ~ "~" ~ comparison_value }
+prop_name = @{ (ASCII_ALPHANUMERIC | "_")+ }
+prop = _{ ^"prop" ~ "(" ~ prop_name ~ ")" }
+number = @{ "-"? ~ ASCII_DIGIT+ ~ ("." ~ ASCII_DIGIT+)? }
+compare_op = { ">=" | "<=" | "!=" | "=" | ">" | "<" }
+prop_match = { "~" ~ regexp }
+prop_compare = { compare_op ~ (number | regexp) }
+prop_comparison = { prop ~ (prop_match | prop_compare) }
+template_name = @{ (ASCII_ALPHANUMERIC | "_")+ }
+template_ref = { ^"template" ~ "!{" ~ template_name ~ "}" }
+regexp_list = { "[" ~ regexp ~ ("," ~ regexp)* ~ "]" }
+not_in = { ^"not" ~ ^"in" }
+in_set = { ^"in" }
+membership = { field ~ (not_in | in_set) ~ (regexp_list | template_ref) }
 bool_op = { and | or}
 expr_group = { "(" ~ expr ~ ")" }
 expr = {
   comparison ~ (bool_op ~ expr)*
+  | membership ~ (bool_op ~ expr)*
+  | prop_comparison ~ (bool_op ~ expr)*
+  | template_ref ~ (bool_op ~ expr)*
   | expr_group ~ (bool_op ~ expr)*
   | not ~ expr ~ (bool_op ~ expr)*
 }
@@ -114,20 +148,119 @@ impl std::fmt::Display for BinaryOperator {
     }
 }
 
+#[derive(Debug, Clone)]
+pub(crate) enum SetOperator {
+    In,
+    NotIn,
+}
+
+impl std::fmt::Display for SetOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            SetOperator::In => write!(f, "IN"),
+            SetOperator::NotIn => write!(f, "NOT IN"),
+        }
+    }
+}
+
+/// The regular expressions of a list in the filter or of a list template.
+#[derive(Debug, Clone)]
+pub(crate) struct FilterSet {
+    /// the list or the template reference as written in the filter
+    pub source: String,
+    pub regexps: Vec<RegexWithCaptures>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum CompareOperator {
+    Eq,
+    NotEq,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+}
+
+impl CompareOperator {
+    fn matches(&self, ordering: Ordering) -> bool {
+        match self {
+            CompareOperator::Eq => ordering == Ordering::Equal,
+            CompareOperator::NotEq => ordering != Ordering::Equal,
+            CompareOperator::Greater => ordering == Ordering::Greater,
+            CompareOperator::GreaterEq => ordering != Ordering::Less,
+            CompareOperator::Less => ordering == Ordering::Less,
+            CompareOperator::LessEq => ordering != Ordering::Greater,
+        }
+    }
+}
+
+impl std::fmt::Display for CompareOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            CompareOperator::Eq => write!(f, "="),
+            CompareOperator::NotEq => write!(f, "!="),
+            CompareOperator::Greater => write!(f, ">"),
+            CompareOperator::GreaterEq => write!(f, ">="),
+            CompareOperator::Less => write!(f, "<"),
+            CompareOperator::LessEq => write!(f, "<="),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum CompareValue {
+    Number(String, f64),
+    Text(String),
+}
+
+impl CompareValue {
+    /// Numbers are compared numerically, property values which are not a number never match.
+    fn compare(&self, value: &str, op: &CompareOperator) -> bool {
+        match self {
+            CompareValue::Number(_, num) => value.trim().parse::<f64>().ok()
+                .and_then(|prop_num| prop_num.partial_cmp(num))
+                .is_some_and(|ordering| op.matches(ordering)),
+            CompareValue::Text(text) => op.matches(value.cmp(text.as_str())),
+        }
+    }
+}
+
+impl std::fmt::Display for CompareValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CompareValue::Number(text, _) => write!(f, "{}", text),
+            CompareValue::Text(text) => write!(f, "\"{}\"", text),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) enum Filter {
     Group(Box<Filter>),
     Comparison(ItemField, RegexWithCaptures),
+    Membership(ItemField, SetOperator, FilterSet),
+    PropertyMatch(String, RegexWithCaptures),
+    PropertyComparison(String, CompareOperator, CompareValue),
     UnaryExpression(UnaryOperator, Box<Filter>),
     BinaryExpression(Box<Filter>, BinaryOperator, Box<Filter>),
 }
 
 impl Filter {
+    /// The comparison which is evaluated first and has to match, `None` if the filter can match without it.
+    pub fn get_required_comparison(&self) -> Option<(&ItemField, &RegexWithCaptures)> {
+        match self {
+            Filter::Group(expr) => expr.get_required_comparison(),
+            Filter::Comparison(field, rewc) => Some((field, rewc)),
+            Filter::BinaryExpression(left, BinaryOperator::And, _) => left.get_required_comparison(),
+            _ => None,
+        }
+    }
+
     pub fn filter(&self, provider: &ValueProvider, processor: &mut dyn ValueProcessor) -> bool {
         match self {
             Filter::Comparison(field, rewc) => {
                 let value = provider.call(field);
-                let is_match = rewc.re.is_match(value.as_str());
+                let is_match = rewc.re.is_match(&value);
                 if is_match {
                     if log_enabled!(Level::Debug) {
                         debug!("Match found: {:?} {} => {}={}", &rewc, &rewc.restr, &field, &value);
@@ -136,6 +269,26 @@ impl Filter {
                 }
                 is_match
             }
+            Filter::Membership(field, op, set) => {
+                let value = provider.call(field);
+                let found = set.regexps.iter().find(|rewc| rewc.re.is_match(&value));
+                match op {
+                    SetOperator::In => match found {
+                        Some(rewc) => {
+                            processor.process(field, &value, rewc);
+                            true
+                        }
+                        None => false,
+                    },
+                    SetOperator::NotIn => found.is_none(),
+                }
+            }
+            Filter::PropertyMatch(name, rewc) => {
+                provider.get_property(name).is_some_and(|value| rewc.re.is_match(value.as_str()))
+            }
+            Filter::PropertyComparison(name, op, compare_value) => {
+                provider.get_property(name).is_some_and(|value| compare_value.compare(value.as_str(), op))
+            }
             Filter::Group(expr) => {
                 expr.filter(provider, processor)
             }
@@ -162,6 +315,15 @@ impl std::fmt::Display for Filter {
             Filter::Comparison(field, rewc) => {
                 write!(f, "{} ~ \"{}\"", field, String::from(&rewc.restr))
             }
+            Filter::Membership(field, op, set) => {
+                write!(f, "{} {} {}", field, op, set.source)
+            }
+            Filter::PropertyMatch(name, rewc) => {
+                write!(f, "Prop({}) ~ \"{}\"", name, String::from(&rewc.restr))
+            }
+            Filter::PropertyComparison(name, op, value) => {
+                write!(f, "Prop({}) {} {}", name, op, value)
+            }
             Filter::Group(stmt) => {
                 write!(f, "({})", stmt)
             }
@@ -190,34 +352,79 @@ fn get_parser_item_field(expr: Pair<Rule>) -> Result<ItemField, M3uFilterError>
     create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "unknown field: {}", expr.as_str())
 }
 
+fn create_regexp(text: &str, templates: &Vec<PatternTemplate>) -> Result<RegexWithCaptures, M3uFilterError> {
+    let mut regstr = String::from(text);
+    for t in templates {
+        regstr = regstr.replace(format!("!{}!", &t.name).as_str(), &t.value);
+    }
+    let re = regex::Regex::new(regstr.as_str());
+    if re.is_err() {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant parse regex: {}", regstr);
+    }
+    let regexp = re.unwrap();
+    let captures = regexp.capture_names()
+        .flatten().map(String::from).filter(|x| !x.is_empty()).collect::<Vec<String>>();
+    if log_enabled!(Level::Debug) {
+        debug!("Created regex: {} with captures: [{}]", regstr, captures.join(", "));
+    }
+    Ok(RegexWithCaptures {
+        restr: regstr,
+        re: regexp,
+        captures,
+    })
+}
+
 fn get_parser_regexp(expr: Pair<Rule>, templates: &Vec<PatternTemplate>) -> Result<RegexWithCaptures, M3uFilterError> {
     if expr.as_rule() == Rule::regexp {
         let mut parsed_text = String::from(expr.as_str());
         parsed_text.pop();
         parsed_text.remove(0);
-        let mut regstr = String::from(parsed_text.as_str());
-        for t in templates {
-            regstr = regstr.replace(format!("!{}!", &t.name).as_str(), &t.value);
-        }
-        let re = regex::Regex::new(regstr.as_str());
-        if re.is_err() {
-            return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant parse regex: {}", regstr);
-        }
-        let regexp = re.unwrap();
-        let captures = regexp.capture_names()
-            .flatten().map(String::from).filter(|x| !x.is_empty()).collect::<Vec<String>>();
-        if log_enabled!(Level::Debug) {
-            debug!("Created regex: {} with captures: [{}]", regstr, captures.join(", "));
-        }
-        return Ok(RegexWithCaptures {
-            restr: regstr,
-            re: regexp,
-            captures,
-        });
+        return create_regexp(&parsed_text, templates);
     }
     create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "unknown field: {}", expr.as_str())
 }
 
+fn get_parser_template<'a>(expr: Pair<Rule>, templates: &'a [PatternTemplate]) -> Result<&'a PatternTemplate, M3uFilterError> {
+    let name = expr.into_inner().next().unwrap().as_str();
+    match templates.iter().find(|t| t.name.eq(name)) {
+        Some(template) => Ok(template),
+        None => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "unknown template: {}", name),
+    }
+}
+
+fn get_parser_membership(expr: Pair<Rule>, templates: &Vec<PatternTemplate>) -> Result<Filter, M3uFilterError> {
+    let mut expr_inner = expr.into_inner();
+    let field = get_parser_item_field(expr_inner.next().unwrap())?;
+    let op = if expr_inner.next().unwrap().as_rule() == Rule::not_in { SetOperator::NotIn } else { SetOperator::In };
+    let list = expr_inner.next().unwrap();
+    let source = list.as_str().to_string();
+    let regexps = match list.as_rule() {
+        Rule::template_ref => {
+            let template = get_parser_template(list, templates)?;
+            match &template.values {
+                Some(values) => values.iter().map(|value| create_regexp(value, templates)).collect::<Result<Vec<_>, _>>()?,
+                None => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "template {} has no values", template.name),
+            }
+        }
+        _ => list.into_inner().map(|regexp| get_parser_regexp(regexp, templates)).collect::<Result<Vec<_>, _>>()?,
+    };
+    Ok(Filter::Membership(field, op, FilterSet { source, regexps }))
+}
+
+/// The expression of the template is parsed as a group, `NOT template!{name}` negates the whole expression.
+fn get_parser_template_expression(expr: Pair<Rule>, templates: &Vec<PatternTemplate>, expanding: &[&str]) -> Result<Filter, M3uFilterError> {
+    let template = get_parser_template(expr, templates)?;
+    if expanding.contains(&template.name.as_str()) {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cyclic template reference: {}", template.name);
+    }
+    if template.values.is_some() {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "list template {} can only be used with IN", template.name);
+    }
+    let mut template_expanding = expanding.to_vec();
+    template_expanding.push(&template.name);
+    Ok(Filter::Group(Box::new(parse_filter(&template.value, templates, &template_expanding)?)))
+}
+
 fn get_parser_comparison(expr: Pair<Rule>, templates: &Vec<PatternTemplate>) -> Result<Filter, M3uFilterError> {
     let mut expr_inner = expr.into_inner();
     match get_parser_item_field(expr_inner.next().unwrap()) {
@@ -231,14 +438,56 @@ fn get_parser_comparison(expr: Pair<Rule>, templates: &Vec<PatternTemplate>) ->
     }
 }
 
+fn get_parser_compare_op(expr: Pair<Rule>) -> Result<CompareOperator, M3uFilterError> {
+    match expr.as_str() {
+        "=" => Ok(CompareOperator::Eq),
+        "!=" => Ok(CompareOperator::NotEq),
+        ">" => Ok(CompareOperator::Greater),
+        ">=" => Ok(CompareOperator::GreaterEq),
+        "<" => Ok(CompareOperator::Less),
+        "<=" => Ok(CompareOperator::LessEq),
+        _ => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Unknown compare operator {}", expr.as_str())
+    }
+}
+
+fn get_parser_compare_value(expr: Pair<Rule>) -> Result<CompareValue, M3uFilterError> {
+    let text = expr.as_str();
+    match expr.as_rule() {
+        Rule::number => match text.parse::<f64>() {
+            Ok(num) => Ok(CompareValue::Number(text.to_string(), num)),
+            Err(_) => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant parse number: {}", text)
+        },
+        Rule::regexp => Ok(CompareValue::Text(String::from(&text[1..text.len() - 1]))),
+        _ => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "unknown value: {}", text)
+    }
+}
+
+fn get_parser_prop_comparison(expr: Pair<Rule>, templates: &Vec<PatternTemplate>) -> Result<Filter, M3uFilterError> {
+    let mut expr_inner = expr.into_inner();
+    let name = expr_inner.next().unwrap().as_str().to_string();
+    let operation = expr_inner.next().unwrap();
+    match operation.as_rule() {
+        Rule::prop_match => {
+            let regexp = get_parser_regexp(operation.into_inner().next().unwrap(), templates)?;
+            Ok(Filter::PropertyMatch(name, regexp))
+        }
+        _ => {
+            let mut compare_inner = operation.into_inner();
+            let op = get_parser_compare_op(compare_inner.next().unwrap())?;
+            let value = get_parser_compare_value(compare_inner.next().unwrap())?;
+            Ok(Filter::PropertyComparison(name, op, value))
+        }
+    }
+}
+
 macro_rules! handle_expr {
     ($bop: expr, $uop: expr, $stmts: expr, $exp: expr) => {
         {
-            let result = match $bop {
-                Some(binop) => {
-                    let lhs = $stmts.pop().unwrap();
-                    $bop = None;
-                    Filter::BinaryExpression(Box::new(lhs), binop.clone(), Box::new($exp))
+            let result = match $bop.take() {
+                // without lhs the parsing of it failed and the error is already reported
+                Some(binop) => match $stmts.pop() {
+                    Some(lhs) => Filter::BinaryExpression(Box::new(lhs), binop, Box::new($exp)),
+                    None => $exp
                 },
                 _ => match $uop {
                     Some(unop) => {
@@ -253,7 +502,8 @@ macro_rules! handle_expr {
     }
 }
 
-fn get_parser_expression(expr: Pair<Rule>, templates: &Vec<PatternTemplate>, errors: &mut Vec<String>) -> Filter {
+/// Returns `None` if a part of the expression could not be parsed, the errors are added to `errors`.
+fn get_parser_expression(expr: Pair<Rule>, templates: &Vec<PatternTemplate>, expanding: &[&str], errors: &mut Vec<String>) -> Option<Filter> {
     let mut stmts = Vec::new();
     let pairs = expr.into_inner();
     let mut bop: Option<BinaryOperator> = None;
@@ -268,11 +518,33 @@ fn get_parser_expression(expr: Pair<Rule>, templates: &Vec<PatternTemplate>, err
                     Err(err) => errors.push(err.to_string()),
                 }
             }
+            Rule::membership => {
+                match get_parser_membership(pair, templates) {
+                    Ok(comp) => handle_expr!(bop, uop, stmts, comp),
+                    Err(err) => errors.push(err.to_string()),
+                }
+            }
+            Rule::prop_comparison => {
+                match get_parser_prop_comparison(pair, templates) {
+                    Ok(comp) => handle_expr!(bop, uop, stmts, comp),
+                    Err(err) => errors.push(err.to_string()),
+                }
+            }
+            Rule::template_ref => {
+                match get_parser_template_expression(pair, templates, expanding) {
+                    Ok(comp) => handle_expr!(bop, uop, stmts, comp),
+                    Err(err) => errors.push(err.to_string()),
+                }
+            }
             Rule::expr => {
-                handle_expr!(bop, uop, stmts, get_parser_expression(pair, templates, errors));
+                if let Some(exp) = get_parser_expression(pair, templates, expanding, errors) {
+                    handle_expr!(bop, uop, stmts, exp);
+                }
             }
             Rule::expr_group => {
-                handle_expr!(bop, uop, stmts, Filter::Group(Box::new(get_parser_expression(pair.into_inner().next().unwrap(), templates, errors))));
+                if let Some(exp) = get_parser_expression(pair.into_inner().next().unwrap(), templates, expanding, errors) {
+                    handle_expr!(bop, uop, stmts, Filter::Group(Box::new(exp)));
+                }
             }
             Rule::not => {
                 uop = Some(UnaryOperator::Not);
@@ -292,10 +564,10 @@ fn get_parser_expression(expr: Pair<Rule>, templates: &Vec<PatternTemplate>, err
             }
         }
     }
-    if stmts.is_empty() || stmts.len() > 1 {
+    if stmts.len() > 1 {
         errors.push(format!("did not expect multiple rule: {:?}", stmts));
     }
-    stmts.pop().unwrap()
+    stmts.pop()
 }
 
 fn get_parser_binary_op(expr: Pair<Rule>) -> Result<BinaryOperator, M3uFilterError> {
@@ -308,7 +580,11 @@ fn get_parser_binary_op(expr: Pair<Rule>) -> Result<BinaryOperator, M3uFilterErr
 
 pub(crate) fn get_filter(filter_text: &str, templates: Option<&Vec<PatternTemplate>>) -> Result<Filter, M3uFilterError> {
     let empty_list = Vec::new();
-    let template_list: &Vec<PatternTemplate> = templates.unwrap_or(&empty_list);
+    parse_filter(filter_text, templates.unwrap_or(&empty_list), &[])
+}
+
+/// `expanding` holds the names of the templates referenced with `template!{name}` which are parsed at the moment.
+fn parse_filter(filter_text: &str, template_list: &Vec<PatternTemplate>, expanding: &[&str]) -> Result<Filter, M3uFilterError> {
     let mut source = String::from(filter_text);
     for t in template_list {
         source = source.replace(format!("!{}!", &t.name).as_str(), &t.value);
@@ -325,13 +601,14 @@ pub(crate) fn get_filter(filter_text: &str, templates: Option<&Vec<PatternTempla
                         for expr in pair.into_inner() {
                             match expr.as_rule() {
                                 Rule::expr => {
-                                    let expr = get_parser_expression(expr, template_list, &mut errors);
-                                    match &op {
-                                        Some(binop) => {
-                                            result = Some(Filter::BinaryExpression(Box::new(result.unwrap()), binop.clone(), Box::new(expr)));
-                                            op = None;
+                                    if let Some(expr) = get_parser_expression(expr, template_list, expanding, &mut errors) {
+                                        match (&op, result.take()) {
+                                            (Some(binop), Some(lhs)) => {
+                                                result = Some(Filter::BinaryExpression(Box::new(lhs), binop.clone(), Box::new(expr)));
+                                                op = None;
+                                            }
+                                            _ => result = Some(expr)
                                         }
-                                        _ => result = Some(expr)
                                     }
                                 }
                                 Rule::bool_op => {
@@ -376,7 +653,8 @@ pub(crate) fn get_filter(filter_text: &str, templates: Option<&Vec<PatternTempla
 type GraphDependency<'a> = (DiGraph<String, ()>, HashMap<usize, String>, HashMap<&'a String, Vec<String>>, bool);
 
 fn build_dependency_graph(templates: &Vec<PatternTemplate>) -> GraphDependency {
-    let regex = regex::Regex::new("!(.*?)!").unwrap();
+    // `template!{name}` references are resolved by the parser and are no dependencies
+    let regex = regex::Regex::new("!([^!{]*?)!").unwrap();
     let mut graph = DiGraph::new();
     let mut node_ids = HashMap::new();
     let mut node_names = HashMap::new();
@@ -3,16 +3,18 @@ extern crate pest;
 #[macro_use]
 extern crate pest_derive;
 
+use std::path::Path;
 use std::sync::Arc;
 use actix_rt::System;
 
-use clap::Parser;
-use env_logger::Builder;
-use log::{error, info, LevelFilter};
+use clap::{Parser, Subcommand};
+use log::{error, info, warn};
 
-use crate::model::config::{Config, ProcessTargets, validate_targets};
-use crate::processing::playlist_processor;
-use crate::utils::{config_reader, file_utils};
+use crate::m3u_filter_error::M3uFilterError;
+use crate::model::config::{Config, ConfigInput, ConfigTenant, ProcessTargets, validate_targets};
+use crate::processing::{filter_test, group_selection, mapping_test, playlist_processor};
+use crate::utils::{config_reader, config_validator, daemon, download, file_utils, input_health, logger};
+use crate::utils::secret_store::SecretStore;
 
 mod m3u_filter_error;
 mod model;
@@ -32,61 +34,160 @@ mod utils;
 struct Args {
 
     /// The config directory
-    #[arg(short = 'p', long = "config-path")]
+    #[arg(short = 'p', long = "config-path", global = true)]
     config_path: Option<String>,
 
     /// The config file
-    #[arg(short = 'c', long = "config")]
+    #[arg(short = 'c', long = "config", global = true)]
     config_file: Option<String>,
 
     /// The source config file
-    #[arg(short = 'i', long = "source")]
+    #[arg(short = 'i', long = "source", global = true)]
     source_file: Option<String>,
 
     /// The mapping file
-    #[arg(short = 'm', long = "mapping")]
+    #[arg(short = 'm', long = "mapping", global = true)]
     mapping_file: Option<String>,
 
 
     /// The target to process
-    #[arg(short = 't', long)]
+    #[arg(short = 't', long, global = true)]
     target: Option<Vec<String>>,
 
     /// The user file
-    #[arg(short = 'a', long = "api-proxy")]
+    #[arg(short = 'a', long = "api-proxy", global = true)]
     api_proxy: Option<String>,
 
-    /// Run in server mode
+    /// Run in server mode, same as the `serve` command
     #[arg(short = 's', long, default_value_t = false, default_missing_value = "true")]
     server: bool,
 
-    /// log level
-    #[arg(short = 'l', long = "log-level", default_missing_value = "info")]
+    /// Run the server in the background, unix only
+    #[arg(long, default_value_t = false, global = true)]
+    daemon: bool,
+
+    /// Write the process id of the server into the file, it is removed when the server stops
+    #[arg(long = "pid-file", global = true)]
+    pid_file: Option<String>,
+
+    /// Check the config and sources file, print all problems with their location and exit
+    #[arg(long = "check-config", default_value_t = false)]
+    check_config: bool,
+
+    /// log level, can contain levels for modules like `info,hyper=warn`
+    #[arg(short = 'l', long = "log-level", default_missing_value = "info", global = true)]
     log_level: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Process the targets once, this is the default
+    Run,
+    /// Run in server mode
+    Serve,
+    /// Validate the config, mappings and api-proxy config and print the targets
+    Check,
+    /// Process the targets given with `--target` without writing anything and print the channel count of each group
+    DryRun,
+    /// Evaluate a filter against a playlist file or url and print the matching and not matching channels
+    TestFilter {
+        #[arg(long)]
+        filter: String,
+        /// m3u playlist file or url
+        #[arg(long)]
+        input: String,
+        /// Number of channels printed for each result
+        #[arg(long, default_value_t = 10)]
+        samples: usize,
+    },
+    /// Apply the mappings of the `--mapping` file to a playlist file or url and print the changed values of each channel
+    TestMapping {
+        /// m3u playlist file or url
+        #[arg(long)]
+        input: String,
+        /// Mapping ids to apply in the given order, default are all mappings of the file
+        #[arg(long = "id")]
+        mapping_ids: Vec<String>,
+    },
+    /// List the groups of an input with their channel count and print a filter for the selected groups
+    ///
+    /// Without `--select` the groups are selected by their numbers read from stdin.
+    Groups {
+        /// id or name of a configured input, or a m3u playlist file or url
+        #[arg(long)]
+        input: String,
+        /// Group names or globs with `*` and `?` to select
+        #[arg(long)]
+        select: Vec<String>,
+        /// Print an `include_groups` block instead of a filter
+        #[arg(long)]
+        include_groups: bool,
+    },
+    /// Manage the encrypted secrets referenced with `!secret <name>`
+    Secret {
+        #[command(subcommand)]
+        action: SecretAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum SecretAction {
+    /// Create the secret key file
+    Init,
+    /// Add or replace a secret, the value is read from stdin if not given
+    Set {
+        name: String,
+        #[arg(long)]
+        value: Option<String>,
+    },
+    /// Remove a secret
+    Remove {
+        name: String,
+    },
+    /// List the secret names
+    List,
+    /// Encrypt all secrets with a new key
+    Rotate,
 }
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 fn main() {
     let args = Args::parse();
-    init_logger(args.log_level.as_ref().unwrap_or(&"info".to_string()));
+    logger::init_logger(args.log_level.as_deref());
 
     let config_path: String = args.config_path.unwrap_or(file_utils::get_default_config_path());
     let config_file: String = args.config_file.unwrap_or(file_utils::get_default_config_file_path(&config_path));
     let sources_file: String = args.source_file.unwrap_or(file_utils::get_default_sources_file_path(&config_path));
 
+    if let Some(Command::Secret { action }) = args.command {
+        exec_secret_action(&config_path, action).unwrap_or_else(|err| exit!("{}", err));
+        return;
+    }
+    if args.check_config {
+        exec_check_config(&config_path, &config_file, &sources_file, args.mapping_file.as_deref(), args.api_proxy.as_deref());
+    }
+    let command = args.command.unwrap_or(if args.server { Command::Serve } else { Command::Run });
+    if matches!(command, Command::DryRun) && args.target.is_none() {
+        exit!("dry-run needs at least one target, use --target");
+    }
 
-    let mut cfg = config_reader::read_config(config_path.as_str(), config_file.as_str(), sources_file.as_str()).unwrap_or_else(|err| exit!("{}", err));
+    let mut cfg = config_reader::read_config(config_path.as_str(), config_file.as_str(), sources_file.as_str()).unwrap_or_else(|err| {
+        // the problems have the location in the files, the error of the reader only the first message
+        config_validator::check_config_files(&config_path, &config_file, &sources_file, None, None).iter()
+            .filter(|problem| problem.message != err.message)
+            .for_each(|problem| error!("{}", problem));
+        exit!("{}", err)
+    });
 
-    // this does not work
-    // if args.log_level.is_none() {
-    //      if let Some(log_level) =  &cfg.log_level {
-    //          info!("Setting log level to: {}", log_level.as_str());
-    //          log::set_max_level(get_log_level(log_level.as_str()));
-    //      }
-    // }
+    if let Some(log_config) = &cfg.log {
+        logger::configure_logger(args.log_level.as_deref(), log_config);
+    }
 
-    let targets = validate_targets(&args.target, &cfg.sources).unwrap_or_else(|err| exit!("{}", err));
+    let mut targets = validate_targets(&args.target, &cfg.sources).unwrap_or_else(|err| exit!("{}", err));
 
     info!("Version: {}", VERSION);
     info!("Current time: {}", chrono::offset::Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
@@ -99,43 +200,242 @@ fn main() {
         exit!("{}", err);
     }
 
-    if args.server {
-        config_reader::read_api_proxy_config(args.api_proxy, &mut cfg);
-        start_in_server_mode(Arc::new(cfg), Arc::new(targets));
-    } else {
-        start_in_cli_mode(Arc::new(cfg), Arc::new(targets))
+    match command {
+        Command::Serve => {
+            config_reader::read_api_proxy_config(args.api_proxy, &mut cfg);
+            let tenants = read_tenants(&cfg);
+            if args.daemon {
+                if cfg.log.as_ref().is_none_or(|log_config| log_config.file.is_none()) {
+                    warn!("Daemon mode without log file, the log is discarded");
+                }
+                daemon::daemonize().unwrap_or_else(|err| exit!("{}", err));
+            }
+            // the pid file is removed when the server stops
+            let _pid_file = args.pid_file.map(|path| daemon::PidFile::create(Path::new(&path)).unwrap_or_else(|err| exit!("{}", err)));
+            start_in_server_mode(Arc::new(cfg), Arc::new(targets), tenants);
+        }
+        Command::Check => exec_check(cfg, &targets, args.api_proxy),
+        Command::DryRun => {
+            targets.dry_run = true;
+            start_dry_run(Arc::new(cfg), Arc::new(targets));
+        }
+        Command::TestFilter { filter, input, samples } => exec_test_filter(&cfg, &filter, &input, samples),
+        Command::TestMapping { input, mapping_ids } => exec_test_mapping(&cfg, &input, &mapping_ids),
+        Command::Groups { input, select, include_groups } => exec_groups(&cfg, &input, &select, include_groups),
+        _ => start_in_cli_mode(Arc::new(cfg), Arc::new(targets)),
     }
 }
 
-fn start_in_cli_mode(cfg: Arc<Config>, targets: Arc<ProcessTargets>) {
-    System::new().block_on(async { playlist_processor::exec_processing(cfg, targets).await });
+/// Reads the configs of the tenants, each tenant needs its own working dir.
+fn read_tenants(cfg: &Config) -> Vec<(ConfigTenant, Config)> {
+    let mut working_dirs = vec![cfg.working_dir.to_string()];
+    cfg.tenants.iter().flatten().map(|tenant| {
+        let tenant_cfg = config_reader::read_tenant_config(tenant).unwrap_or_else(|err| exit!("{}", err));
+        if working_dirs.contains(&tenant_cfg.working_dir) {
+            exit!("tenant {} needs its own working_dir: {}", &tenant.name, &tenant_cfg.working_dir);
+        }
+        working_dirs.push(tenant_cfg.working_dir.to_string());
+        (tenant.clone(), tenant_cfg)
+    }).collect()
 }
 
-fn start_in_server_mode(cfg: Arc<Config>, targets: Arc<ProcessTargets>) {
-    info!("Web root: {}", &cfg.api.web_root);
-    info!("Server running: http://{}:{}", &cfg.api.host, &cfg.api.port);
-    match api::main_api::start_server(cfg, targets) {
-        Ok(_) => {}
-        Err(e) => {
-            exit!("Can't start server: {}", e);
+/// Prints all problems of the config files and exits, the exit code is `1` if there are problems.
+fn exec_check_config(config_path: &str, config_file: &str, sources_file: &str, mapping_file: Option<&str>, api_proxy_file: Option<&str>) {
+    let problems = config_validator::check_config_files(config_path, config_file, sources_file, mapping_file, api_proxy_file);
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        println!("Config is valid");
+        std::process::exit(0);
+    }
+    println!("{} {} found", problems.len(), if problems.len() == 1 { "problem" } else { "problems" });
+    std::process::exit(1);
+}
+
+/// The config and the mappings are already validated when they are read,
+/// an api-proxy config is optional but has to be valid if it exists.
+fn exec_check(mut cfg: Config, targets: &ProcessTargets, api_proxy_file: Option<String>) {
+    let api_proxy_file = api_proxy_file.unwrap_or(file_utils::get_default_api_proxy_config_path(cfg._config_path.as_str()));
+    if Path::new(&api_proxy_file).exists() {
+        config_reader::read_api_proxy_config(Some(api_proxy_file.to_string()), &mut cfg);
+        if cfg._api_proxy.read().unwrap().is_none() {
+            exit!("Invalid api-proxy config: {}", api_proxy_file);
+        }
+    }
+    for source in &cfg.sources {
+        let inputs: Vec<String> = source.inputs.iter()
+            .map(|input| format!("{} ({})", input.name.as_ref().map_or_else(|| input.id.to_string(), |name| name.to_string()), input.input_type.to_string()))
+            .collect();
+        for target in source.targets.iter().filter(|target| !targets.enabled || targets.has_target(target.id)) {
+            let outputs: Vec<String> = target.output.iter()
+                .map(|output| output.filename.as_ref().map_or_else(|| output.target.to_string(), |filename| format!("{} {}", output.target, filename)))
+                .collect();
+            println!("Target {}{}", &target.name, if target.enabled { "" } else { " (disabled)" });
+            println!("  inputs: {}", inputs.join(", "));
+            println!("  output: {}", outputs.join(", "));
+            if let Some(output_dir) = target.get_output_dir(&cfg) {
+                println!("  output dir: {}", output_dir.to_string_lossy());
+            }
+            println!("  processing order: {}", target.processing_order);
+            println!("  filter: {}", target._filter.as_ref().map_or_else(|| target.filter.to_string(), |filter| filter.to_string()));
+            if let Some(mapping) = &target.mapping {
+                println!("  mapping: {}", mapping.join(", "));
+            }
+            if let Some(condition) = &target.condition {
+                let mut conditions = vec![];
+                if !condition.time.is_empty() { conditions.push(format!("time {}", condition.time.join(", "))); }
+                if !condition.inputs_up.is_empty() { conditions.push(format!("up {}", condition.inputs_up.join(", "))); }
+                if !condition.inputs_down.is_empty() { conditions.push(format!("down {}", condition.inputs_down.join(", "))); }
+                println!("  condition: {}", conditions.join(", "));
+            }
+        }
+    }
+    for (tenant, tenant_cfg) in read_tenants(&cfg) {
+        let target_names: Vec<&str> = tenant_cfg.sources.iter().flat_map(|source| &source.targets).map(|target| target.name.as_str()).collect();
+        println!("Tenant {}", &tenant.name);
+        println!("  config: {}", &tenant.config_path);
+        println!("  targets: {}", target_names.join(", "));
+    }
+    info!("Config is valid");
+}
+
+fn exec_secret_action(config_path: &str, action: SecretAction) -> Result<(), M3uFilterError> {
+    match action {
+        SecretAction::Init => {
+            if SecretStore::init(config_path)? {
+                info!("Secret key created in {}", config_path);
+            } else {
+                info!("Secret key exists");
+            }
+        }
+        SecretAction::Set { name, value } => {
+            let mut store = SecretStore::open(config_path)?;
+            let value = match value {
+                Some(value) => value,
+                None => {
+                    let mut line = String::new();
+                    if let Err(err) = std::io::stdin().read_line(&mut line) {
+                        exit!("cant read secret from stdin: {}", err);
+                    }
+                    line.trim_end_matches(['\r', '\n']).to_string()
+                }
+            };
+            store.set(&name, &value)?;
+            store.save()?;
+            info!("Secret {} saved", name);
         }
+        SecretAction::Remove { name } => {
+            let mut store = SecretStore::open(config_path)?;
+            if store.remove(&name) {
+                store.save()?;
+                info!("Secret {} removed", name);
+            } else {
+                info!("Secret {} not found", name);
+            }
+        }
+        SecretAction::List => SecretStore::open(config_path)?.names().iter().for_each(|name| println!("{}", name)),
+        SecretAction::Rotate => {
+            match SecretStore::open(config_path)?.rotate()? {
+                Some(key) => {
+                    info!("Secrets encrypted with a new key, replace the key in the environment");
+                    println!("{}", key);
+                }
+                None => info!("Secrets encrypted with a new key"),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn start_in_cli_mode(cfg: Arc<Config>, targets: Arc<ProcessTargets>) {
+    System::new().block_on(async { playlist_processor::exec_processing(cfg, targets, None).await });
+}
+
+// relative inputs are otherwise resolved against the working dir
+fn create_test_input(input: &str) -> ConfigInput {
+    let input = match std::fs::canonicalize(input) {
+        Ok(path) => path.to_string_lossy().to_string(),
+        Err(_) => input.to_string(),
     };
+    download::create_config_input_for_url(&input)
+}
+
+fn exec_test_filter(cfg: &Config, filter: &str, input: &str, samples: usize) {
+    let config_input = create_test_input(input);
+    let result = System::new().block_on(async { filter_test::test_filter(cfg, filter, &config_input, samples).await })
+        .unwrap_or_else(|err| exit!("{}", err));
+    println!("Filter: {}", result.filter);
+    println!("Matched: {}", result.matched);
+    result.matched_samples.iter().for_each(|sample| println!("  {}: {}", sample.group, sample.title));
+    println!("Not matched: {}", result.not_matched);
+    result.not_matched_samples.iter().for_each(|sample| println!("  {}: {}", sample.group, sample.title));
+}
+
+fn exec_test_mapping(cfg: &Config, input: &str, mapping_ids: &[String]) {
+    let mappings = config_reader::read_mapping(&cfg._mapping_file_path)
+        .unwrap_or_else(|err| exit!("{}", err))
+        .unwrap_or_else(|| exit!("No mappings found in {}", cfg._mapping_file_path));
+    let config_input = create_test_input(input);
+    let result = System::new().block_on(async { mapping_test::test_mapping(cfg, &mappings, mapping_ids, &config_input).await })
+        .unwrap_or_else(|err| exit!("{}", err));
+    println!("Mappings: {}", result.mappings.join(", "));
+    for channel in &result.channels {
+        println!("{}: {}", channel.group, channel.title);
+        channel.matched.iter().for_each(|mapper| println!("  matched {}", mapper));
+        channel.changes.iter().for_each(|change| println!("  {}: \"{}\" -> \"{}\"", change.field, change.before, change.after));
+    }
+    println!("Affected: {} of {} channels", result.channels.len(), result.channel_count);
 }
 
-fn get_log_level(log_level: &str) -> LevelFilter {
-    match log_level.to_lowercase().as_str() {
-        "trace" => LevelFilter::Trace,
-        "debug" => LevelFilter::Debug,
-        "info" => LevelFilter::Info,
-        "warn" => LevelFilter::Warn,
-        "error" => LevelFilter::Error,
-        _ => LevelFilter::Info,
+fn exec_groups(cfg: &Config, input: &str, patterns: &[String], include_groups: bool) {
+    let config_input = input.parse::<u16>().ok().and_then(|input_id| cfg.get_input_by_id(&input_id))
+        .or_else(|| input_health::get_input_by_name(cfg, input).cloned())
+        .unwrap_or_else(|| create_test_input(input));
+    let groups = System::new().block_on(async { group_selection::get_group_counts(cfg, &config_input).await })
+        .unwrap_or_else(|err| exit!("{}", err));
+    let selected = if patterns.is_empty() {
+        for (index, group) in groups.iter().enumerate() {
+            println!("{:>4}  {} ({})", index + 1, group.title, group.channels);
+        }
+        eprint!("Select groups like 1,3-5, empty for none: ");
+        let mut selection = String::new();
+        std::io::stdin().read_line(&mut selection).unwrap_or_else(|err| exit!("{}", err));
+        group_selection::select_groups_by_number(&groups, &selection)
+    } else {
+        group_selection::select_groups_by_pattern(&groups, patterns)
+    }.unwrap_or_else(|err| exit!("{}", err));
+    if selected.is_empty() {
+        return;
     }
+    if include_groups {
+        print!("{}", group_selection::get_include_groups(&selected).unwrap_or_else(|err| exit!("{}", err)));
+    } else {
+        println!("{}", group_selection::get_filter_expression(&selected));
+    }
+}
+
+fn start_dry_run(cfg: Arc<Config>, targets: Arc<ProcessTargets>) {
+    let (_, errors) = System::new().block_on(async { playlist_processor::process_sources(cfg, targets, None).await });
+    errors.iter().for_each(|err| error!("{}", err.message));
 }
 
-fn init_logger(log_level: &str) {
-    let mut log_builder = Builder::from_default_env();
-    // Set the log level based on the parsed value
-    log_builder.filter_level(get_log_level(log_level));
-    log_builder.init();
+fn start_in_server_mode(cfg: Arc<Config>, targets: Arc<ProcessTargets>, tenants: Vec<(ConfigTenant, Config)>) {
+    info!("Web root: {}", if cfg.api.web_root.is_empty() { "embedded" } else { &cfg.api.web_root });
+    match cfg.api.tls.as_ref() {
+        Some(tls) => match tls.port {
+            Some(tls_port) => info!("Server running: http://{}:{} https://{}:{} ({})", &cfg.api.host, &cfg.api.port, &cfg.api.host, tls_port, cfg.api.scope),
+            None => info!("Server running: https://{}:{} ({})", &cfg.api.host, &cfg.api.port, cfg.api.scope),
+        },
+        None => info!("Server running: http://{}:{} ({})", &cfg.api.host, &cfg.api.port, cfg.api.scope),
+    }
+    for listen in &cfg.api.listen {
+        info!("Server running: {}://{} ({})", if listen.tls { "https" } else { "http" }, listen.get_address(), listen.scope);
+    }
+    match api::main_api::start_server(cfg, targets, tenants) {
+        Ok(_) => {}
+        Err(e) => {
+            exit!("Can't start server: {}", e);
+        }
+    };
 }
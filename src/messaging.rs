@@ -1,8 +1,9 @@
+use std::collections::HashMap;
 use log::{debug, error};
-use reqwest::header;
-use crate::model::config::{MessagingConfig};
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use crate::model::config::{MessagingConfig, NtfyMessagingConfig, WebhookMessagingConfig};
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, utoipa::ToSchema)]
 pub(crate) enum MsgKind {
     #[serde(rename = "info")]
     Info,
@@ -12,12 +13,93 @@ pub(crate) enum MsgKind {
     Error,
     #[serde(rename = "watch")]
     Watch,
+    #[serde(rename = "diff")]
+    Diff,
+    #[serde(rename = "account")]
+    Account,
+}
+
+impl std::fmt::Display for MsgKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            MsgKind::Info => "info",
+            MsgKind::Stats => "stats",
+            MsgKind::Error => "error",
+            MsgKind::Watch => "watch",
+            MsgKind::Diff => "diff",
+            MsgKind::Account => "account",
+        })
+    }
 }
 
 fn is_enabled(kind: &MsgKind, cfg: &MessagingConfig) -> bool {
     cfg.notify_on.contains(kind)
 }
 
+fn post_message(provider: &'static str, url: &str, headers: HeaderMap, body: String) {
+    let url = url.to_owned();
+    actix_rt::spawn(async move {
+        let client = reqwest::Client::new();
+        match client.post(&url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await {
+            Ok(response) if response.status().is_success() => debug!("Text message sent successfully to {}", provider),
+            Ok(response) => error!("Text message wasn't sent to {} because of: {}", provider, response.status()),
+            Err(e) => error!("Text message wasn't sent to {} because of: {}", provider, e)
+        }
+    });
+}
+
+fn get_json_headers(custom_headers: &HashMap<String, String>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    for (key, value) in custom_headers {
+        match (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(value)) {
+            (Ok(name), Ok(val)) => { headers.insert(name, val); }
+            _ => error!("Invalid webhook header {}", key),
+        }
+    }
+    headers
+}
+
+/// Escapes the text to be placed inside a json string.
+fn escape_json_text(text: &str) -> String {
+    let quoted = serde_json::Value::String(text.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+fn get_ntfy_url(ntfy: &NtfyMessagingConfig) -> String {
+    format!("{}/{}", ntfy.url.trim_end_matches('/'), ntfy.topic)
+}
+
+fn get_ntfy_headers(ntfy: &NtfyMessagingConfig, kind: &MsgKind) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Ok(title) = HeaderValue::from_str(&format!("m3u-filter {}", kind)) {
+        headers.insert("Title", title);
+    }
+    headers.insert("Tags", HeaderValue::from_static("tv"));
+    if let Some(priority) = ntfy.priority {
+        headers.insert("Priority", HeaderValue::from(u16::from(priority)));
+    }
+    if let Some(token) = &ntfy.token {
+        match HeaderValue::from_str(&format!("Bearer {}", token)) {
+            Ok(auth) => { headers.insert(header::AUTHORIZATION, auth); }
+            Err(_) => error!("Invalid ntfy token"),
+        }
+    }
+    headers
+}
+
+fn get_webhook_body(webhook: &WebhookMessagingConfig, kind: &MsgKind, msg: &str) -> String {
+    match &webhook.body {
+        Some(template) => template.replace("{message}", &escape_json_text(msg))
+            .replace("{kind}", &kind.to_string()),
+        None => serde_json::json!({"kind": kind.to_string(), "message": msg}).to_string(),
+    }
+}
+
 pub(crate) fn send_message(kind: &MsgKind, cfg: &Option<MessagingConfig>, msg: &str) {
     if let Some(messaging) = cfg {
         if is_enabled(kind, messaging) {
@@ -33,19 +115,33 @@ pub(crate) fn send_message(kind: &MsgKind, cfg: &Option<MessagingConfig>, msg: &
             }
 
             if let Some(rest) = &messaging.rest {
-                let url = rest.url.to_owned();
-                let data = msg.to_owned();
-                actix_rt::spawn(async move {
-                    let client = reqwest::Client::new();
-                    match client.post(&url)
-                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.to_string())
-                        .body(data)
-                        .send()
-                        .await {
-                        Ok(_) => debug!("Text message sent successfully to rest api"),
-                        Err(e) => error!("Text message wasn't sent to rest api because of: {}", e)
-                    }
+                post_message("rest api", &rest.url, get_json_headers(&HashMap::new()), msg.to_owned());
+            }
+
+            if let Some(discord) = &messaging.discord {
+                let body = serde_json::json!({"content": msg}).to_string();
+                post_message("discord", &discord.url, get_json_headers(&HashMap::new()), body);
+            }
+
+            if let Some(webhook) = &messaging.webhook {
+                post_message("webhook", &webhook.url, get_json_headers(&webhook.headers), get_webhook_body(webhook, kind, msg));
+            }
+
+            if let Some(pushover) = &messaging.pushover {
+                let mut body = serde_json::json!({
+                    "token": pushover.token,
+                    "user": pushover.user,
+                    "title": format!("m3u-filter {}", kind),
+                    "message": msg,
                 });
+                if let Some(priority) = pushover.priority {
+                    body["priority"] = serde_json::Value::from(priority);
+                }
+                post_message("pushover", &pushover.url, get_json_headers(&HashMap::new()), body.to_string());
+            }
+
+            if let Some(ntfy) = &messaging.ntfy {
+                post_message("ntfy", &get_ntfy_url(ntfy), get_ntfy_headers(ntfy, kind), msg.to_owned());
             }
         }
     }
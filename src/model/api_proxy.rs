@@ -1,12 +1,15 @@
 use std::collections::HashSet;
+use std::net::IpAddr;
 use std::str::FromStr;
 use enum_iterator::Sequence;
+use ipnet::IpNet;
 use crate::create_m3u_filter_error_result;
 
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::model_config::default_as_zero;
 
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Sequence, PartialEq)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Sequence, PartialEq, utoipa::ToSchema)]
 pub(crate) enum ProxyType {
     #[serde(rename = "reverse")]
     Reverse,
@@ -44,7 +47,7 @@ impl FromStr for ProxyType {
 }
 
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 pub(crate) struct UserCredentials {
     pub username: String,
     pub password: String,
@@ -52,6 +55,26 @@ pub(crate) struct UserCredentials {
     #[serde(default = "ProxyType::default")]
     pub proxy: ProxyType,
     pub server: Option<String>,
+    #[serde(default = "default_as_zero")]
+    pub max_connections: u8,
+    /// transfer rate of each stream of a `reverse` proxy user in kilobits per second
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bitrate_kbps: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<String>>,
+    /// networks like `192.168.1.0/24` or addresses the user can connect from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_networks: Option<Vec<String>>,
+    /// country codes like `DE`, looked up in the `geoip_db`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_countries: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub denied_countries: Option<Vec<String>>,
+}
+
+fn parse_network(network: &str) -> Option<IpNet> {
+    let network = network.trim();
+    network.parse::<IpNet>().ok().or_else(|| network.parse::<IpAddr>().ok().map(IpNet::from))
 }
 
 impl UserCredentials {
@@ -66,6 +89,35 @@ impl UserCredentials {
         self.username.eq(username) && self.password.eq(password)
     }
 
+    /// Returns true if the user has no group restriction or the group is part of the users bouquet.
+    pub fn has_group(&self, group: &str) -> bool {
+        match &self.groups {
+            None => true,
+            Some(groups) => groups.iter().any(|g| g.eq(group)),
+        }
+    }
+
+    pub fn has_client_restriction(&self) -> bool {
+        self.allowed_networks.is_some() || self.has_country_restriction()
+    }
+
+    pub fn has_country_restriction(&self) -> bool {
+        self.allowed_countries.is_some() || self.denied_countries.is_some()
+    }
+
+    pub fn is_allowed_network(&self, ip: &IpAddr) -> bool {
+        match &self.allowed_networks {
+            None => true,
+            Some(networks) => networks.iter().filter_map(|network| parse_network(network)).any(|network| network.contains(ip)),
+        }
+    }
+
+    /// A client without a known country is denied if `allowed_countries` is set.
+    pub fn is_allowed_country(&self, country: Option<&str>) -> bool {
+        let contains = |countries: &Vec<String>| country.is_some_and(|code| countries.iter().any(|c| c.eq_ignore_ascii_case(code)));
+        self.allowed_countries.as_ref().is_none_or(contains) && !self.denied_countries.as_ref().is_some_and(contains)
+    }
+
     pub fn trim(&mut self) {
         self.username = self.username.trim().to_string();
         self.password = self.password.trim().to_string();
@@ -78,7 +130,7 @@ impl UserCredentials {
     }
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 pub(crate) struct TargetUser {
     pub target: String,
     pub credentials: Vec<UserCredentials>,
@@ -99,7 +151,7 @@ fn default_as_443() -> String { "443".to_string() }
 
 fn default_as_1935() -> String { "1935".to_string() }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 pub(crate) struct ApiProxyServerInfo {
     pub name: String,
     pub protocol: String,
@@ -153,10 +205,13 @@ impl ApiProxyServerInfo {
     }
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 pub(crate) struct ApiProxyConfig {
     pub server: Vec<ApiProxyServerInfo>,
     pub user: Vec<TargetUser>,
+    /// MaxMind country or city database for the country restrictions of the users
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geoip_db: Option<String>,
     #[serde(skip_serializing, skip_deserializing)]
     pub _file_path: String,
 }
@@ -202,6 +257,14 @@ impl ApiProxyConfig {
                         errors.push(format!("No server info with name {} found for user {}", server_info_name, &user.username));
                     }
                 }
+                if let Some(networks) = &user.allowed_networks {
+                    for network in networks.iter().filter(|network| parse_network(network).is_none()) {
+                        errors.push(format!("Invalid network {} for user {}", network, &user.username));
+                    }
+                }
+                if user.has_country_restriction() && self.geoip_db.is_none() {
+                    errors.push(format!("Country restriction for user {} needs a geoip_db", &user.username));
+                }
             }
         }
         if errors.is_empty() {
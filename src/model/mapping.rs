@@ -1,16 +1,17 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::{Arc, OnceLock};
 
 use log::{debug, error};
-use regex::Regex;
+use regex::{Regex, RegexSet, SetMatches};
+use unidecode::unidecode;
 
 use crate::{handle_m3u_filter_error_result, valid_property};
-use crate::filter::{Filter, get_filter, PatternTemplate, prepare_templates, RegexWithCaptures, ValueProcessor};
+use crate::filter::{Filter, get_field_value, get_filter, PatternTemplate, prepare_templates, RegexWithCaptures, ValueProcessor};
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
 use crate::model::model_config::{AFFIX_FIELDS, default_as_empty_map, default_as_empty_str,
                                  default_as_false, ItemField, MAPPER_ATTRIBUTE_FIELDS, };
-use crate::model::model_playlist::{FieldAccessor, PlaylistItem};
+use crate::model::model_playlist::{FieldAccessor, PlaylistItem, PlaylistItemHeader};
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub(crate) struct MappingTag {
@@ -36,6 +37,13 @@ pub(crate) struct Mapper {
     prefix: HashMap<String, String>,
     #[serde(default = "default_as_empty_map")]
     assignments: HashMap<String, String>,
+    #[serde(default = "default_as_empty_map")]
+    m3u_attributes: HashMap<String, String>,
+    #[serde(default = "default_as_empty_map")]
+    headers: HashMap<String, String>,
+    /// rhai script or path of a `.rhai` file, run for each matching channel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    script: Option<String>,
     #[serde(skip_serializing, skip_deserializing)]
     pub(crate) _filter: Option<Filter>,
     #[serde(skip_serializing, skip_deserializing)]
@@ -46,6 +54,18 @@ pub(crate) struct Mapper {
     pub _tagre: Option<Regex>,
     #[serde(skip_serializing, skip_deserializing)]
     pub _attre: Option<Regex>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub(crate) _script: Option<rhai::AST>,
+}
+
+/// The engine limits the operations, a faulty script can't block the processing.
+fn script_engine() -> &'static rhai::Engine {
+    static ENGINE: OnceLock<rhai::Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut engine = rhai::Engine::new();
+        engine.set_max_operations(100_000);
+        engine
+    })
 }
 
 impl Mapper {
@@ -68,6 +88,18 @@ impl Mapper {
                 };
                 self._tagre = Some(Regex::new("<tag:(.*?)>").unwrap());
                 self._attre = Some(Regex::new("<(.*?)>").unwrap());
+                if let Some(script) = &self.script {
+                    let source = if script.trim_end().ends_with(".rhai") {
+                        std::fs::read_to_string(script.trim()).map_err(|err|
+                            M3uFilterError::new(M3uFilterErrorKind::Info, format!("Cant read mapping script {}: {}", script, err)))?
+                    } else {
+                        script.to_string()
+                    };
+                    match script_engine().compile(source) {
+                        Ok(ast) => self._script = Some(ast),
+                        Err(err) => return Err(M3uFilterError::new(M3uFilterErrorKind::Info, format!("Invalid mapping script: {}", err))),
+                    }
+                }
                 Ok(())
             }
             Err(err) => Err(err)
@@ -81,7 +113,7 @@ pub(crate) struct MappingValueProcessor<'a> {
 }
 
 impl MappingValueProcessor<'_> {
-    fn get_property(&self, key: &str) -> Option<Rc<String>> {
+    fn get_property(&self, key: &str) -> Option<Arc<str>> {
         self.pli.borrow().header.borrow().get_field(key)
     }
 
@@ -111,6 +143,72 @@ impl MappingValueProcessor<'_> {
         }
     }
 
+    fn apply_header_entries<F>(&mut self, captured_names: &HashMap<&str, &str>, values: &HashMap<String, String>, select: F)
+        where F: Fn(&mut PlaylistItemHeader) -> &mut Option<Vec<(String, String)>> {
+        if values.is_empty() {
+            return;
+        }
+        let attr_re = &self.mapper._attre.as_ref().unwrap();
+        let pli = self.pli.borrow();
+        let mut header = pli.header.borrow_mut();
+        let entries = select(&mut header).get_or_insert_with(Vec::new);
+        for (key, value) in values {
+            let entry_value = if value.contains('<') { // possible replacement
+                attr_re.replace_all(value, |captures: &regex::Captures| {
+                    let capture_name = &captures[1];
+                    captured_names.get(&capture_name).unwrap_or(&&captures[0]).to_string()
+                }).to_string()
+            } else {
+                value.to_string()
+            };
+            debug!("Header entry {} set to {}", key, entry_value);
+            match entries.iter_mut().find(|(name, _)| name.eq(key)) {
+                Some(entry) => entry.1 = entry_value,
+                None => entries.push((key.to_string(), entry_value)),
+            }
+        }
+    }
+
+    /// The header fields are script variables, changed values are written back to the channel.
+    fn apply_script(&mut self, captured_names: &HashMap<&str, &str>) {
+        let ast = match &self.mapper._script {
+            Some(ast) => ast,
+            None => return,
+        };
+        let mut scope = rhai::Scope::new();
+        let mut values = HashMap::new();
+        {
+            let pli = self.pli.borrow();
+            let header = pli.header.borrow();
+            for field in MAPPER_ATTRIBUTE_FIELDS {
+                if let Some(value) = header.get_field(field) {
+                    scope.push(*field, value.to_string());
+                    values.insert(*field, value);
+                }
+            }
+            let properties: rhai::Map = header.additional_properties.iter().flatten()
+                .map(|(key, value)| (key.into(), rhai::serde::to_dynamic(value).unwrap_or(rhai::Dynamic::UNIT)))
+                .collect();
+            scope.push_constant("properties", properties);
+        }
+        let captures: rhai::Map = captured_names.iter()
+            .map(|(key, value)| ((*key).into(), rhai::Dynamic::from(value.to_string()))).collect();
+        scope.push_constant("captures", captures);
+
+        match script_engine().run_ast_with_scope(&mut scope, ast) {
+            Ok(()) => {
+                for (field, old_value) in values {
+                    if let Some(new_value) = scope.get_value::<String>(field) {
+                        if new_value.ne(old_value.as_ref()) {
+                            self.set_property(field, &new_value);
+                        }
+                    }
+                }
+            }
+            Err(err) => error!("Mapping script failed: {}", err),
+        }
+    }
+
     fn apply_tags(&mut self, value: &String, captures: &HashMap<&str, &str>) -> Option<String> {
         let mut new_value = String::from(value);
         let tag_captures = self.mapper._tagre.as_ref().unwrap().captures_iter(value)
@@ -218,17 +316,292 @@ impl ValueProcessor for MappingValueProcessor<'_> {
         let _ = &MappingValueProcessor::<'_>::apply_suffix(self, &captured_values);
         let _ = &MappingValueProcessor::<'_>::apply_prefix(self, &captured_values);
         let _ = &MappingValueProcessor::<'_>::apply_assignments(self);
+        let mapper = self.mapper;
+        self.apply_header_entries(&captured_values, &mapper.m3u_attributes, |header| &mut header.m3u_attributes);
+        self.apply_header_entries(&captured_values, &mapper.headers, |header| &mut header.headers);
+        self.apply_script(&captured_values);
         true
     }
 }
 
 
+/// Moves all channels matching the filter into the group.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct GroupMapper {
+    pub filter: String,
+    pub group: String,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub(crate) _filter: Option<Filter>,
+}
+
+impl GroupMapper {
+    pub fn prepare(&mut self, templates: Option<&Vec<PatternTemplate>>) -> Result<(), M3uFilterError> {
+        if self.group.trim().is_empty() {
+            return Err(M3uFilterError::new(M3uFilterErrorKind::Info, format!("group is mandatory for group mapping: {}", self.filter)));
+        }
+        match get_filter(&self.filter, templates) {
+            Ok(filter) => {
+                self._filter = Some(filter);
+                Ok(())
+            }
+            Err(err) => Err(err)
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct LogoRule {
+    pub pattern: String,
+    /// file name of the icon, can contain the captures of the pattern like `<name>.png`
+    pub logo: String,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub(crate) _re: Option<Regex>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct LogoMapper {
+    /// local icon pack, only existing icons are assigned
+    pub directory: Option<String>,
+    /// base url the icons are served from
+    pub url: String,
+    /// replace the provider logos, otherwise only missing logos are assigned
+    #[serde(default = "default_as_false")]
+    pub replace: bool,
+    #[serde(default = "default_as_empty_logo_rules")]
+    pub rules: Vec<LogoRule>,
+    /// icon file names of the directory by their normalized name
+    #[serde(skip_serializing, skip_deserializing)]
+    pub(crate) _icons: Option<HashMap<String, String>>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub(crate) _attre: Option<Regex>,
+}
+
+fn default_as_empty_logo_rules() -> Vec<LogoRule> { vec![] }
+
+/// Icons are matched case insensitive and without whitespace and punctuation.
+pub(crate) fn normalize_logo_name(name: &str) -> String {
+    unidecode(name).chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase()
+}
+
+impl LogoMapper {
+    pub fn prepare(&mut self) -> Result<(), M3uFilterError> {
+        self._attre = Some(Regex::new("<(.*?)>").unwrap());
+        for rule in &mut self.rules {
+            match Regex::new(&rule.pattern) {
+                Ok(re) => rule._re = Some(re),
+                Err(err) => return Err(M3uFilterError::new(M3uFilterErrorKind::Info, format!("Invalid logo pattern {}: {}", &rule.pattern, err))),
+            }
+        }
+        if let Some(directory) = &self.directory {
+            match std::fs::read_dir(directory) {
+                Ok(entries) => {
+                    self._icons = Some(entries.filter_map(|entry| entry.ok())
+                        .filter(|entry| entry.path().is_file())
+                        .filter_map(|entry| {
+                            let path = entry.path();
+                            let stem = path.file_stem()?.to_str()?;
+                            Some((normalize_logo_name(stem), entry.file_name().to_str()?.to_string()))
+                        }).collect());
+                }
+                Err(err) => return Err(M3uFilterError::new(M3uFilterErrorKind::Info, format!("Cant read logo directory {}: {}", directory, err))),
+            }
+        }
+        Ok(())
+    }
+
+    fn get_logo_url(&self, file_name: &str) -> String {
+        format!("{}/{}", self.url.trim_end_matches('/'), file_name.replace(' ', "%20"))
+    }
+
+    fn find_icon(&self, file_name: &str) -> Option<String> {
+        match &self._icons {
+            None => Some(file_name.to_string()),
+            Some(icons) => {
+                let stem = file_name.rsplit_once('.').map_or(file_name, |(stem, _)| stem);
+                icons.get(&normalize_logo_name(stem)).cloned()
+            }
+        }
+    }
+
+    /// Returns the logo url for the channel name, the rules are checked first and then the icons of the directory.
+    pub fn get_logo(&self, channel_name: &str) -> Option<String> {
+        let attr_re = self._attre.as_ref().unwrap();
+        for rule in &self.rules {
+            if let Some(captures) = rule._re.as_ref().and_then(|re| re.captures(channel_name)) {
+                let file_name = attr_re.replace_all(&rule.logo, |caps: &regex::Captures| {
+                    captures.name(&caps[1]).map_or_else(|| caps[0].to_string(), |m| m.as_str().to_string())
+                });
+                if let Some(icon) = self.find_icon(&file_name) {
+                    return Some(self.get_logo_url(&icon));
+                }
+            }
+        }
+        self._icons.as_ref()
+            .and_then(|icons| icons.get(&normalize_logo_name(channel_name)))
+            .map(|icon| self.get_logo_url(icon))
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub(crate) enum CounterScope {
+    /// one counter for all matching channels of the playlist
+    #[serde(rename = "match")]
+    Match,
+    /// the counter starts again for each group
+    #[serde(rename = "group")]
+    Group,
+}
+
+fn default_as_counter_scope() -> CounterScope { CounterScope::Match }
+
+fn default_as_counter_start() -> u32 { 1 }
+
+/// Numbers the channels matching the filter, the `value` template is written into `field`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct MappingCounter {
+    pub filter: String,
+    pub field: String,
+    /// template with `<counter>` and the channel fields like `<name>`
+    pub value: String,
+    #[serde(default = "default_as_counter_scope")]
+    pub scope: CounterScope,
+    #[serde(default = "default_as_counter_start")]
+    pub start: u32,
+    /// the counter is padded with zeros to this length
+    #[serde(default)]
+    pub digits: usize,
+    /// numbers the channels in natural order of the field instead of the playlist order
+    #[serde(default = "default_as_false")]
+    pub sort: bool,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub(crate) _filter: Option<Filter>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub(crate) _attre: Option<Regex>,
+}
+
+impl MappingCounter {
+    pub fn prepare(&mut self, templates: Option<&Vec<PatternTemplate>>) -> Result<(), M3uFilterError> {
+        if !valid_property!(self.field.as_str(), MAPPER_ATTRIBUTE_FIELDS) {
+            return Err(M3uFilterError::new(M3uFilterErrorKind::Info, format!("Invalid counter field: {}", self.field)));
+        }
+        self._attre = Some(Regex::new("<(.*?)>").unwrap());
+        self._filter = Some(get_filter(&self.filter, templates)?);
+        Ok(())
+    }
+
+    /// Returns the value for the channel with the counter and the channel fields replaced.
+    pub fn get_value(&self, header: &PlaylistItemHeader, counter: u32) -> String {
+        let counter_value = format!("{:0width$}", counter, width = self.digits);
+        self._attre.as_ref().unwrap().replace_all(&self.value, |captures: &regex::Captures| {
+            match &captures[1] {
+                "counter" => counter_value.to_string(),
+                field => header.get_field(field).map_or_else(|| captures[0].to_string(), |value| value.to_string()),
+            }
+        }).to_string()
+    }
+}
+
+fn default_as_empty_mapper_list() -> Vec<Mapper> { vec![] }
+
+/// With fewer mappers trying each regex is faster than the regex set.
+const MAPPER_SET_MIN_SIZE: usize = 8;
+
+#[derive(Debug, Clone)]
+struct MapperFieldSet {
+    field: ItemField,
+    set: RegexSet,
+}
+
+/// The first comparison of each mapper, combined into one regex set per field.
+/// A mapper is only tried if the regex of its first comparison matches the channel,
+/// the mappers without such a comparison are always tried.
+#[derive(Debug, Clone)]
+pub(crate) struct MapperSet {
+    fields: Vec<MapperFieldSet>,
+    /// the field set and the regex in it for each mapper
+    mappers: Vec<Option<(usize, usize)>>,
+}
+
+impl MapperSet {
+    fn new(mappers: &[Mapper]) -> Result<Option<MapperSet>, M3uFilterError> {
+        let mut patterns: Vec<(ItemField, Vec<&str>)> = vec![];
+        let mut mapper_indexes = vec![];
+        for mapper in mappers {
+            // the filter is evaluated before the pattern
+            let comparison = mapper._filter.as_ref().or(mapper._pattern.as_ref())
+                .and_then(Filter::get_required_comparison);
+            mapper_indexes.push(comparison.map(|(field, rewc)| {
+                let field_index = match patterns.iter().position(|(set_field, _)| set_field == field) {
+                    Some(index) => index,
+                    None => {
+                        patterns.push((field.clone(), vec![]));
+                        patterns.len() - 1
+                    }
+                };
+                let regexps = &mut patterns[field_index].1;
+                regexps.push(rewc.restr.as_str());
+                (field_index, regexps.len() - 1)
+            }));
+        }
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+        let fields = patterns.into_iter().map(|(field, regexps)| match RegexSet::new(regexps) {
+            Ok(set) => Ok(MapperFieldSet { field, set }),
+            Err(err) => Err(M3uFilterError::new(M3uFilterErrorKind::Info, format!("Cant create regex set for the mappers: {}", err))),
+        }).collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(MapperSet { fields, mappers: mapper_indexes }))
+    }
+
+    pub(crate) fn matcher(&self) -> MapperSetMatcher<'_> {
+        MapperSetMatcher { mapper_set: self, matches: vec![None; self.fields.len()] }
+    }
+}
+
+/// Selects the mappers for one channel.
+pub(crate) struct MapperSetMatcher<'a> {
+    mapper_set: &'a MapperSet,
+    /// the matches of the field sets, computed again when a mapper changed the value of the field
+    matches: Vec<Option<(Arc<str>, SetMatches)>>,
+}
+
+impl MapperSetMatcher<'_> {
+    /// Returns `false` if the mapper with the index can't match the channel.
+    pub(crate) fn is_candidate(&mut self, channel: &PlaylistItem, index: usize) -> bool {
+        let (field_index, regex_index) = match self.mapper_set.mappers.get(index) {
+            Some(Some(indexes)) => *indexes,
+            _ => return true,
+        };
+        let field_set = &self.mapper_set.fields[field_index];
+        let value = get_field_value(channel, &field_set.field);
+        let cached = &mut self.matches[field_index];
+        if !cached.as_ref().is_some_and(|(cached_value, _)| Arc::ptr_eq(cached_value, &value)) {
+            let matches = field_set.set.matches(&value);
+            *cached = Some((value, matches));
+        }
+        cached.as_ref().is_some_and(|(_, matches)| matches.matched(regex_index))
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub(crate) struct Mapping {
     pub id: String,
     #[serde(default = "default_as_false")]
     pub match_as_ascii: bool,
+    #[serde(default = "default_as_empty_mapper_list")]
     pub mapper: Vec<Mapper>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<GroupMapper>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_order: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logos: Option<LogoMapper>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub counters: Option<Vec<MappingCounter>>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub _group_order: Option<Vec<Regex>>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub(crate) _mapper_set: Option<MapperSet>,
 }
 
 
@@ -238,8 +611,37 @@ impl Mapping {
         for mapper in &mut self.mapper {
             handle_m3u_filter_error_result!(M3uFilterErrorKind::Info, mapper.prepare(templates, tags));
         }
+        if self.mapper.len() >= MAPPER_SET_MIN_SIZE {
+            self._mapper_set = MapperSet::new(&self.mapper)?;
+        }
+        if let Some(groups) = &mut self.groups {
+            for group_mapper in groups {
+                handle_m3u_filter_error_result!(M3uFilterErrorKind::Info, group_mapper.prepare(templates));
+            }
+        }
+        if let Some(logos) = &mut self.logos {
+            handle_m3u_filter_error_result!(M3uFilterErrorKind::Info, logos.prepare());
+        }
+        if let Some(counters) = &mut self.counters {
+            for counter in counters {
+                handle_m3u_filter_error_result!(M3uFilterErrorKind::Info, counter.prepare(templates));
+            }
+        }
+        if let Some(group_order) = &self.group_order {
+            let regexps: Result<Vec<Regex>, _> = group_order.iter().map(|s| Regex::new(s)).collect();
+            match regexps {
+                Ok(group_order_re) => self._group_order = Some(group_order_re),
+                Err(err) => {
+                    return Err(M3uFilterError::new(M3uFilterErrorKind::Info, format!("Invalid group_order regular expression: {}", err)));
+                }
+            }
+        }
         Ok(())
     }
+
+    pub fn has_channel_mapping(&self) -> bool {
+        !self.mapper.is_empty() || self.groups.as_ref().is_some_and(|g| !g.is_empty()) || self.logos.is_some()
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -1,15 +1,18 @@
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::{Arc, OnceLock};
 use enum_iterator::Sequence;
 
 pub(crate) const MAPPER_ATTRIBUTE_FIELDS: &[&str] = &[
-    "name", "title", "group", "id", "logo",
+    "name", "title", "group", "id", "chno", "logo",
     "logo_small",
     "parent_code",
     "audio_track",
     "time_shift",
     "rec",
     "url",
+    "catchup",
+    "catchup_days",
+    "catchup_source",
 ];
 pub(crate) const AFFIX_FIELDS: &[&str] = &["name", "title", "group"];
 
@@ -26,14 +29,18 @@ pub(crate) fn default_as_true() -> bool { true }
 pub(crate) fn default_as_false() -> bool { false }
 
 pub(crate) fn default_as_empty_str() -> String { String::from("") }
-pub(crate) fn default_as_empty_rc_str() -> Rc<String> { Rc::new(String::from("")) }
+pub(crate) fn default_as_empty_rc_str() -> Arc<str> {
+    // most fields of a channel are empty, they share one string
+    static EMPTY: OnceLock<Arc<str>> = OnceLock::new();
+    Arc::clone(EMPTY.get_or_init(|| Arc::from("")))
+}
 
 pub(crate) fn default_as_empty_map() -> HashMap<String, String> { HashMap::new() }
 
 pub(crate) fn default_as_zero() -> u8 { 0 }
 
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Sequence, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Sequence, PartialEq, Eq, Hash, utoipa::ToSchema)]
 pub(crate) enum TargetType {
     #[serde(rename = "m3u")]
     M3u,
@@ -41,6 +48,12 @@ pub(crate) enum TargetType {
     Strm,
     #[serde(rename = "xtream")]
     Xtream,
+    #[serde(rename = "kodi")]
+    Kodi,
+    #[serde(rename = "json")]
+    Json,
+    #[serde(rename = "csv")]
+    Csv,
 }
 
 impl std::fmt::Display for TargetType {
@@ -49,11 +62,14 @@ impl std::fmt::Display for TargetType {
             TargetType::M3u => write!(f, "M3u"),
             TargetType::Strm => write!(f, "Strm"),
             TargetType::Xtream => write!(f, "Xtream"),
+            TargetType::Kodi => write!(f, "Kodi"),
+            TargetType::Json => write!(f, "Json"),
+            TargetType::Csv => write!(f, "Csv"),
         }
     }
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Sequence, PartialEq)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Sequence, PartialEq, utoipa::ToSchema)]
 pub(crate) enum ProcessingOrder {
     #[serde(rename = "frm")]
     Frm,
@@ -82,7 +98,7 @@ impl std::fmt::Display for ProcessingOrder {
     }
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Sequence)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Sequence, PartialEq, utoipa::ToSchema)]
 pub(crate) enum ItemField {
     #[serde(rename = "group")]
     Group,
@@ -113,10 +129,164 @@ pub(crate) enum FilterMode {
     Include,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, utoipa::ToSchema)]
+pub(crate) enum QualityMode {
+    #[serde(rename = "best")]
+    Best,
+    #[serde(rename = "order")]
+    Order,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, Hash, utoipa::ToSchema)]
+pub(crate) enum Quality {
+    #[serde(rename = "uhd")]
+    Uhd,
+    #[serde(rename = "fhd")]
+    Fhd,
+    #[serde(rename = "hd")]
+    Hd,
+    #[serde(rename = "sd")]
+    Sd,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, utoipa::ToSchema)]
+pub(crate) enum StrmLayout {
+    /// one directory per group
+    #[serde(rename = "flat")]
+    Flat,
+    #[serde(rename = "kodi")]
+    Kodi,
+    #[serde(rename = "jellyfin")]
+    Jellyfin,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, utoipa::ToSchema)]
+pub(crate) enum MergeStrategy {
+    #[serde(rename = "concat")]
+    Concat,
+    #[serde(rename = "prefer_first")]
+    PreferFirst,
+    #[serde(rename = "interleave")]
+    Interleave,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, utoipa::ToSchema)]
+pub(crate) enum DuplicateEpgId {
+    #[serde(rename = "keep")]
+    Keep,
+    #[serde(rename = "drop")]
+    Drop,
+    #[serde(rename = "clear")]
+    Clear,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, utoipa::ToSchema)]
+pub(crate) enum HealthAction {
+    #[serde(rename = "mark")]
+    Mark,
+    #[serde(rename = "remove")]
+    Remove,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, utoipa::ToSchema)]
+pub(crate) enum LogFormat {
+    #[serde(rename = "text")]
+    Text,
+    #[serde(rename = "json")]
+    Json,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, utoipa::ToSchema)]
+pub(crate) enum DedupKey {
+    #[serde(rename = "url")]
+    Url,
+    #[serde(rename = "name")]
+    Name,
+    #[serde(rename = "stream_id")]
+    StreamId,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 pub(crate) enum SortOrder {
     #[serde(rename = "asc")]
     Asc,
     #[serde(rename = "desc")]
     Desc,
-}
\ No newline at end of file
+}
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, utoipa::ToSchema)]
+pub(crate) enum RemuxContainer {
+    #[serde(rename = "mkv")]
+    Mkv,
+    #[serde(rename = "mp4")]
+    Mp4,
+}
+
+impl RemuxContainer {
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            RemuxContainer::Mkv => "mkv",
+            RemuxContainer::Mp4 => "mp4",
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, utoipa::ToSchema)]
+pub(crate) enum VideoSearchProvider {
+    #[serde(rename = "tmdb")]
+    Tmdb,
+    #[serde(rename = "imdb")]
+    Imdb,
+    #[serde(rename = "custom")]
+    Custom,
+}
+
+/// Role of a user of the management api.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, utoipa::ToSchema)]
+pub(crate) enum ApiRole {
+    #[serde(rename = "admin")]
+    Admin,
+    /// manages the api-proxy users of its targets
+    #[serde(rename = "operator")]
+    Operator,
+    #[serde(rename = "viewer")]
+    Viewer,
+}
+
+/// The endpoints served on a listen address, `streaming` are the player endpoints and `management` the Web-UI and its rest api.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, utoipa::ToSchema)]
+pub(crate) enum ListenScope {
+    #[serde(rename = "all")]
+    All,
+    #[serde(rename = "streaming")]
+    Streaming,
+    #[serde(rename = "management")]
+    Management,
+}
+
+impl std::fmt::Display for ListenScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            ListenScope::All => write!(f, "all"),
+            ListenScope::Streaming => write!(f, "streaming"),
+            ListenScope::Management => write!(f, "management"),
+        }
+    }
+}
+
+impl ListenScope {
+    pub fn serves_streaming(self) -> bool {
+        self != ListenScope::Management
+    }
+
+    pub fn serves_management(self) -> bool {
+        self != ListenScope::Streaming
+    }
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, utoipa::ToSchema)]
+pub(crate) enum AccessLogStorage {
+    #[serde(rename = "file")]
+    File,
+    #[serde(rename = "sqlite")]
+    Sqlite,
+}
@@ -1,13 +1,14 @@
 use std::cell::RefCell;
 use std::fmt::{Display, Formatter};
-use std::rc::Rc;
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::model::config::{ConfigInput, ConfigTargetOptions};
-use crate::model::model_config::{default_as_false};
+use crate::model::model_config::{default_as_empty_rc_str, default_as_false};
 use crate::model::xmltv::TVGuide;
+use crate::utils::string_utils;
 
 // https://de.wikipedia.org/wiki/M3U
 // https://siptv.eu/howto/playlist.html
@@ -61,30 +62,58 @@ pub(crate) fn default_playlist_item_type() -> PlaylistItemType { PlaylistItemTyp
 
 
 pub(crate) trait FieldAccessor {
-    fn get_field(&self, field: &str) -> Option<Rc<String>>;
+    fn get_field(&self, field: &str) -> Option<Arc<str>>;
     fn set_field(&mut self, field: &str, value: &str) -> bool;
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
 pub(crate) struct PlaylistItemHeader {
-    pub id: Rc<String>,
-    pub name: Rc<String>,
-    pub logo: Rc<String>,
-    pub logo_small: Rc<String>,
-    pub group: Rc<String>,
-    pub title: Rc<String>,
-    pub parent_code: Rc<String>,
-    pub audio_track: Rc<String>,
-    pub time_shift: Rc<String>,
-    pub rec: Rc<String>,
-    pub source: Rc<String>,
+    #[schema(value_type = String)]
+    pub id: Arc<str>,
+    #[schema(value_type = String)]
+    pub name: Arc<str>,
+    #[schema(value_type = String)]
+    pub logo: Arc<str>,
+    #[schema(value_type = String)]
+    pub logo_small: Arc<str>,
+    #[schema(value_type = String)]
+    pub group: Arc<str>,
+    #[schema(value_type = String)]
+    pub title: Arc<str>,
+    #[serde(default = "default_as_empty_rc_str")]
+    #[schema(value_type = String)]
+    pub chno: Arc<str>,
+    #[schema(value_type = String)]
+    pub parent_code: Arc<str>,
+    #[schema(value_type = String)]
+    pub audio_track: Arc<str>,
+    #[schema(value_type = String)]
+    pub time_shift: Arc<str>,
+    #[schema(value_type = String)]
+    pub rec: Arc<str>,
+    #[schema(value_type = String)]
+    pub catchup: Arc<str>,
+    #[schema(value_type = String)]
+    pub catchup_days: Arc<str>,
+    #[schema(value_type = String)]
+    pub catchup_source: Arc<str>,
+    #[schema(value_type = String)]
+    pub source: Arc<str>,
     // this is the source content not the url
-    pub url: Rc<String>,
-    pub epg_channel_id: Option<Rc<String>>,
+    #[schema(value_type = String)]
+    pub url: Arc<str>,
+    #[schema(value_type = Option<String>)]
+    pub epg_channel_id: Option<Arc<str>>,
     #[serde(default = "default_stream_cluster", skip_serializing, skip_deserializing)]
     pub xtream_cluster: XtreamCluster,
     #[serde(skip_serializing, skip_deserializing)]
     pub additional_properties: Option<Vec<(String, Value)>>,
+    /// custom EXTINF attributes set by mappings
+    #[serde(skip_serializing, skip_deserializing)]
+    pub m3u_attributes: Option<Vec<(String, String)>>,
+    /// http headers needed to play the channel, set by mappings
+    #[serde(skip_serializing, skip_deserializing)]
+    pub headers: Option<Vec<(String, String)>>,
     #[serde(default = "default_playlist_item_type", skip_serializing, skip_deserializing)]
     pub item_type:  PlaylistItemType,
     #[serde(default = "default_as_false", skip_serializing, skip_deserializing)]
@@ -96,7 +125,7 @@ macro_rules! update_fields {
         match $field {
             $(
                 stringify!($prop) => {
-                    $self.$prop = Rc::new($val);
+                    $self.$prop = Arc::from($val);
                     true
                 },
             )*
@@ -117,25 +146,29 @@ macro_rules! get_fields {
 }
 
 impl FieldAccessor for PlaylistItemHeader {
-    fn get_field(&self, field: &str) -> Option<Rc<String>> {
-        get_fields!(self, field, id, name, logo, logo_small, group, title, parent_code, audio_track, time_shift, rec, source, url;)
+    fn get_field(&self, field: &str) -> Option<Arc<str>> {
+        get_fields!(self, field, id, name, logo, logo_small, group, title, chno, parent_code, audio_track, time_shift, rec, catchup, catchup_days, catchup_source, source, url;)
     }
 
     fn set_field(&mut self, field: &str, value: &str) -> bool {
+        if field == "group" {
+            self.group = string_utils::intern(value);
+            return true;
+        }
         let val = String::from(value);
-        update_fields!(self, field, id, name, logo, logo_small, group, title, parent_code, audio_track, time_shift, rec, source, url; val)
+        update_fields!(self, field, id, name, logo, logo_small, group, title, chno, parent_code, audio_track, time_shift, rec, catchup, catchup_days, catchup_source, source, url; val)
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
 pub(crate) struct PlaylistItem {
     pub header: RefCell<PlaylistItemHeader>,
 }
 
 macro_rules! to_m3u_non_empty_fields {
-    ($header:expr, $line:expr, $(($prop:ident, $field:expr)),*;) => {
+    ($header:expr, $line:expr, $enabled:expr, $(($prop:ident, $field:expr)),*;) => {
         $(
-           if !$header.$prop.is_empty() {
+           if !$header.$prop.is_empty() && $enabled($field) {
                 $line = format!("{} {}=\"{}\"", $line, $field, $header.$prop);
             }
          )*
@@ -146,32 +179,70 @@ macro_rules! to_m3u_non_empty_fields {
 impl PlaylistItem {
     pub fn to_m3u(&self, options: &Option<ConfigTargetOptions>) -> String {
         let header = self.header.borrow();
-        let ignore_logo = options.as_ref().map_or(false, |o| o.ignore_logo);
-        let mut line = format!("#EXTINF:-1 tvg-id=\"{}\" tvg-name=\"{}\" group-title=\"{}\"",
-                               header.epg_channel_id.as_ref().map_or("", |o| o.as_ref()),
-                               header.name, header.group);
+        let ignore_logo = options.as_ref().is_some_and(|o| o.ignore_logo);
+        // if attributes are configured, only these are written
+        let attributes = options.as_ref().and_then(|o| o.m3u_attributes.as_ref());
+        let is_enabled = |attribute: &str| attributes.is_none_or(|list| list.iter().any(|a| a.eq(attribute)));
+
+        let mut line = String::from("#EXTINF:-1");
+        if is_enabled("tvg-id") {
+            line = format!("{} tvg-id=\"{}\"", line, header.epg_channel_id.as_ref().map_or("", |o| o.as_ref()));
+        }
+        if is_enabled("tvg-name") {
+            line = format!("{} tvg-name=\"{}\"", line, header.name);
+        }
+        if is_enabled("group-title") {
+            line = format!("{} group-title=\"{}\"", line, header.group);
+        }
 
-        // line = format!("{} tvg-chno=\"{}\"", line, header.chno);
+        to_m3u_non_empty_fields!(header, line, is_enabled, (chno, "tvg-chno"););
 
         if !ignore_logo {
-            to_m3u_non_empty_fields!(header, line, (logo, "tvg-logo"), (logo_small, "tvg-logo-small"););
+            to_m3u_non_empty_fields!(header, line, is_enabled, (logo, "tvg-logo"), (logo_small, "tvg-logo-small"););
         }
 
-        to_m3u_non_empty_fields!(header, line,
+        to_m3u_non_empty_fields!(header, line, is_enabled,
             (parent_code, "parent-code"),
             (audio_track, "audio-track"),
             (time_shift, "timeshift"),
-            (rec, "tvg-rec"););
+            (rec, "tvg-rec"),
+            (catchup, "catchup"),
+            (catchup_days, "catchup-days"),
+            (catchup_source, "catchup-source"););
+
+        if let Some(m3u_attributes) = &header.m3u_attributes {
+            for (key, value) in m3u_attributes {
+                if !value.is_empty() && is_enabled(key) {
+                    line = format!("{} {}=\"{}\"", line, key, value);
+                }
+            }
+        }
+
+        let mut options = String::new();
+        if let Some(headers) = &header.headers {
+            let mut other_headers = serde_json::Map::new();
+            for (key, value) in headers {
+                match key.to_lowercase().as_str() {
+                    "user-agent" => options.push_str(&format!("#EXTVLCOPT:http-user-agent={}\n", value)),
+                    "referer" | "referrer" => options.push_str(&format!("#EXTVLCOPT:http-referrer={}\n", value)),
+                    _ => { other_headers.insert(key.to_string(), serde_json::Value::String(value.to_string())); }
+                }
+            }
+            if !other_headers.is_empty() {
+                options.push_str(&format!("#EXTHTTP:{}\n", serde_json::Value::Object(other_headers)));
+            }
+        }
 
-        format!("{},{}\n{}", line, header.title, header.url)
+        format!("{},{}\n{}{}", line, header.title, options, header.url)
     }
 }
 
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
 pub(crate) struct PlaylistGroup {
     pub id: u32,
-    pub title: Rc<String>,
+    #[schema(value_type = String)]
+    pub title: Arc<str>,
     pub channels: Vec<PlaylistItem>,
     #[serde(default = "default_stream_cluster", skip_serializing, skip_deserializing)]
     pub xtream_cluster: XtreamCluster,
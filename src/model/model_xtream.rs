@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use serde::{Deserialize, Deserializer, Serialize};
 use serde::de::DeserializeOwned;
@@ -7,6 +7,7 @@ use serde_json::Value;
 
 use crate::model::model_config::{default_as_empty_rc_str};
 use crate::model::model_playlist::{PlaylistItem};
+use crate::utils::string_utils;
 
 fn default_as_empty_list() -> Vec<PlaylistItem> { vec![] }
 
@@ -57,31 +58,38 @@ fn value_to_string(v: &Value) -> Option<String> {
     }
 }
 
-fn deserialize_as_option_rc_string<'de, D>(deserializer: D) -> Result<Option<Rc<String>>, D::Error>
+fn deserialize_as_option_rc_string<'de, D>(deserializer: D) -> Result<Option<Arc<str>>, D::Error>
     where
         D: Deserializer<'de>,
 {
     let value: Value = Deserialize::deserialize(deserializer)?;
 
     match &value {
-        Value::String(s) => Ok(Some(Rc::new(s.to_owned()))),
-        Value::Number(s) => Ok(Some(Rc::new(s.to_string()))),
+        Value::String(s) => Ok(Some(Arc::from(s.as_str()))),
+        Value::Number(s) => Ok(Some(Arc::from(s.to_string()))),
         _ => Ok(None),
     }
 }
 
-fn deserialize_as_rc_string<'de, D>(deserializer: D) -> Result<Rc<String>, D::Error>
+fn deserialize_as_rc_string<'de, D>(deserializer: D) -> Result<Arc<str>, D::Error>
     where
         D: Deserializer<'de>,
 {
     let value: Value = Deserialize::deserialize(deserializer)?;
 
     match &value {
-        Value::String(s) => Ok(Rc::new(s.to_owned())),
-        _ => Ok(Rc::new(value.to_string())),
+        Value::String(s) => Ok(Arc::from(s.as_str())),
+        _ => Ok(Arc::from(value.to_string())),
     }
 }
 
+fn deserialize_as_interned_string<'de, D>(deserializer: D) -> Result<Arc<str>, D::Error>
+    where
+        D: Deserializer<'de>,
+{
+    deserialize_as_rc_string(deserializer).map(|value| string_utils::intern(&value))
+}
+
 fn deserialize_as_string_array<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
     where
         D: Deserializer<'de>,
@@ -95,9 +103,9 @@ fn deserialize_as_string_array<'de, D>(deserializer: D) -> Result<Option<Vec<Str
 #[derive(Deserialize)]
 pub(crate) struct XtreamCategory {
     #[serde(deserialize_with = "deserialize_as_rc_string")]
-    pub category_id: Rc<String>,
-    #[serde(deserialize_with = "deserialize_as_rc_string")]
-    pub category_name: Rc<String>,
+    pub category_id: Arc<str>,
+    #[serde(deserialize_with = "deserialize_as_interned_string")]
+    pub category_name: Arc<str>,
     //pub parent_id: i32,
     #[serde(default = "default_as_empty_list")]
     pub channels: Vec<PlaylistItem>,
@@ -112,55 +120,55 @@ impl XtreamCategory {
 #[derive(Serialize, Deserialize)]
 pub(crate) struct XtreamStream {
     #[serde(default, deserialize_with = "deserialize_as_rc_string")]
-    pub name: Rc<String>,
+    pub name: Arc<str>,
     #[serde(default, deserialize_with = "deserialize_as_rc_string")]
-    pub category_id: Rc<String>,
+    pub category_id: Arc<str>,
     #[serde(default, deserialize_with = "deserialize_number_from_string")]
     pub stream_id: Option<i32>,
     #[serde(default, deserialize_with = "deserialize_number_from_string")]
     pub series_id: Option<i32>,
     #[serde(default = "default_as_empty_rc_str", deserialize_with = "deserialize_as_rc_string")]
-    pub stream_icon: Rc<String>,
+    pub stream_icon: Arc<str>,
     #[serde(default = "default_as_empty_rc_str", deserialize_with = "deserialize_as_rc_string")]
-    pub direct_source: Rc<String>,
+    pub direct_source: Arc<str>,
 
     // optional attributes
     #[serde(default, deserialize_with = "deserialize_as_string_array")]
     pub backdrop_path: Option<Vec<String>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub added: Option<Rc<String>>,
+    pub added: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub cast: Option<Rc<String>>,
+    pub cast: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub container_extension: Option<Rc<String>>,
+    pub container_extension: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub cover: Option<Rc<String>>,
+    pub cover: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub director: Option<Rc<String>>,
+    pub director: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub episode_run_time: Option<Rc<String>>,
+    pub episode_run_time: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub genre: Option<Rc<String>>,
+    pub genre: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub last_modified: Option<Rc<String>>,
+    pub last_modified: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub plot: Option<Rc<String>>,
+    pub plot: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_number_from_string")]
     pub rating: Option<f64>,
     #[serde(default, deserialize_with = "deserialize_number_from_string")]
     pub rating_5based: Option<f64>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub release_date: Option<Rc<String>>,
+    pub release_date: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub stream_type: Option<Rc<String>>,
+    pub stream_type: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub title: Option<Rc<String>>,
+    pub title: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub year: Option<Rc<String>>,
+    pub year: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub youtube_trailer: Option<Rc<String>>,
+    pub youtube_trailer: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_as_option_rc_string")]
-    pub epg_channel_id: Option<Rc<String>>,
+    pub epg_channel_id: Option<Arc<str>>,
     #[serde(default, deserialize_with = "deserialize_number_from_string")]
     pub tv_archive: Option<i32>,
     #[serde(default, deserialize_with = "deserialize_number_from_string")]
@@ -327,6 +335,8 @@ impl XtreamSeriesInfoEpisode {
         add_str_property_if_exists!(result, self.info.releasedate, "release_date");
         add_str_property_if_exists!(result, self.title, "title");
         add_i64_property_if_exists!(result, self.season, "season");
+        add_i64_property_if_exists!(result, self.episode_num, "episode");
+        add_str_property_if_exists!(result, series_info.info.name, "series_name");
         add_str_property_if_exists!(result, series_info.info.youtube_trailer, "youtube_trailer");
         if result.is_empty() { None } else { Some(result) }
     }
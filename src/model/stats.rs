@@ -19,12 +19,19 @@ pub(crate) struct InputStats {
     pub error_count: usize,
     pub raw_stats: PlaylistStats,
     pub processed_stats: PlaylistStats,
+    /// xtream clusters which failed, the data of the previous run is used for them
+    pub failed_clusters: Vec<String>,
 }
 
 impl ToString for InputStats {
     fn to_string(&self) -> String {
-        format!("{{\"name\": {}, \"type\": {}, \"errors\": {}, \"raw\": {}, \"processed\": {}}}",
+        let failed = if self.failed_clusters.is_empty() {
+            String::new()
+        } else {
+            format!(", \"failed\": [{}]", self.failed_clusters.join(", "))
+        };
+        format!("{{\"name\": {}, \"type\": {}, \"errors\": {}, \"raw\": {}, \"processed\": {}{}}}",
                 self.name, self.input_type.to_string(), self.error_count,
-                self.raw_stats.to_string(), self.processed_stats.to_string())
+                self.raw_stats.to_string(), self.processed_stats.to_string(), failed)
     }
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
+use std::sync::Arc;
 use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
 use quick_xml::{Error, Writer};
 
@@ -67,20 +68,20 @@ pub(crate) struct TVGuide {
 }
 
 impl TVGuide {
-    pub(crate) fn filter(&self, channel_ids: &HashSet<Rc<String>>) -> Option<Epg> {
+    pub(crate) fn filter(&self, channel_ids: &HashSet<Arc<str>>) -> Option<Epg> {
         if !channel_ids.is_empty() {
             let children: Vec<Rc<XmlTag>> = self.epg.children.as_ref().unwrap().iter().filter(|c| {
                 match c.name.as_str() {
                     "channel" => {
                         match c.get_attribute_value("id") {
                             None => false,
-                            Some(val) => channel_ids.contains(val)
+                            Some(val) => channel_ids.contains(val.as_str())
                         }
                     }
                     "programme" => {
                         match c.get_attribute_value("channel") {
                             None => false,
-                            Some(val) => channel_ids.contains(val)
+                            Some(val) => channel_ids.contains(val.as_str())
                         }
                     }
                     _ => false,
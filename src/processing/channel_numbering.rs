@@ -0,0 +1,96 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::{error, warn};
+use regex::Regex;
+
+use crate::model::config::{Config, ConfigNumbering, ConfigTarget};
+use crate::model::model_playlist::{PlaylistGroup, XtreamCluster};
+use crate::utils::file_utils;
+
+/// Returns the first and last number for channels of the group.
+fn get_number_range(numbering: &ConfigNumbering, group: &str) -> (u32, Option<u32>) {
+    numbering.ranges.iter().find(|range| range.matches(group))
+        .map_or((numbering.start, None), |range| (range.start, range.end))
+}
+
+fn get_numbers_path(cfg: &Config, target_name: &str) -> Option<PathBuf> {
+    let filename_re = Regex::new(r"[^A-Za-z0-9_-]").unwrap();
+    let filename = format!("chno_{}.json", filename_re.replace_all(target_name, "_"));
+    file_utils::get_file_path(&cfg.working_dir, Some(PathBuf::from(filename)))
+}
+
+fn load_numbers(path: &Path) -> HashMap<String, u32> {
+    std::fs::read_to_string(path).ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Assigns the live channels of the target a channel number.
+/// Channels keep the number of the previous run as long as it fits into the range of their group,
+/// new channels get the lowest free number of the range.
+pub(crate) fn assign_channel_numbers(cfg: &Config, target: &ConfigTarget, playlist: &[PlaylistGroup]) {
+    let numbering = match &target.numbering {
+        Some(numbering) => numbering,
+        None => return,
+    };
+    let path = match get_numbers_path(cfg, &target.name) {
+        Some(path) => path,
+        None => {
+            error!("failed to get channel number file for target: {}", &target.name);
+            return;
+        }
+    };
+    let previous = load_numbers(&path);
+    let mut numbers = HashMap::new();
+    let mut used = HashSet::new();
+    let channels = || playlist.iter()
+        .flat_map(|group| group.channels.iter().map(move |channel| (group, channel)))
+        .filter(|(_, channel)| channel.header.borrow().xtream_cluster == XtreamCluster::Live);
+
+    // channels with a number from the previous run
+    for (group, channel) in channels() {
+        let url = channel.header.borrow().url.to_string();
+        if let Some(&number) = previous.get(&url) {
+            let (start, end) = get_number_range(numbering, &group.title);
+            if number >= start && end.is_none_or(|end| number <= end) && used.insert(number) {
+                numbers.insert(url, number);
+            }
+        }
+    }
+
+    // new channels and channels which were moved to another range
+    let mut next_numbers = HashMap::<u32, u32>::new();
+    for (group, channel) in channels() {
+        let mut header = channel.header.borrow_mut();
+        let url = header.url.to_string();
+        let number = match numbers.get(&url) {
+            Some(&number) => Some(number),
+            None => {
+                let (start, end) = get_number_range(numbering, &group.title);
+                let next = next_numbers.entry(start).or_insert(start);
+                while used.contains(next) {
+                    *next += 1;
+                }
+                if end.is_some_and(|end| *next > end) {
+                    warn!("no channel number left for {} in group {} of target {}", &header.title, &group.title, &target.name);
+                    None
+                } else {
+                    used.insert(*next);
+                    numbers.insert(url, *next);
+                    Some(*next)
+                }
+            }
+        };
+        header.chno = Arc::from(number.map_or_else(String::new, |n| n.to_string()));
+    }
+
+    // numbers of channels missing in this run are kept while no other channel uses them
+    previous.into_iter()
+        .filter(|(_, number)| !used.contains(number))
+        .for_each(|(url, number)| { numbers.entry(url).or_insert(number); });
+    if let Err(err) = std::fs::write(&path, serde_json::to_string(&numbers).unwrap_or_default()) {
+        error!("failed to write channel numbers {}: {}", path.to_str().unwrap_or_default(), err);
+    }
+}
@@ -0,0 +1,204 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use log::error;
+use serde::Serialize;
+
+use crate::create_m3u_filter_error_result;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{check_custom_channel_ids, Config, ConfigCustomChannel, ConfigTarget};
+use crate::model::model_config::default_as_empty_rc_str;
+use crate::model::model_playlist::{PlaylistGroup, PlaylistItem, PlaylistItemHeader, PlaylistItemType, XtreamCluster};
+use crate::processing::playlist_radio::mark_radio;
+use crate::utils::{file_utils, string_utils};
+
+const CUSTOM_CHANNELS_FILE: &str = "custom_channels.json";
+
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub(crate) struct CustomChannelEntry {
+    pub id: i32,
+    #[serde(flatten)]
+    pub channel: ConfigCustomChannel,
+}
+
+/// The channels added through the api are changed under the lock.
+fn file_lock() -> &'static Mutex<()> {
+    static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+    LOCK.get_or_init(|| Mutex::new(()))
+}
+
+type ChannelsCache = HashMap<PathBuf, (SystemTime, Arc<Vec<ConfigCustomChannel>>)>;
+
+/// The streams of the custom channels look up their url, the file is read again only if it was replaced.
+fn channels_cache() -> &'static Mutex<ChannelsCache> {
+    static CACHE: OnceLock<Mutex<ChannelsCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn get_custom_channels_path(cfg: &Config) -> Option<PathBuf> {
+    file_utils::get_file_path(&cfg.working_dir, Some(PathBuf::from(CUSTOM_CHANNELS_FILE)))
+}
+
+fn load_channels(cfg: &Config) -> Arc<Vec<ConfigCustomChannel>> {
+    let path = match get_custom_channels_path(cfg) {
+        Some(path) => path,
+        None => return Arc::default(),
+    };
+    let modified = match std::fs::metadata(&path).and_then(|meta| meta.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return Arc::default(),
+    };
+    let mut cache = channels_cache().lock().unwrap();
+    if let Some((cached_modified, channels)) = cache.get(&path) {
+        if *cached_modified == modified {
+            return Arc::clone(channels);
+        }
+    }
+    let channels: Arc<Vec<ConfigCustomChannel>> = Arc::new(std::fs::read_to_string(&path).ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default());
+    cache.insert(path, (modified, Arc::clone(&channels)));
+    channels
+}
+
+fn save_channels(cfg: &Config, channels: &[ConfigCustomChannel]) -> Result<(), M3uFilterError> {
+    let path = match get_custom_channels_path(cfg) {
+        Some(path) => path,
+        None => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant write custom channels"),
+    };
+    let tmp_path = path.with_extension("tmp");
+    let result = serde_json::to_string_pretty(channels).map_err(|err| err.to_string())
+        .and_then(|content| std::fs::write(&tmp_path, content).and_then(|_| std::fs::rename(&tmp_path, &path)).map_err(|err| err.to_string()));
+    // the modification time can be unchanged for saves in quick succession
+    channels_cache().lock().unwrap().remove(&path);
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            error!("cant write custom channels {}: {}", path.to_str().unwrap_or("?"), err);
+            create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant write custom channels: {}", err)
+        }
+    }
+}
+
+fn to_entry(channel: ConfigCustomChannel) -> CustomChannelEntry {
+    CustomChannelEntry { id: channel.get_stream_id(), channel }
+}
+
+/// Returns the channels added through the api.
+pub(crate) fn get_custom_channels(cfg: &Config) -> Vec<CustomChannelEntry> {
+    load_channels(cfg).iter().cloned().map(to_entry).collect()
+}
+
+/// Adds the channel or replaces the one with the same url and target.
+/// A channel whose stream id is taken by another url is rejected.
+pub(crate) fn put_custom_channel(cfg: &Config, mut channel: ConfigCustomChannel) -> Result<CustomChannelEntry, M3uFilterError> {
+    channel.prepare()?;
+    let _guard = file_lock().lock().unwrap();
+    let mut channels = (*load_channels(cfg)).clone();
+    let target_channels = cfg.sources.iter().flat_map(|source| &source.targets).flat_map(|target| &target.custom_channels);
+    check_custom_channel_ids(channels.iter().chain(target_channels).chain(std::iter::once(&channel)))?;
+    let target = channel.target.as_deref().map(str::to_lowercase);
+    match channels.iter_mut().find(|existing| existing.url == channel.url && existing.target.as_deref().map(str::to_lowercase) == target) {
+        Some(existing) => *existing = channel.clone(),
+        None => channels.push(channel.clone()),
+    }
+    save_channels(cfg, &channels)?;
+    Ok(to_entry(channel))
+}
+
+/// Removes the channels with the stream id, returns the number of removed channels.
+pub(crate) fn remove_custom_channel(cfg: &Config, id: i32) -> Result<usize, M3uFilterError> {
+    let _guard = file_lock().lock().unwrap();
+    let mut channels = (*load_channels(cfg)).clone();
+    let count = channels.len();
+    channels.retain(|channel| channel.get_stream_id() != id);
+    let removed = count - channels.len();
+    if removed > 0 {
+        save_channels(cfg, &channels)?;
+    }
+    Ok(removed)
+}
+
+/// The channels of the target config and the ones added through the api for the target or all targets.
+fn get_target_channels(cfg: &Config, target: &ConfigTarget) -> Vec<ConfigCustomChannel> {
+    let mut channels = target.custom_channels.clone();
+    channels.extend(load_channels(cfg).iter()
+        .filter(|channel| is_target_channel(channel, target)).cloned());
+    channels
+}
+
+fn is_target_channel(channel: &ConfigCustomChannel, target: &ConfigTarget) -> bool {
+    channel.target.as_ref().is_none_or(|name| name.eq_ignore_ascii_case(&target.name))
+}
+
+fn create_playlist_item(channel: &ConfigCustomChannel, group: &Arc<str>) -> PlaylistItem {
+    let name: Arc<str> = Arc::from(channel.name.as_str());
+    let item = PlaylistItem {
+        header: RefCell::new(PlaylistItemHeader {
+            id: Arc::from(channel.get_stream_id().to_string()),
+            name: Arc::clone(&name),
+            logo: channel.logo.as_ref().map_or_else(default_as_empty_rc_str, |logo| Arc::from(logo.as_str())),
+            logo_small: default_as_empty_rc_str(),
+            group: Arc::clone(group),
+            title: name,
+            chno: default_as_empty_rc_str(),
+            parent_code: default_as_empty_rc_str(),
+            audio_track: default_as_empty_rc_str(),
+            time_shift: default_as_empty_rc_str(),
+            rec: default_as_empty_rc_str(),
+            catchup: default_as_empty_rc_str(),
+            catchup_days: default_as_empty_rc_str(),
+            catchup_source: default_as_empty_rc_str(),
+            source: default_as_empty_rc_str(),
+            url: Arc::from(channel.url.as_str()),
+            epg_channel_id: channel.epg_channel_id.as_ref().map(|id| Arc::from(id.as_str())),
+            xtream_cluster: XtreamCluster::Live,
+            additional_properties: None,
+            m3u_attributes: None,
+            headers: None,
+            item_type: PlaylistItemType::Live,
+            series_fetched: false,
+        })
+    };
+    if channel.radio {
+        mark_radio(&mut item.header.borrow_mut(), true);
+    }
+    item
+}
+
+/// Adds the custom channels of the target to the live groups of the playlist, they are not filtered.
+/// A channel whose stream id is taken by an earlier channel with another url is skipped, its stream would be unreachable.
+pub(crate) fn add_custom_channels(cfg: &Config, target: &ConfigTarget, playlist: &mut Vec<PlaylistGroup>) {
+    let mut urls: HashMap<i32, String> = HashMap::new();
+    for channel in get_target_channels(cfg, target) {
+        let url = urls.entry(channel.get_stream_id()).or_insert_with(|| channel.url.clone());
+        if *url != channel.url {
+            error!("custom channel {} skipped, its stream id is taken by {}", channel.url, url);
+            continue;
+        }
+        let index = match playlist.iter().position(|group| group.xtream_cluster == XtreamCluster::Live && *group.title == *channel.group) {
+            Some(index) => index,
+            None => {
+                let id = playlist.iter().map(|group| group.id).max().unwrap_or(0) + 1;
+                playlist.push(PlaylistGroup { id, title: string_utils::intern(&channel.group), channels: vec![], xtream_cluster: XtreamCluster::Live });
+                playlist.len() - 1
+            }
+        };
+        let group = &mut playlist[index];
+        let item = create_playlist_item(&channel, &group.title);
+        group.channels.push(item);
+    }
+}
+
+/// Returns the url of the custom channel, if the stream id in the action path belongs to one of the target.
+pub(crate) fn get_stream_url(cfg: &Config, target: &ConfigTarget, action_path: &str) -> Option<String> {
+    let stream_id = action_path.rsplit('/').next()?.split('.').next()?.parse::<i32>().ok()?;
+    let channels = load_channels(cfg);
+    target.custom_channels.iter()
+        .chain(channels.iter().filter(|channel| is_target_channel(channel, target)))
+        .find(|channel| channel.get_stream_id() == stream_id)
+        .map(|channel| channel.url.clone())
+}
@@ -0,0 +1,67 @@
+use std::cell::RefCell;
+
+use serde::Serialize;
+
+use crate::filter::{get_filter, MockValueProcessor, ValueProvider};
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{Config, ConfigInput, InputType};
+use crate::model::model_playlist::PlaylistGroup;
+use crate::utils::download;
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct FilterTestSample {
+    pub group: String,
+    pub title: String,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct FilterTestResult {
+    /// the filter with the templates replaced
+    pub filter: String,
+    pub matched: usize,
+    pub not_matched: usize,
+    pub matched_samples: Vec<FilterTestSample>,
+    pub not_matched_samples: Vec<FilterTestSample>,
+}
+
+/// Evaluates the filter against the playlist of the input, like the filter of a target without the other processing steps.
+/// The first `samples` channels of both results are returned.
+pub(crate) async fn test_filter(cfg: &Config, filter_text: &str, input: &ConfigInput, samples: usize) -> Result<FilterTestResult, M3uFilterError> {
+    let filter = get_filter(filter_text, cfg.templates.as_ref())?;
+    let (playlist, errors) = match input.input_type {
+        InputType::M3u => download::get_m3u_playlist(cfg, input, &cfg.working_dir, false).await,
+        InputType::Xtream => {
+            let (playlist, errors, _) = download::get_xtream_playlist(input, &cfg.working_dir, false).await;
+            (playlist, errors)
+        }
+    };
+    if playlist.is_empty() {
+        let messages: Vec<String> = errors.iter().map(|err| err.message.to_string()).collect();
+        return Err(M3uFilterError::new(M3uFilterErrorKind::Info,
+                                       format!("playlist is empty {}", messages.join(", "))));
+    }
+    let mut result = FilterTestResult {
+        filter: filter.to_string(),
+        matched: 0,
+        not_matched: 0,
+        matched_samples: vec![],
+        not_matched_samples: vec![],
+    };
+    let mut processor = MockValueProcessor {};
+    for (group, channel) in playlist.iter().flat_map(|group: &PlaylistGroup| group.channels.iter().map(move |channel| (group, channel))) {
+        let provider = ValueProvider { pli: RefCell::new(channel) };
+        let (count, list) = if filter.filter(&provider, &mut processor) {
+            (&mut result.matched, &mut result.matched_samples)
+        } else {
+            (&mut result.not_matched, &mut result.not_matched_samples)
+        };
+        *count += 1;
+        if list.len() < samples {
+            list.push(FilterTestSample {
+                group: group.title.to_string(),
+                title: channel.header.borrow().title.to_string(),
+            });
+        }
+    }
+    Ok(result)
+}
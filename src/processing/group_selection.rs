@@ -0,0 +1,91 @@
+use crate::create_m3u_filter_error_result;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{Config, ConfigInput, escape_glob, glob_to_regex, InputType, quote_regexp};
+use crate::utils::download;
+
+#[derive(Debug)]
+pub(crate) struct GroupCount {
+    pub title: String,
+    pub channels: usize,
+}
+
+/// Downloads the playlist of the input and counts the channels of each group in the order of the playlist.
+/// Groups with the same title in different xtream clusters are counted together, a filter can't tell them apart.
+pub(crate) async fn get_group_counts(cfg: &Config, input: &ConfigInput) -> Result<Vec<GroupCount>, M3uFilterError> {
+    let (playlist, errors) = match input.input_type {
+        InputType::M3u => download::get_m3u_playlist(cfg, input, &cfg.working_dir, false).await,
+        InputType::Xtream => {
+            let (playlist, errors, _) = download::get_xtream_playlist(input, &cfg.working_dir, false).await;
+            (playlist, errors)
+        }
+    };
+    if playlist.is_empty() {
+        let messages: Vec<String> = errors.iter().map(|err| err.message.to_string()).collect();
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "playlist is empty {}", messages.join(", "));
+    }
+    let mut groups: Vec<GroupCount> = vec![];
+    for group in &playlist {
+        match groups.iter_mut().find(|count| count.title.as_str() == &*group.title) {
+            Some(count) => count.channels += group.channels.len(),
+            None => groups.push(GroupCount { title: group.title.to_string(), channels: group.channels.len() }),
+        }
+    }
+    Ok(groups)
+}
+
+/// Returns the groups matching one of the patterns, which are group names or globs like `include_groups`.
+pub(crate) fn select_groups_by_pattern<'a>(groups: &'a [GroupCount], patterns: &[String]) -> Result<Vec<&'a GroupCount>, M3uFilterError> {
+    let mut regexps = vec![];
+    for pattern in patterns {
+        let re = regex::Regex::new(&glob_to_regex(pattern)).unwrap();
+        if !groups.iter().any(|group| re.is_match(&group.title)) {
+            return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "no group matches: {}", pattern);
+        }
+        regexps.push(re);
+    }
+    Ok(groups.iter().filter(|group| regexps.iter().any(|re| re.is_match(&group.title))).collect())
+}
+
+/// Returns the groups of a selection of numbers and ranges like `1, 3-5`, the groups are numbered from 1.
+pub(crate) fn select_groups_by_number<'a>(groups: &'a [GroupCount], selection: &str) -> Result<Vec<&'a GroupCount>, M3uFilterError> {
+    let parse_number = |text: &str| match text.trim().parse::<usize>() {
+        Ok(number) if number >= 1 && number <= groups.len() => Ok(number),
+        _ => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "invalid group number: {}, the groups are numbered from 1 to {}", text.trim(), groups.len()),
+    };
+    let mut selected = vec![false; groups.len()];
+    for part in selection.split(|c: char| c == ',' || c.is_whitespace()).filter(|part| !part.is_empty()) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse_number(start)?, parse_number(end)?);
+                if start > end {
+                    return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "invalid group range: {}, the start is after the end", part);
+                }
+                (start, end)
+            }
+            None => {
+                let number = parse_number(part)?;
+                (number, number)
+            }
+        };
+        (start..=end).for_each(|number| selected[number - 1] = true);
+    }
+    Ok(groups.iter().zip(selected).filter(|(_, is_selected)| *is_selected).map(|(group, _)| group).collect())
+}
+
+/// A filter which takes exactly the groups.
+pub(crate) fn get_filter_expression(groups: &[&GroupCount]) -> String {
+    let regexps: Vec<String> = groups.iter()
+        .map(|group| quote_regexp(&format!("^{}$", regex::escape(&group.title))))
+        .collect();
+    format!("Group IN [{}]", regexps.join(", "))
+}
+
+/// An `include_groups` block for a target, a `*`, `?` or `\` in a group name is escaped.
+pub(crate) fn get_include_groups(groups: &[&GroupCount]) -> Result<String, M3uFilterError> {
+    #[derive(serde::Serialize)]
+    struct IncludeGroups {
+        include_groups: Vec<String>,
+    }
+    let block = IncludeGroups { include_groups: groups.iter().map(|group| escape_glob(&group.title)).collect() };
+    serde_yaml::to_string(&block).map_err(|err| M3uFilterError::new(M3uFilterErrorKind::Info, err.to_string()))
+}
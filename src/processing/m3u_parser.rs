@@ -1,9 +1,10 @@
 use std::borrow::{BorrowMut};
 use std::cell::RefCell;
-use std::rc::Rc;
+use std::sync::Arc;
 use crate::model::config::Config;
 use crate::model::model_config::default_as_empty_rc_str;
 use crate::model::model_playlist::{default_playlist_item_type, default_stream_cluster, PlaylistGroup, PlaylistItem, PlaylistItemHeader, PlaylistItemType, XtreamCluster};
+use crate::processing::playlist_radio::RADIO_ATTRIBUTE;
 use crate::utils::string_utils;
 
 fn token_value(it: &mut std::str::Chars) -> String {
@@ -61,16 +62,22 @@ fn create_empty_playlistitem_header(content: &String, url: String) -> PlaylistIt
         logo_small: default_as_empty_rc_str(),
         group: default_as_empty_rc_str(),
         title: default_as_empty_rc_str(),
+        chno: default_as_empty_rc_str(),
         parent_code: default_as_empty_rc_str(),
         audio_track: default_as_empty_rc_str(),
         time_shift: default_as_empty_rc_str(),
         rec: default_as_empty_rc_str(),
-        source: Rc::new(content.to_owned()),
-        url: Rc::new(url),
+        catchup: default_as_empty_rc_str(),
+        catchup_days: default_as_empty_rc_str(),
+        catchup_source: default_as_empty_rc_str(),
+        source: Arc::from(content.to_owned()),
+        url: Arc::from(url),
         epg_channel_id: None,
         item_type: default_playlist_item_type(),
         xtream_cluster: default_stream_cluster(),
         additional_properties: None,
+        m3u_attributes: None,
+        headers: None,
         series_fetched: false,
     }
 }
@@ -79,7 +86,7 @@ macro_rules! process_header_fields {
     ($header:expr, $token:expr, $(($prop:ident, $field:expr)),*; $val:expr) => {
         match $token {
             $(
-               $field => $header.$prop = Rc::new($val),
+               $field => $header.$prop = Arc::from($val),
              )*
             _ => {}
         }
@@ -97,21 +104,31 @@ fn process_header(video_suffixes: &Vec<&str>, content: &String, url: String) ->
                 break;
             }
             match c.unwrap() {
-                ',' => plih.title = Rc::new(get_value(&mut it)),
+                ',' => plih.title = Arc::from(get_value(&mut it)),
                 _ => {
                     let token = token_till(&mut it, '=');
                     if let Some(t) = token {
                         let value = token_value(&mut it);
-                        process_header_fields!(plih, t.as_str(),
-                        (id, "tvg-id"),
-                        (group, "group-title"),
-                        (name, "tvg-name"),
-                        (parent_code, "parent-code"),
-                        (audio_track, "audio-track"),
-                        (logo, "tvg-logo"),
-                        (logo_small, "tvg-logo-small"),
-                        (time_shift, "timeshift"),
-                        (rec, "tvg-rec"); value)
+                        if t == RADIO_ATTRIBUTE {
+                            // the radio attribute is kept for the output
+                            plih.m3u_attributes.get_or_insert_with(Vec::new).push((t, value));
+                        } else {
+                            process_header_fields!(plih, t.as_str(),
+                                (id, "tvg-id"),
+                                (group, "group-title"),
+                                (name, "tvg-name"),
+                                (chno, "tvg-chno"),
+                                (chno, "channel-number"),
+                                (parent_code, "parent-code"),
+                                (audio_track, "audio-track"),
+                                (logo, "tvg-logo"),
+                                (logo_small, "tvg-logo-small"),
+                                (time_shift, "timeshift"),
+                                (rec, "tvg-rec"),
+                                (catchup, "catchup"),
+                                (catchup_days, "catchup-days"),
+                                (catchup_source, "catchup-source"); value)
+                        }
                     }
                 }
             }
@@ -119,10 +136,10 @@ fn process_header(video_suffixes: &Vec<&str>, content: &String, url: String) ->
         }
         if plih.id.is_empty() {
             if let Some(chanid) = extract_id_from_url(url.as_str()) {
-                plih.id = Rc::new(chanid);
+                plih.id = Arc::from(chanid);
             }
         }
-        plih.epg_channel_id = Some(Rc::clone(&plih.id));
+        plih.epg_channel_id = Some(Arc::clone(&plih.id));
     }
 
     for suffix in video_suffixes {
@@ -153,11 +170,22 @@ fn extract_id_from_url(url: &str) -> Option<String> {
     None
 }
 
+/// Maps the vlc http options to request headers.
+fn get_vlc_option_header(option: &str) -> Option<(String, String)> {
+    let (key, value) = option.split_once('=')?;
+    match key.trim() {
+        "http-user-agent" => Some((String::from("User-Agent"), value.trim().to_string())),
+        "http-referrer" => Some((String::from("Referer"), value.trim().to_string())),
+        _ => None,
+    }
+}
+
 pub(crate) fn parse_m3u(cfg: &Config, lines: &Vec<String>) -> Vec<PlaylistGroup> {
-    let mut groups: std::collections::HashMap<Rc<String>, Vec<PlaylistItem>> = std::collections::HashMap::new();
-    let mut sort_order: Vec<Rc<String>> = vec![];
+    let mut groups: std::collections::HashMap<Arc<str>, Vec<PlaylistItem>> = std::collections::HashMap::new();
+    let mut sort_order: Vec<Arc<str>> = vec![];
     let mut header: Option<String> = None;
     let mut group: Option<String> = None;
+    let mut headers: Vec<(String, String)> = vec![];
 
     let mut playlist = Vec::new();
     let video_suffixes = cfg.video.as_ref().unwrap().extensions.iter().map(|ext| ext.as_str()).collect();
@@ -170,6 +198,12 @@ pub(crate) fn parse_m3u(cfg: &Config, lines: &Vec<String>) -> Vec<PlaylistGroup>
             group = Some(String::from(&line[8..]));
             continue;
         }
+        if let Some(option) = line.strip_prefix("#EXTVLCOPT:") {
+            if let Some(http_header) = get_vlc_option_header(option) {
+                headers.push(http_header);
+            }
+            continue;
+        }
         if line.starts_with('#') {
             continue;
         }
@@ -177,25 +211,31 @@ pub(crate) fn parse_m3u(cfg: &Config, lines: &Vec<String>) -> Vec<PlaylistGroup>
             let item = PlaylistItem { header: RefCell::new(process_header(&video_suffixes, &header_value, String::from(line))) };
             if item.header.borrow().group.is_empty() {
                 if let Some(group_value) = group {
-                    item.header.borrow_mut().group = Rc::new(group_value);
+                    item.header.borrow_mut().group = Arc::from(group_value);
                 } else {
                     let current_title = item.header.borrow().title.to_owned();
-                    item.header.borrow_mut().group = Rc::new(string_utils::get_title_group(current_title.as_str()));
+                    item.header.borrow_mut().group = Arc::from(string_utils::get_title_group(&current_title));
                 }
             }
+            if !headers.is_empty() {
+                item.header.borrow_mut().headers = Some(std::mem::take(&mut headers));
+            }
             playlist.push(item);
         }
         header = None;
         group = None;
+        headers.clear();
     }
 
     playlist.drain(..).for_each(|item| {
-        let key = Rc::clone(&item.header.borrow().group);
+        // the channels of a group share the title
+        let key = string_utils::intern(&item.header.borrow().group);
+        item.header.borrow_mut().group = Arc::clone(&key);
         // let key2 = String::from(&item.header.group);
-        match groups.entry(Rc::clone(&key)) {
+        match groups.entry(Arc::clone(&key)) {
             std::collections::hash_map::Entry::Vacant(e) => {
                 e.insert(vec![item]);
-                sort_order.push(Rc::clone(&key));
+                sort_order.push(Arc::clone(&key));
             }
             std::collections::hash_map::Entry::Occupied(mut e) => { e.get_mut().push(item); }
         }
@@ -204,7 +244,7 @@ pub(crate) fn parse_m3u(cfg: &Config, lines: &Vec<String>) -> Vec<PlaylistGroup>
     let mut result: Vec<PlaylistGroup> = vec![];
     for (grp_id, (key, channels)) in (1_u32..).zip(groups.into_iter()) {
         let cluster = channels.first().map(|pli| pli.header.borrow().xtream_cluster.clone());
-        result.push(PlaylistGroup { id: grp_id, xtream_cluster: cluster.unwrap(), title: Rc::clone(&key), channels });
+        result.push(PlaylistGroup { id: grp_id, xtream_cluster: cluster.unwrap(), title: Arc::clone(&key), channels });
     }
     result.sort_by(|f, s| {
         let i1 = sort_order.iter().position(|r| **r == *f.title).unwrap();
@@ -0,0 +1,106 @@
+use serde::Serialize;
+
+use crate::create_m3u_filter_error_result;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{Config, ConfigInput};
+use crate::model::mapping::{Mapping, Mappings};
+use crate::model::model_playlist::{FieldAccessor, PlaylistItemHeader};
+use crate::processing::playlist_processor;
+use crate::utils::download;
+
+const COMPARED_FIELDS: &[&str] = &["id", "name", "title", "group", "logo", "logo_small", "chno", "parent_code", "audio_track",
+    "time_shift", "rec", "catchup", "catchup_days", "catchup_source", "url"];
+
+#[derive(Debug, Serialize)]
+pub(crate) struct MappingTestChange {
+    pub field: String,
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct MappingTestChannel {
+    pub group: String,
+    pub title: String,
+    /// the matched mappers like `mapping_id[1]: pattern`, the index starts with 1
+    pub matched: Vec<String>,
+    pub changes: Vec<MappingTestChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct MappingTestResult {
+    pub mappings: Vec<String>,
+    pub channel_count: usize,
+    pub channels: Vec<MappingTestChannel>,
+}
+
+fn format_pairs(pairs: Option<&Vec<(String, String)>>) -> String {
+    pairs.map(|list| list.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<String>>().join(", "))
+        .unwrap_or_default()
+}
+
+fn get_changes(before: &PlaylistItemHeader, after: &PlaylistItemHeader) -> Vec<MappingTestChange> {
+    let mut values: Vec<(&str, String, String)> = COMPARED_FIELDS.iter().map(|field| (*field,
+        before.get_field(field).map(|v| v.to_string()).unwrap_or_default(),
+        after.get_field(field).map(|v| v.to_string()).unwrap_or_default())).collect();
+    values.push(("epg_channel_id",
+                 before.epg_channel_id.as_ref().map(|v| v.to_string()).unwrap_or_default(),
+                 after.epg_channel_id.as_ref().map(|v| v.to_string()).unwrap_or_default()));
+    values.push(("m3u_attributes", format_pairs(before.m3u_attributes.as_ref()), format_pairs(after.m3u_attributes.as_ref())));
+    values.push(("headers", format_pairs(before.headers.as_ref()), format_pairs(after.headers.as_ref())));
+    values.into_iter().filter(|(_, before, after)| before != after)
+        .map(|(field, before, after)| MappingTestChange { field: field.to_string(), before, after })
+        .collect()
+}
+
+fn select_mappings<'a>(mappings: &'a Mappings, mapping_ids: &[String]) -> Result<Vec<&'a Mapping>, M3uFilterError> {
+    if mapping_ids.is_empty() {
+        return Ok(mappings.mappings.mapping.iter().collect());
+    }
+    mapping_ids.iter().map(|mapping_id| match mappings.mappings.mapping.iter().find(|mapping| mapping.id.eq(mapping_id)) {
+        Some(mapping) => Ok(mapping),
+        None => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "unknown mapping: {}", mapping_id),
+    }).collect()
+}
+
+/// Applies the mappings to each channel of the playlist of the input, in the given order or in the order of the mapping file.
+/// Only the channels which are changed or matched by a mapper are returned.
+pub(crate) async fn test_mapping(cfg: &Config, mappings: &Mappings, mapping_ids: &[String], input: &ConfigInput) -> Result<MappingTestResult, M3uFilterError> {
+    let selected = select_mappings(mappings, mapping_ids)?;
+    let (playlist, errors) = download::get_m3u_playlist(cfg, input, &cfg.working_dir, false).await;
+    if playlist.is_empty() {
+        let messages: Vec<String> = errors.iter().map(|err| err.message.to_string()).collect();
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "playlist is empty {}", messages.join(", "));
+    }
+    let mut result = MappingTestResult {
+        mappings: selected.iter().map(|mapping| mapping.id.to_string()).collect(),
+        channel_count: 0,
+        channels: vec![],
+    };
+    let mut mapped = vec![];
+    for channel in playlist.iter().flat_map(|group| &group.channels) {
+        let before = channel.header.borrow().clone();
+        let mut matched = vec![];
+        for mapping in selected.iter().filter(|mapping| mapping.has_channel_mapping()) {
+            for index in playlist_processor::apply_mapping(channel, mapping) {
+                matched.push(format!("{}[{}]: {}", mapping.id, index + 1, mapping.mapper[index].pattern));
+            }
+        }
+        mapped.push((before, matched));
+    }
+    // the counters number the playlist after all channels are mapped
+    selected.iter().for_each(|mapping| playlist_processor::apply_counters(&playlist, mapping));
+    for (channel, (before, matched)) in playlist.iter().flat_map(|group| &group.channels).zip(mapped) {
+        result.channel_count += 1;
+        let changes = get_changes(&before, &channel.header.borrow());
+        if !matched.is_empty() || !changes.is_empty() {
+            result.channels.push(MappingTestChannel {
+                group: before.group.to_string(),
+                title: before.title.to_string(),
+                matched,
+                changes,
+            });
+        }
+    }
+    Ok(result)
+}
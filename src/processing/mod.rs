@@ -2,4 +2,18 @@ pub(crate) mod m3u_parser;
 pub(crate) mod xtream_parser;
 pub(crate) mod playlist_processor;
 pub(crate) mod playlist_watch;
-pub(crate) mod xmltv_parser;
\ No newline at end of file
+pub(crate) mod playlist_diff;
+pub(crate) mod channel_numbering;
+pub(crate) mod playlist_health;
+pub(crate) mod playlist_hooks;
+pub(crate) mod playlist_quality;
+pub(crate) mod playlist_merge;
+pub(crate) mod playlist_enrichment;
+pub(crate) mod filter_test;
+pub(crate) mod mapping_test;
+pub(crate) mod group_selection;
+pub(crate) mod playlist_preview;
+pub(crate) mod playlist_overrides;
+pub(crate) mod playlist_radio;
+pub(crate) mod xmltv_parser;
+pub(crate) mod custom_channels;
@@ -0,0 +1,103 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use log::{error, info};
+use openssl::sha::Sha256;
+use regex::Regex;
+
+use crate::messaging::{MsgKind, send_message};
+use crate::model::config::{Config, ConfigTarget};
+use crate::model::model_playlist::PlaylistGroup;
+use crate::utils::file_utils;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct PlaylistFingerprint {
+    hash: [u8; 32],
+    channels: BTreeSet<String>,
+}
+
+/// The fingerprint of a changed playlist, it is saved with `save` after the playlist was persisted.
+pub(crate) struct PlaylistDiff {
+    path: PathBuf,
+    fingerprint: PlaylistFingerprint,
+}
+
+impl PlaylistDiff {
+    pub(crate) fn save(&self) {
+        if let Err(err) = save_fingerprint(&self.path, &self.fingerprint) {
+            error!("failed to write diff_file {}: {}", &self.path.to_str().unwrap_or_default(), err);
+        }
+    }
+}
+
+// The hash is stored on disk, it has to be the same for every build and platform.
+fn hash_value(hasher: &mut Sha256, value: &str) {
+    hasher.update(value.as_bytes());
+    hasher.update(&[0]);
+}
+
+fn create_fingerprint(cfg: &Config, target: &ConfigTarget, playlist: &[PlaylistGroup]) -> PlaylistFingerprint {
+    let mut hasher = Sha256::new();
+    // output changes have to be written even if the playlist is the same, like a new `{date}` of the output dir
+    hash_value(&mut hasher, &serde_json::to_string(&target.output).unwrap_or_default());
+    hash_value(&mut hasher, &target.get_output_dir(cfg).map(|dir| dir.to_string_lossy().to_string()).unwrap_or_default());
+    let mut channels = BTreeSet::new();
+    playlist.iter().for_each(|pg| {
+        hash_value(&mut hasher, &pg.title);
+        pg.channels.iter().for_each(|chan| {
+            let header = chan.header.borrow();
+            hash_value(&mut hasher, &serde_json::to_string(&*header).unwrap_or_default());
+            channels.insert(format!("{}/{}", pg.title, header.title));
+        });
+    });
+    PlaylistFingerprint { hash: hasher.finish(), channels }
+}
+
+fn handle_diff_notification(cfg: &Config, target_name: &str, added: &BTreeSet<String>, removed: &BTreeSet<String>) {
+    let mut message = vec![];
+    if !added.is_empty() {
+        message.push(format!("added: [\n\t{}\n]\n", added.iter().cloned().collect::<Vec<String>>().join("\n\t")));
+    }
+    if !removed.is_empty() {
+        message.push(format!("removed: [\n\t{}\n]\n", removed.iter().cloned().collect::<Vec<String>>().join("\n\t")));
+    }
+    if !message.is_empty() {
+        let msg = format!("Changes {}\n{}", target_name, message.join(""));
+        info!("{}", &msg);
+        send_message(&MsgKind::Diff, &cfg.messaging, &msg);
+    }
+}
+
+fn load_fingerprint(path: &Path) -> Option<PlaylistFingerprint> {
+    std::fs::read(path).ok().and_then(|encoded| bincode::deserialize(&encoded[..]).ok())
+}
+
+fn save_fingerprint(path: &Path, fingerprint: &PlaylistFingerprint) -> std::io::Result<()> {
+    let encoded: Vec<u8> = bincode::serialize(fingerprint).unwrap();
+    std::fs::write(path, encoded)
+}
+
+/// Compares the playlist with the one from the previous run and sends the added and removed channels.
+/// Returns `false` if the playlist is unchanged, a changed playlist comes with the diff to save once it is written.
+pub(crate) fn process_playlist_diff(cfg: &Config, target: &ConfigTarget, playlist: &[PlaylistGroup]) -> (bool, Option<PlaylistDiff>) {
+    let filename_re = Regex::new(r"[^A-Za-z0-9_-]").unwrap();
+    let diff_filename = format!("diff_{}.bin", filename_re.replace_all(&target.name, "_"));
+    match file_utils::get_file_path(&cfg.working_dir, Some(std::path::PathBuf::from(&diff_filename))) {
+        Some(path) => {
+            let fingerprint = create_fingerprint(cfg, target, playlist);
+            if let Some(previous) = load_fingerprint(&path) {
+                if previous.hash == fingerprint.hash {
+                    return (false, None);
+                }
+                let added: BTreeSet<String> = fingerprint.channels.difference(&previous.channels).cloned().collect();
+                let removed: BTreeSet<String> = previous.channels.difference(&fingerprint.channels).cloned().collect();
+                handle_diff_notification(cfg, &target.name, &added, &removed);
+            }
+            (true, Some(PlaylistDiff { path, fingerprint }))
+        }
+        None => {
+            error!("failed to write diff_file {}", &diff_filename);
+            (true, None)
+        }
+    }
+}
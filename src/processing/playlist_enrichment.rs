@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::model::config::{Config, ConfigTarget, TmdbConfig};
+use crate::model::model_playlist::{PlaylistGroup, PlaylistItemHeader, PlaylistItemType};
+use crate::utils::{file_utils, video_search};
+
+const TMDB_CACHE_FILE: &str = "tmdb_cache.json";
+const SECONDS_PER_DAY: i64 = 86_400;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum TmdbKind {
+    Movie,
+    Tv,
+}
+
+impl Display for TmdbKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            TmdbKind::Movie => "movie",
+            TmdbKind::Tv => "tv",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TmdbMetadata {
+    pub id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plot: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year: Option<String>,
+    pub genres: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poster: Option<String>,
+}
+
+/// `metadata` is `None` if the title was not found.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CachedLookup {
+    pub checked: i64,
+    pub metadata: Option<TmdbMetadata>,
+}
+
+struct TmdbLookup {
+    kind: TmdbKind,
+    title: String,
+    year: Option<u16>,
+    tmdb_id: Option<u64>,
+}
+
+impl TmdbLookup {
+    fn get_key(&self) -> String {
+        match self.tmdb_id {
+            Some(id) => format!("{}/id/{}", self.kind, id),
+            None => format!("{}/{}/{}", self.kind, self.title.to_lowercase(), self.year.map(|year| year.to_string()).unwrap_or_default()),
+        }
+    }
+}
+
+fn get_property<'a>(header: &'a PlaylistItemHeader, name: &str) -> Option<&'a Value> {
+    header.additional_properties.as_ref()
+        .and_then(|props| props.iter().find(|(key, _)| key == name))
+        .map(|(_, value)| value)
+}
+
+fn has_property(header: &PlaylistItemHeader, name: &str) -> bool {
+    get_property(header, name).is_some_and(|value| match value {
+        Value::String(text) => !text.trim().is_empty() && text != "0",
+        Value::Number(num) => num.as_f64() != Some(0.0),
+        Value::Null => false,
+        _ => true,
+    })
+}
+
+fn set_missing_property(header: &mut PlaylistItemHeader, name: &str, value: Value) {
+    if has_property(header, name) {
+        return;
+    }
+    let props = header.additional_properties.get_or_insert_with(Vec::new);
+    props.retain(|(key, _)| key != name);
+    props.push((name.to_string(), value));
+}
+
+/// Movies and series are looked up by title, episodes by the name of their series.
+/// Items which have all fields are skipped.
+fn get_lookup(header: &PlaylistItemHeader) -> Option<TmdbLookup> {
+    let (kind, fields, title): (TmdbKind, &[&str], Option<String>) = match header.item_type {
+        PlaylistItemType::Movie => (TmdbKind::Movie, &["tmdb_id", "plot", "year", "genre"], Some(header.title.to_string())),
+        PlaylistItemType::SeriesInfo => (TmdbKind::Tv, &["tmdb_id", "plot", "year", "genre"], Some(header.title.to_string())),
+        PlaylistItemType::Series => (TmdbKind::Tv, &["tmdb_id", "genre"], get_property(header, "series_name").and_then(Value::as_str).map(String::from)),
+        PlaylistItemType::Live => return None,
+    };
+    let missing_logo = header.item_type != PlaylistItemType::Series && header.logo.is_empty();
+    if !missing_logo && fields.iter().all(|field| has_property(header, field)) {
+        return None;
+    }
+    let (title, year) = video_search::split_year_suffix(&title?);
+    if title.is_empty() {
+        return None;
+    }
+    let tmdb_id = get_property(header, "tmdb_id").and_then(|value| match value {
+        Value::Number(num) => num.as_u64(),
+        Value::String(text) => text.trim().parse::<u64>().ok(),
+        _ => None,
+    }).filter(|id| *id > 0);
+    let year = year.or_else(|| get_property(header, "year").and_then(|value| match value {
+        Value::Number(num) => num.to_string().parse::<u16>().ok(),
+        Value::String(text) => text.trim().parse::<u16>().ok(),
+        _ => None,
+    }));
+    Some(TmdbLookup { kind, title, year, tmdb_id })
+}
+
+fn get_cache_path(cfg: &Config) -> Option<PathBuf> {
+    file_utils::get_file_path(&cfg.working_dir, Some(PathBuf::from(TMDB_CACHE_FILE)))
+}
+
+/// Expired lookups are dropped.
+pub(crate) fn load_cache(path: &Path, tmdb: &TmdbConfig, now: i64) -> HashMap<String, CachedLookup> {
+    let mut cache: HashMap<String, CachedLookup> = std::fs::read_to_string(path).ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    let max_age = i64::from(tmdb.cache_days) * SECONDS_PER_DAY;
+    cache.retain(|_, lookup| now - lookup.checked < max_age);
+    cache
+}
+
+/// The targets are enriched in parallel and share the cache, the saves are serialized.
+fn cache_lock() -> &'static Mutex<()> {
+    static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+    LOCK.get_or_init(|| Mutex::new(()))
+}
+
+/// Merges the lookups of this run into the cache file, lookups saved by other targets since the load are kept.
+/// Each save writes its own tmp file, which replaces the cache with the rename.
+pub(crate) fn save_cache(path: &Path, tmdb: &TmdbConfig, now: i64, lookups: &HashMap<String, CachedLookup>) {
+    static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+    let _guard = cache_lock().lock().unwrap();
+    let mut cache = load_cache(path, tmdb, now);
+    cache.extend(lookups.iter().map(|(key, lookup)| (key.to_string(), lookup.clone())));
+    let tmp_path = path.with_extension(format!("{}.{}.tmp", std::process::id(), TMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+    let result = std::fs::write(&tmp_path, serde_json::to_string(&cache).unwrap_or_default())
+        .and_then(|()| std::fs::rename(&tmp_path, path));
+    if let Err(err) = result {
+        let _ = std::fs::remove_file(&tmp_path);
+        error!("failed to write tmdb cache {}: {}", path.to_str().unwrap_or_default(), err);
+    }
+}
+
+/// Hands out one request per interval of the configured `rate`.
+struct TmdbClient<'a> {
+    tmdb: &'a TmdbConfig,
+    client: reqwest::Client,
+    interval: Duration,
+    next: Instant,
+}
+
+impl TmdbClient<'_> {
+    async fn get(&mut self, path: &str, params: &[(&str, String)]) -> Result<Value, String> {
+        let now = Instant::now();
+        if self.next > now {
+            actix_rt::time::sleep(self.next - now).await;
+        }
+        self.next = self.next.max(now) + self.interval;
+        let mut url = url::Url::parse(&format!("{}/{}", &self.tmdb.api_url, path)).map_err(|err| err.to_string())?;
+        {
+            let mut pairs = url.query_pairs_mut();
+            pairs.append_pair("api_key", &self.tmdb.api_key);
+            if let Some(language) = &self.tmdb.language {
+                pairs.append_pair("language", language);
+            }
+            params.iter().for_each(|(key, value)| { pairs.append_pair(key, value); });
+        }
+        let response = self.client.get(url).send().await.map_err(|err| err.without_url().to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("tmdb responded with status {}", response.status()));
+        }
+        response.json().await.map_err(|err| err.without_url().to_string())
+    }
+
+    async fn search(&mut self, lookup: &TmdbLookup) -> Result<Option<u64>, String> {
+        let mut params = vec![("query", lookup.title.to_string())];
+        if let Some(year) = lookup.year {
+            params.push((if lookup.kind == TmdbKind::Movie { "year" } else { "first_air_date_year" }, year.to_string()));
+        }
+        let content = self.get(&format!("search/{}", lookup.kind), &params).await?;
+        Ok(content.get("results").and_then(Value::as_array)
+            .and_then(|results| results.first())
+            .and_then(|result| result.get("id")).and_then(Value::as_u64))
+    }
+
+    async fn lookup(&mut self, lookup: &TmdbLookup) -> Result<Option<TmdbMetadata>, String> {
+        let id = match lookup.tmdb_id {
+            Some(id) => id,
+            None => match self.search(lookup).await? {
+                Some(id) => id,
+                None => return Ok(None),
+            },
+        };
+        let details = self.get(&format!("{}/{}", lookup.kind, id), &[]).await?;
+        let get_text = |field: &str| details.get(field).and_then(Value::as_str)
+            .map(str::trim).filter(|text| !text.is_empty()).map(String::from);
+        Ok(Some(TmdbMetadata {
+            id,
+            plot: get_text("overview"),
+            year: get_text(if lookup.kind == TmdbKind::Movie { "release_date" } else { "first_air_date" })
+                .and_then(|date| date.get(..4).map(String::from)),
+            genres: details.get("genres").and_then(Value::as_array).map_or(vec![], |genres| genres.iter()
+                .filter_map(|genre| genre.get("name").and_then(Value::as_str).map(String::from)).collect()),
+            poster: get_text("poster_path").map(|path| format!("{}{}", video_search::TMDB_POSTER_URL, path)),
+        }))
+    }
+}
+
+/// Only missing fields are set, the plot, year and poster of a series are not applied to its episodes.
+fn apply_metadata(header: &mut PlaylistItemHeader, metadata: &TmdbMetadata) {
+    set_missing_property(header, "tmdb_id", Value::from(metadata.id));
+    if !metadata.genres.is_empty() {
+        set_missing_property(header, "genre", Value::String(metadata.genres.join(", ")));
+    }
+    if header.item_type == PlaylistItemType::Series {
+        return;
+    }
+    if let Some(plot) = &metadata.plot {
+        set_missing_property(header, "plot", Value::String(plot.to_string()));
+    }
+    if let Some(year) = &metadata.year {
+        set_missing_property(header, "year", Value::String(year.to_string()));
+    }
+    if let Some(poster) = &metadata.poster {
+        set_missing_property(header, "cover", Value::String(poster.to_string()));
+        if header.logo.is_empty() {
+            header.logo = Arc::from(poster.as_str());
+        }
+    }
+}
+
+/// Fills missing metadata of the movies and series of targets with `tmdb_enrich` from TMDB.
+/// The lookups are cached in the working dir for `cache_days`, a failed request stops the lookups of this run.
+pub(crate) async fn enrich_playlist(cfg: &Config, target: &ConfigTarget, playlist: &mut [PlaylistGroup]) {
+    let tmdb = match &cfg.tmdb {
+        Some(tmdb) if target.options.as_ref().is_some_and(|options| options.tmdb_enrich) => tmdb,
+        _ => return,
+    };
+    let path = match get_cache_path(cfg) {
+        Some(path) => path,
+        None => {
+            error!("failed to get tmdb cache file for target: {}", &target.name);
+            return;
+        }
+    };
+    let client = match reqwest::Client::builder().timeout(Duration::from_secs(10)).build() {
+        Ok(client) => client,
+        Err(err) => {
+            error!("failed to create http client for tmdb: {}", err);
+            return;
+        }
+    };
+    let now = chrono::Local::now().timestamp();
+    let mut cache = load_cache(&path, tmdb, now);
+    let mut lookups = HashMap::new();
+    let mut tmdb_client = TmdbClient { tmdb, client, interval: Duration::from_secs_f64(1.0 / tmdb.rate), next: Instant::now() };
+    let mut failed = false;
+    let (mut requested, mut enriched) = (0, 0);
+    for channel in playlist.iter().flat_map(|group| &group.channels) {
+        let lookup = match get_lookup(&channel.header.borrow()) {
+            Some(lookup) => lookup,
+            None => continue,
+        };
+        let key = lookup.get_key();
+        if !cache.contains_key(&key) {
+            if failed {
+                continue;
+            }
+            requested += 1;
+            match tmdb_client.lookup(&lookup).await {
+                Ok(metadata) => {
+                    debug!("tmdb lookup {}: {}", &key, metadata.as_ref().map_or(0, |meta| meta.id));
+                    lookups.insert(key.to_string(), CachedLookup { checked: now, metadata: metadata.clone() });
+                    cache.insert(key.to_string(), CachedLookup { checked: now, metadata });
+                }
+                Err(err) => {
+                    error!("tmdb lookup failed for {}, skipping further lookups: {}", &lookup.title, err);
+                    failed = true;
+                    continue;
+                }
+            }
+        }
+        if let Some(metadata) = cache.get(&key).and_then(|cached| cached.metadata.as_ref()) {
+            apply_metadata(&mut channel.header.borrow_mut(), metadata);
+            enriched += 1;
+        }
+    }
+    info!("TMDB enrichment {}: {} items enriched, {} lookups", &target.name, enriched, requested);
+    if !lookups.is_empty() {
+        save_cache(&path, tmdb, now, &lookups);
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use log::{error, info};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::model::config::{Config, ConfigHealthCheck, ConfigTarget};
+use crate::model::model_config::HealthAction;
+use crate::model::model_playlist::{PlaylistGroup, PlaylistItemType};
+use crate::utils::{file_utils, request_utils};
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub(crate) struct ChannelHealth {
+    pub title: String,
+    pub group: String,
+    pub alive: bool,
+    pub checked: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct HealthReport {
+    pub target: String,
+    pub alive: usize,
+    pub dead: usize,
+    pub channels: Vec<ChannelHealth>,
+}
+
+fn get_health_path(cfg: &Config, target_name: &str) -> Option<PathBuf> {
+    let filename_re = Regex::new(r"[^A-Za-z0-9_-]").unwrap();
+    let filename = format!("health_{}.json", filename_re.replace_all(target_name, "_"));
+    file_utils::get_file_path(&cfg.working_dir, Some(PathBuf::from(filename)))
+}
+
+/// The results are stored by channel url.
+fn load_health(path: &Path) -> BTreeMap<String, ChannelHealth> {
+    std::fs::read_to_string(path).ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+async fn probe_with_ffprobe(ffprobe: String, url: String, timeout: u16) -> Result<(), String> {
+    let result = actix_rt::task::spawn_blocking(move || {
+        std::process::Command::new(ffprobe)
+            .args(["-v", "error", "-rw_timeout", &(u64::from(timeout) * 1_000_000).to_string(),
+                "-show_entries", "stream=codec_type", "-of", "csv=p=0", &url])
+            .output()
+    }).await;
+    match result {
+        Ok(Ok(output)) if output.status.success() && !output.stdout.is_empty() => Ok(()),
+        Ok(Ok(output)) => Err(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+        Ok(Err(err)) => Err(format!("failed to execute ffprobe: {}", err)),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// A channel is alive if the stream responds and delivers data.
+/// Only the first chunk is read, many providers don't support HEAD requests.
+async fn probe_with_request(client: reqwest::Client, url: String, headers: HashMap<String, String>) -> Result<(), String> {
+    let mut response = client.get(url.as_str())
+        .headers(request_utils::get_request_headers(&headers, None))
+        .send().await.map_err(|err| err.without_url().to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("status {}", response.status()));
+    }
+    match response.chunk().await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(String::from("empty response")),
+        Err(err) => Err(err.without_url().to_string()),
+    }
+}
+
+async fn probe_channel(health_check: &ConfigHealthCheck, client: reqwest::Client,
+                       url: String, headers: HashMap<String, String>) -> Result<(), String> {
+    match &health_check.ffprobe {
+        Some(ffprobe) => probe_with_ffprobe(ffprobe.to_string(), url, health_check.timeout).await,
+        None => probe_with_request(client, url, headers).await,
+    }
+}
+
+/// The probes go through the proxy of the inputs of the target,
+/// the channels don't know their input, so the first configured proxy is used for all.
+fn get_target_proxy<'a>(cfg: &'a Config, target_name: &str) -> Option<&'a str> {
+    cfg.sources.iter()
+        .filter(|source| source.targets.iter().any(|target| target.name == target_name))
+        .flat_map(|source| &source.inputs)
+        .find_map(|input| input._proxy.as_deref())
+        .or(cfg.proxy.as_deref())
+}
+
+/// Probes the live channels of the target and marks or removes the dead channels.
+/// If a sample size is set, the channels with the oldest result are probed,
+/// the other channels keep the result of a previous run.
+pub(crate) async fn check_playlist_health(cfg: &Config, target: &ConfigTarget, playlist: &mut Vec<PlaylistGroup>) {
+    let health_check = match &target.health_check {
+        Some(health_check) => health_check,
+        None => return,
+    };
+    let path = match get_health_path(cfg, &target.name) {
+        Some(path) => path,
+        None => {
+            error!("failed to get health file for target: {}", &target.name);
+            return;
+        }
+    };
+    let previous = load_health(&path);
+
+    let mut seen_urls = HashSet::new();
+    let mut candidates: Vec<(i64, String, HashMap<String, String>)> = playlist.iter()
+        .flat_map(|group| &group.channels)
+        .filter_map(|channel| {
+            let header = channel.header.borrow();
+            if header.item_type == PlaylistItemType::Live && seen_urls.insert(header.url.to_string()) {
+                let checked = previous.get(header.url.as_ref()).map_or(0, |health| health.checked);
+                let headers = header.headers.as_ref().map(|h| h.iter().cloned().collect()).unwrap_or_default();
+                Some((checked, header.url.to_string(), headers))
+            } else {
+                None
+            }
+        }).collect();
+    candidates.sort_by_key(|(checked, _, _)| *checked);
+    candidates.truncate(health_check.sample.unwrap_or(candidates.len()));
+
+    let builder = reqwest::Client::builder().timeout(Duration::from_secs(u64::from(health_check.timeout)));
+    let client = match request_utils::build_client(builder, get_target_proxy(cfg, &target.name)) {
+        Ok(client) => client,
+        Err(err) => {
+            error!("failed to create http client for health check: {}", err);
+            return;
+        }
+    };
+    let probed_count = candidates.len();
+    let results: HashMap<String, Result<(), String>> = futures::stream::iter(candidates)
+        .map(|(_, url, headers)| {
+            let client = client.clone();
+            async move {
+                let result = probe_channel(health_check, client, url.clone(), headers).await;
+                (url, result)
+            }
+        })
+        .buffer_unordered(usize::from(health_check.parallel.max(1)))
+        .collect().await;
+
+    let now = chrono::Local::now().timestamp();
+    let mut health = BTreeMap::new();
+    for group in playlist.iter() {
+        for channel in &group.channels {
+            let header = channel.header.borrow();
+            let channel_health = match results.get(header.url.as_ref()) {
+                Some(result) => Some(ChannelHealth {
+                    title: header.title.to_string(),
+                    group: group.title.to_string(),
+                    alive: result.is_ok(),
+                    checked: now,
+                    error: result.as_ref().err().cloned(),
+                }),
+                None => previous.get(header.url.as_ref()).map(|prev| ChannelHealth {
+                    title: header.title.to_string(),
+                    group: group.title.to_string(),
+                    ..prev.clone()
+                }),
+            };
+            if let Some(channel_health) = channel_health {
+                health.insert(header.url.to_string(), channel_health);
+            }
+        }
+    }
+
+    let is_dead = |url: &str| health.get(url).is_some_and(|h| !h.alive);
+    let mut dead_count = 0;
+    match health_check.action {
+        HealthAction::Remove => {
+            for group in playlist.iter_mut() {
+                group.channels.retain(|channel| {
+                    let dead = is_dead(channel.header.borrow().url.as_ref());
+                    if dead { dead_count += 1; }
+                    !dead
+                });
+            }
+            playlist.retain(|group| !group.channels.is_empty());
+        }
+        HealthAction::Mark => {
+            for channel in playlist.iter().flat_map(|group| &group.channels) {
+                let mut header = channel.header.borrow_mut();
+                if is_dead(&header.url) {
+                    dead_count += 1;
+                    header.title = Arc::from(format!("{}{}", health_check.mark_prefix, header.title));
+                    header.name = Arc::from(format!("{}{}", health_check.mark_prefix, header.name));
+                }
+            }
+        }
+    }
+    info!("Health check {}: {} channels probed, {} dead", &target.name, probed_count, dead_count);
+
+    if let Err(err) = std::fs::write(&path, serde_json::to_string(&health).unwrap_or_default()) {
+        error!("failed to write health file {}: {}", path.to_str().unwrap_or_default(), err);
+    }
+}
+
+/// Returns the health check results of the last run for the targets with a health check.
+pub(crate) fn get_health_reports(cfg: &Config, target_name: Option<&str>) -> Vec<HealthReport> {
+    cfg.sources.iter().flat_map(|source| &source.targets)
+        .filter(|target| target.health_check.is_some())
+        .filter(|target| target_name.is_none_or(|name| target.name.eq_ignore_ascii_case(name)))
+        .filter_map(|target| {
+            let channels: Vec<ChannelHealth> = load_health(&get_health_path(cfg, &target.name)?).into_values().collect();
+            let alive = channels.iter().filter(|channel| channel.alive).count();
+            Some(HealthReport {
+                target: target.name.to_string(),
+                alive,
+                dead: channels.len() - alive,
+                channels,
+            })
+        }).collect()
+}
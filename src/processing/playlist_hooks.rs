@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::debug;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::create_m3u_filter_error_result;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{Config, ConfigHook, ConfigTarget};
+use crate::model::model_config::TargetType;
+use crate::model::model_playlist::{PlaylistGroup, PlaylistItemHeader};
+use crate::repository::xtream_repository::get_xtream_storage_path;
+
+/// The hook is killed after its `timeout`, a hung hook doesn't stall the processing.
+async fn execute_hook(hook: &ConfigHook, stage: &str, target_name: &str, input: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut child = Command::new(&hook.command)
+        .args(&hook.args)
+        .env("M3U_FILTER_STAGE", stage)
+        .env("M3U_FILTER_TARGET", target_name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn().map_err(|err| err.to_string())?;
+    // the playlist is written while the output is read, a hook can start writing before it has read everything
+    let mut stdin = child.stdin.take().ok_or_else(|| String::from("failed to open stdin"))?;
+    let write_input = async move {
+        // a hook which doesn't read its input closes the pipe, this is not an error
+        let _ = stdin.write_all(&input).await;
+    };
+    let execution = futures::future::join(write_input, child.wait_with_output());
+    match tokio::time::timeout(Duration::from_secs(u64::from(hook.timeout)), execution).await {
+        Ok((_, Ok(output))) if output.status.success() => Ok(output.stdout),
+        Ok((_, Ok(output))) => Err(format!("exited with {}", output.status)),
+        Ok((_, Err(err))) => Err(err.to_string()),
+        Err(_) => Err(format!("killed after {} seconds", hook.timeout)),
+    }
+}
+
+/// Fields which are not part of the json are taken from the channel with the same url.
+fn restore_hidden_fields(original: &[PlaylistGroup], playlist: &mut [PlaylistGroup]) {
+    let headers: HashMap<Arc<str>, PlaylistItemHeader> = original.iter().flat_map(|group| &group.channels)
+        .map(|channel| {
+            let header = channel.header.borrow();
+            (Arc::clone(&header.url), header.clone())
+        }).collect();
+    let clusters: HashMap<u32, _> = original.iter().map(|group| (group.id, group.xtream_cluster.clone())).collect();
+    for group in playlist.iter_mut() {
+        for channel in &group.channels {
+            let mut header = channel.header.borrow_mut();
+            if let Some(orig) = headers.get(&header.url) {
+                header.xtream_cluster = orig.xtream_cluster.clone();
+                header.additional_properties = orig.additional_properties.clone();
+                header.m3u_attributes = orig.m3u_attributes.clone();
+                header.headers = orig.headers.clone();
+                header.item_type = orig.item_type.clone();
+                header.series_fetched = orig.series_fetched;
+            }
+        }
+        group.xtream_cluster = match clusters.get(&group.id) {
+            Some(cluster) => cluster.clone(),
+            None => group.channels.first().map_or(group.xtream_cluster.clone(), |channel| channel.header.borrow().xtream_cluster.clone()),
+        };
+    }
+}
+
+/// Runs the hook with the playlist as json on stdin. If the hook writes a playlist to stdout,
+/// it replaces the playlist, an empty output keeps the playlist unchanged.
+pub(crate) async fn run_hook(hook: Option<&ConfigHook>, stage: &str, target: &ConfigTarget, playlist: &mut Vec<PlaylistGroup>) -> Result<(), M3uFilterError> {
+    let hook = match hook {
+        Some(hook) => hook,
+        None => return Ok(()),
+    };
+    debug!("Running {} hook for target {}: {}", stage, &target.name, &hook.command);
+    let input = match serde_json::to_vec(playlist) {
+        Ok(input) => input,
+        Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to serialize playlist for {} hook: {}", stage, err),
+    };
+    let output = match execute_hook(hook, stage, &target.name, input).await {
+        Ok(output) => output,
+        Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "{} hook {} failed for target {}: {}", stage, &hook.command, &target.name, err),
+    };
+    if output.iter().all(u8::is_ascii_whitespace) {
+        return Ok(());
+    }
+    match serde_json::from_slice::<Vec<PlaylistGroup>>(&output) {
+        Ok(mut new_playlist) => {
+            restore_hidden_fields(playlist, &mut new_playlist);
+            new_playlist.retain(|group| !group.channels.is_empty());
+            *playlist = new_playlist;
+            Ok(())
+        }
+        Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "invalid playlist from {} hook {}: {}", stage, &hook.command, err),
+    }
+}
+
+fn get_output_env(cfg: &Config, target: &ConfigTarget) -> Vec<(String, String)> {
+    target.output.iter().filter_map(|output| {
+        let path = match output.target {
+            TargetType::Xtream => get_xtream_storage_path(cfg, &target.name),
+            _ => output.filename.as_ref().and_then(|filename| target.get_output_file_path(cfg, filename)),
+        }?;
+        Some((format!("M3U_FILTER_OUTPUT_{}", output.target.to_string().to_uppercase()), path.to_string_lossy().to_string()))
+    }).collect()
+}
+
+/// Runs the `exec` command of the target after its files are written. The paths of the outputs
+/// are passed as `M3U_FILTER_OUTPUT_<TYPE>`, like `M3U_FILTER_OUTPUT_M3U`. The command is killed after its `timeout`.
+pub(crate) async fn run_exec(cfg: &Config, target: &ConfigTarget, playlist: &[PlaylistGroup]) -> Result<(), M3uFilterError> {
+    let exec = match &target.exec {
+        Some(exec) => exec,
+        None => return Ok(()),
+    };
+    debug!("Running exec for target {}: {}", &target.name, &exec.command);
+    let channel_count: usize = playlist.iter().map(|group| group.channels.len()).sum();
+    let child = Command::new(&exec.command)
+        .args(&exec.args)
+        .env("M3U_FILTER_TARGET", &target.name)
+        .env("M3U_FILTER_GROUP_COUNT", playlist.len().to_string())
+        .env("M3U_FILTER_CHANNEL_COUNT", channel_count.to_string())
+        .envs(get_output_env(cfg, target))
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "exec {} failed for target {}: {}", &exec.command, &target.name, err),
+    };
+    match tokio::time::timeout(Duration::from_secs(u64::from(exec.timeout)), child.wait()).await {
+        Ok(Ok(status)) if status.success() => Ok(()),
+        Ok(Ok(status)) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "exec {} failed for target {}: exited with {}", &exec.command, &target.name, status),
+        Ok(Err(err)) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "exec {} failed for target {}: {}", &exec.command, &target.name, err),
+        Err(_) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "exec {} for target {} killed after {} seconds", &exec.command, &target.name, exec.timeout),
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+
+use log::info;
+
+use crate::model::config::ConfigTarget;
+use crate::model::model_config::{DuplicateEpgId, MergeStrategy};
+use crate::model::model_playlist::{PlaylistGroup, PlaylistItem};
+
+/// Calls `resolve` for each channel whose key is already used by a previous input and removes the channel
+/// if `resolve` returns `false`. Channels of the same input are no conflict. Returns the number of conflicts.
+fn resolve_conflicts<K, R>(playlists: &mut [Vec<PlaylistGroup>], get_key: K, mut resolve: R) -> usize
+    where K: Fn(&PlaylistItem) -> Option<String>,
+          R: FnMut(&PlaylistItem) -> bool {
+    let mut known: HashSet<String> = HashSet::new();
+    let mut conflicts = 0;
+    for playlist in playlists.iter_mut() {
+        let mut input_keys = HashSet::new();
+        for group in playlist.iter_mut() {
+            group.channels.retain(|channel| match get_key(channel) {
+                Some(key) => {
+                    let conflict = known.contains(&key);
+                    input_keys.insert(key);
+                    if conflict {
+                        conflicts += 1;
+                        resolve(channel)
+                    } else {
+                        true
+                    }
+                }
+                None => true,
+            });
+        }
+        playlist.retain(|group| !group.channels.is_empty());
+        known.extend(input_keys);
+    }
+    conflicts
+}
+
+fn get_name_key(channel: &PlaylistItem) -> Option<String> {
+    let name = channel.header.borrow().name.trim().to_lowercase();
+    if name.is_empty() { None } else { Some(name) }
+}
+
+fn get_epg_id_key(channel: &PlaylistItem) -> Option<String> {
+    channel.header.borrow().epg_channel_id.as_ref()
+        .map(|epg_id| epg_id.trim().to_string())
+        .filter(|epg_id| !epg_id.is_empty())
+}
+
+/// Takes the groups of the inputs in turns, groups with the same title and cluster are merged into the first one.
+fn interleave_groups(playlists: Vec<Vec<PlaylistGroup>>) -> Vec<PlaylistGroup> {
+    let mut merged: Vec<PlaylistGroup> = vec![];
+    let mut iterators: Vec<_> = playlists.into_iter().map(|playlist| playlist.into_iter()).collect();
+    loop {
+        let mut taken = false;
+        for iterator in &mut iterators {
+            if let Some(group) = iterator.next() {
+                taken = true;
+                match merged.iter_mut().find(|grp| grp.title == group.title && grp.xtream_cluster == group.xtream_cluster) {
+                    Some(grp) => grp.channels.extend(group.channels),
+                    None => merged.push(group),
+                }
+            }
+        }
+        if !taken {
+            return merged;
+        }
+    }
+}
+
+/// Merges the playlists of the inputs of a source in input order with the `merge` strategy of the target.
+/// Without `merge` the playlists are concatenated.
+pub(crate) fn merge_playlists(target: &ConfigTarget, mut playlists: Vec<Vec<PlaylistGroup>>) -> Vec<PlaylistGroup> {
+    let merge = match &target.merge {
+        Some(merge) if playlists.len() > 1 => merge,
+        _ => return playlists.into_iter().flatten().collect(),
+    };
+    if merge.strategy == MergeStrategy::PreferFirst {
+        let removed = resolve_conflicts(&mut playlists, get_name_key, |_| false);
+        if removed > 0 {
+            info!("Removed {} channels with names of previous inputs from {}", removed, &target.name);
+        }
+    }
+    let conflicts = match merge.duplicate_epg_id {
+        DuplicateEpgId::Keep => 0,
+        DuplicateEpgId::Drop => resolve_conflicts(&mut playlists, get_epg_id_key, |_| false),
+        DuplicateEpgId::Clear => resolve_conflicts(&mut playlists, get_epg_id_key, |channel| {
+            channel.header.borrow_mut().epg_channel_id = None;
+            true
+        }),
+    };
+    if conflicts > 0 {
+        info!("Resolved {} channels with epg ids of previous inputs for {}", conflicts, &target.name);
+    }
+    match merge.strategy {
+        MergeStrategy::Interleave => interleave_groups(playlists),
+        MergeStrategy::Concat | MergeStrategy::PreferFirst => playlists.into_iter().flatten().collect(),
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+
+use crate::create_m3u_filter_error_result;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{Config, ConfigTarget};
+use crate::model::model_playlist::{PlaylistGroup, PlaylistItem, XtreamCluster};
+use crate::utils::{file_utils, string_utils};
+
+const OVERRIDES_FILE: &str = "overrides.json";
+const CLUSTERS: [&str; 3] = ["live", "movie", "series"];
+
+/// A manual change of one channel, identified by its stream id.
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub(crate) struct ChannelOverride {
+    pub id: String,
+    /// `live`, `movie` or `series`, without it the override applies to the id in all clusters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<String>,
+    /// without a target the override applies to all targets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// the channel is kept even if the filter of the target removes it
+    #[serde(default)]
+    pub pin: bool,
+    #[serde(default)]
+    pub hide: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+impl ChannelOverride {
+    fn is_same(&self, other: &ChannelOverride) -> bool {
+        self.id == other.id && self.cluster == other.cluster
+            && self.target.as_deref().map(str::to_lowercase) == other.target.as_deref().map(str::to_lowercase)
+    }
+
+    fn get_key(&self) -> String {
+        match &self.cluster {
+            Some(cluster) => format!("{}/{}", cluster, self.id),
+            None => self.id.to_string(),
+        }
+    }
+
+    pub(crate) fn validate(&mut self) -> Result<(), M3uFilterError> {
+        self.id = self.id.trim().to_string();
+        if self.id.is_empty() {
+            return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "override needs the stream id of the channel");
+        }
+        if let Some(cluster) = &self.cluster {
+            if !CLUSTERS.contains(&cluster.as_str()) {
+                return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "invalid cluster {}, valid values are {}", cluster, CLUSTERS.join(", "));
+            }
+        }
+        self.target = self.target.as_ref().map(|target| target.trim().to_string()).filter(|target| !target.is_empty());
+        self.title = self.title.as_ref().map(|title| title.trim().to_string()).filter(|title| !title.is_empty());
+        self.group = self.group.as_ref().map(|group| group.trim().to_string()).filter(|group| !group.is_empty());
+        if self.pin && self.hide {
+            return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "override of {} can't pin and hide the channel", self.id);
+        }
+        if !self.pin && !self.hide && self.title.is_none() && self.group.is_none() {
+            return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "override of {} changes nothing", self.id);
+        }
+        Ok(())
+    }
+}
+
+/// The overrides file is read and written under the lock, api requests can change it while a target is processed.
+fn file_lock() -> &'static Mutex<()> {
+    static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+    LOCK.get_or_init(|| Mutex::new(()))
+}
+
+fn get_overrides_path(cfg: &Config) -> Option<PathBuf> {
+    file_utils::get_file_path(&cfg.working_dir, Some(PathBuf::from(OVERRIDES_FILE)))
+}
+
+fn load_overrides(cfg: &Config) -> Vec<ChannelOverride> {
+    get_overrides_path(cfg)
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str::<Vec<ChannelOverride>>(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_overrides(cfg: &Config, overrides: &[ChannelOverride]) -> Result<(), M3uFilterError> {
+    let path = match get_overrides_path(cfg) {
+        Some(path) => path,
+        None => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant write overrides"),
+    };
+    let tmp_path = path.with_extension("tmp");
+    let result = serde_json::to_string_pretty(overrides).map_err(|err| err.to_string())
+        .and_then(|content| std::fs::write(&tmp_path, content).and_then(|_| std::fs::rename(&tmp_path, &path)).map_err(|err| err.to_string()));
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            error!("cant write overrides {}: {}", path.to_str().unwrap_or("?"), err);
+            create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant write overrides: {}", err)
+        }
+    }
+}
+
+/// Returns the overrides, only the ones of the target and the ones for all targets if a target is given.
+pub(crate) fn get_overrides(cfg: &Config, target_name: Option<&str>) -> Vec<ChannelOverride> {
+    let _guard = file_lock().lock().unwrap();
+    let overrides = load_overrides(cfg);
+    match target_name {
+        Some(name) => overrides.into_iter()
+            .filter(|item| item.target.as_ref().is_none_or(|target| target.eq_ignore_ascii_case(name))).collect(),
+        None => overrides,
+    }
+}
+
+/// Adds the override or replaces the one with the same id, cluster and target.
+pub(crate) fn put_override(cfg: &Config, mut item: ChannelOverride) -> Result<ChannelOverride, M3uFilterError> {
+    item.validate()?;
+    let _guard = file_lock().lock().unwrap();
+    let mut overrides = load_overrides(cfg);
+    match overrides.iter_mut().find(|existing| existing.is_same(&item)) {
+        Some(existing) => *existing = item.clone(),
+        None => overrides.push(item.clone()),
+    }
+    save_overrides(cfg, &overrides)?;
+    Ok(item)
+}
+
+/// Removes the overrides of the stream id, restricted to the cluster and target if they are given.
+/// Returns the number of removed overrides.
+pub(crate) fn remove_overrides(cfg: &Config, id: &str, cluster: Option<&str>, target_name: Option<&str>) -> Result<usize, M3uFilterError> {
+    let _guard = file_lock().lock().unwrap();
+    let mut overrides = load_overrides(cfg);
+    let count = overrides.len();
+    overrides.retain(|item| !(item.id == id
+        && cluster.is_none_or(|cluster| item.cluster.as_deref() == Some(cluster))
+        && target_name.is_none_or(|name| item.target.as_ref().is_some_and(|target| target.eq_ignore_ascii_case(name)))));
+    let removed = count - overrides.len();
+    if removed > 0 {
+        save_overrides(cfg, &overrides)?;
+    }
+    Ok(removed)
+}
+
+/// The overrides of a target by `cluster/id` or `id`, the ones of the target replace the ones for all targets.
+pub(crate) struct TargetOverrides {
+    overrides: HashMap<String, ChannelOverride>,
+}
+
+impl TargetOverrides {
+    pub(crate) fn load(cfg: &Config, target: &ConfigTarget) -> TargetOverrides {
+        let mut overrides: Vec<ChannelOverride> = get_overrides(cfg, Some(&target.name));
+        // the overrides for all targets first, they are replaced by the ones of the target
+        overrides.sort_by_key(|item| item.target.is_some());
+        TargetOverrides {
+            overrides: overrides.into_iter().map(|item| (item.get_key(), item)).collect(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.overrides.is_empty()
+    }
+
+    fn get(&self, item: &PlaylistItem) -> Option<&ChannelOverride> {
+        let header = item.header.borrow();
+        if header.id.is_empty() {
+            return None;
+        }
+        // an override of the target wins, then the one with the cluster
+        [format!("{}/{}", header.xtream_cluster, header.id), header.id.to_string()].iter()
+            .filter_map(|key| self.overrides.get(key))
+            .min_by_key(|item_override| item_override.target.is_none())
+    }
+
+    /// Returns the pinned channels of the input playlist which were removed by the processing of the target, in their input groups.
+    pub(crate) fn get_missing_pinned(&self, original: &[PlaylistGroup], playlist: &[PlaylistGroup]) -> Vec<PlaylistGroup> {
+        if !self.overrides.values().any(|item| item.pin) {
+            return vec![];
+        }
+        let get_key = |item: &PlaylistItem| { let header = item.header.borrow(); format!("{}/{}", header.xtream_cluster, header.id) };
+        let present: HashSet<String> = playlist.iter().flat_map(|group| &group.channels).map(get_key).collect();
+        let mut pinned = vec![];
+        for group in original {
+            let channels: Vec<PlaylistItem> = group.channels.iter()
+                .filter(|item| self.get(item).is_some_and(|item_override| item_override.pin))
+                .filter(|item| !present.contains(&get_key(item)))
+                .cloned().collect();
+            if !channels.is_empty() {
+                channels.iter().for_each(|item| debug!("Pinned channel {} added", item.header.borrow().title));
+                pinned.push(PlaylistGroup { id: group.id, title: Arc::clone(&group.title), channels, xtream_cluster: group.xtream_cluster.clone() });
+            }
+        }
+        pinned
+    }
+
+    /// Hides, renames and moves the channels of the playlist.
+    pub(crate) fn apply(&self, playlist: &mut Vec<PlaylistGroup>) {
+        let mut moved = vec![];
+        for group in playlist.iter_mut() {
+            let current_title = Arc::clone(&group.title);
+            group.channels.retain(|item| {
+                let item_override = match self.get(item) {
+                    Some(item_override) => item_override,
+                    None => return true,
+                };
+                if item_override.hide {
+                    return false;
+                }
+                {
+                    let mut header = item.header.borrow_mut();
+                    if let Some(title) = &item_override.title {
+                        header.title = Arc::from(title.as_str());
+                        header.name = Arc::clone(&header.title);
+                    }
+                    if let Some(group_title) = &item_override.group {
+                        header.group = string_utils::intern(group_title);
+                    }
+                }
+                match &item_override.group {
+                    Some(group_title) if group_title.as_str() != &*current_title => {
+                        moved.push((group_title.to_string(), item.clone()));
+                        false
+                    }
+                    _ => true,
+                }
+            });
+        }
+        for (group_title, item) in moved {
+            let cluster = item.header.borrow().xtream_cluster.clone();
+            let next_id = playlist.iter().map(|group| group.id).max().unwrap_or(0) + 1;
+            get_group(playlist, &string_utils::intern(&group_title), &cluster, next_id).channels.push(item);
+        }
+        playlist.retain(|group| !group.channels.is_empty());
+    }
+}
+
+/// Adds the channels of the groups to the groups of the playlist with the same title and cluster.
+pub(crate) fn add_groups(playlist: &mut Vec<PlaylistGroup>, groups: Vec<PlaylistGroup>) {
+    for group in groups {
+        get_group(playlist, &group.title, &group.xtream_cluster, group.id).channels.extend(group.channels);
+    }
+}
+
+/// Returns the group with the title in the cluster, it is created with the id if it doesn't exist.
+pub(crate) fn get_group<'a>(playlist: &'a mut Vec<PlaylistGroup>, title: &Arc<str>, cluster: &XtreamCluster, id: u32) -> &'a mut PlaylistGroup {
+    let index = match playlist.iter().position(|group| group.title == *title && &group.xtream_cluster == cluster) {
+        Some(index) => index,
+        None => {
+            playlist.push(PlaylistGroup { id, title: Arc::clone(title), channels: vec![], xtream_cluster: cluster.clone() });
+            playlist.len() - 1
+        }
+    };
+    &mut playlist[index]
+}
@@ -0,0 +1,113 @@
+use serde::Serialize;
+
+use crate::create_m3u_filter_error_result;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{Config, ConfigInput, ConfigTarget, TargetOutput};
+use crate::model::model_config::TargetType;
+use crate::model::model_playlist::{FetchedPlaylist, PlaylistGroup};
+use crate::processing::playlist_processor;
+use crate::repository::raw_repository;
+use crate::utils::config_reader;
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct PreviewChannel {
+    pub id: String,
+    pub name: String,
+    pub title: String,
+    pub logo: String,
+    pub url: String,
+    pub epg_channel_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct PreviewGroup {
+    pub title: String,
+    pub cluster: String,
+    pub channel_count: usize,
+    pub channels: Vec<PreviewChannel>,
+}
+
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct PlaylistPreview {
+    pub group_count: usize,
+    pub channel_count: usize,
+    pub groups: Vec<PreviewGroup>,
+}
+
+fn prepare_target(cfg: &Config, target: &mut ConfigTarget) -> Result<(), M3uFilterError> {
+    if let Some(parent_name) = &target.extends {
+        // the targets of the config are already resolved
+        match cfg.get_target_by_name(parent_name.trim()) {
+            Some(parent) => target.inherit(parent),
+            None => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "unknown target: {}", parent_name),
+        }
+    }
+    // nothing is written, the output is only needed to pass the validation of the target
+    if target.output.is_empty() {
+        target.output.push(TargetOutput { target: TargetType::M3u, filename: Some(String::from("preview.m3u")) });
+    }
+    target.prepare(0, cfg.templates.as_ref())?;
+    if let Some(mapping_ids) = &target.mapping {
+        let mappings = config_reader::read_mapping(&cfg._mapping_file_path)?;
+        let mut target_mappings = Vec::new();
+        for mapping_id in mapping_ids {
+            match mappings.as_ref().and_then(|mapping_list| mapping_list.get_mapping(mapping_id)) {
+                Some(mapping) => target_mappings.push(mapping),
+                None => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "unknown mapping: {}", mapping_id),
+            }
+        }
+        target._mapping = if target_mappings.is_empty() { None } else { Some(target_mappings) };
+    }
+    Ok(())
+}
+
+fn to_preview_group(group: &PlaylistGroup, limit: usize) -> PreviewGroup {
+    PreviewGroup {
+        title: group.title.to_string(),
+        cluster: group.xtream_cluster.to_string(),
+        channel_count: group.channels.len(),
+        channels: group.channels.iter().take(limit).map(|item| {
+            let header = item.header.borrow();
+            PreviewChannel {
+                id: header.id.to_string(),
+                name: header.name.to_string(),
+                title: header.title.to_string(),
+                logo: header.logo.to_string(),
+                url: header.url.to_string(),
+                epg_channel_id: header.epg_channel_id.as_ref().map(|id| id.to_string()),
+            }
+        }).collect(),
+    }
+}
+
+/// Processes the raw playlists of the inputs with the target, the inputs need `persist` to have a raw playlist.
+/// Without `input_ids` all inputs with a raw playlist are used. At most `limit` channels are returned.
+pub(crate) fn preview_target(cfg: &Config, mut target: ConfigTarget, input_ids: &[u16], limit: usize) -> Result<PlaylistPreview, M3uFilterError> {
+    prepare_target(cfg, &mut target)?;
+    let inputs: Vec<&ConfigInput> = cfg.sources.iter().flat_map(|source| &source.inputs)
+        .filter(|input| input_ids.is_empty() || input_ids.contains(&input.id))
+        .collect();
+    if let Some(input_id) = input_ids.iter().find(|input_id| !inputs.iter().any(|input| input.id == **input_id)) {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "unknown input: {}", input_id);
+    }
+    let playlists: Vec<FetchedPlaylist> = inputs.into_iter()
+        .filter_map(|input| raw_repository::get_raw_playlist(cfg, input.id)
+            .map(|playlist| FetchedPlaylist { input, playlist, epg: None }))
+        .collect();
+    if playlists.is_empty() {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "no raw playlist found, the inputs need persist and a processing run");
+    }
+
+    let playlist = playlist_processor::preview_playlist(playlists, &target);
+    let mut remaining = limit;
+    let groups = playlist.iter().map(|group| {
+        let preview_group = to_preview_group(group, remaining);
+        remaining -= preview_group.channels.len();
+        preview_group
+    }).collect();
+    Ok(PlaylistPreview {
+        group_count: playlist.len(),
+        channel_count: playlist.iter().map(|group| group.channels.len()).sum(),
+        groups,
+    })
+}
@@ -2,30 +2,50 @@ extern crate unidecode;
 
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
-use std::rc::Rc;
+use std::fmt::Write;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Instant;
 use actix_rt::System;
+use chrono::NaiveTime;
 
 use log::{debug, error, info, Level, log_enabled};
+use regex::Regex;
 use unidecode::unidecode;
 
 use crate::{Config, get_errors_notify_message, model::config, valid_property};
 use crate::filter::{get_field_value, MockValueProcessor, set_field_value, ValueProvider};
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
 use crate::messaging::{MsgKind, send_message};
-use crate::model::config::{ConfigTarget, default_as_default, InputAffix, InputType, ProcessTargets};
-use crate::model::mapping::{Mapping, MappingValueProcessor};
-use crate::model::model_config::{AFFIX_FIELDS, ItemField, ProcessingOrder, SortOrder::{Asc, Desc}, TargetType};
+use crate::model::config::{ConfigInput, ConfigTarget, ConfigTargetCondition, default_as_default, InputAffix, InputType, ProcessTargets};
+use crate::model::mapping::{CounterScope, MapperSet, Mapping, MappingValueProcessor};
+use crate::model::model_config::{AFFIX_FIELDS, DedupKey, ItemField, ProcessingOrder, SortOrder, SortOrder::{Asc, Desc}, TargetType};
 use crate::model::model_playlist::{FetchedPlaylist, FieldAccessor, PlaylistGroup, PlaylistItem, PlaylistItemHeader};
 use crate::model::stats::{InputStats, PlaylistStats};
 use crate::model::xmltv::{Epg};
+use crate::processing::channel_numbering::assign_channel_numbers;
+use crate::processing::playlist_diff::process_playlist_diff;
+use crate::processing::playlist_enrichment::enrich_playlist;
+use crate::processing::playlist_health::check_playlist_health;
+use crate::processing::playlist_hooks::{run_exec, run_hook};
+use crate::processing::playlist_merge::merge_playlists;
+use crate::processing::custom_channels;
+use crate::processing::playlist_quality::apply_quality_preference;
 use crate::processing::playlist_watch::process_group_watch;
+use crate::processing::playlist_overrides;
+use crate::processing::playlist_overrides::TargetOverrides;
+use crate::processing::playlist_radio::apply_radio;
 use crate::processing::xmltv_parser::flatten_tvguide;
 use crate::repository::epg_repository::write_epg;
+use crate::repository::export_repository::{write_csv_playlist, write_json_playlist};
+use crate::repository::kodi_repository::write_kodi_playlist;
 use crate::repository::m3u_repository::{write_m3u_playlist, write_strm_playlist};
+use crate::repository::raw_repository::write_raw_playlist;
+use crate::repository::search_repository::write_search_index;
 use crate::repository::xtream_repository::write_xtream_playlist;
-use crate::utils::download;
+use crate::repository::history_repository;
+use crate::utils::{download, input_health, jobs, metrics, string_utils};
+use crate::utils::string_utils::natural_cmp;
 
 fn filter_playlist(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Option<Vec<PlaylistGroup>> {
     debug!("Filtering {} groups", playlist.len());
@@ -73,9 +93,9 @@ fn apply_affixes(fetched_playlists: &mut [FetchedPlaylist]) {
                 let get_affix_applied_value = |header: &mut PlaylistItemHeader, affix: &InputAffix, prefix: bool| {
                     if let Some(field_value) = header.get_field(affix.field.as_str()) {
                         return if prefix {
-                            format!("{}{}", &affix.value, field_value.as_str())
+                            format!("{}{}", &affix.value, field_value)
                         } else {
-                            format!("{}{}", field_value.as_str(), &affix.value)
+                            format!("{}{}", field_value, &affix.value)
                         };
                     }
                     String::from(&affix.value)
@@ -108,37 +128,45 @@ fn apply_affixes(fetched_playlists: &mut [FetchedPlaylist]) {
     });
 }
 
-fn sort_playlist(target: &ConfigTarget, new_playlist: &mut [PlaylistGroup]) {
+/// Items are ordered by the first matching expression of the sequence, items without match are placed behind them.
+/// Items with the same position are ordered by their value. The sort values are computed once for each item.
+fn sort_by_sequence<T, F>(items: &mut Vec<T>, get_value: F, sequence: Option<&Vec<Regex>>, order: &SortOrder, natural: bool)
+    where F: Fn(&T) -> Arc<str> {
+    let mut keyed: Vec<(usize, Arc<str>, T)> = std::mem::take(items).into_iter().map(|item| {
+        let value = get_value(&item);
+        let position = sequence.map_or(0, |regexps| regexps.iter().position(|re| re.is_match(&value)).unwrap_or(regexps.len()));
+        (position, value, item)
+    }).collect();
+    keyed.sort_by(|(position_a, value_a, _), (position_b, value_b, _)| {
+        position_a.cmp(position_b).then_with(|| {
+            let ordering = if natural { natural_cmp(value_a, value_b) } else { value_a.cmp(value_b) };
+            match order {
+                Asc => ordering,
+                Desc => ordering.reverse()
+            }
+        })
+    });
+    items.extend(keyed.into_iter().map(|(_, _, item)| item));
+}
+
+fn sort_playlist(target: &ConfigTarget, new_playlist: &mut Vec<PlaylistGroup>) {
     if let Some(sort) = &target.sort {
         let match_as_ascii = &sort.match_as_ascii;
         if let Some(group_sort) = &sort.groups {
-            new_playlist.sort_by(|a, b| {
-                let value_a = if *match_as_ascii { Rc::new(unidecode(&a.title)) } else { Rc::clone(&a.title) };
-                let value_b = if *match_as_ascii { Rc::new(unidecode(&b.title)) } else { Rc::clone(&b.title) };
-                let ordering = value_a.partial_cmp(&value_b).unwrap();
-                match group_sort.order {
-                    Asc => ordering,
-                    Desc => ordering.reverse()
-                }
-            });
+            sort_by_sequence(new_playlist,
+                             |group| if *match_as_ascii { Arc::from(unidecode(&group.title)) } else { Arc::clone(&group.title) },
+                             group_sort._sequence.as_ref(), &group_sort.order, sort.natural);
         }
         if let Some(channel_sorts) = &sort.channels {
             channel_sorts.iter().for_each(|channel_sort| {
                 let regexp = channel_sort.re.as_ref().unwrap();
                 new_playlist.iter_mut().for_each(|group| {
-                    let group_title = if *match_as_ascii { Rc::new(unidecode(&group.title)) } else { Rc::clone(&group.title) };
-                    if regexp.is_match(group_title.as_str()) {
-                        group.channels.sort_by(|a, b| {
-                            let raw_value_a = get_field_value(a, &channel_sort.field);
-                            let raw_value_b = get_field_value(b, &channel_sort.field);
-                            let value_a = if *match_as_ascii { Rc::new(unidecode(&raw_value_a)) } else { raw_value_a };
-                            let value_b = if *match_as_ascii { Rc::new(unidecode(&raw_value_b)) } else { raw_value_b };
-                            let ordering = value_a.partial_cmp(&value_b).unwrap();
-                            match channel_sort.order {
-                                Asc => ordering,
-                                Desc => ordering.reverse()
-                            }
-                        });
+                    let group_title = if *match_as_ascii { Arc::from(unidecode(&group.title)) } else { Arc::clone(&group.title) };
+                    if regexp.is_match(&group_title) {
+                        sort_by_sequence(&mut group.channels, |channel| {
+                            let value = get_field_value(channel, &channel_sort.field);
+                            if *match_as_ascii { Arc::from(unidecode(&value)) } else { value }
+                        }, channel_sort._sequence.as_ref(), &channel_sort.order, sort.natural);
                     }
                 });
             });
@@ -146,6 +174,56 @@ fn sort_playlist(target: &ConfigTarget, new_playlist: &mut [PlaylistGroup]) {
     }
 }
 
+fn get_dedup_key(pli: &PlaylistItem, key: &DedupKey) -> String {
+    let header = pli.header.borrow();
+    match key {
+        DedupKey::Url => header.url.to_string(),
+        DedupKey::Name => unidecode(&header.name).to_lowercase().chars().filter(|c| c.is_alphanumeric()).collect(),
+        DedupKey::StreamId => if header.id.is_empty() { String::new() } else { format!("{}/{}", header.xtream_cluster, header.id) },
+    }
+}
+
+/// Removes channels which are listed more than once.
+/// The first channel is kept, unless a later duplicate matches the priority filter.
+pub(crate) fn dedup_playlist(target: &ConfigTarget, playlist: &mut Vec<PlaylistGroup>) {
+    if let Some(dedup) = &target.dedup {
+        let mut keep: HashSet<(usize, usize)> = HashSet::new();
+        let mut winners: HashMap<String, (usize, usize, bool)> = HashMap::new();
+        for (group_idx, group) in playlist.iter().enumerate() {
+            for (channel_idx, pli) in group.channels.iter().enumerate() {
+                let key = get_dedup_key(pli, &dedup.key);
+                if key.is_empty() {
+                    // without a key we can't decide, the channel is kept
+                    keep.insert((group_idx, channel_idx));
+                    continue;
+                }
+                let winner_priority = winners.get(&key).map(|(_, _, priority)| *priority);
+                if winner_priority != Some(true) {
+                    let priority = dedup.is_priority(&ValueProvider { pli: RefCell::new(pli) });
+                    if priority || winner_priority.is_none() {
+                        winners.insert(key, (group_idx, channel_idx, priority));
+                    }
+                }
+            }
+        }
+        keep.extend(winners.values().map(|(group_idx, channel_idx, _)| (*group_idx, *channel_idx)));
+
+        let channel_count: usize = playlist.iter().map(|group| group.channels.len()).sum();
+        for (group_idx, group) in playlist.iter_mut().enumerate() {
+            let mut channel_idx = 0;
+            group.channels.retain(|_| {
+                let retain = keep.contains(&(group_idx, channel_idx));
+                channel_idx += 1;
+                retain
+            });
+        }
+        playlist.retain(|group| !group.channels.is_empty());
+        let removed = channel_count - keep.len();
+        if removed > 0 {
+            info!("Removed {} duplicate channels from {}", removed, &target.name);
+        }
+    }
+}
 
 fn is_valid(pli: &mut PlaylistItem, target: &ConfigTarget) -> bool {
     let provider = ValueProvider { pli: RefCell::new(pli) };
@@ -158,12 +236,15 @@ fn exec_rename(pli: &mut PlaylistItem, rename: &Option<Vec<config::ConfigRename>
             let result = pli;
             for r in renames {
                 let value = get_field_value(result, &r.field);
-                let cap = r.re.as_ref().unwrap().replace_all(value.as_str(), &r.new_name);
+                let cap = r.re.as_ref().unwrap().replace_all(&value, &r.new_name);
                 if log_enabled!(Level::Debug) {
                     debug!("Renamed {}={} to {}", &r.field, value, cap);
                 }
-                let value = cap.into_owned();
-                set_field_value(result, &r.field, Rc::new(value));
+                let value = match r.field {
+                    ItemField::Group => string_utils::intern(&cap),
+                    _ => Arc::from(cap.as_ref()),
+                };
+                set_field_value(result, &r.field, value);
             }
         }
     }
@@ -182,7 +263,7 @@ fn rename_playlist(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Opt
                             if log_enabled!(Level::Debug) {
                                 debug!("Renamed group {} to {} for {}", &grp.title, cap, target.name);
                             }
-                            grp.title = Rc::new(cap.into_owned());
+                            grp.title = string_utils::intern(&cap);
                         }
                     }
 
@@ -199,44 +280,124 @@ fn rename_playlist(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Opt
 
 macro_rules! apply_pattern {
     ($pattern:expr, $provider:expr, $processor:expr) => {{
-            if let Some(ptrn) = $pattern {
-               ptrn.filter($provider, $processor);
-            };
+            match $pattern {
+                Some(ptrn) => ptrn.filter($provider, $processor),
+                None => false,
+            }
     }};
 }
 
-fn map_channel(channel: PlaylistItem, mapping: &Mapping) -> PlaylistItem {
+fn map_channel_group(channel: &PlaylistItem, mapping: &Mapping) {
+    if let Some(group_mappers) = &mapping.groups {
+        let provider = ValueProvider { pli: RefCell::new(channel) };
+        let mut mock_processor = MockValueProcessor {};
+        let group = group_mappers.iter()
+            .find(|gm| gm._filter.as_ref().is_some_and(|f| f.filter(&provider, &mut mock_processor)))
+            .map(|gm| string_utils::intern(&gm.group));
+        if let Some(group_name) = group {
+            if log_enabled!(Level::Debug) {
+                debug!("Moved {} to group {}", &channel.header.borrow().name, &group_name);
+            }
+            channel.header.borrow_mut().group = group_name;
+        }
+    }
+}
+
+fn sort_groups_by_order(groups: &mut [PlaylistGroup], group_order: &[&Regex]) {
+    if !group_order.is_empty() {
+        // stable sort, groups without match keep their order behind the ordered ones
+        groups.sort_by_key(|grp| group_order.iter().position(|re| re.is_match(&grp.title)).unwrap_or(group_order.len()));
+    }
+}
+
+fn map_channel_logo(channel: &PlaylistItem, mapping: &Mapping) {
+    if let Some(logos) = &mapping.logos {
+        let mut header = channel.header.borrow_mut();
+        if logos.replace || header.logo.is_empty() {
+            if let Some(logo) = logos.get_logo(&header.name) {
+                header.logo = Arc::from(logo);
+            }
+        }
+    }
+}
+
+/// Applies the mapping to the channel and returns the indexes of the mappers which matched.
+pub(crate) fn apply_mapping(channel: &PlaylistItem, mapping: &Mapping) -> Vec<usize> {
+    let mut matched_mappers = vec![];
+    map_channel_group(channel, mapping);
     if !mapping.mapper.is_empty() {
         let header = channel.header.borrow();
-        let channel_name = if mapping.match_as_ascii { Rc::new(unidecode(&header.name)) } else { header.name.clone() };
+        let channel_name = if mapping.match_as_ascii { Arc::from(unidecode(&header.name)) } else { header.name.clone() };
         if mapping.match_as_ascii && log_enabled!(Level::Debug) { debug!("Decoded {} for matching to {}", &header.name, &channel_name); };
         drop(header);
-        let ref_chan = RefCell::new(&channel);
+        let ref_chan = RefCell::new(channel);
         let provider = ValueProvider { pli: ref_chan.clone() };
         let mut mock_processor = MockValueProcessor {};
-        for m in &mapping.mapper {
+        let mut mapper_matcher = mapping._mapper_set.as_ref().map(MapperSet::matcher);
+        for (index, m) in mapping.mapper.iter().enumerate() {
+            if mapper_matcher.as_mut().is_some_and(|matcher| !matcher.is_candidate(channel, index)) {
+                continue;
+            }
             let mut processor = MappingValueProcessor { pli: ref_chan.clone(), mapper: m };
-            match &m._filter {
+            let matched = match &m._filter {
                 Some(filter) => {
-                    if filter.filter(&provider, &mut mock_processor) {
-                        apply_pattern!(&m._pattern, &provider, &mut processor);
-                    }
+                    filter.filter(&provider, &mut mock_processor) && apply_pattern!(&m._pattern, &provider, &mut processor)
                 }
                 _ => {
-                    apply_pattern!(&m._pattern, &provider, &mut processor);
+                    apply_pattern!(&m._pattern, &provider, &mut processor)
                 }
             };
+            if matched {
+                matched_mappers.push(index);
+            }
+        }
+    }
+    map_channel_logo(channel, mapping);
+    matched_mappers
+}
+
+/// Numbers the matching channels of the playlist with the counters of the mapping.
+pub(crate) fn apply_counters(playlist: &[PlaylistGroup], mapping: &Mapping) {
+    if let Some(counters) = &mapping.counters {
+        let mut mock_processor = MockValueProcessor {};
+        for counter in counters {
+            let filter = counter._filter.as_ref().unwrap();
+            let mut counter_value = counter.start;
+            for group in playlist {
+                if counter.scope == CounterScope::Group {
+                    counter_value = counter.start;
+                }
+                let mut channels: Vec<&PlaylistItem> = group.channels.iter()
+                    .filter(|channel| filter.filter(&ValueProvider { pli: RefCell::new(channel) }, &mut mock_processor))
+                    .collect();
+                if counter.sort {
+                    channels.sort_by(|a, b| natural_cmp(
+                        &a.header.borrow().get_field(&counter.field).unwrap_or_default(),
+                        &b.header.borrow().get_field(&counter.field).unwrap_or_default()));
+                }
+                for channel in channels {
+                    let value = counter.get_value(&channel.header.borrow(), counter_value);
+                    if log_enabled!(Level::Debug) {
+                        debug!("Counter set {} to {}", &counter.field, &value);
+                    }
+                    channel.header.borrow_mut().set_field(&counter.field, &value);
+                    counter_value += 1;
+                }
+            }
         }
     }
+}
+
+fn map_channel(channel: PlaylistItem, mapping: &Mapping) -> PlaylistItem {
+    apply_mapping(&channel, mapping);
     channel
 }
 
-fn map_playlist(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Option<Vec<PlaylistGroup>> {
-    if target._mapping.is_some() {
+pub(crate) fn map_playlist(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Option<Vec<PlaylistGroup>> {
+    if let Some(mappings) = &target._mapping {
         let new_playlist: Vec<PlaylistGroup> = playlist.iter().map(|playlist_group| {
             let mut grp = playlist_group.clone();
-            let mappings = target._mapping.as_ref().unwrap();
-            mappings.iter().filter(|mapping| !mapping.mapper.is_empty()).for_each(|mapping|
+            mappings.iter().filter(|mapping| mapping.has_channel_mapping()).for_each(|mapping|
                 grp.channels = grp.channels.drain(..).map(|chan| map_channel(chan, mapping)).collect());
             grp
         }).collect();
@@ -255,7 +416,7 @@ fn map_playlist(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Option
                         grp_id += 1;
                         new_groups.push(PlaylistGroup {
                             id: grp_id,
-                            title: Rc::clone(title),
+                            title: Arc::clone(title),
                             channels: vec![channel.clone()],
                             xtream_cluster: cluster.clone()
                         })
@@ -263,6 +424,12 @@ fn map_playlist(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Option
                 }
             }
         }
+        let group_order: Vec<&Regex> = mappings.iter()
+            .filter_map(|mapping| mapping._group_order.as_ref())
+            .flatten().collect();
+        sort_groups_by_order(&mut new_groups, &group_order);
+        // the channels are numbered in the order of the output
+        mappings.iter().for_each(|mapping| apply_counters(&new_groups, mapping));
         Some(new_groups)
     } else {
         None
@@ -283,21 +450,75 @@ fn is_target_enabled(target: &ConfigTarget, user_targets: &ProcessTargets) -> bo
     (!user_targets.enabled && target.enabled) || (user_targets.enabled && user_targets.has_target(target.id))
 }
 
+/// The inputs are checked with the state of their last download, the inputs of the source are downloaded before.
+pub(crate) fn is_input_condition_met(cfg: &Config, condition: &ConfigTargetCondition) -> bool {
+    let is_up = |name: &String| input_health::get_input_by_name(cfg, name).is_some_and(|input| input_health::is_input_up(cfg, input));
+    condition.inputs_up.iter().all(is_up) && !condition.inputs_down.iter().any(is_up)
+}
+
+/// Leaves out the targets whose time windows are not met, returns `None` if no target remains.
+/// The input conditions are checked by the processing after the downloads of the run.
+/// Processing started through the api or the command line doesn't check the conditions.
+pub(crate) fn get_scheduled_targets(cfg: &Config, targets: &Arc<ProcessTargets>, now: NaiveTime) -> Option<Arc<ProcessTargets>> {
+    let mut skipped = false;
+    let mut scheduled_targets = vec![];
+    let mut inputs = vec![];
+    for source in &cfg.sources {
+        let count = scheduled_targets.len();
+        for target in source.targets.iter().filter(|target| is_target_enabled(target, targets)) {
+            if target.condition.as_ref().is_none_or(|condition| condition.is_time_met(now)) {
+                scheduled_targets.push(target.id);
+            } else {
+                info!("Skipping target {}, its condition is not met", &target.name);
+                skipped = true;
+            }
+        }
+        // only the inputs of the sources with remaining targets are downloaded
+        if scheduled_targets.len() > count {
+            inputs.extend(source.inputs.iter().map(|input| input.id));
+        }
+    }
+    if scheduled_targets.is_empty() {
+        return None;
+    }
+    let (enabled, inputs, scheduled_targets) = if skipped || targets.enabled {
+        (true, inputs, scheduled_targets)
+    } else {
+        (false, targets.inputs.clone(), targets.targets.clone())
+    };
+    Some(Arc::new(ProcessTargets { enabled, inputs, targets: scheduled_targets, dry_run: targets.dry_run, names: targets.names.clone(), conditions: true }))
+}
+
 async fn process_source(cfg: Arc<Config>, source_idx: usize, user_targets: Arc<ProcessTargets>) -> (Vec<InputStats>, Vec<M3uFilterError>) {
     let source = cfg.sources.get(source_idx).unwrap();
+    if user_targets.enabled && !source.targets.iter().any(|target| is_target_enabled(target, &user_targets)) {
+        debug!("Skipping source at {}, none of its targets is processed", source_idx);
+        return (vec![], vec![]);
+    }
     let mut all_playlist = Vec::new();
     let enabled_inputs = source.inputs.iter().filter(|item| item.enabled).count();
+    let active_inputs: Vec<&ConfigInput> = source.inputs.iter()
+        .filter(|input| is_input_enabled(enabled_inputs, input.enabled, input.id, &user_targets)).collect();
+    // the targets of a source are built from all inputs, skip only if none of them changed
+    if !active_inputs.is_empty() && active_inputs.iter().all(|input| download::is_local_input_unmodified(input, &cfg.working_dir)) {
+        info!("Skipping source at {}, local inputs are unmodified", source_idx);
+        return (vec![], vec![]);
+    }
     let mut errors = vec![];
     let mut stats = HashMap::<u16, InputStats>::new();
     for input in &source.inputs {
         let input_id = input.id;
         if is_input_enabled(enabled_inputs, input.enabled, input_id, &user_targets) {
-            let (playlist, mut error_list) = match input.input_type {
-                InputType::M3u => download::get_m3u_playlist(&cfg, input, &cfg.working_dir).await,
-                InputType::Xtream => download::get_xtream_playlist(input, &cfg.working_dir).await,
+            let (playlist, mut error_list, failed_clusters) = match input.input_type {
+                InputType::M3u => {
+                    let (playlist, errors) = download::get_m3u_playlist(&cfg, input, &cfg.working_dir, user_targets.dry_run).await;
+                    (playlist, errors, vec![])
+                }
+                InputType::Xtream => download::get_xtream_playlist(input, &cfg.working_dir, user_targets.dry_run).await,
             };
-            let (tvguide, mut tvguide_errors) = if error_list.is_empty() {
-                download::get_xmltv(&cfg, input, &cfg.working_dir).await
+            // a partially failed xtream input still has an epg
+            let (tvguide, mut tvguide_errors) = if error_list.is_empty() || (!failed_clusters.is_empty() && !playlist.is_empty()) {
+                download::get_xmltv(&cfg, input, &cfg.working_dir, user_targets.dry_run).await
             } else {
                 (None, vec![])
             };
@@ -307,6 +528,10 @@ async fn process_source(cfg: Arc<Config>, source_idx: usize, user_targets: Arc<P
                 None => input.url.as_str(),
                 Some(name_val) => name_val.as_str()
             };
+            if !user_targets.dry_run {
+                metrics::record_failed_clusters(&cfg.working_dir, input_name, &failed_clusters);
+                input_health::record_download(&cfg, input_id, !playlist.is_empty());
+            }
             let group_count = playlist.len();
             let channel_count = playlist.iter()
                 .map(|group| group.channels.len())
@@ -315,6 +540,12 @@ async fn process_source(cfg: Arc<Config>, source_idx: usize, user_targets: Arc<P
                 info!("source is empty {}", input.url);
                 errors.push(M3uFilterError::new(M3uFilterErrorKind::Notify, format!("source is empty {}", input_name)));
             } else {
+                // the original playlist of persisted inputs can be browsed through the api
+                if !user_targets.dry_run && input.persist.as_ref().is_some_and(|persist| !persist.trim().is_empty()) {
+                    if let Err(err) = write_raw_playlist(&cfg, input, &playlist) {
+                        errors.push(err);
+                    }
+                }
                 all_playlist.push(
                     FetchedPlaylist {
                         input,
@@ -335,6 +566,7 @@ async fn process_source(cfg: Arc<Config>, source_idx: usize, user_targets: Arc<P
                     group_count: 0,
                     channel_count: 0,
                 },
+                failed_clusters: failed_clusters.iter().map(ToString::to_string).collect(),
             });
         }
     }
@@ -349,17 +581,24 @@ async fn process_source(cfg: Arc<Config>, source_idx: usize, user_targets: Arc<P
         }
         for target in &source.targets {
             if is_target_enabled(target, &user_targets) {
-                match process_playlist(&mut all_playlist, target, &cfg, &mut stats, &mut errors).await {
+                if user_targets.conditions && target.condition.as_ref().is_some_and(|condition| !is_input_condition_met(&cfg, condition)) {
+                    info!("Skipping target {}, its condition is not met", &target.name);
+                    continue;
+                }
+                match process_playlist(&mut all_playlist, target, &cfg, &mut stats, &mut errors, user_targets.dry_run).await {
                     Ok(_) => {}
                     Err(mut err) => err.drain(..).for_each(|e| errors.push(e))
                 }
             }
         }
     }
+    if errors.is_empty() && !user_targets.dry_run {
+        active_inputs.iter().for_each(|input| download::update_local_input_modified(input, &cfg.working_dir));
+    }
     (stats.drain().map(|(_, v)| v).collect(), errors)
 }
 
-pub(crate) async fn process_sources(config: Arc<Config>, user_targets: Arc<ProcessTargets>) -> (Vec<InputStats>, Vec<M3uFilterError>) {
+pub(crate) async fn process_sources(config: Arc<Config>, user_targets: Arc<ProcessTargets>, job_id: Option<String>) -> (Vec<InputStats>, Vec<M3uFilterError>) {
     let mut handle_list = vec![];
     let thread_num = config.threads;
     let process_parallel = thread_num > 1 && config.sources.len() > 1;
@@ -373,6 +612,7 @@ pub(crate) async fn process_sources(config: Arc<Config>, user_targets: Arc<Proce
         let shared_stats = stats.clone();
         let cfg = config.clone();
         let usr_trgts = user_targets.clone();
+        let source_job_id = job_id.clone();
         if process_parallel {
             let handles = &mut handle_list;
             let process = move || {
@@ -383,6 +623,9 @@ pub(crate) async fn process_sources(config: Arc<Config>, user_targets: Arc<Proce
                     .for_each(|err| shared_errors.lock().unwrap().push(err));
                 res_stats.drain(..)
                     .for_each(|stat| shared_stats.lock().unwrap().push(stat));
+                if let Some(id) = source_job_id {
+                    jobs::record_source_processed(&id);
+                }
             };
             handles.push(thread::spawn(process));
             if handles.len() as u8 >= thread_num {
@@ -394,37 +637,64 @@ pub(crate) async fn process_sources(config: Arc<Config>, user_targets: Arc<Proce
                 .for_each(|err| shared_errors.lock().unwrap().push(err));
             res_stats.drain(..)
                 .for_each(|stat| shared_stats.lock().unwrap().push(stat));
+            if let Some(id) = source_job_id {
+                jobs::record_source_processed(&id);
+            }
         }
     }
     for handle in handle_list {
         let _ = handle.join();
     }
+    string_utils::release_interned();
     (Arc::try_unwrap(stats).unwrap().into_inner().unwrap(), Arc::try_unwrap(errors).unwrap().into_inner().unwrap())
 }
 
 
 type ProcessingPipe = Vec<fn(playlist: &mut [PlaylistGroup], target: &ConfigTarget) -> Option<Vec<PlaylistGroup>>>;
 
-fn get_processing_pipe(target: &ConfigTarget) -> ProcessingPipe {
+/// Keeps the playlist unchanged, it replaces the filter for the pinned channels.
+fn skip_filter(_playlist: &mut [PlaylistGroup], _target: &ConfigTarget) -> Option<Vec<PlaylistGroup>> {
+    None
+}
+
+fn get_processing_pipe(target: &ConfigTarget, with_filter: bool) -> ProcessingPipe {
+    let filter: fn(&mut [PlaylistGroup], &ConfigTarget) -> Option<Vec<PlaylistGroup>> = if with_filter { filter_playlist } else { skip_filter };
     match &target.processing_order {
-        ProcessingOrder::Frm => vec![filter_playlist, rename_playlist, map_playlist],
-        ProcessingOrder::Fmr => vec![filter_playlist, map_playlist, rename_playlist],
-        ProcessingOrder::Rfm => vec![rename_playlist, filter_playlist, map_playlist],
-        ProcessingOrder::Rmf => vec![rename_playlist, map_playlist, filter_playlist],
-        ProcessingOrder::Mfr => vec![map_playlist, filter_playlist, rename_playlist],
-        ProcessingOrder::Mrf => vec![map_playlist, rename_playlist, filter_playlist]
+        ProcessingOrder::Frm => vec![filter, rename_playlist, map_playlist],
+        ProcessingOrder::Fmr => vec![filter, map_playlist, rename_playlist],
+        ProcessingOrder::Rfm => vec![rename_playlist, filter, map_playlist],
+        ProcessingOrder::Rmf => vec![rename_playlist, map_playlist, filter],
+        ProcessingOrder::Mfr => vec![map_playlist, filter, rename_playlist],
+        ProcessingOrder::Mrf => vec![map_playlist, rename_playlist, filter]
+    }
+}
+
+/// Adds the pinned channels which were removed, a pin bypasses only the filter, the channels are renamed and mapped.
+pub(crate) fn add_pinned_channels(overrides: &TargetOverrides, target: &ConfigTarget, original: &[PlaylistGroup], playlist: &mut Vec<PlaylistGroup>) {
+    let mut pinned = overrides.get_missing_pinned(original, playlist);
+    if pinned.is_empty() {
+        return;
+    }
+    for f in &get_processing_pipe(target, false) {
+        if let Some(v) = f(&mut pinned, target) {
+            pinned = v;
+        }
     }
+    playlist_overrides::add_groups(playlist, pinned);
 }
 
 pub(crate) async fn process_playlist<'a>(playlists: &mut [FetchedPlaylist<'a>],
                                          target: &ConfigTarget, cfg: &Config,
                                          stats: &mut HashMap<u16, InputStats>,
-                                         errors: &mut Vec<M3uFilterError>) -> Result<(), Vec<M3uFilterError>> {
-    let pipe = get_processing_pipe(target);
+                                         errors: &mut Vec<M3uFilterError>,
+                                         dry_run: bool) -> Result<(), Vec<M3uFilterError>> {
+    let pipe = get_processing_pipe(target, true);
+    let hooks = target.hooks.as_ref();
     if log_enabled!(Level::Debug) {
         debug!("Processing order is {}", &target.processing_order);
     }
 
+    let overrides = TargetOverrides::load(cfg, target);
     let mut new_fetched_playlists: Vec<FetchedPlaylist> = vec![];
     for fpl in playlists.iter_mut() {
         let mut new_fpl = FetchedPlaylist {
@@ -432,6 +702,11 @@ pub(crate) async fn process_playlist<'a>(playlists: &mut [FetchedPlaylist<'a>],
             playlist: fpl.playlist.clone(), // we need to clone, because of multiple target definitions, we cant change the initial playlist.
             epg: fpl.epg.clone(),
         };
+        if !dry_run {
+            if let Err(err) = run_hook(hooks.and_then(|h| h.after_download.as_ref()), "after_download", target, &mut new_fpl.playlist).await {
+                errors.push(err);
+            }
+        }
         for f in &pipe {
             let playlist = &mut new_fpl.playlist;
             let r = f(playlist, target);
@@ -439,15 +714,12 @@ pub(crate) async fn process_playlist<'a>(playlists: &mut [FetchedPlaylist<'a>],
                 new_fpl.playlist = v;
             }
         }
-        let (resolve_series, resolve_series_delay) =
-            if let Some(options) = &target.options {
-                (options.xtream_resolve_series && fpl.input.input_type == InputType::Xtream && target.has_output(&TargetType::M3u),
-                 options.xtream_resolve_series_delay)
-            } else {
-                (false, 0)
-            };
-        if resolve_series {
-            let mut series_playlist = download::get_xtream_playlist_series(fpl, errors, resolve_series_delay).await;
+        add_pinned_channels(&overrides, target, &fpl.playlist, &mut new_fpl.playlist);
+        let resolve_series = target.options.as_ref().filter(|options|
+            options.xtream_resolve_series && fpl.input.input_type == InputType::Xtream
+                && [TargetType::M3u, TargetType::Strm, TargetType::Kodi, TargetType::Xtream].iter().any(|output| target.has_output(output)));
+        if let Some(options) = resolve_series {
+            let mut series_playlist = download::get_xtream_playlist_series(fpl, &cfg.working_dir, errors, options, dry_run).await;
             // original content saved into original list
             for plg in &series_playlist {
                 fpl.update_playlist(plg);
@@ -478,33 +750,82 @@ pub(crate) async fn process_playlist<'a>(playlists: &mut [FetchedPlaylist<'a>],
     }
 
     apply_affixes(&mut new_fetched_playlists);
-    let mut new_playlist = vec![];
-    let mut new_epg = vec![];
+    let mut input_playlists = vec![];
     let mut tv_guides = vec![];
-    new_fetched_playlists.drain(..).for_each(|mut fp| {
-        fp.playlist.drain(..).for_each(|group| new_playlist.push(group));
+    new_fetched_playlists.drain(..).for_each(|fp| {
+        input_playlists.push(fp.playlist);
         if let Some(tv_guide) = fp.epg {
             tv_guides.push(tv_guide);
-            let guide = tv_guides.last().unwrap();
-            if log_enabled!(Level::Debug) {
-                debug!("found epg information for {}", &target.name);
-            }
-            let channel_ids: HashSet<_> = new_playlist.iter().flat_map(|g| &g.channels)
-                .filter_map(|c| c.header.borrow().epg_channel_id.clone()).collect();
-            if !channel_ids.is_empty() {
-                if let Some(epg) = guide.filter(&channel_ids) {
-                    new_epg.push(epg);
-                }
-            } else if log_enabled!(Level::Debug) {
-                debug!("channel ids are empty");
-            }
         }
     });
+    let mut new_playlist = merge_playlists(target, input_playlists);
+    // custom channels are not filtered, but they can be changed by the hooks and the overrides
+    custom_channels::add_custom_channels(cfg, target, &mut new_playlist);
+    let mut new_epg = vec![];
+    if !tv_guides.is_empty() {
+        if log_enabled!(Level::Debug) {
+            debug!("found epg information for {}", &target.name);
+        }
+        let channel_ids: HashSet<_> = new_playlist.iter().flat_map(|g| &g.channels)
+            .filter_map(|c| c.header.borrow().epg_channel_id.clone()).collect();
+        if !channel_ids.is_empty() {
+            tv_guides.iter().filter_map(|guide| guide.filter(&channel_ids)).for_each(|epg| new_epg.push(epg));
+        } else if log_enabled!(Level::Debug) {
+            debug!("channel ids are empty");
+        }
+    }
+
+    if !dry_run {
+        if let Err(err) = run_hook(hooks.and_then(|h| h.after_filter.as_ref()), "after_filter", target, &mut new_playlist).await {
+            errors.push(err);
+        }
+    }
+    apply_radio(target, &mut new_playlist);
+    // the manual overrides are applied after the rules of the target
+    if !overrides.is_empty() {
+        overrides.apply(&mut new_playlist);
+    }
+    dedup_playlist(target, &mut new_playlist);
+    apply_quality_preference(target, &mut new_playlist).await;
+    // the enrichment, the health check, the channel numbering and the metrics store their state,
+    // the hooks and the exec command can change anything, a dry run has no side effects
+    if !dry_run {
+        enrich_playlist(cfg, target, &mut new_playlist).await;
+        check_playlist_health(cfg, target, &mut new_playlist).await;
+        metrics::record_target(&cfg.working_dir, &target.name, &new_playlist);
+    }
 
     if !new_playlist.is_empty() {
         sort_playlist(target, &mut new_playlist);
+        if !dry_run {
+            assign_channel_numbers(cfg, target, &new_playlist);
+        }
+        if dry_run {
+            print_playlist_summary(target, &new_playlist, &get_dry_run_skipped_stages(cfg, target));
+            return Ok(());
+        }
+        if let Err(err) = run_hook(hooks.and_then(|h| h.before_write.as_ref()), "before_write", target, &mut new_playlist).await {
+            errors.push(err);
+        }
+
+        // `{date}` of the output dir is resolved once, all files of the run are written into the same dir
+        target.resolve_run_output_dir(cfg);
+        let skip_unchanged = target.options.as_ref().is_some_and(|o| o.skip_unchanged);
+        let (changed, diff) = if skip_unchanged {
+            if default_as_default().eq_ignore_ascii_case(&target.name) {
+                error!("cant diff a target with no unique name");
+                (true, None)
+            } else {
+                process_playlist_diff(cfg, target, &new_playlist)
+            }
+        } else {
+            (true, None)
+        };
+        if !changed {
+            info!("Playlist is unchanged: {}", &target.name);
+        }
 
-        if target._watch_re.is_some() {
+        if changed && target._watch_re.is_some() {
             if default_as_default().eq_ignore_ascii_case(&target.name) {
                 error!("cant watch a target with no unique name");
             } else {
@@ -517,22 +838,97 @@ pub(crate) async fn process_playlist<'a>(playlists: &mut [FetchedPlaylist<'a>],
             }
         }
 
-        persist_playlist(&new_playlist, flatten_tvguide(&new_epg), target, cfg)
+        persist_playlist(&new_playlist, flatten_tvguide(&new_epg), target, cfg, changed)?;
+        // a failed write is retried with the next run
+        if let Some(diff) = diff {
+            diff.save();
+        }
+        // nothing to publish, if the playlist was not written
+        if changed {
+            if let Err(err) = history_repository::write_snapshot(cfg, target, &new_playlist) {
+                errors.push(err);
+            }
+            if let Err(err) = run_exec(cfg, target, &new_playlist).await {
+                errors.push(err);
+            }
+        }
+        Ok(())
     } else {
         info!("Playlist is empty: {}", &target.name);
         Ok(())
     }
 }
 
+/// Runs the processing of the target over the playlists like `process_playlist`, but without the hooks, the series resolution
+/// and the steps which probe the streams or keep state in the working dir. Nothing is written.
+pub(crate) fn preview_playlist(mut playlists: Vec<FetchedPlaylist>, target: &ConfigTarget) -> Vec<PlaylistGroup> {
+    let pipe = get_processing_pipe(target, true);
+    for fpl in &mut playlists {
+        for f in &pipe {
+            if let Some(v) = f(&mut fpl.playlist, target) {
+                fpl.playlist = v;
+            }
+        }
+    }
+    apply_affixes(&mut playlists);
+    let mut new_playlist = merge_playlists(target, playlists.into_iter().map(|fpl| fpl.playlist).collect());
+    dedup_playlist(target, &mut new_playlist);
+    sort_playlist(target, &mut new_playlist);
+    new_playlist
+}
+
+/// The stages of the target which a dry run skips, they write files or run commands.
+pub(crate) fn get_dry_run_skipped_stages(cfg: &Config, target: &ConfigTarget) -> Vec<&'static str> {
+    let options = target.options.as_ref();
+    [
+        ("hooks", target.hooks.as_ref().is_some_and(|hooks| hooks.iter().next().is_some())),
+        ("tmdb enrichment", cfg.tmdb.is_some() && options.is_some_and(|o| o.tmdb_enrich)),
+        ("health check", target.health_check.is_some()),
+        ("channel numbering", target.numbering.is_some()),
+        ("series cache", options.is_some_and(|o| o.xtream_resolve_series)),
+        ("writing the outputs", true),
+        ("exec", target.exec.is_some()),
+    ].iter().filter(|(_, configured)| *configured).map(|(stage, _)| *stage).collect()
+}
+
+fn print_playlist_summary(target: &ConfigTarget, playlist: &[PlaylistGroup], skipped_stages: &[&str]) {
+    let mut summary = format!("Target {}", &target.name);
+    for group in playlist {
+        let _ = write!(summary, "\n  [{}] {}: {}", group.xtream_cluster, &group.title, group.channels.len());
+    }
+    let channel_count: usize = playlist.iter().map(|group| group.channels.len()).sum();
+    let _ = write!(summary, "\n  {} groups, {} channels", playlist.len(), channel_count);
+    let _ = write!(summary, "\n  skipped: {}", skipped_stages.join(", "));
+    // printed at once, the sources can be processed in parallel
+    println!("{}", summary);
+}
+
 fn persist_playlist(playlist: &[PlaylistGroup], epg: Option<Epg>,
-                    target: &ConfigTarget, cfg: &Config) -> Result<(), Vec<M3uFilterError>> {
+                    target: &ConfigTarget, cfg: &Config, write_playlist: bool) -> Result<(), Vec<M3uFilterError>> {
     let mut errors = vec![];
+    if write_playlist {
+        if let Some(output_dir) = target.get_output_dir(cfg) {
+            if let Err(err) = std::fs::create_dir_all(&output_dir) {
+                return Err(vec![M3uFilterError::new(M3uFilterErrorKind::Notify,
+                                                    format!("failed to create output dir {}: {}", output_dir.to_string_lossy(), err))]);
+            }
+        }
+    }
     for output in &target.output {
-        match match output.target {
-            TargetType::M3u => write_m3u_playlist(target, cfg, playlist, &output.filename),
-            TargetType::Strm => write_strm_playlist(target, cfg, playlist, &output.filename),
-            TargetType::Xtream => write_xtream_playlist(target, cfg, playlist)
-        } {
+        // an unchanged playlist is not written again, but the epg can still change
+        let result = if write_playlist {
+            match output.target {
+                TargetType::M3u => write_m3u_playlist(target, cfg, playlist, &output.filename),
+                TargetType::Strm => write_strm_playlist(target, cfg, playlist, &output.filename),
+                TargetType::Xtream => write_xtream_playlist(target, cfg, playlist),
+                TargetType::Kodi => write_kodi_playlist(target, cfg, playlist, &output.filename),
+                TargetType::Json => write_json_playlist(cfg, target, playlist, &output.filename),
+                TargetType::Csv => write_csv_playlist(cfg, target, playlist, &output.filename),
+            }
+        } else {
+            Ok(())
+        };
+        match result {
             Ok(_) => {
                 if !playlist.is_empty() {
                     match write_epg(target, cfg, &epg, output) {
@@ -544,12 +940,51 @@ fn persist_playlist(playlist: &[PlaylistGroup], epg: Option<Epg>,
             Err(err) => errors.push(err)
         }
     }
+    if write_playlist {
+        if let Err(err) = write_search_index(target, cfg, playlist) {
+            errors.push(err);
+        }
+    }
 
     if errors.is_empty() { Ok(()) } else { Err(errors) }
 }
 
-pub(crate) async fn exec_processing(cfg: Arc<Config>, targets: Arc<ProcessTargets>) {
-    let (stats, errors) = process_sources(cfg.to_owned(), targets.to_owned()).await;
+/// Writes the outputs of the target from a snapshot of its history, the epg is kept.
+/// Returns `Ok(false)` if the snapshot does not exist.
+pub(crate) fn restore_snapshot(cfg: &Config, target: &ConfigTarget, id: &str) -> Result<bool, Vec<M3uFilterError>> {
+    let playlist = match history_repository::load_snapshot(cfg, &target.name, id) {
+        Some(playlist) => playlist,
+        None => return Ok(false),
+    };
+    persist_playlist(&playlist, None, target, cfg, true)?;
+    metrics::record_target(&cfg.working_dir, &target.name, &playlist);
+    info!("Restored snapshot {} of target {}", id, &target.name);
+    Ok(true)
+}
+
+pub(crate) fn get_process_target_names(cfg: &Config, targets: &ProcessTargets) -> Vec<String> {
+    cfg.sources.iter().flat_map(|source| &source.targets)
+        .filter(|target| is_target_enabled(target, targets)).map(|target| target.name.to_string()).collect()
+}
+
+/// Processes the targets as job, a new job is created if no `job_id` is given.
+/// The job waits for a free slot and until no other job processes one of its targets.
+pub(crate) async fn exec_processing(cfg: Arc<Config>, targets: Arc<ProcessTargets>, job_id: Option<String>) {
+    let target_names = get_process_target_names(&cfg, &targets);
+    let job_id = job_id.unwrap_or_else(|| jobs::create_job(&cfg.working_dir, target_names.clone(), cfg.sources.len()));
+    let _permit = match jobs::acquire(&cfg.working_dir, target_names, cfg.max_parallel_jobs).await {
+        Some(permit) => permit,
+        None => {
+            info!("Job {} not started, server is shutting down", &job_id);
+            jobs::finish_job(&job_id, vec![String::from("server is shutting down")]);
+            return;
+        }
+    };
+    debug!("Starting job {}", &job_id);
+    jobs::start_job(&job_id);
+    let start_time = Instant::now();
+    let (stats, errors) = process_sources(cfg.to_owned(), targets.to_owned(), Some(job_id.clone())).await;
+    metrics::record_processing(&cfg.working_dir, start_time.elapsed());
     let stats_msg = format!("{{\"stats\": {}}}", stats.iter().map(|stat| stat.to_string()).collect::<Vec<String>>().join("\n"));
     // print stats
     info!("{}", stats_msg);
@@ -562,4 +997,5 @@ pub(crate) async fn exec_processing(cfg: Arc<Config>, targets: Arc<ProcessTarget
         let error_msg = format!("{{\"errors\": \"{}\"}}",message.as_str());
         send_message(&MsgKind::Error, &cfg.messaging, error_msg.as_str());
     }
+    jobs::finish_job(&job_id, errors.iter().map(|err| err.message.to_string()).collect());
 }
\ No newline at end of file
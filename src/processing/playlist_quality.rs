@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use log::{debug, info};
+use unidecode::unidecode;
+
+use crate::model::config::{ConfigQualityPreference, ConfigTarget};
+use crate::model::model_config::{Quality, QualityMode};
+use crate::model::model_playlist::{PlaylistGroup, PlaylistItemType};
+
+fn get_token_quality(token: &str) -> Option<Quality> {
+    match token {
+        "4K" | "UHD" | "2160P" | "2160" => Some(Quality::Uhd),
+        "FHD" | "1080P" | "1080I" | "1080" => Some(Quality::Fhd),
+        "HD" | "720P" | "720" => Some(Quality::Hd),
+        "SD" | "576P" | "576I" | "480P" => Some(Quality::Sd),
+        _ => None,
+    }
+}
+
+/// Returns the name without quality tokens, used to group the variants of a channel, and the quality of the name.
+fn get_variant(name: &str) -> (String, Option<Quality>) {
+    let mut quality = None;
+    let mut key = String::new();
+    for token in unidecode(name).split(|c: char| !c.is_alphanumeric()).filter(|t| !t.is_empty()) {
+        match get_token_quality(&token.to_uppercase()) {
+            Some(token_quality) => { quality.get_or_insert(token_quality); }
+            None => key.push_str(&token.to_lowercase()),
+        }
+    }
+    (key, quality)
+}
+
+fn get_height_quality(height: u32) -> Quality {
+    match height {
+        2160.. => Quality::Uhd,
+        1080.. => Quality::Fhd,
+        720.. => Quality::Hd,
+        _ => Quality::Sd,
+    }
+}
+
+async fn probe_quality(ffprobe: String, url: String) -> Option<Quality> {
+    let output = actix_rt::task::spawn_blocking(move || {
+        std::process::Command::new(ffprobe)
+            .args(["-v", "error", "-rw_timeout", "5000000", "-select_streams", "v:0",
+                "-show_entries", "stream=height", "-of", "csv=p=0", &url])
+            .output()
+    }).await.ok()?.ok()?;
+    let height = String::from_utf8_lossy(&output.stdout).trim().parse::<u32>().ok()?;
+    Some(get_height_quality(height))
+}
+
+struct Variant {
+    group_idx: usize,
+    channel_idx: usize,
+    rank: usize,
+}
+
+/// Groups the live channels by their name without quality tokens like `HD` or `1080p`.
+/// With mode `best` only the variant with the most preferred quality is kept,
+/// with mode `order` the variants inside a group are ordered by the preference.
+pub(crate) async fn apply_quality_preference(target: &ConfigTarget, playlist: &mut Vec<PlaylistGroup>) {
+    let preference: &ConfigQualityPreference = match &target.quality_preference {
+        Some(preference) => preference,
+        None => return,
+    };
+    // channels without a known quality are placed after all listed qualities
+    let get_rank = |quality: Option<Quality>| quality
+        .and_then(|q| preference.order.iter().position(|o| *o == q))
+        .unwrap_or(preference.order.len());
+
+    let mut variants: HashMap<String, Vec<(Variant, Option<Quality>, String)>> = HashMap::new();
+    for (group_idx, group) in playlist.iter().enumerate() {
+        for (channel_idx, pli) in group.channels.iter().enumerate() {
+            let header = pli.header.borrow();
+            if header.item_type != PlaylistItemType::Live {
+                continue;
+            }
+            let (key, quality) = get_variant(&header.name);
+            if !key.is_empty() {
+                variants.entry(key).or_default()
+                    .push((Variant { group_idx, channel_idx, rank: 0 }, quality, header.url.to_string()));
+            }
+        }
+    }
+    variants.retain(|_, list| list.len() > 1);
+
+    let mut variant_sets: Vec<Vec<Variant>> = vec![];
+    for list in variants.into_values() {
+        let mut set = vec![];
+        for (mut variant, quality, url) in list {
+            let quality = match (quality, &preference.ffprobe) {
+                (None, Some(ffprobe)) => {
+                    let probed = probe_quality(ffprobe.to_string(), url).await;
+                    debug!("probed quality {:?} for channel {}/{}", probed, variant.group_idx, variant.channel_idx);
+                    probed
+                }
+                _ => quality,
+            };
+            variant.rank = get_rank(quality);
+            set.push(variant);
+        }
+        // channels with the same name and quality are left to dedup
+        if set.iter().any(|variant| variant.rank != set[0].rank) {
+            variant_sets.push(set);
+        }
+    }
+
+    match preference.mode {
+        QualityMode::Best => {
+            let mut removed = vec![];
+            for mut set in variant_sets {
+                // the first of the most preferred variants is kept
+                set.sort_by_key(|variant| (variant.rank, variant.group_idx, variant.channel_idx));
+                removed.extend(set.into_iter().skip(1).map(|variant| (variant.group_idx, variant.channel_idx)));
+            }
+            let removed_count = removed.len();
+            removed.sort_unstable_by(|a, b| b.cmp(a));
+            for (group_idx, channel_idx) in removed {
+                playlist[group_idx].channels.remove(channel_idx);
+            }
+            playlist.retain(|group| !group.channels.is_empty());
+            info!("Quality preference {}: {} variants removed", &target.name, removed_count);
+        }
+        QualityMode::Order => {
+            for set in variant_sets {
+                let mut by_group: HashMap<usize, Vec<Variant>> = HashMap::new();
+                set.into_iter().for_each(|variant| by_group.entry(variant.group_idx).or_default().push(variant));
+                // the positions of the variants inside the group are filled in preference order
+                for (group_idx, group_variants) in by_group {
+                    let mut positions: Vec<usize> = group_variants.iter().map(|variant| variant.channel_idx).collect();
+                    positions.sort_unstable();
+                    let mut ordered = group_variants;
+                    ordered.sort_by_key(|variant| (variant.rank, variant.channel_idx));
+                    let channels = &playlist[group_idx].channels;
+                    let items: Vec<_> = ordered.iter().map(|variant| channels[variant.channel_idx].clone()).collect();
+                    let channels = &mut playlist[group_idx].channels;
+                    for (position, item) in positions.into_iter().zip(items) {
+                        channels[position] = item;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use log::debug;
+use serde_json::Value;
+
+use crate::filter::ValueProvider;
+use crate::model::config::ConfigTarget;
+use crate::model::model_playlist::{PlaylistGroup, PlaylistItemHeader, XtreamCluster};
+use crate::processing::playlist_overrides::get_group;
+use crate::utils::string_utils;
+
+const AUDIO_EXTENSIONS: [&str; 8] = ["aac", "flac", "m4a", "mp3", "oga", "ogg", "opus", "wav"];
+pub(crate) const RADIO_ATTRIBUTE: &str = "radio";
+pub(crate) const RADIO_STREAM_TYPE: &str = "radio_streams";
+
+fn is_audio_url(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.rsplit('/').next()
+        .and_then(|file| file.rsplit_once('.'))
+        .is_some_and(|(_, extension)| AUDIO_EXTENSIONS.iter().any(|audio| audio.eq_ignore_ascii_case(extension)))
+}
+
+/// A live channel is a radio station, if the input marks it with `radio="true"` or the xtream
+/// stream type `radio_streams`, or if its url ends with the extension of an audio file.
+pub(crate) fn is_radio(header: &PlaylistItemHeader) -> bool {
+    if header.xtream_cluster != XtreamCluster::Live {
+        return false;
+    }
+    header.m3u_attributes.as_ref().is_some_and(|attributes| attributes.iter()
+        .any(|(key, value)| key == RADIO_ATTRIBUTE && value.eq_ignore_ascii_case("true")))
+        || header.additional_properties.as_ref().is_some_and(|properties| properties.iter()
+        .any(|(key, value)| key == "stream_type" && value.as_str() == Some(RADIO_STREAM_TYPE)))
+        || is_audio_url(&header.url)
+}
+
+/// Sets the xtream stream type and the m3u attribute of a radio station.
+pub(crate) fn mark_radio(header: &mut PlaylistItemHeader, m3u_attribute: bool) {
+    let properties = header.additional_properties.get_or_insert_with(Vec::new);
+    properties.retain(|(key, _)| key != "stream_type");
+    properties.push((String::from("stream_type"), Value::String(String::from(RADIO_STREAM_TYPE))));
+    let attributes = header.m3u_attributes.get_or_insert_with(Vec::new);
+    attributes.retain(|(key, _)| key != RADIO_ATTRIBUTE);
+    if m3u_attribute {
+        attributes.push((String::from(RADIO_ATTRIBUTE), String::from("true")));
+    }
+}
+
+/// Marks the radio stations of the live groups and moves them into the radio group, if the target has one.
+pub(crate) fn apply_radio(target: &ConfigTarget, playlist: &mut Vec<PlaylistGroup>) {
+    let radio = match &target.radio {
+        Some(radio) => radio,
+        None => return,
+    };
+    let mut count = 0;
+    let mut moved = vec![];
+    for group in playlist.iter_mut().filter(|group| group.xtream_cluster == XtreamCluster::Live) {
+        let current_title = Arc::clone(&group.title);
+        group.channels.retain(|item| {
+            let detected = is_radio(&item.header.borrow()) || radio.is_match(&ValueProvider { pli: RefCell::new(item) });
+            if !detected {
+                return true;
+            }
+            count += 1;
+            let mut header = item.header.borrow_mut();
+            mark_radio(&mut header, radio.m3u_attribute);
+            match &radio.group {
+                Some(radio_group) if radio_group.as_str() != &*current_title => {
+                    header.group = string_utils::intern(radio_group);
+                    drop(header);
+                    moved.push(item.clone());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+    if let Some(radio_group) = &radio.group {
+        let title = string_utils::intern(radio_group);
+        for item in moved {
+            let next_id = playlist.iter().map(|group| group.id).max().unwrap_or(0) + 1;
+            get_group(playlist, &title, &XtreamCluster::Live, next_id).channels.push(item);
+        }
+        playlist.retain(|group| !group.channels.is_empty());
+    }
+    debug!("Target {} has {} radio stations", target.name, count);
+}
@@ -1,4 +1,4 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Path};
 use log::{error, info};
 use regex::Regex;
@@ -7,12 +7,27 @@ use crate::model::config::Config;
 use crate::model::model_playlist::PlaylistGroup;
 use crate::utils::file_utils;
 
+/// Channel name to url of a watched group.
+type WatchTree = BTreeMap<String, String>;
+
+struct WatchChanges {
+    added: Vec<(String, String)>,
+    removed: Vec<(String, String)>,
+    changed: Vec<(String, String)>,
+}
+
+impl WatchChanges {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
 pub(crate) fn process_group_watch(cfg: &Config, target_name: &str, pl: &PlaylistGroup) {
-    let mut new_tree = BTreeSet::new();
+    let mut new_tree = WatchTree::new();
     pl.channels.iter().for_each(|chan| {
         let header = chan.header.borrow();
-        let title = if header.title.is_empty() { header.title.to_string() } else { header.name.to_string() };
-        new_tree.insert(title);
+        let title = if header.title.is_empty() { header.name.to_string() } else { header.title.to_string() };
+        new_tree.insert(title, header.url.to_string());
     });
 
     let filename_re = Regex::new(r"[^A-Za-z0-9_-]").unwrap();
@@ -25,12 +40,10 @@ pub(crate) fn process_group_watch(cfg: &Config, target_name: &str, pl: &Playlist
             if path.exists() {
                 match load_watch_tree(&path) {
                     Some(loaded_tree) => {
-                        // Find elements in set2 but not in set1
-                        let added_difference: BTreeSet<String> = new_tree.difference(&loaded_tree).cloned().collect();
-                        let removed_difference: BTreeSet<String> = loaded_tree.difference(&new_tree).cloned().collect();
-                        if !added_difference.is_empty() || !removed_difference.is_empty() {
+                        let changes = diff_watch_trees(&loaded_tree, &new_tree);
+                        if !changes.is_empty() {
                             changed = true;
-                            handle_watch_notification(cfg, added_difference, removed_difference, target_name, &pl.title);
+                            handle_watch_notification(cfg, &changes, target_name, &pl.title);
                         }
                     }
                     None => {
@@ -42,7 +55,7 @@ pub(crate) fn process_group_watch(cfg: &Config, target_name: &str, pl: &Playlist
                 changed = true;
             }
             if changed {
-                match save_watch_tree(&save_path, new_tree) {
+                match save_watch_tree(&save_path, &new_tree) {
                     Ok(_) => {}
                     Err(err) => {
                         error!("failed to write watch_file {}: {}", &save_path.to_str().unwrap_or_default(), err)
@@ -56,41 +69,66 @@ pub(crate) fn process_group_watch(cfg: &Config, target_name: &str, pl: &Playlist
     }
 }
 
-fn handle_watch_notification(cfg: &Config, added: BTreeSet<String>, removed: BTreeSet<String>, target_name: &str, group_name: &str) {
-    let added_entries = added.iter().map(|name| name.to_string()).collect::<Vec<String>>().join("\n\t");
-    let removed_entries = removed.iter().map(|name| name.to_string()).collect::<Vec<String>>().join("\n\t");
-
-    let mut message = vec![];
-    if !added_entries.is_empty() {
-        message.push("added: [\n\t".to_string());
-        message.push(added_entries);
-        message.push("\n]\n".to_string());
-    }
-    if !removed_entries.is_empty() {
-        message.push("removed: [\n\t".to_string());
-        message.push(removed_entries);
-        message.push("\n]\n".to_string());
+fn diff_watch_trees(old_tree: &WatchTree, new_tree: &WatchTree) -> WatchChanges {
+    let to_entry = |(name, url): (&String, &String)| (name.to_string(), url.to_string());
+    WatchChanges {
+        added: new_tree.iter().filter(|(name, _)| !old_tree.contains_key(*name)).map(to_entry).collect(),
+        removed: old_tree.iter().filter(|(name, _)| !new_tree.contains_key(*name)).map(to_entry).collect(),
+        // watch files of older versions have no urls
+        changed: new_tree.iter().filter(|(name, url)| old_tree.get(*name)
+            .is_some_and(|old_url| !old_url.is_empty() && old_url != *url)).map(to_entry).collect(),
     }
+}
 
-    if !message.is_empty() {
-        let msg = format!("Changes {}/{}\n{}", target_name, group_name, message.join(""));
+fn format_watch_template(template: &str, change: &str, target_name: &str, group_name: &str, entry: &(String, String)) -> String {
+    template.replace("{change}", change)
+        .replace("{target}", target_name)
+        .replace("{group}", group_name)
+        .replace("{name}", &entry.0)
+        .replace("{url}", &entry.1)
+}
+
+fn handle_watch_notification(cfg: &Config, changes: &WatchChanges, target_name: &str, group_name: &str) {
+    let sections = [("added", &changes.added), ("removed", &changes.removed), ("changed", &changes.changed)];
+    let template = cfg.messaging.as_ref().and_then(|m| m.watch_template.as_ref());
+    let msg = match template {
+        Some(tmpl) => sections.iter()
+            .flat_map(|(change, entries)| entries.iter()
+                .map(move |entry| format_watch_template(tmpl, change, target_name, group_name, entry)))
+            .collect::<Vec<String>>().join("\n"),
+        None => {
+            let mut message = vec![];
+            for (change, entries) in &sections {
+                if !entries.is_empty() {
+                    let names = entries.iter().map(|(name, _)| name.as_str()).collect::<Vec<&str>>().join("\n\t");
+                    message.push(format!("{}: [\n\t{}\n]\n", change, names));
+                }
+            }
+            format!("Changes {}/{}\n{}", target_name, group_name, message.join(""))
+        }
+    };
+
+    if !msg.is_empty() {
         info!("{}", &msg);
         send_message(&MsgKind::Watch, &cfg.messaging, &msg);
     }
 }
 
-fn load_watch_tree(path: &Path) -> Option<BTreeSet<String>> {
+fn load_watch_tree(path: &Path) -> Option<WatchTree> {
     match std::fs::read(path) {
         Ok(encoded) => {
-            let decoded: BTreeSet<String> = bincode::deserialize(&encoded[..]).unwrap();
-            Some(decoded)
+            match bincode::deserialize::<WatchTree>(&encoded[..]) {
+                Ok(decoded) => Some(decoded),
+                // watch files of older versions contain only the channel names
+                Err(_) => bincode::deserialize::<BTreeSet<String>>(&encoded[..]).ok()
+                    .map(|names| names.into_iter().map(|name| (name, String::new())).collect()),
+            }
         }
         Err(_) => None,
     }
 }
 
-fn save_watch_tree(path: &Path, tree: BTreeSet<String>) -> std::io::Result<()> {
-    let encoded: Vec<u8> = bincode::serialize(&tree).unwrap();
+fn save_watch_tree(path: &Path, tree: &WatchTree) -> std::io::Result<()> {
+    let encoded: Vec<u8> = bincode::serialize(tree).unwrap();
     std::fs::write(path, encoded)
 }
-
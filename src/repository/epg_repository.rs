@@ -15,14 +15,16 @@ fn write_epg_file(target: &ConfigTarget, epg: &Epg, path: &Path) -> Result<(), M
     match epg.write_to(&mut writer) {
         Ok(_) => {
             let result = writer.into_inner().into_inner();
-            match File::create(path) {
+            // the epg is written to a temporary file and renamed, requests never see a partial file
+            let tmp_path = path.with_extension("tmp");
+            match File::create(&tmp_path) {
                 Ok(mut epg_file) => {
                     match epg_file.write_all("<?xml version=\"1.0\" encoding=\"utf-8\" ?><!DOCTYPE tv SYSTEM \"xmltv.dtd\">".as_bytes()) {
                         Ok(_) => {}
                         Err(err) => return Err(M3uFilterError::new(
                             M3uFilterErrorKind::Notify, format!("failed to write epg: {} - {}", path.to_str().unwrap_or("?"), err))),
                     }
-                    match epg_file.write_all(&result) {
+                    match epg_file.write_all(&result).and_then(|_| std::fs::rename(&tmp_path, path)) {
                         Ok(_) => {
                             if log_enabled!(Level::Debug) {
                                 debug!("Epg for target {} written to {}", target.name, path.to_str().unwrap_or("?"))
@@ -51,7 +53,7 @@ pub(crate) fn write_epg(target: &ConfigTarget, cfg: &Config, epg: &Option<Epg>,
                         M3uFilterErrorKind::Notify,
                         format!("write epg for target {} failed: No filename set", target.name)));
                 }
-                if let Some(path) = get_m3u_epg_file_path(cfg, &output.filename) {
+                if let Some(path) = get_m3u_epg_file_path(cfg, target, &output.filename) {
                     if log_enabled!(Level::Debug) {
                         debug!("writing m3u epg to {}", path.to_str().unwrap_or("?"));
                     }
@@ -72,7 +74,7 @@ pub(crate) fn write_epg(target: &ConfigTarget, cfg: &Config, epg: &Option<Epg>,
                         format!("failed to serialize epg for target: {}, storage path not found", target.name))),
                 }
             }
-            TargetType::Strm => {}
+            TargetType::Strm | TargetType::Kodi | TargetType::Json | TargetType::Csv => {}
         }
     }
     Ok(())
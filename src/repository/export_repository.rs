@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use serde_json::{Map, Value};
+
+use crate::create_m3u_filter_error_result;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{Config, ConfigTarget};
+use crate::model::model_playlist::{PlaylistGroup, PlaylistItemHeader, PlaylistItemType};
+
+const CSV_HEADER: &str = "group,title,name,id,type,logo,epg_channel_id,url";
+
+pub(crate) fn get_item_type_name(item_type: &PlaylistItemType) -> &'static str {
+    match item_type {
+        PlaylistItemType::Live => "live",
+        PlaylistItemType::Movie => "movie",
+        PlaylistItemType::Series => "series",
+        PlaylistItemType::SeriesInfo => "series_info",
+    }
+}
+
+fn get_export_file_path(cfg: &Config, target: &ConfigTarget, filename: &Option<String>, type_name: &str) -> Result<PathBuf, M3uFilterError> {
+    match filename.as_ref().and_then(|fname| target.get_output_file_path(cfg, fname)) {
+        Some(path) => Ok(path),
+        None => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "write {} playlist failed: no filename set", type_name)
+    }
+}
+
+fn header_to_json(header: &PlaylistItemHeader) -> Value {
+    let mut value = serde_json::to_value(header).unwrap_or(Value::Object(Map::new()));
+    if let Value::Object(map) = &mut value {
+        map.insert(String::from("type"), Value::String(get_item_type_name(&header.item_type).to_string()));
+        if let Some(props) = &header.additional_properties {
+            let properties: Map<String, Value> = props.iter().map(|(k, v)| (k.to_string(), v.clone())).collect();
+            map.insert(String::from("properties"), Value::Object(properties));
+        }
+    }
+    value
+}
+
+/// Writes the playlist as json, a list of groups with their channels including the additional provider properties.
+pub(crate) fn write_json_playlist(cfg: &Config, target: &ConfigTarget, new_playlist: &[PlaylistGroup], filename: &Option<String>) -> Result<(), M3uFilterError> {
+    if !new_playlist.is_empty() {
+        let path = get_export_file_path(cfg, target, filename, "json")?;
+        let groups: Vec<Value> = new_playlist.iter().map(|pg| {
+            let mut group = Map::new();
+            group.insert(String::from("group"), Value::String(pg.title.to_string()));
+            group.insert(String::from("channels"), Value::Array(pg.channels.iter().map(|pli| header_to_json(&pli.header.borrow())).collect()));
+            Value::Object(group)
+        }).collect();
+        match File::create(&path) {
+            Ok(file) => {
+                if let Err(err) = serde_json::to_writer(BufWriter::new(file), &groups) {
+                    return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write json playlist: {} - {}", path.to_str().unwrap_or("?"), err);
+                }
+            }
+            Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write json playlist: {} - {}", path.to_str().unwrap_or("?"), err)
+        }
+    }
+    Ok(())
+}
+
+fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Writes the playlist as csv with one line per channel.
+pub(crate) fn write_csv_playlist(cfg: &Config, target: &ConfigTarget, new_playlist: &[PlaylistGroup], filename: &Option<String>) -> Result<(), M3uFilterError> {
+    if !new_playlist.is_empty() {
+        let path = get_export_file_path(cfg, target, filename, "csv")?;
+        let result = File::create(&path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            writeln!(writer, "{}", CSV_HEADER)?;
+            for pg in new_playlist {
+                for pli in &pg.channels {
+                    let header = pli.header.borrow();
+                    let columns = [pg.title.as_ref(), header.title.as_ref(), header.name.as_ref(), header.id.as_ref(),
+                        get_item_type_name(&header.item_type), header.logo.as_ref(),
+                        header.epg_channel_id.as_ref().map_or("", |id| id.as_ref()), header.url.as_ref()];
+                    writeln!(writer, "{}", columns.iter().map(|col| csv_escape(col)).collect::<Vec<String>>().join(","))?;
+                }
+            }
+            writer.flush()
+        });
+        if let Err(err) = result {
+            return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write csv playlist: {} - {}", path.to_str().unwrap_or("?"), err);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::create_m3u_filter_error_result;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{Config, ConfigTarget};
+use crate::model::model_playlist::{PlaylistGroup, PlaylistItem, PlaylistItemHeader, PlaylistItemType};
+use crate::repository::export_repository::get_item_type_name;
+use crate::repository::raw_repository::get_cluster;
+use crate::utils::{file_utils, string_utils};
+
+const HISTORY_DIR: &str = "history";
+const HISTORY_INDEX_FILE: &str = "index.json";
+const SNAPSHOT_ID_FORMAT: &str = "%Y%m%d%H%M%S%3f";
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub(crate) struct SnapshotEntry {
+    pub id: String,
+    pub timestamp: i64,
+    pub groups: usize,
+    pub channels: usize,
+    pub size: u64,
+}
+
+/// The header with the fields which are not serialized for the other outputs.
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotChannel {
+    #[serde(flatten)]
+    header: PlaylistItemHeader,
+    #[serde(rename = "type")]
+    item_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    additional_properties: Option<Vec<(String, Value)>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    m3u_attributes: Option<Vec<(String, String)>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headers: Option<Vec<(String, String)>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotGroup {
+    id: u32,
+    title: String,
+    cluster: String,
+    channels: Vec<SnapshotChannel>,
+}
+
+fn get_item_type(name: &str) -> PlaylistItemType {
+    match name {
+        "movie" => PlaylistItemType::Movie,
+        "series" => PlaylistItemType::Series,
+        "series_info" => PlaylistItemType::SeriesInfo,
+        _ => PlaylistItemType::Live,
+    }
+}
+
+fn get_history_path(cfg: &Config, target_name: &str) -> Option<PathBuf> {
+    file_utils::get_file_path(&cfg.working_dir, Some(PathBuf::from(HISTORY_DIR).join(target_name.replace(' ', "_"))))
+}
+
+/// Snapshot ids are timestamps, anything else can't be a file of the history.
+fn get_snapshot_path(path: &Path, id: &str) -> Option<PathBuf> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(path.join(format!("{}.json", id)))
+}
+
+fn read_index(path: &Path) -> Vec<SnapshotEntry> {
+    fs::read_to_string(path.join(HISTORY_INDEX_FILE)).ok()
+        .and_then(|content| serde_json::from_str::<Vec<SnapshotEntry>>(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write_file(path: &Path, content: &str) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, content)?;
+    fs::rename(&tmp_path, path)
+}
+
+fn to_snapshot(playlist: &[PlaylistGroup]) -> Vec<SnapshotGroup> {
+    playlist.iter().map(|group| SnapshotGroup {
+        id: group.id,
+        title: group.title.to_string(),
+        cluster: group.xtream_cluster.to_string(),
+        channels: group.channels.iter().map(|item| {
+            let header = item.header.borrow();
+            SnapshotChannel {
+                item_type: get_item_type_name(&header.item_type).to_string(),
+                additional_properties: header.additional_properties.clone(),
+                m3u_attributes: header.m3u_attributes.clone(),
+                headers: header.headers.clone(),
+                header: header.clone(),
+            }
+        }).collect(),
+    }).collect()
+}
+
+fn from_snapshot(groups: Vec<SnapshotGroup>) -> Vec<PlaylistGroup> {
+    groups.into_iter().map(|group| {
+        let xtream_cluster = get_cluster(&group.cluster);
+        PlaylistGroup {
+            id: group.id,
+            title: string_utils::intern(&group.title),
+            channels: group.channels.into_iter().map(|channel| {
+                let mut header = channel.header;
+                header.xtream_cluster = xtream_cluster.clone();
+                header.item_type = get_item_type(&channel.item_type);
+                header.additional_properties = channel.additional_properties;
+                header.m3u_attributes = channel.m3u_attributes;
+                header.headers = channel.headers;
+                PlaylistItem { header: RefCell::new(header) }
+            }).collect(),
+            xtream_cluster,
+        }
+    }).collect()
+}
+
+/// Keeps the playlist of the target if `history` is set in its options.
+/// The oldest snapshots exceeding `history` are deleted.
+pub(crate) fn write_snapshot(cfg: &Config, target: &ConfigTarget, playlist: &[PlaylistGroup]) -> Result<(), M3uFilterError> {
+    let keep = target.options.as_ref().map_or(0, |options| usize::from(options.history));
+    if keep == 0 || playlist.is_empty() {
+        return Ok(());
+    }
+    let path = match get_history_path(cfg, &target.name) {
+        Some(path) => path,
+        None => return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write history of target {}", target.name),
+    };
+    let now = chrono::Local::now();
+    let id = now.format(SNAPSHOT_ID_FORMAT).to_string();
+    let snapshot_path = path.join(format!("{}.json", id));
+    let content = match serde_json::to_string(&to_snapshot(playlist)) {
+        Ok(content) => content,
+        Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to serialize history of target {}: {}", target.name, err),
+    };
+    if let Err(err) = fs::create_dir_all(&path).and_then(|_| write_file(&snapshot_path, &content)) {
+        return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write history: {} - {}", snapshot_path.to_str().unwrap_or("?"), err);
+    }
+
+    let mut entries = read_index(&path);
+    entries.insert(0, SnapshotEntry {
+        id,
+        timestamp: now.timestamp(),
+        groups: playlist.len(),
+        channels: playlist.iter().map(|group| group.channels.len()).sum(),
+        size: content.len() as u64,
+    });
+    if entries.len() > keep {
+        for entry in entries.drain(keep..) {
+            if let Some(old_path) = get_snapshot_path(&path, &entry.id) {
+                if let Err(err) = fs::remove_file(&old_path) {
+                    error!("Could not remove history snapshot {}: {}", old_path.to_str().unwrap_or("?"), err);
+                }
+            }
+        }
+    }
+    match serde_json::to_string(&entries).map_err(|err| err.to_string())
+        .and_then(|index| write_file(&path.join(HISTORY_INDEX_FILE), &index).map_err(|err| err.to_string())) {
+        Ok(_) => Ok(()),
+        Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write history index of target {}: {}", target.name, err),
+    }
+}
+
+/// Lists the snapshots of the target, newest first.
+pub(crate) fn list_snapshots(cfg: &Config, target_name: &str) -> Vec<SnapshotEntry> {
+    match get_history_path(cfg, target_name) {
+        Some(path) => read_index(&path).into_iter()
+            .filter(|entry| get_snapshot_path(&path, &entry.id).is_some_and(|snapshot_path| snapshot_path.exists()))
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Returns `None` if the target has no snapshot with the id.
+pub(crate) fn load_snapshot(cfg: &Config, target_name: &str, id: &str) -> Option<Vec<PlaylistGroup>> {
+    let path = get_history_path(cfg, target_name).and_then(|path| get_snapshot_path(&path, id))?;
+    let content = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str::<Vec<SnapshotGroup>>(&content) {
+        Ok(groups) => {
+            info!("Loaded history snapshot {} of target {}", id, target_name);
+            Some(from_snapshot(groups))
+        }
+        Err(err) => {
+            error!("cant read history snapshot {}: {}", path.to_str().unwrap_or("?"), err);
+            None
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::model_playlist::XtreamCluster;
+
+const ID_MAPPING_FILE: &str = "id_mapping_v2.db";
+/// file of the store before removed ids expired, it is converted with the next save
+const LEGACY_ID_MAPPING_FILE: &str = "id_mapping.db";
+/// seconds until the id of a removed stream is dropped, a stream which comes back within this time gets its previous id
+const REMOVED_ID_GRACE_PERIOD: i64 = 30 * 86_400;
+
+/// The kind of a stream, series and their episodes have separate provider ids.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) enum VirtualIdKind {
+    Live,
+    Video,
+    Series,
+    Episode,
+}
+
+impl VirtualIdKind {
+    pub(crate) fn from_cluster(cluster: &XtreamCluster) -> Self {
+        match cluster {
+            XtreamCluster::Live => VirtualIdKind::Live,
+            XtreamCluster::Video => VirtualIdKind::Video,
+            XtreamCluster::Series => VirtualIdKind::Series,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VirtualIdEntry {
+    kind: VirtualIdKind,
+    provider_id: i32,
+    title: String,
+    /// timestamp of the first run without the stream
+    removed: Option<i64>,
+}
+
+/// The virtual ids of a target, kept across processing runs.
+/// Entries of removed streams are kept for `REMOVED_ID_GRACE_PERIOD`, a stream which comes back gets its previous id.
+/// Ids are never given to another stream, `next_id` only grows.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct VirtualIdStore {
+    streams: BTreeMap<i32, VirtualIdEntry>,
+    categories: HashMap<String, u32>,
+    next_id: i32,
+}
+
+#[derive(Deserialize)]
+struct LegacyVirtualIdEntry {
+    kind: VirtualIdKind,
+    provider_id: i32,
+    title: String,
+}
+
+#[derive(Deserialize)]
+struct LegacyVirtualIdStore {
+    streams: BTreeMap<i32, LegacyVirtualIdEntry>,
+    categories: HashMap<String, u32>,
+}
+
+impl From<LegacyVirtualIdStore> for VirtualIdStore {
+    fn from(legacy: LegacyVirtualIdStore) -> Self {
+        VirtualIdStore {
+            next_id: legacy.streams.keys().next_back().map_or(1, |id| id + 1),
+            streams: legacy.streams.into_iter().map(|(id, entry)|
+                (id, VirtualIdEntry { kind: entry.kind, provider_id: entry.provider_id, title: entry.title, removed: None })).collect(),
+            categories: legacy.categories,
+        }
+    }
+}
+
+/// Assigns the virtual ids while the xtream collections are written.
+pub(crate) struct VirtualIdMapper {
+    store: VirtualIdStore,
+    by_provider_id: HashMap<(VirtualIdKind, i32), i32>,
+    by_title: HashMap<(VirtualIdKind, String), Vec<i32>>,
+    /// provider ids of the current playlist, their ids can't be taken over by a renumbered stream
+    provider_ids: HashSet<(VirtualIdKind, i32)>,
+    assigned: HashSet<i32>,
+}
+
+fn get_id_mapping_path(path: &Path) -> PathBuf {
+    path.join(ID_MAPPING_FILE)
+}
+
+/// The path of the store file and whether it has the legacy format.
+fn get_existing_id_mapping_path(path: &Path) -> (PathBuf, bool) {
+    let file_path = get_id_mapping_path(path);
+    let legacy_path = path.join(LEGACY_ID_MAPPING_FILE);
+    if !file_path.exists() && legacy_path.exists() {
+        (legacy_path, true)
+    } else {
+        (file_path, false)
+    }
+}
+
+fn get_title_key(title: &str) -> String {
+    title.trim().to_lowercase()
+}
+
+fn get_category_key(cluster: &XtreamCluster, title: &str) -> String {
+    format!("{}/{}", cluster, get_title_key(title))
+}
+
+fn read_store(file_path: &Path, legacy: bool) -> Option<VirtualIdStore> {
+    let encoded = fs::read(file_path).ok()?;
+    if legacy {
+        bincode::deserialize::<LegacyVirtualIdStore>(&encoded).ok().map(VirtualIdStore::from)
+    } else {
+        bincode::deserialize(&encoded).ok()
+    }
+}
+
+impl VirtualIdMapper {
+    /// Loads the ids of the previous runs, `provider_ids` are the streams of the playlist which is written.
+    pub(crate) fn load(path: &Path, provider_ids: HashSet<(VirtualIdKind, i32)>) -> Self {
+        let (file_path, legacy) = get_existing_id_mapping_path(path);
+        let store = read_store(&file_path, legacy).unwrap_or_default();
+        let mut by_provider_id = HashMap::new();
+        let mut by_title: HashMap<(VirtualIdKind, String), Vec<i32>> = HashMap::new();
+        for (virtual_id, entry) in &store.streams {
+            by_provider_id.insert((entry.kind, entry.provider_id), *virtual_id);
+            by_title.entry((entry.kind, get_title_key(&entry.title))).or_default().push(*virtual_id);
+        }
+        VirtualIdMapper { store, by_provider_id, by_title, provider_ids, assigned: HashSet::new() }
+    }
+
+    /// Returns the virtual id of the stream, a stream listed in several groups keeps one id.
+    /// A stream with a new provider id takes over the id of a removed stream with the same title, otherwise it gets a new id.
+    pub(crate) fn get_stream_id(&mut self, kind: VirtualIdKind, provider_id: i32, title: &str) -> i32 {
+        let known_id = self.by_provider_id.get(&(kind, provider_id)).copied();
+        let virtual_id = known_id.or_else(|| self.by_title.get(&(kind, get_title_key(title)))
+            .and_then(|ids| ids.iter().copied().find(|id| !self.assigned.contains(id)
+                && self.store.streams.get(id).is_some_and(|entry| !self.provider_ids.contains(&(entry.kind, entry.provider_id))))))
+            .unwrap_or_else(|| {
+                let next_id = self.store.next_id.max(self.store.streams.keys().next_back().map_or(1, |id| id + 1));
+                self.store.next_id = next_id + 1;
+                next_id
+            });
+        if let Some(previous) = self.store.streams.insert(virtual_id, VirtualIdEntry { kind, provider_id, title: title.to_string(), removed: None }) {
+            self.by_provider_id.remove(&(previous.kind, previous.provider_id));
+        }
+        self.by_provider_id.insert((kind, provider_id), virtual_id);
+        self.assigned.insert(virtual_id);
+        virtual_id
+    }
+
+    /// Returns the virtual id of the category, categories are identified by cluster and title.
+    pub(crate) fn get_category_id(&mut self, cluster: &XtreamCluster, title: &str) -> u32 {
+        let key = get_category_key(cluster, title);
+        if let Some(id) = self.store.categories.get(&key) {
+            return *id;
+        }
+        let id = self.store.categories.values().max().map_or(1, |id| id + 1);
+        self.store.categories.insert(key, id);
+        id
+    }
+
+    /// Marks the ids of the streams which are not in the playlist as removed,
+    /// the ids which are removed longer than `REMOVED_ID_GRACE_PERIOD` are dropped.
+    fn expire_removed(&mut self, now: i64) {
+        let provider_ids = &self.provider_ids;
+        let assigned = &self.assigned;
+        self.store.streams.retain(|id, entry| {
+            if assigned.contains(id) || provider_ids.contains(&(entry.kind, entry.provider_id)) {
+                entry.removed = None;
+                return true;
+            }
+            now - *entry.removed.get_or_insert(now) < REMOVED_ID_GRACE_PERIOD
+        });
+    }
+
+    /// Saves the ids, `now` is the time of the run which expires the removed ids.
+    pub(crate) fn save(&mut self, path: &Path, now: i64) -> std::io::Result<()> {
+        self.expire_removed(now);
+        let file_path = get_id_mapping_path(path);
+        let tmp_path = file_path.with_extension("tmp");
+        fs::write(&tmp_path, bincode::serialize(&self.store).unwrap_or_default())?;
+        fs::rename(&tmp_path, &file_path)?;
+        // the modification time can be unchanged for saves in quick succession
+        id_mapping_cache().lock().unwrap().remove(&file_path);
+        let _ = fs::remove_file(path.join(LEGACY_ID_MAPPING_FILE));
+        Ok(())
+    }
+}
+
+type IdMappingCache = HashMap<PathBuf, (SystemTime, Arc<VirtualIdStore>)>;
+
+fn id_mapping_cache() -> &'static Mutex<IdMappingCache> {
+    static CACHE: OnceLock<Mutex<IdMappingCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The store is read again only if a processing run has replaced the file.
+fn load_store(path: &Path) -> Option<Arc<VirtualIdStore>> {
+    let (file_path, legacy) = get_existing_id_mapping_path(path);
+    let modified = fs::metadata(&file_path).and_then(|meta| meta.modified()).ok()?;
+    let mut cache = id_mapping_cache().lock().unwrap();
+    if let Some((cached_modified, store)) = cache.get(&file_path) {
+        if *cached_modified == modified {
+            return Some(Arc::clone(store));
+        }
+    }
+    let store = Arc::new(read_store(&file_path, legacy)?);
+    cache.insert(file_path, (modified, Arc::clone(&store)));
+    Some(store)
+}
+
+/// Returns the provider id of the virtual id, `None` if the id is unknown or has another kind.
+pub(crate) fn get_provider_id(path: &Path, virtual_id: i32, kinds: &[VirtualIdKind]) -> Option<i32> {
+    load_store(path)?.streams.get(&virtual_id)
+        .filter(|entry| kinds.contains(&entry.kind))
+        .map(|entry| entry.provider_id)
+}
@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use log::error;
+use quick_xml::escape::escape;
+use serde_json::Value;
+
+use crate::create_m3u_filter_error_result;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{Config, ConfigTarget};
+use crate::model::model_config::StrmLayout;
+use crate::model::model_playlist::{PlaylistGroup, PlaylistItemHeader, PlaylistItemType};
+use crate::repository::m3u_repository::sanitize_for_filename;
+
+const MOVIES_DIR: &str = "Movies";
+const TV_SHOWS_DIR: &str = "TV Shows";
+const SHOWS_DIR: &str = "Shows";
+
+fn get_property(header: &PlaylistItemHeader, name: &str) -> Option<String> {
+    header.additional_properties.as_ref()
+        .and_then(|props| props.iter().find(|(key, _)| key.eq(name)))
+        .and_then(|(_, value)| match value {
+            Value::String(text) => Some(text.to_string()),
+            Value::Number(num) => Some(num.to_string()),
+            _ => None,
+        })
+        .filter(|text| !text.trim().is_empty())
+}
+
+fn get_year(header: &PlaylistItemHeader) -> Option<String> {
+    get_property(header, "year")
+        .or_else(|| get_property(header, "release_date").map(|date| date.chars().take(4).collect()))
+        .filter(|year| year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn add_nfo_element(nfo: &mut String, tag: &str, value: Option<String>) {
+    if let Some(text) = value {
+        nfo.push_str(&format!("  <{}>{}</{}>\n", tag, escape(text.as_str()), tag));
+    }
+}
+
+fn add_nfo_cast(nfo: &mut String, header: &PlaylistItemHeader) {
+    if let Some(cast) = get_property(header, "cast") {
+        cast.split(',').map(str::trim).filter(|name| !name.is_empty()).for_each(|name| {
+            nfo.push_str(&format!("  <actor>\n    <name>{}</name>\n  </actor>\n", escape(name)));
+        });
+    }
+}
+
+fn create_movie_nfo(header: &PlaylistItemHeader) -> String {
+    let mut nfo = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\" ?>\n<movie>\n");
+    add_nfo_element(&mut nfo, "title", Some(header.title.to_string()));
+    add_nfo_element(&mut nfo, "plot", get_property(header, "plot"));
+    add_nfo_element(&mut nfo, "year", get_year(header));
+    add_nfo_element(&mut nfo, "rating", get_property(header, "rating"));
+    add_nfo_element(&mut nfo, "genre", get_property(header, "genre"));
+    add_nfo_element(&mut nfo, "director", get_property(header, "director"));
+    add_nfo_element(&mut nfo, "thumb", Some(header.logo.to_string()).filter(|logo| !logo.is_empty()));
+    if let Some(tmdb_id) = get_property(header, "tmdb_id").filter(|id| id != "0") {
+        nfo.push_str(&format!("  <uniqueid type=\"tmdb\" default=\"true\">{}</uniqueid>\n", escape(tmdb_id.as_str())));
+    }
+    add_nfo_cast(&mut nfo, header);
+    nfo.push_str("</movie>\n");
+    nfo
+}
+
+fn create_episode_nfo(header: &PlaylistItemHeader, season: &Option<String>, episode: &Option<String>) -> String {
+    let mut nfo = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\" ?>\n<episodedetails>\n");
+    add_nfo_element(&mut nfo, "title", Some(header.title.to_string()));
+    add_nfo_element(&mut nfo, "showtitle", get_property(header, "series_name"));
+    add_nfo_element(&mut nfo, "season", season.clone());
+    add_nfo_element(&mut nfo, "episode", episode.clone());
+    add_nfo_element(&mut nfo, "plot", get_property(header, "plot"));
+    add_nfo_element(&mut nfo, "aired", get_property(header, "release_date"));
+    add_nfo_element(&mut nfo, "rating", get_property(header, "rating"));
+    add_nfo_element(&mut nfo, "director", get_property(header, "director"));
+    add_nfo_element(&mut nfo, "thumb", Some(header.logo.to_string()).filter(|logo| !logo.is_empty()));
+    add_nfo_cast(&mut nfo, header);
+    nfo.push_str("</episodedetails>\n");
+    nfo
+}
+
+/// Returns the directory and the file name without extension for the item in the kodi or jellyfin library layout.
+/// Live channels have no place in the library.
+pub(crate) fn get_library_file_path(root: &Path, header: &PlaylistItemHeader, underscore_whitespace: bool, layout: &StrmLayout) -> Option<(PathBuf, String)> {
+    let title = sanitize_for_filename(&header.title, underscore_whitespace);
+    match header.item_type {
+        PlaylistItemType::Movie => {
+            let name = match get_year(header) {
+                Some(year) => format!("{} ({})", title, year),
+                None => title,
+            };
+            Some((root.join(MOVIES_DIR).join(&name), name))
+        }
+        PlaylistItemType::Series => {
+            let show = sanitize_for_filename(&get_property(header, "series_name").unwrap_or_else(|| header.group.to_string()), underscore_whitespace);
+            match (get_property(header, "season"), get_property(header, "episode")) {
+                (Some(season), Some(episode)) => {
+                    let season_num = season.parse::<u32>().unwrap_or(0);
+                    let episode_num = episode.parse::<u32>().unwrap_or(0);
+                    let season_dir = format!("Season {:02}", season_num);
+                    if *layout == StrmLayout::Jellyfin {
+                        Some((root.join(SHOWS_DIR).join(&show).join(season_dir), format!("S{:02}E{:02}", season_num, episode_num)))
+                    } else {
+                        Some((root.join(TV_SHOWS_DIR).join(&show).join(season_dir), format!("{} S{:02}E{:02}", show, season_num, episode_num)))
+                    }
+                }
+                _ => Some((root.join(if *layout == StrmLayout::Jellyfin { SHOWS_DIR } else { TV_SHOWS_DIR }).join(&show), title))
+            }
+        }
+        PlaylistItemType::Live | PlaylistItemType::SeriesInfo => None,
+    }
+}
+
+fn write_kodi_file(path: &Path, content: &str) -> Result<(), M3uFilterError> {
+    match File::create(path) {
+        Ok(mut file) => match file.write_all(content.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write kodi playlist: {}", err),
+        },
+        Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write kodi playlist: {}", err),
+    }
+}
+
+/// Writes movies and series episodes as `.strm` files with `.nfo` metadata into a kodi library layout.
+/// Live channels are not supported by the kodi library and are skipped.
+pub(crate) fn write_kodi_playlist(target: &ConfigTarget, cfg: &Config, new_playlist: &[PlaylistGroup], filename: &Option<String>) -> Result<(), M3uFilterError> {
+    if !new_playlist.is_empty() {
+        if filename.is_none() {
+            return Err(M3uFilterError::new(M3uFilterErrorKind::Notify, "write kodi playlist failed: ".to_string()));
+        }
+        let underscore_whitespace = target.options.as_ref().is_some_and(|o| o.underscore_whitespace);
+        let cleanup = target.options.as_ref().is_some_and(|o| o.cleanup);
+
+        if let Some(path) = target.get_output_file_path(cfg, filename.as_ref().unwrap()) {
+            if cleanup {
+                let _ = std::fs::remove_dir_all(&path);
+            }
+            for pg in new_playlist {
+                for pli in &pg.channels {
+                    let header = pli.header.borrow();
+                    if let Some((dir_path, file_name)) = get_library_file_path(&path, &header, underscore_whitespace, &StrmLayout::Kodi) {
+                        if let Err(e) = std::fs::create_dir_all(&dir_path) {
+                            error!("cant create directory: {:?}", &dir_path);
+                            return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write kodi playlist: {}", e);
+                        };
+                        let nfo = if header.item_type == PlaylistItemType::Movie {
+                            create_movie_nfo(&header)
+                        } else {
+                            create_episode_nfo(&header, &get_property(&header, "season"), &get_property(&header, "episode"))
+                        };
+                        write_kodi_file(&dir_path.join(format!("{}.strm", file_name)), &header.url)?;
+                        write_kodi_file(&dir_path.join(format!("{}.nfo", file_name)), &nfo)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
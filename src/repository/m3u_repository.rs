@@ -1,15 +1,22 @@
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 
 use chrono::Datelike;
-use log::error;
+use log::{debug, error};
 
 use crate::{create_m3u_filter_error_result};
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
 use crate::model::config::{Config, ConfigTarget};
+use crate::model::model_config::StrmLayout;
 use crate::model::model_playlist::{PlaylistGroup, PlaylistItemType};
+use crate::processing::m3u_parser;
+use crate::repository::kodi_repository::get_library_file_path;
 use crate::utils::file_utils;
 
+const LIVE_DIR: &str = "Live";
+
 fn check_write(res: std::io::Result<()>) -> Result<(), std::io::Error> {
     match res {
         Ok(_) => Ok(()),
@@ -17,7 +24,7 @@ fn check_write(res: std::io::Result<()>) -> Result<(), std::io::Error> {
     }
 }
 
-fn sanitize_for_filename(text: &str, underscore_whitespace: bool) -> String {
+pub(crate) fn sanitize_for_filename(text: &str, underscore_whitespace: bool) -> String {
     return text.chars().filter(|c| c.is_alphanumeric() || c.is_whitespace())
         .map(|c| if underscore_whitespace { if c.is_whitespace() { '_' } else { c } } else { c })
         .collect::<String>();
@@ -82,15 +89,33 @@ fn kodi_style_rename(name: &String, style: &KodiStyle) -> String {
     String::from(name)
 }
 
-pub(crate) fn get_m3u_file_path(cfg: &Config, filename: &Option<String>) -> Option<std::path::PathBuf> {
-    file_utils::get_file_path(&cfg.working_dir, Some(std::path::PathBuf::from(&filename.as_ref().unwrap())))
+pub(crate) fn get_m3u_file_path(cfg: &Config, target: &ConfigTarget, filename: &Option<String>) -> Option<std::path::PathBuf> {
+    target.get_output_file_path(cfg, filename.as_ref().unwrap())
 }
 
-pub(crate) fn get_m3u_epg_file_path(cfg: &Config, filename: &Option<String>) -> Option<std::path::PathBuf> {
-    file_utils::get_file_path(&cfg.working_dir, Some(std::path::PathBuf::from(&filename.as_ref().unwrap())))
+pub(crate) fn get_m3u_epg_file_path(cfg: &Config, target: &ConfigTarget, filename: &Option<String>) -> Option<std::path::PathBuf> {
+    target.get_output_file_path(cfg, filename.as_ref().unwrap())
         .map(|path| file_utils::add_prefix_to_filename(&path, "epg_", Some("xml")))
 }
 
+/// Reads the persisted m3u playlist of the target.
+pub(crate) fn load_m3u_playlist(cfg: &Config, target: &ConfigTarget) -> Option<Vec<PlaylistGroup>> {
+    let filename = target.get_m3u_filename();
+    filename.as_ref()?;
+    match get_m3u_file_path(cfg, target, &filename) {
+        Some(path) => match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                let lines = content.lines().map(String::from).collect();
+                Some(m3u_parser::parse_m3u(cfg, &lines))
+            }
+            Err(err) => {
+                error!("cant read m3u playlist: {} - {}", path.to_str().unwrap_or("?"), err);
+                None
+            }
+        },
+        None => None
+    }
+}
 
 pub(crate) fn write_m3u_playlist(target: &ConfigTarget, cfg: &Config, new_playlist: &[PlaylistGroup], filename: &Option<String>) -> Result<(), M3uFilterError> {
     macro_rules! cant_write_result {
@@ -104,87 +129,126 @@ pub(crate) fn write_m3u_playlist(target: &ConfigTarget, cfg: &Config, new_playli
                 M3uFilterErrorKind::Notify,
                 format!("write m3u playlist for target {} failed: No filename set", target.name)));
         }
-        if let Some(path) = get_m3u_file_path(cfg, filename) {
-            match File::create(&path) {
-                Ok(mut m3u_file) => {
-                    match check_write(m3u_file.write_all(b"#EXTM3U\n")) {
-                        Ok(_) => (),
-                        Err(e) => return cant_write_result!(&path, e),
-                    }
-                    for pg in new_playlist {
-                        for pli in &pg.channels {
-                            if pli.header.borrow().item_type == PlaylistItemType::SeriesInfo {
-                                // we skip series info, because this is only necessary when writing xtream files
-                                continue;
-                            }
-                            let content = pli.to_m3u(&target.options);
-                            match check_write(m3u_file.write_all(content.as_bytes())) {
-                                Ok(_) => (),
-                                Err(e) => return cant_write_result!(&path, e),
-                            }
-                            match check_write(m3u_file.write_all(b"\n")) {
-                                Ok(_) => (),
-                                Err(e) => return cant_write_result!(&path, e),
-                            }
-                        }
-                    }
-                }
-                Err(e) => return cant_write_result!(&path, e),
+        if let Some(path) = get_m3u_file_path(cfg, target, filename) {
+            // the playlist is written entry by entry into a temporary file, clients read the previous file until it is replaced
+            let mut tmp_path = path.clone().into_os_string();
+            tmp_path.push(".tmp");
+            let tmp_path = PathBuf::from(tmp_path);
+            if let Err(e) = write_m3u_entries(&tmp_path, target, new_playlist).and_then(|_| std::fs::rename(&tmp_path, &path)) {
+                let _ = std::fs::remove_file(&tmp_path);
+                return cant_write_result!(&path, e);
             }
         }
     }
     Ok(())
 }
 
+fn write_m3u_entries(path: &Path, target: &ConfigTarget, playlist: &[PlaylistGroup]) -> std::io::Result<()> {
+    let mut m3u_file = BufWriter::new(File::create(path)?);
+    check_write(m3u_file.write_all(b"#EXTM3U\n"))?;
+    for pg in playlist {
+        for pli in &pg.channels {
+            if pli.header.borrow().item_type == PlaylistItemType::SeriesInfo {
+                // we skip series info, because this is only necessary when writing xtream files
+                continue;
+            }
+            check_write(m3u_file.write_all(pli.to_m3u(&target.options).as_bytes()))?;
+            check_write(m3u_file.write_all(b"\n"))?;
+        }
+    }
+    check_write(m3u_file.flush())
+}
+
+fn write_strm_file(path: &Path, url: &str) -> Result<(), M3uFilterError> {
+    // unchanged files are not written again, media servers rescan files with a new modification time
+    if std::fs::read_to_string(path).is_ok_and(|content| content.eq(url)) {
+        return Ok(());
+    }
+    match File::create(path) {
+        Ok(mut strm_file) => match check_write(strm_file.write_all(url.as_bytes())) {
+            Ok(_) => Ok(()),
+            Err(e) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write strm playlist: {}", e),
+        },
+        Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write strm playlist: {}", err),
+    }
+}
+
+/// Removes the `.strm` files which were not written and the directories which are empty afterwards.
+/// Returns `true` if the directory is empty.
+fn remove_stale_strm_files(dir: &Path, written: &HashSet<PathBuf>) -> bool {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+    let mut empty = true;
+    for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        let removed = if path.is_dir() {
+            remove_stale_strm_files(&path, written) && std::fs::remove_dir(&path).is_ok()
+        } else {
+            path.extension().is_some_and(|ext| ext == "strm") && !written.contains(&path) && std::fs::remove_file(&path).is_ok()
+        };
+        if removed {
+            debug!("Removed stale strm entry {:?}", &path);
+        } else {
+            empty = false;
+        }
+    }
+    empty
+}
+
 pub(crate) fn write_strm_playlist(target: &ConfigTarget, cfg: &Config, new_playlist: &[PlaylistGroup], filename: &Option<String>) -> Result<(), M3uFilterError> {
     if !new_playlist.is_empty() {
         if filename.is_none() {
             return Err(M3uFilterError::new(M3uFilterErrorKind::Notify, "write strm playlist failed: ".to_string()));
         }
-        let underscore_whitespace = target.options.as_ref().map_or(false, |o| o.underscore_whitespace);
-        let cleanup = target.options.as_ref().map_or(false, |o| o.cleanup);
-        let kodi_style = target.options.as_ref().map_or(false, |o| o.kodi_style);
+        let underscore_whitespace = target.options.as_ref().is_some_and(|o| o.underscore_whitespace);
+        let cleanup = target.options.as_ref().is_some_and(|o| o.cleanup);
+        let kodi_style = target.options.as_ref().is_some_and(|o| o.kodi_style);
+        let layout = target.options.as_ref().map_or(StrmLayout::Flat, |o| o.strm_layout.clone());
+        let style = KodiStyle {
+            season: regex::Regex::new(r"[Ss]\d\d").unwrap(),
+            episode: regex::Regex::new(r"[Ee]\d\d").unwrap(),
+            year: regex::Regex::new(r"\d\d\d\d").unwrap(),
+            whitespace: regex::Regex::new(r"\s+").unwrap(),
+        };
 
-        if let Some(path) = file_utils::get_file_path(&cfg.working_dir, Some(std::path::PathBuf::from(&filename.as_ref().unwrap()))) {
-            if cleanup {
-                let _ = std::fs::remove_dir_all(&path);
-            }
+        if let Some(path) = target.get_output_file_path(cfg, filename.as_ref().unwrap()) {
             if let Err(e) = std::fs::create_dir_all(&path) {
                 error!("cant create directory: {:?}", &path);
                 return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write strm playlist: {}", e);
             };
+            let mut written = HashSet::new();
             for pg in new_playlist {
                 for pli in &pg.channels {
                     let header = &pli.header.borrow();
-                    let dir_path = path.join(sanitize_for_filename(&header.group, underscore_whitespace));
+                    let library_path = match layout {
+                        StrmLayout::Flat => None,
+                        // the series info is no playable stream, the library needs the resolved episodes
+                        _ if header.item_type == PlaylistItemType::SeriesInfo => continue,
+                        _ => get_library_file_path(&path, header, underscore_whitespace, &layout),
+                    };
+                    let (dir_path, file_name) = match library_path {
+                        Some(library_path) => library_path,
+                        None => {
+                            // live channels are kept in group directories beside the library
+                            let group_path = if layout == StrmLayout::Flat { path.clone() } else { path.join(LIVE_DIR) };
+                            let file_name = sanitize_for_filename(&header.title, underscore_whitespace);
+                            (group_path.join(sanitize_for_filename(&header.group, underscore_whitespace)),
+                             if kodi_style { kodi_style_rename(&file_name, &style) } else { file_name })
+                        }
+                    };
                     if let Err(e) = std::fs::create_dir_all(&dir_path) {
-                        error!("cant create directory: {:?}", &path);
+                        error!("cant create directory: {:?}", &dir_path);
                         return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write strm playlist: {}", e);
                     };
-                    let mut file_name = sanitize_for_filename(&header.title, underscore_whitespace);
-                    if kodi_style {
-                        let style = KodiStyle {
-                            season: regex::Regex::new(r"[Ss]\d\d").unwrap(),
-                            episode: regex::Regex::new(r"[Ee]\d\d").unwrap(),
-                            year: regex::Regex::new(r"\d\d\d\d").unwrap(),
-                            whitespace: regex::Regex::new(r"\s+").unwrap(),
-                        };
-                        file_name = kodi_style_rename(&file_name, &style);
-                    }
                     let file_path = dir_path.join(format!("{}.strm", file_name));
-                    match File::create(&file_path) {
-                        Ok(mut strm_file) => {
-                            match check_write(strm_file.write_all(header.url.as_bytes())) {
-                                Ok(_) => (),
-                                Err(e) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write strm playlist: {}", e),
-                            }
-                        }
-                        Err(err) => {
-                            return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write strm playlist: {}", err);
-                        }
-                    }
+                    write_strm_file(&file_path, &header.url)?;
+                    written.insert(file_path);
                 }
             }
+            if cleanup {
+                remove_stale_strm_files(&path, &written);
+            }
         }
     }
     Ok(())
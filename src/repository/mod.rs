@@ -1,3 +1,10 @@
 pub(crate) mod m3u_repository;
 pub(crate) mod xtream_repository;
-pub(crate) mod epg_repository;
\ No newline at end of file
+pub(crate) mod epg_repository;
+pub(crate) mod kodi_repository;
+pub(crate) mod export_repository;
+pub(crate) mod sqlite_repository;
+pub(crate) mod search_repository;
+pub(crate) mod raw_repository;
+pub(crate) mod id_mapping_repository;
+pub(crate) mod history_repository;
@@ -0,0 +1,213 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::create_m3u_filter_error_result;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{Config, ConfigInput};
+use crate::model::model_config::default_as_empty_rc_str;
+use crate::model::model_playlist::{PlaylistGroup, PlaylistItem, PlaylistItemHeader, PlaylistItemType, XtreamCluster};
+use crate::utils::{file_utils, string_utils};
+
+const RAW_PLAYLIST_DIR: &str = "raw_playlist";
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub(crate) struct RawChannel {
+    pub id: String,
+    pub name: String,
+    pub title: String,
+    pub logo: String,
+    pub url: String,
+    pub epg_channel_id: Option<String>,
+}
+
+/// The fields needed by filters and mappings which are not shown when browsing the raw playlist.
+#[derive(Debug, Serialize, Deserialize)]
+struct RawItem {
+    channel: RawChannel,
+    chno: String,
+    // bincode can't read a `serde_json::Value`, the values are kept as json text
+    additional_properties: Option<Vec<(String, String)>>,
+    m3u_attributes: Option<Vec<(String, String)>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RawGroup {
+    id: u32,
+    title: String,
+    cluster: String,
+    channels: Vec<RawItem>,
+}
+
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub(crate) struct RawGroupInfo {
+    pub id: u32,
+    pub title: String,
+    pub cluster: String,
+    pub channel_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub(crate) struct RawChannelPage {
+    pub total: usize,
+    pub channels: Vec<RawChannel>,
+}
+
+fn get_raw_playlist_path(cfg: &Config, input_id: u16) -> Option<PathBuf> {
+    file_utils::get_file_path(&cfg.working_dir, Some(PathBuf::from(RAW_PLAYLIST_DIR)))
+        .map(|dir| dir.join(format!("input_{}.bin", input_id)))
+}
+
+/// Keeps the playlist of a persisted input as downloaded, before filter, rename and mapping,
+/// it replaces the playlist of the previous run.
+pub(crate) fn write_raw_playlist(cfg: &Config, input: &ConfigInput, playlist: &[PlaylistGroup]) -> Result<(), M3uFilterError> {
+    let groups: Vec<RawGroup> = playlist.iter().map(|group| RawGroup {
+        id: group.id,
+        title: group.title.to_string(),
+        cluster: group.xtream_cluster.to_string(),
+        channels: group.channels.iter().map(|item| {
+            let header = item.header.borrow();
+            RawItem {
+                channel: RawChannel {
+                    id: header.id.to_string(),
+                    name: header.name.to_string(),
+                    title: header.title.to_string(),
+                    logo: header.logo.to_string(),
+                    url: header.url.to_string(),
+                    epg_channel_id: header.epg_channel_id.as_ref().map(|id| id.to_string()),
+                },
+                chno: header.chno.to_string(),
+                additional_properties: header.additional_properties.as_ref().map(|props| props.iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string())).collect()),
+                m3u_attributes: header.m3u_attributes.clone(),
+            }
+        }).collect(),
+    }).collect();
+
+    match get_raw_playlist_path(cfg, input.id) {
+        Some(path) => {
+            let result = path.parent().map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| {
+                    let tmp_path = path.with_extension("tmp");
+                    fs::write(&tmp_path, bincode::serialize(&groups).unwrap_or_default())?;
+                    fs::rename(&tmp_path, &path)
+                });
+            match result {
+                Ok(_) => Ok(()),
+                Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write raw playlist: {} - {}", path.to_str().unwrap_or("?"), err),
+            }
+        }
+        None => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write raw playlist for input: {}", input.id),
+    }
+}
+
+type RawPlaylistCache = HashMap<PathBuf, (SystemTime, Arc<Vec<RawGroup>>)>;
+
+fn raw_playlist_cache() -> &'static Mutex<RawPlaylistCache> {
+    static CACHE: OnceLock<Mutex<RawPlaylistCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The playlist is browsed page by page, it is read again only if a processing run has replaced the file.
+fn load_raw_playlist(path: &Path) -> Option<Arc<Vec<RawGroup>>> {
+    let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok()?;
+    let mut cache = raw_playlist_cache().lock().unwrap();
+    if let Some((cached_modified, groups)) = cache.get(path) {
+        if *cached_modified == modified {
+            return Some(Arc::clone(groups));
+        }
+    }
+    let groups: Arc<Vec<RawGroup>> = Arc::new(bincode::deserialize(&fs::read(path).ok()?).ok()?);
+    cache.insert(path.to_path_buf(), (modified, Arc::clone(&groups)));
+    Some(groups)
+}
+
+/// Returns `None` if there is no raw playlist of the input, it is written by the processing of persisted inputs.
+pub(crate) fn get_raw_groups(cfg: &Config, input_id: u16) -> Option<Vec<RawGroupInfo>> {
+    let groups = load_raw_playlist(&get_raw_playlist_path(cfg, input_id)?)?;
+    Some(groups.iter().map(|group| RawGroupInfo {
+        id: group.id,
+        title: group.title.to_string(),
+        cluster: group.cluster.to_string(),
+        channel_count: group.channels.len(),
+    }).collect())
+}
+
+/// Returns the channels of the group or of all groups, `offset` and `limit` select a page.
+pub(crate) fn get_raw_channels(cfg: &Config, input_id: u16, group_id: Option<u32>, offset: usize, limit: usize) -> Option<RawChannelPage> {
+    let groups = load_raw_playlist(&get_raw_playlist_path(cfg, input_id)?)?;
+    let channels = groups.iter()
+        .filter(|group| group_id.is_none_or(|id| group.id == id))
+        .flat_map(|group| &group.channels);
+    let total = channels.clone().count();
+    Some(RawChannelPage {
+        total,
+        channels: channels.skip(offset).take(limit).map(|item| item.channel.clone()).collect(),
+    })
+}
+
+pub(crate) fn get_cluster(cluster: &str) -> XtreamCluster {
+    match cluster {
+        "movie" => XtreamCluster::Video,
+        "series" => XtreamCluster::Series,
+        _ => XtreamCluster::Live,
+    }
+}
+
+fn create_playlist_item(item: &RawItem, group_title: &Arc<str>, xtream_cluster: &XtreamCluster) -> PlaylistItem {
+    let channel = &item.channel;
+    PlaylistItem {
+        header: RefCell::new(PlaylistItemHeader {
+            id: Arc::from(channel.id.as_str()),
+            name: Arc::from(channel.name.as_str()),
+            logo: Arc::from(channel.logo.as_str()),
+            logo_small: default_as_empty_rc_str(),
+            group: Arc::clone(group_title),
+            title: Arc::from(channel.title.as_str()),
+            chno: Arc::from(item.chno.as_str()),
+            parent_code: default_as_empty_rc_str(),
+            audio_track: default_as_empty_rc_str(),
+            time_shift: default_as_empty_rc_str(),
+            rec: default_as_empty_rc_str(),
+            catchup: default_as_empty_rc_str(),
+            catchup_days: default_as_empty_rc_str(),
+            catchup_source: default_as_empty_rc_str(),
+            source: default_as_empty_rc_str(),
+            url: Arc::from(channel.url.as_str()),
+            epg_channel_id: channel.epg_channel_id.as_ref().map(|id| Arc::from(id.as_str())),
+            item_type: match xtream_cluster {
+                XtreamCluster::Live => PlaylistItemType::Live,
+                XtreamCluster::Video => PlaylistItemType::Movie,
+                XtreamCluster::Series => PlaylistItemType::SeriesInfo,
+            },
+            xtream_cluster: xtream_cluster.clone(),
+            additional_properties: item.additional_properties.as_ref().map(|props| props.iter()
+                .map(|(key, value)| (key.to_string(), serde_json::from_str(value).unwrap_or(Value::Null))).collect()),
+            m3u_attributes: item.m3u_attributes.clone(),
+            headers: None,
+            series_fetched: false,
+        })
+    }
+}
+
+/// Creates the playlist of the input from its raw playlist, only with the fields kept in the raw playlist,
+/// these are the fields used by filters and mappings.
+pub(crate) fn get_raw_playlist(cfg: &Config, input_id: u16) -> Option<Vec<PlaylistGroup>> {
+    let groups = load_raw_playlist(&get_raw_playlist_path(cfg, input_id)?)?;
+    Some(groups.iter().map(|group| {
+        let title = string_utils::intern(&group.title);
+        let xtream_cluster = get_cluster(&group.cluster);
+        PlaylistGroup {
+            id: group.id,
+            channels: group.channels.iter().map(|item| create_playlist_item(item, &title, &xtream_cluster)).collect(),
+            title,
+            xtream_cluster,
+        }
+    }).collect())
+}
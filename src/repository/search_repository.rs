@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use unidecode::unidecode;
+
+use crate::create_m3u_filter_error_result;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{Config, ConfigTarget};
+use crate::model::model_playlist::PlaylistGroup;
+use crate::repository::export_repository::get_item_type_name;
+use crate::utils::file_utils;
+
+const SEARCH_INDEX_DIR: &str = "search_index";
+/// xtream properties added to the searchable text
+const SEARCH_PROPERTIES: &[&str] = &["plot", "cast", "director", "genre"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub(crate) struct SearchEntry {
+    pub target: String,
+    pub group: String,
+    pub title: String,
+    pub name: String,
+    pub id: String,
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub plot: Option<String>,
+    pub cast: Option<String>,
+}
+
+/// Entries of a target with the entry positions for each token.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SearchIndex {
+    entries: Vec<SearchEntry>,
+    tokens: BTreeMap<String, Vec<u32>>,
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item=String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| unidecode(token).to_lowercase())
+}
+
+fn get_search_index_path(cfg: &Config, target_name: &str) -> Option<PathBuf> {
+    file_utils::get_file_path(&cfg.working_dir, Some(PathBuf::from(SEARCH_INDEX_DIR)))
+        .map(|dir| dir.join(format!("{}.idx", target_name.replace(' ', "_"))))
+}
+
+fn get_property(props: &Option<Vec<(String, Value)>>, key: &str) -> Option<String> {
+    props.as_ref()?.iter().find(|(name, _)| name.eq(key)).and_then(|(_, value)| match value {
+        Value::String(text) if !text.is_empty() => Some(text.to_string()),
+        _ => None,
+    })
+}
+
+/// Writes the search index of the target, it replaces the index of the previous run.
+pub(crate) fn write_search_index(target: &ConfigTarget, cfg: &Config, playlist: &[PlaylistGroup]) -> Result<(), M3uFilterError> {
+    let mut index = SearchIndex::default();
+    for group in playlist {
+        for item in &group.channels {
+            let header = item.header.borrow();
+            let position = index.entries.len() as u32;
+            let mut text = format!("{} {} {}", header.group, header.title, header.name);
+            for property in SEARCH_PROPERTIES {
+                if let Some(value) = get_property(&header.additional_properties, property) {
+                    text.push(' ');
+                    text.push_str(&value);
+                }
+            }
+            tokenize(&text).collect::<HashSet<String>>().into_iter()
+                .for_each(|token| index.tokens.entry(token).or_default().push(position));
+            index.entries.push(SearchEntry {
+                target: target.name.to_string(),
+                group: header.group.to_string(),
+                title: header.title.to_string(),
+                name: header.name.to_string(),
+                id: header.id.to_string(),
+                item_type: get_item_type_name(&header.item_type).to_string(),
+                plot: get_property(&header.additional_properties, "plot"),
+                cast: get_property(&header.additional_properties, "cast"),
+            });
+        }
+    }
+
+    match get_search_index_path(cfg, &target.name) {
+        Some(path) => {
+            let result = path.parent().map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| {
+                    let tmp_path = path.with_extension("tmp");
+                    fs::write(&tmp_path, bincode::serialize(&index).unwrap_or_default())?;
+                    fs::rename(&tmp_path, &path)
+                });
+            match result {
+                Ok(_) => Ok(()),
+                Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write search index: {} - {}", path.to_str().unwrap_or("?"), err),
+            }
+        }
+        None => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "failed to write search index for target: {}", &target.name),
+    }
+}
+
+type IndexCache = HashMap<PathBuf, (SystemTime, Arc<SearchIndex>)>;
+
+fn index_cache() -> &'static Mutex<IndexCache> {
+    static CACHE: OnceLock<Mutex<IndexCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Loads the index, it is read again only if a processing run has replaced the file.
+fn load_search_index(path: &Path) -> Option<Arc<SearchIndex>> {
+    let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok()?;
+    let mut cache = index_cache().lock().unwrap();
+    if let Some((cached_modified, index)) = cache.get(path) {
+        if *cached_modified == modified {
+            return Some(Arc::clone(index));
+        }
+    }
+    let index: SearchIndex = bincode::deserialize(&fs::read(path).ok()?).ok()?;
+    let index = Arc::new(index);
+    cache.insert(path.to_path_buf(), (modified, Arc::clone(&index)));
+    Some(index)
+}
+
+/// Returns the positions of the entries containing a token starting with each of the query tokens.
+fn find_entries(index: &SearchIndex, query_tokens: &[String]) -> Vec<u32> {
+    let mut result: Option<HashSet<u32>> = None;
+    for query_token in query_tokens {
+        let matches: HashSet<u32> = index.tokens.range(query_token.to_string()..)
+            .take_while(|(token, _)| token.starts_with(query_token.as_str()))
+            .flat_map(|(_, positions)| positions.iter().copied())
+            .collect();
+        result = Some(match result {
+            None => matches,
+            Some(found) => found.intersection(&matches).copied().collect(),
+        });
+    }
+    let mut positions: Vec<u32> = result.unwrap_or_default().into_iter().collect();
+    positions.sort_unstable();
+    positions
+}
+
+/// Searches the processed playlists of the targets, the first `limit` matches are returned.
+pub(crate) fn search(cfg: &Config, query: &str, target_name: Option<&str>, limit: usize) -> Vec<SearchEntry> {
+    let query_tokens: Vec<String> = tokenize(query).collect();
+    if query_tokens.is_empty() {
+        return vec![];
+    }
+    let mut result = vec![];
+    for target in cfg.sources.iter().flat_map(|source| &source.targets)
+        .filter(|target| target_name.is_none_or(|name| target.name.eq_ignore_ascii_case(name))) {
+        if let Some(index) = get_search_index_path(cfg, &target.name).and_then(|path| load_search_index(&path)) {
+            for position in find_entries(&index, &query_tokens) {
+                if result.len() >= limit {
+                    return result;
+                }
+                if let Some(entry) = index.entries.get(position as usize) {
+                    result.push(entry.clone());
+                }
+            }
+        }
+    }
+    result
+}
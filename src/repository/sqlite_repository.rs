@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::debug;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde_json::Value;
+
+use crate::repository::xtream_repository::get_category_id;
+
+const DB_FILE: &str = "xtream.sqlite";
+/// entries which are read at once while a collection is iterated
+const PAGE_SIZE: i64 = 1000;
+
+fn get_db_path(path: &Path) -> PathBuf {
+    path.join(DB_FILE)
+}
+
+fn open_db(path: &Path) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(get_db_path(path))?;
+    // readers are not blocked while a processing run writes the collections
+    conn.busy_timeout(Duration::from_secs(5))?;
+    conn.execute_batch("PRAGMA journal_mode=WAL;
+        CREATE TABLE IF NOT EXISTS collection_item (
+            collection TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            stream_id INTEGER,
+            category_id TEXT,
+            content TEXT NOT NULL);
+        CREATE INDEX IF NOT EXISTS idx_collection_category ON collection_item (collection, category_id);
+        CREATE INDEX IF NOT EXISTS idx_collection_stream ON collection_item (collection, stream_id);
+        CREATE TABLE IF NOT EXISTS stream_headers (
+            stream_id INTEGER PRIMARY KEY,
+            headers TEXT NOT NULL);
+        CREATE TABLE IF NOT EXISTS stream_info (
+            cluster TEXT NOT NULL,
+            stream_id INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            PRIMARY KEY (cluster, stream_id));
+        CREATE TABLE IF NOT EXISTS collection_version (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            version TEXT NOT NULL);")?;
+    Ok(conn)
+}
+
+fn open_existing_db(path: &Path) -> Option<Connection> {
+    if get_db_path(path).exists() {
+        open_db(path).ok()
+    } else {
+        None
+    }
+}
+
+/// Identifies an entry across writes, streams by their id and categories by theirs.
+/// A stream listed in several categories is told apart by its occurrence.
+type ItemKey = (Option<i64>, Option<String>, usize);
+
+fn get_item_key(occurrences: &mut HashMap<(Option<i64>, Option<String>), usize>, stream_id: Option<i64>, category_id: Option<String>) -> ItemKey {
+    // the category of a stream can change, it is only part of the key for entries without stream id
+    let id = if stream_id.is_some() { (stream_id, None) } else { (None, category_id) };
+    let occurrence = occurrences.entry(id.clone()).or_default();
+    *occurrence += 1;
+    (id.0, id.1, *occurrence)
+}
+
+/// Updates the collections of the target in one transaction, only the changed entries are written.
+/// The entries are matched by their stream id, a stream which moved keeps its row and only its position is updated.
+/// The stored stream infos are kept.
+pub(crate) fn write_collections(path: &Path, collections: &[(&str, &Vec<Value>)],
+                                stream_headers: &BTreeMap<i32, BTreeMap<String, String>>) -> rusqlite::Result<()> {
+    let mut conn = open_db(path)?;
+    let tx = conn.transaction()?;
+    let mut changed = 0;
+    {
+        let mut select = tx.prepare("SELECT rowid, position, stream_id, category_id, content FROM collection_item WHERE collection = ?1 ORDER BY position")?;
+        let mut insert = tx.prepare("INSERT INTO collection_item (collection, position, stream_id, category_id, content) VALUES (?1, ?2, ?3, ?4, ?5)")?;
+        let mut update = tx.prepare("UPDATE collection_item SET position = ?2, stream_id = ?3, category_id = ?4, content = ?5 WHERE rowid = ?1")?;
+        let mut update_position = tx.prepare("UPDATE collection_item SET position = ?2 WHERE rowid = ?1")?;
+        let mut delete = tx.prepare("DELETE FROM collection_item WHERE rowid = ?1")?;
+        for (collection, items) in collections {
+            let mut occurrences = HashMap::new();
+            let mut existing: HashMap<ItemKey, (i64, i64, String)> = select.query_map(params![collection],
+                |row| Ok((get_item_key(&mut occurrences, row.get(2)?, row.get(3)?), (row.get(0)?, row.get(1)?, row.get(4)?))))?
+                .collect::<rusqlite::Result<_>>()?;
+            let mut occurrences = HashMap::new();
+            for (position, item) in items.iter().enumerate() {
+                let position = position as i64;
+                let content = item.to_string();
+                let stream_id = item.get("stream_id").or_else(|| item.get("series_id")).and_then(Value::as_i64);
+                let category_id = item.get("category_id").and_then(get_category_id);
+                changed += match existing.remove(&get_item_key(&mut occurrences, stream_id, category_id.clone())) {
+                    Some((_, existing_position, existing_content)) if existing_position == position && existing_content == content => 0,
+                    Some((rowid, _, existing_content)) if existing_content == content => update_position.execute(params![rowid, position])?,
+                    Some((rowid, _, _)) => update.execute(params![rowid, position, stream_id, category_id, content])?,
+                    None => insert.execute(params![collection, position, stream_id, category_id, content])?,
+                };
+            }
+            for (rowid, _, _) in existing.values() {
+                changed += delete.execute(params![rowid])?;
+            }
+        }
+
+        let mut select = tx.prepare("SELECT stream_id, headers FROM stream_headers")?;
+        let existing: HashMap<i32, String> = select.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        let mut upsert = tx.prepare("INSERT OR REPLACE INTO stream_headers (stream_id, headers) VALUES (?1, ?2)")?;
+        for (stream_id, headers) in stream_headers {
+            let content = serde_json::to_string(headers).unwrap_or_default();
+            if existing.get(stream_id) != Some(&content) {
+                changed += upsert.execute(params![stream_id, content])?;
+            }
+        }
+        let mut delete = tx.prepare("DELETE FROM stream_headers WHERE stream_id = ?1")?;
+        for stream_id in existing.keys().filter(|stream_id| !stream_headers.contains_key(stream_id)) {
+            changed += delete.execute(params![stream_id])?;
+        }
+    }
+    let has_version = tx.query_row("SELECT 1 FROM collection_version WHERE id = 1", [], |_| Ok(())).optional()?.is_some();
+    if changed > 0 || !has_version {
+        tx.execute("INSERT OR REPLACE INTO collection_version (id, version) VALUES (1, ?1)",
+                   params![chrono::Local::now().format("%Y%m%d%H%M%S%3f").to_string()])?;
+    }
+    debug!("Collections updated, {} entries changed", changed);
+    tx.commit()
+}
+
+/// Returns the collection as json array, only the entries of the given categories are returned.
+pub(crate) fn query_collection(path: &Path, collection: &str, category_ids: Option<&HashSet<String>>,
+                               category_id: &str) -> rusqlite::Result<String> {
+    let conn = open_db(path)?;
+    let rows: Vec<(Option<String>, String)> = if category_id.is_empty() {
+        let mut stmt = conn.prepare("SELECT category_id, content FROM collection_item WHERE collection = ?1 ORDER BY position")?;
+        let result = stmt.query_map(params![collection], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<_>>()?;
+        result
+    } else {
+        let mut stmt = conn.prepare("SELECT category_id, content FROM collection_item WHERE collection = ?1 AND category_id = ?2 ORDER BY position")?;
+        let result = stmt.query_map(params![collection, category_id], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<_>>()?;
+        result
+    };
+    let items: Vec<String> = rows.into_iter()
+        .filter(|(id, _)| category_ids.is_none_or(|ids| id.as_ref().is_some_and(|id| ids.contains(id))))
+        .map(|(_, content)| content).collect();
+    Ok(format!("[{}]", items.join(",")))
+}
+
+pub(crate) fn get_collection(path: &Path, collection: &str) -> Vec<Value> {
+    open_existing_db(path).and_then(|conn| {
+        let mut stmt = conn.prepare("SELECT content FROM collection_item WHERE collection = ?1 ORDER BY position").ok()?;
+        let rows = stmt.query_map(params![collection], |row| row.get::<_, String>(0)).ok()?;
+        Some(rows.filter_map(|content| content.ok().and_then(|c| serde_json::from_str(&c).ok())).collect())
+    }).unwrap_or_default()
+}
+
+/// The version changes with every write which changed the collections.
+pub(crate) fn get_collection_version(path: &Path) -> Option<String> {
+    let conn = open_existing_db(path)?;
+    conn.query_row("SELECT version FROM collection_version WHERE id = 1", [], |row| row.get(0)).optional().ok()?
+}
+
+fn read_collection_page(conn: &Connection, collection: &str, after_position: i64) -> rusqlite::Result<Vec<(i64, String)>> {
+    let mut stmt = conn.prepare_cached("SELECT position, content FROM collection_item WHERE collection = ?1 AND position > ?2 ORDER BY position LIMIT ?3")?;
+    let rows = stmt.query_map(params![collection, after_position, PAGE_SIZE], |row| Ok((row.get(0)?, row.get(1)?)))?.collect();
+    rows
+}
+
+/// Returns the documents of the collection one after another, they are read page by page.
+pub(crate) fn iter_collection(path: &Path, collection: &str) -> impl Iterator<Item=Value> {
+    let conn = open_existing_db(path);
+    let collection = collection.to_string();
+    let mut last_position = -1;
+    let mut page = Vec::new().into_iter();
+    std::iter::from_fn(move || loop {
+        if let Some(document) = page.next() {
+            return Some(document);
+        }
+        let rows = read_collection_page(conn.as_ref()?, &collection, last_position).ok()?;
+        last_position = rows.last()?.0;
+        page = rows.into_iter().filter_map(|(_, content)| serde_json::from_str::<Value>(&content).ok()).collect::<Vec<_>>().into_iter();
+    })
+}
+
+pub(crate) fn get_collection_item(path: &Path, collection: &str, stream_id: i32) -> Option<String> {
+    let conn = open_existing_db(path)?;
+    conn.query_row("SELECT content FROM collection_item WHERE collection = ?1 AND stream_id = ?2",
+                   params![collection, stream_id], |row| row.get(0)).optional().ok()?
+}
+
+pub(crate) fn get_stream_headers(path: &Path, stream_id: i32) -> Option<HashMap<String, String>> {
+    let conn = open_existing_db(path)?;
+    let headers: Option<String> = conn.query_row("SELECT headers FROM stream_headers WHERE stream_id = ?1",
+                                                 params![stream_id], |row| row.get(0)).optional().ok()?;
+    serde_json::from_str(&headers?).ok()
+}
+
+pub(crate) fn get_stream_info(path: &Path, cluster: &str, stream_id: i32) -> Option<String> {
+    let conn = open_existing_db(path)?;
+    conn.query_row("SELECT content FROM stream_info WHERE cluster = ?1 AND stream_id = ?2",
+                   params![cluster, stream_id], |row| row.get(0)).optional().ok()?
+}
+
+pub(crate) fn put_stream_info(path: &Path, cluster: &str, stream_id: i32, content: &str) -> rusqlite::Result<()> {
+    let conn = open_db(path)?;
+    conn.execute("INSERT OR REPLACE INTO stream_info (cluster, stream_id, content) VALUES (?1, ?2, ?3)",
+                 params![cluster, stream_id, content])?;
+    Ok(())
+}
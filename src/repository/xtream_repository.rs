@@ -1,19 +1,24 @@
 use std::cell::Ref;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::fs::{File, OpenOptions};
 use std::io::{BufReader, BufWriter, Error, Read, Seek, SeekFrom, Write};
 use std::iter::FromIterator;
 use std::path::{Path, PathBuf};
-use log::{error};
+use log::{debug, error};
+use openssl::sha::Sha256;
 use serde::Serialize;
 use serde_json::{json, Map, Value};
-use crate::model::config::{Config, ConfigInput, ConfigTarget};
+use crate::model::config::{Config, ConfigInput, ConfigTarget, StorageType};
 use crate::model::model_playlist::{PlaylistGroup, PlaylistItemHeader, PlaylistItemType, XtreamCluster};
 use crate::{create_m3u_filter_error_result};
 use crate::api::api_model::AppState;
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
-use crate::utils::file_utils;
+use crate::model::api_proxy::UserCredentials;
+use crate::repository::id_mapping_repository;
+use crate::repository::id_mapping_repository::{VirtualIdKind, VirtualIdMapper};
+use crate::repository::sqlite_repository;
+use crate::utils::{file_utils, jobs, json_utils};
 
 type IndexTree = BTreeMap<i32, (u32, u16)>;
 
@@ -24,6 +29,15 @@ pub(crate) static COL_CAT_VOD: &str = "cat_vod";
 pub(crate) static COL_LIVE: &str = "live";
 pub(crate) static COL_SERIES: &str = "series";
 pub(crate) static COL_VOD: &str = "vod";
+/// http headers of channels set by mappings, used by the stream proxy
+static COL_STREAM_HEADERS: &str = "stream_headers";
+/// series info generated from the resolved episodes, one file per series
+static COL_SERIES_INFO: &str = "series_info";
+/// file with the directory name of the current collection version
+const VERSION_POINTER_FILE: &str = "current";
+const VERSION_DIR_PREFIX: &str = "v_";
+/// file with the content hashes of the files of a collection version
+const VERSION_HASHES_FILE: &str = "hashes.json";
 
 const LIVE_STREAM_FIELDS: &[&str] = &[];
 
@@ -53,6 +67,44 @@ fn get_collection_path(path: &Path, collection: &str) -> PathBuf {
     path.join(format!("{}.json", collection))
 }
 
+/// Returns the directory of the current collection version.
+/// Targets written before versioning have their collections in the storage path.
+fn get_current_version_path(path: &Path) -> PathBuf {
+    match fs::read_to_string(path.join(VERSION_POINTER_FILE)) {
+        Ok(version) if !version.trim().is_empty() => path.join(version.trim()),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn get_current_collection_path(cfg: &Config, target_name: &str, collection: &str) -> Option<PathBuf> {
+    get_xtream_storage_path(cfg, target_name).map(|path| get_collection_path(&get_current_version_path(&path), collection))
+}
+
+/// Switches the pointer file to the new version, the rename replaces it atomically.
+fn write_version_pointer(path: &Path, version: &str) -> Result<(), Error> {
+    let tmp_path = path.join(format!("{}.tmp", VERSION_POINTER_FILE));
+    fs::write(&tmp_path, version)?;
+    fs::rename(&tmp_path, path.join(VERSION_POINTER_FILE))
+}
+
+/// Deletes the versions older than the `keep` previous versions.
+/// The previous version is always kept, the requests which started before the switch still read it.
+fn remove_old_versions(path: &Path, current_version: &str, keep: usize) {
+    let mut versions: Vec<String> = fs::read_dir(path).map_or(vec![], |entries| entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().to_str().map(String::from))
+        .filter(|name| name.starts_with(VERSION_DIR_PREFIX) && name != current_version)
+        .collect());
+    // version names contain the timestamp, newest first
+    versions.sort_by(|a, b| b.cmp(a));
+    for version in versions.iter().skip(keep.max(1)) {
+        if let Err(err) = fs::remove_dir_all(path.join(version)) {
+            error!("Could not remove collection version {}: {}", version, err);
+        }
+    }
+}
+
 fn get_info_collection_path(path: &Path, collection: &str) -> PathBuf {
     path.join(format!("{}_info.db", collection))
 }
@@ -77,6 +129,83 @@ fn write_to_file<T>(file: &Path, value: &T) -> Result<(), Error>
     }
 }
 
+/// Hashes the serialized value without keeping the serialized content.
+struct HashWriter(Sha256);
+
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The hashes are persisted with the version, the digest is the same for every build.
+fn get_content_hash<T>(value: &T) -> Option<String>
+    where
+        T: ?Sized + Serialize {
+    let mut writer = HashWriter(Sha256::new());
+    serde_json::to_writer(&mut writer, value).ok()?;
+    Some(writer.0.finish().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Hashes of an older format can't be read, the files of the next version are written again.
+fn read_version_hashes(path: &Path) -> HashMap<String, String> {
+    fs::read_to_string(path.join(VERSION_HASHES_FILE)).ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Writes the files of a new collection version.
+/// Files with the same content as in the current version are linked to it instead of written again.
+struct VersionWriter {
+    current_path: PathBuf,
+    version_path: PathBuf,
+    current_hashes: HashMap<String, String>,
+    hashes: HashMap<String, String>,
+    written: usize,
+    errors: Vec<String>,
+}
+
+impl VersionWriter {
+    fn new(current_path: PathBuf, version_path: PathBuf) -> Self {
+        let current_hashes = read_version_hashes(&current_path);
+        VersionWriter { current_path, version_path, current_hashes, hashes: HashMap::new(), written: 0, errors: vec![] }
+    }
+
+    fn write<T>(&mut self, name: &str, value: &T)
+        where
+            T: ?Sized + Serialize {
+        let file_path = self.version_path.join(name);
+        if let Some(hash) = get_content_hash(value) {
+            let unchanged = self.current_hashes.get(name) == Some(&hash);
+            self.hashes.insert(name.to_string(), hash);
+            if unchanged && fs::hard_link(self.current_path.join(name), &file_path).is_ok() {
+                return;
+            }
+        }
+        self.written += 1;
+        if let Err(err) = write_to_file(&file_path, value) {
+            self.errors.push(format!("Persisting collection failed: {}: {}", &file_path.to_str().unwrap(), err));
+        }
+    }
+
+    /// All files are linked, the current version has the same content.
+    fn is_unchanged(&self) -> bool {
+        self.written == 0 && self.hashes.len() == self.current_hashes.len()
+    }
+
+    fn write_hashes(&mut self) {
+        let hashes_path = self.version_path.join(VERSION_HASHES_FILE);
+        if let Err(err) = write_to_file(&hashes_path, &self.hashes) {
+            self.errors.push(format!("Persisting collection failed: {}: {}", &hashes_path.to_str().unwrap(), err));
+        }
+    }
+}
+
 fn get_info_collection_and_idx_path(path: &Path, cluster: &XtreamCluster) -> (PathBuf, PathBuf) {
     let collection = match cluster {
         XtreamCluster::Live => COL_LIVE,
@@ -88,7 +217,7 @@ fn get_info_collection_and_idx_path(path: &Path, cluster: &XtreamCluster) -> (Pa
 
 fn write_xtream_info(app_state: &AppState, target_name: &str, stream_id: i32, cluster: &XtreamCluster,
                      content: &str, index_tree: &mut IndexTree) -> Result<(), Error> {
-    if let Some(path) = get_xtream_storage_path(&app_state.config, target_name) {
+    if let Some(path) = get_xtream_storage_path(&app_state.get_config(), target_name) {
         let (col_path, idx_path) = get_info_collection_and_idx_path(&path, cluster);
         let mut comp: Vec<u8> = Vec::new();
         lzma_rs::lzma_compress(&mut BufReader::new(content.as_bytes()), &mut comp)?;
@@ -122,6 +251,9 @@ pub(crate) fn write_xtream_playlist(target: &ConfigTarget, cfg: &Config, playlis
 
         let (skip_live_direct_source, skip_video_direct_source) = target.options.as_ref()
             .map_or((false, false), |o| (o.xtream_skip_live_direct_source, o.xtream_skip_video_direct_source));
+        let keep_versions = target.options.as_ref().map_or(1, |o| o.xtream_keep_versions);
+        let mut id_mapper = target.options.as_ref().filter(|o| o.xtream_virtual_ids)
+            .map(|_| VirtualIdMapper::load(&path, get_provider_ids(playlist)));
 
         let mut cat_live_col = vec![];
         let mut cat_series_col = vec![];
@@ -132,37 +264,51 @@ pub(crate) fn write_xtream_playlist(target: &ConfigTarget, cfg: &Config, playlis
 
         let mut vod_map = HashMap::<i32, String>::new();
         let mut series_map = HashMap::<i32, String>::new();
+        let mut stream_headers = BTreeMap::<i32, BTreeMap<String, String>>::new();
+        let mut series_episodes = HashMap::<i32, Vec<Value>>::new();
+        let mut series_provider_ids = HashMap::<i32, i32>::new();
 
         let mut channel_num: i32 = 0;
         let mut errors = Vec::new();
         for plg in playlist {
             if !&plg.channels.is_empty() {
+                let category_id = id_mapper.as_mut().map_or(plg.id, |mapper| mapper.get_category_id(&plg.xtream_cluster, &plg.title));
                 match &plg.xtream_cluster {
                     XtreamCluster::Live => &mut cat_live_col,
                     XtreamCluster::Series => &mut cat_series_col,
                     XtreamCluster::Video => &mut cat_vod_col,
                 }.push(
                     json!({
-                    "category_id": format!("{}", &plg.id),
+                    "category_id": format!("{}", category_id),
                     "category_name": plg.title.clone(),
                     "parent_id": 0
                 }));
 
                 for pli in &plg.channels {
                     let header = &pli.header.borrow();
-                    if let Ok(stream_id) = header.id.parse::<i32>() {
-                        if header.item_type == PlaylistItemType::Series {
-                            // we skip resolved series, because this is only necessary when writing m3u files
+                    if let Ok(provider_id) = header.id.parse::<i32>() {
+                        let kind = get_virtual_id_kind(header);
+                        let stream_id = id_mapper.as_mut().map_or(provider_id, |mapper| mapper.get_stream_id(kind, provider_id, &header.title));
+                        if kind == VirtualIdKind::Episode {
+                            // resolved episodes are served with the series info of their series
+                            if let Some(series_id) = get_property(header, "series_id").and_then(|id| id.as_str().and_then(|id| id.parse::<i32>().ok())) {
+                                series_episodes.entry(series_id).or_default().push(create_series_episode(header, stream_id));
+                            }
                             continue;
                         }
                         channel_num += 1;
+                        // assigned channel numbers replace the running number
+                        let num = header.chno.parse::<i32>().unwrap_or(channel_num);
+                        if let Some(headers) = &header.headers {
+                            stream_headers.insert(stream_id, headers.iter().cloned().collect());
+                        }
                         let mut document = serde_json::Map::from_iter([
-                            ("category_id".to_string(), Value::String(format!("{}", &plg.id))),
-                            ("category_ids".to_string(), Value::Array(Vec::from([Value::Number(serde_json::Number::from(plg.id.to_owned()))]))),
-                            ("name".to_string(), Value::String(header.name.as_ref().clone())),
-                            ("num".to_string(), Value::Number(serde_json::Number::from(channel_num))),
-                            ("title".to_string(), Value::String(header.title.as_ref().clone())),
-                            ("stream_icon".to_string(), Value::String(header.logo.as_ref().clone())),
+                            ("category_id".to_string(), Value::String(format!("{}", category_id))),
+                            ("category_ids".to_string(), Value::Array(Vec::from([Value::Number(serde_json::Number::from(category_id))]))),
+                            ("name".to_string(), Value::String(header.name.to_string())),
+                            ("num".to_string(), Value::Number(serde_json::Number::from(num))),
+                            ("title".to_string(), Value::String(header.title.to_string())),
+                            ("stream_icon".to_string(), Value::String(header.logo.to_string())),
                         ]);
 
                         let stream_id_value = Value::Number(serde_json::Number::from(stream_id));
@@ -172,13 +318,13 @@ pub(crate) fn write_xtream_playlist(target: &ConfigTarget, cfg: &Config, playlis
                                 if skip_live_direct_source {
                                     document.insert("direct_source".to_string(), Value::String("".to_string()));
                                 } else {
-                                    document.insert("direct_source".to_string(), Value::String(header.url.as_ref().clone()));
+                                    document.insert("direct_source".to_string(), Value::String(header.url.to_string()));
                                 }
-                                document.insert("thumbnail".to_string(), Value::String(header.logo_small.as_ref().clone()));
+                                document.insert("thumbnail".to_string(), Value::String(header.logo_small.to_string()));
                                 document.insert("custom_sid".to_string(), Value::String("".to_string()));
                                 document.insert("epg_channel_id".to_string(), match &header.epg_channel_id {
                                     None => Value::Null,
-                                    Some(epg_id) => Value::String(epg_id.as_ref().clone())
+                                    Some(epg_id) => Value::String(epg_id.to_string())
                                 });
                             }
                             XtreamCluster::Video => {
@@ -186,12 +332,13 @@ pub(crate) fn write_xtream_playlist(target: &ConfigTarget, cfg: &Config, playlis
                                 if skip_video_direct_source {
                                     document.insert("direct_source".to_string(), Value::String("".to_string()));
                                 } else {
-                                    document.insert("direct_source".to_string(), Value::String(header.url.as_ref().clone()));
+                                    document.insert("direct_source".to_string(), Value::String(header.url.to_string()));
                                 }
                                 document.insert("custom_sid".to_string(), Value::String("".to_string()));
                             }
                             XtreamCluster::Series => {
                                 document.insert("series_id".to_string(), stream_id_value);
+                                series_provider_ids.insert(stream_id, provider_id);
                             }
                         };
 
@@ -237,17 +384,33 @@ pub(crate) fn write_xtream_playlist(target: &ConfigTarget, cfg: &Config, playlis
             }
         }
 
-        for (col_path, data) in [
-            (get_collection_path(&path, COL_CAT_LIVE), &cat_live_col),
-            (get_collection_path(&path, COL_CAT_VOD), &cat_vod_col),
-            (get_collection_path(&path, COL_CAT_SERIES), &cat_series_col),
-            (get_collection_path(&path, COL_LIVE), &live_col),
-            (get_collection_path(&path, COL_VOD), &vod_col),
-            (get_collection_path(&path, COL_SERIES), &series_col)] {
-            match write_to_file(&col_path, data) {
-                Ok(()) => {}
-                Err(err) => {
-                    errors.push(format!("Persisting collection failed: {}: {}", &col_path.to_str().unwrap(), err));
+        let series_info_col: Vec<Value> = series_col.iter().filter_map(|series| {
+            let series_id = series.get("series_id").and_then(Value::as_i64)?;
+            let episodes = series_episodes.remove(series_provider_ids.get(&(series_id as i32))?)?;
+            Some(create_series_info(series_id, series, episodes))
+        }).collect();
+
+        // the ids are saved before the collections which use them are activated
+        if let Some(mapper) = id_mapper.as_mut() {
+            if let Err(err) = mapper.save(&path, chrono::Local::now().timestamp()) {
+                return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Persisting virtual ids failed: {}: {}", &target.name, err);
+            }
+        }
+
+        let collections = [
+            (COL_CAT_LIVE, &cat_live_col),
+            (COL_CAT_VOD, &cat_vod_col),
+            (COL_CAT_SERIES, &cat_series_col),
+            (COL_LIVE, &live_col),
+            (COL_VOD, &vod_col),
+            (COL_SERIES, &series_col)];
+        match cfg.storage {
+            StorageType::Json => write_collection_version(&path, &collections, &series_info_col, &stream_headers, usize::from(keep_versions), &mut errors),
+            StorageType::Sqlite => {
+                let mut sqlite_collections = collections.to_vec();
+                sqlite_collections.push((COL_SERIES_INFO, &series_info_col));
+                if let Err(err) = sqlite_repository::write_collections(&path, &sqlite_collections, &stream_headers) {
+                    errors.push(format!("Persisting collections failed: {}: {}", &target.name, err));
                 }
             }
         }
@@ -261,6 +424,116 @@ pub(crate) fn write_xtream_playlist(target: &ConfigTarget, cfg: &Config, playlis
     Ok(())
 }
 
+/// Writes the collections into a new version directory, the api serves the current version until the pointer is switched.
+/// Unchanged files are linked to the current version, without any change the current version stays active.
+pub(crate) fn write_collection_version(path: &Path, collections: &[(&str, &Vec<Value>)], series_infos: &[Value],
+                            stream_headers: &BTreeMap<i32, BTreeMap<String, String>>, keep_versions: usize, errors: &mut Vec<String>) {
+    let version = format!("{}{}", VERSION_DIR_PREFIX, chrono::Local::now().format("%Y%m%d%H%M%S%3f"));
+    let version_path = path.join(&version);
+    if let Err(err) = fs::create_dir_all(version_path.join(COL_SERIES_INFO)) {
+        errors.push(format!("Failed to save, can't create directory {}: {}", &version_path.to_str().unwrap(), err));
+        return;
+    }
+    let mut writer = VersionWriter::new(get_current_version_path(path), version_path.clone());
+    for (collection, data) in collections {
+        writer.write(&format!("{}.json", collection), data);
+    }
+    writer.write(&format!("{}.json", COL_STREAM_HEADERS), stream_headers);
+    for series_info in series_infos {
+        let series_id = series_info.get("series_id").and_then(Value::as_i64).unwrap_or_default();
+        writer.write(&format!("{}/{}.json", COL_SERIES_INFO, series_id), series_info);
+    }
+    if writer.errors.is_empty() && writer.is_unchanged() {
+        debug!("Collections unchanged, keeping the current version");
+        let _ = fs::remove_dir_all(&version_path);
+        return;
+    }
+    writer.write_hashes();
+    let mut write_errors = writer.errors;
+    if write_errors.is_empty() {
+        debug!("Collection version {}: {} files written", &version, writer.written);
+        if let Err(err) = write_version_pointer(path, &version) {
+            write_errors.push(format!("Persisting collection version failed: {}: {}", &version, err));
+        }
+    }
+    if !write_errors.is_empty() {
+        // the incomplete version is discarded, the previous version stays active
+        let _ = fs::remove_dir_all(&version_path);
+        errors.append(&mut write_errors);
+        return;
+    }
+    remove_old_versions(path, &version, keep_versions);
+    // collections of the layout before versioning are replaced by the version
+    for collection in [COL_CAT_LIVE, COL_CAT_VOD, COL_CAT_SERIES, COL_LIVE, COL_VOD, COL_SERIES, COL_STREAM_HEADERS] {
+        let _ = fs::remove_file(get_collection_path(path, collection));
+    }
+}
+
+/// Resolved episodes have the series cluster, their ids are separate from the series ids.
+fn get_virtual_id_kind(header: &PlaylistItemHeader) -> VirtualIdKind {
+    if header.item_type == PlaylistItemType::Series {
+        VirtualIdKind::Episode
+    } else {
+        VirtualIdKind::from_cluster(&header.xtream_cluster)
+    }
+}
+
+fn get_provider_ids(playlist: &[PlaylistGroup]) -> HashSet<(VirtualIdKind, i32)> {
+    playlist.iter().flat_map(|group| &group.channels).filter_map(|item| {
+        let header = item.header.borrow();
+        header.id.parse::<i32>().ok().map(|id| (get_virtual_id_kind(&header), id))
+    }).collect()
+}
+
+fn get_property(header: &PlaylistItemHeader, name: &str) -> Option<Value> {
+    header.additional_properties.iter().flatten()
+        .find(|(key, _)| key.eq(name))
+        .map(|(_, value)| value.clone())
+}
+
+/// Creates the episode entry of the series info, without `direct_source` the episode is streamed through the proxy.
+fn create_series_episode(header: &PlaylistItemHeader, episode_id: i32) -> Value {
+    let property = |name: &str| get_property(header, name).unwrap_or(Value::Null);
+    json!({
+        "id": episode_id.to_string(),
+        "episode_num": property("episode"),
+        "title": header.title.to_string(),
+        "container_extension": property("container_extension"),
+        "info": {
+            "movie_image": header.logo.to_string(),
+            "plot": property("plot"),
+            "releasedate": property("release_date"),
+            "season": property("season"),
+        },
+        "custom_sid": "",
+        "added": property("added"),
+        "season": property("season"),
+        "direct_source": "",
+    })
+}
+
+/// Creates the `get_series_info` response of the series with the episodes which are left after processing.
+fn create_series_info(series_id: i64, series: &Value, episodes: Vec<Value>) -> Value {
+    let mut seasons = BTreeMap::<i64, Vec<Value>>::new();
+    for episode in episodes {
+        seasons.entry(episode.get("season").and_then(Value::as_i64).unwrap_or(0)).or_default().push(episode);
+    }
+    seasons.values_mut().for_each(|season_episodes|
+        season_episodes.sort_by_key(|episode| episode.get("episode_num").and_then(Value::as_i64).unwrap_or(0)));
+    json!({
+        "series_id": series_id,
+        "seasons": seasons.iter().map(|(season, season_episodes)| json!({
+            "season_number": season,
+            "name": format!("Season {}", season),
+            "episode_count": season_episodes.len(),
+        })).collect::<Vec<Value>>(),
+        "info": series,
+        "episodes": seasons.into_iter()
+            .map(|(season, season_episodes)| (season.to_string(), Value::Array(season_episodes)))
+            .collect::<Map<String, Value>>(),
+    })
+}
+
 fn append_prepared_series_properties(header: &Ref<PlaylistItemHeader>, document: &mut Map<String, Value>) {
     if let Some(add_props) = &header.additional_properties {
         match add_props.iter().find(|(key, _)| key.eq("rating")) {
@@ -307,9 +580,36 @@ fn append_mandatory_fields(document: &mut Map<String, Value>, fields: &[&str]) {
     }
 }
 
+/// Returns the provider id of a stream which is requested with the id of the xtream output.
+/// Without `xtream_virtual_ids` the ids are the provider ids, `None` if the virtual id is unknown.
+pub(crate) fn xtream_get_provider_id(cfg: &Config, target: &ConfigTarget, stream_id: i32, kinds: &[VirtualIdKind]) -> Option<i32> {
+    if !target.options.as_ref().is_some_and(|o| o.xtream_virtual_ids) {
+        return Some(stream_id);
+    }
+    id_mapping_repository::get_provider_id(&get_xtream_storage_path(cfg, &target.name)?, stream_id, kinds)
+}
+
+/// Returns the series info generated from the resolved episodes of the last processing run.
+pub(crate) fn xtream_get_series_info(cfg: &Config, target_name: &str, series_id: i32) -> Option<String> {
+    let path = get_xtream_storage_path(cfg, target_name)?;
+    if cfg.storage == StorageType::Sqlite {
+        return sqlite_repository::get_collection_item(&path, COL_SERIES_INFO, series_id);
+    }
+    fs::read_to_string(get_collection_path(&get_current_version_path(&path).join(COL_SERIES_INFO), &series_id.to_string())).ok()
+}
+
+/// Returns the http headers set by mappings for the stream.
+pub(crate) fn xtream_get_stream_headers(cfg: &Config, target_name: &str, stream_id: i32) -> Option<HashMap<String, String>> {
+    if cfg.storage == StorageType::Sqlite {
+        return sqlite_repository::get_stream_headers(&get_xtream_storage_path(cfg, target_name)?, stream_id);
+    }
+    let path = get_current_collection_path(cfg, target_name, COL_STREAM_HEADERS)?;
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str::<HashMap<i32, HashMap<String, String>>>(&content).ok()?.remove(&stream_id)
+}
+
 pub(crate) fn xtream_get_collection_path(cfg: &Config, target_name: &str, collection_name: &str) -> Result<(Option<PathBuf>, Option<String>), Error> {
-    if let Some(path) = get_xtream_storage_path(cfg, target_name) {
-        let col_path = get_collection_path(&path, collection_name);
+    if let Some(col_path) = get_current_collection_path(cfg, target_name, collection_name) {
         if col_path.exists() {
             return Ok((Some(col_path), None));
         }
@@ -317,6 +617,109 @@ pub(crate) fn xtream_get_collection_path(cfg: &Config, target_name: &str, collec
     Err(Error::new(std::io::ErrorKind::Other, format!("Cant find collection: {}/{}", target_name, collection_name)))
 }
 
+/// Returns all documents of the collection.
+pub(crate) fn xtream_load_collection(cfg: &Config, target_name: &str, collection_name: &str) -> Vec<Value> {
+    match cfg.storage {
+        StorageType::Json => match xtream_get_collection_path(cfg, target_name, collection_name) {
+            Ok((Some(path), _)) => json_utils::filter_json_file_by(&path, |_| true),
+            _ => vec![],
+        },
+        StorageType::Sqlite => get_xtream_storage_path(cfg, target_name)
+            .map_or(vec![], |path| sqlite_repository::get_collection(&path, collection_name)),
+    }
+}
+
+/// Returns the documents of the collection one after another, the json storage is read entry by entry.
+pub(crate) fn xtream_iter_collection(cfg: &Config, target_name: &str, collection_name: &str) -> Box<dyn Iterator<Item=Value>> {
+    match cfg.storage {
+        StorageType::Json => match xtream_get_collection_path(cfg, target_name, collection_name).and_then(|(path, _)| path.map_or_else(
+            || Err(Error::new(std::io::ErrorKind::NotFound, "collection not found")), File::open)) {
+            Ok(file) => Box::new(json_utils::iter_json_array::<Value, _>(BufReader::new(file)).map_while(Result::ok)),
+            Err(_) => Box::new(std::iter::empty()),
+        },
+        StorageType::Sqlite => match get_xtream_storage_path(cfg, target_name) {
+            Some(path) => Box::new(sqlite_repository::iter_collection(&path, collection_name)),
+            None => Box::new(std::iter::empty()),
+        },
+    }
+}
+
+/// The version of the collections of the target, it changes with every processing run which changed them.
+/// Collections written before versioning have no version.
+pub(crate) fn xtream_get_collection_version(cfg: &Config, target_name: &str) -> Option<String> {
+    let path = get_xtream_storage_path(cfg, target_name)?;
+    match cfg.storage {
+        StorageType::Json => fs::read_to_string(path.join(VERSION_POINTER_FILE)).ok()
+            .map(|version| version.trim().to_string()).filter(|version| !version.is_empty()),
+        StorageType::Sqlite => sqlite_repository::get_collection_version(&path),
+    }
+}
+
+/// Category ids are stored as string, but provider properties can contain them as number.
+pub(crate) fn get_category_id(value: &Value) -> Option<String> {
+    match value {
+        Value::String(id) => Some(id.to_string()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+/// Returns the ids of the categories in the users bouquet, `None` if the user is not restricted to groups.
+pub(crate) fn xtream_get_user_category_ids(cfg: &Config, target_name: &str, collection_name: &str,
+                                           user: &UserCredentials) -> Option<HashSet<String>> {
+    user.groups.as_ref()?;
+    let is_user_category = |category: &Map<String, Value>| category.get("category_name").and_then(Value::as_str).is_some_and(|name| user.has_group(name));
+    let categories = match cfg.storage {
+        StorageType::Json => match xtream_get_collection_path(cfg, target_name, collection_name) {
+            Ok((Some(path), _)) => json_utils::filter_json_file_by(&path, is_user_category),
+            _ => vec![],
+        },
+        StorageType::Sqlite => get_xtream_storage_path(cfg, target_name)
+            .map_or(vec![], |path| sqlite_repository::get_collection(&path, collection_name).into_iter()
+                .filter(|category| category.as_object().is_some_and(is_user_category)).collect()),
+    };
+    Some(categories.iter().filter_map(|category| category.get("category_id").and_then(get_category_id)).collect())
+}
+
+/// Returns the categories and the streams of the cluster, restricted to the categories in the users bouquet.
+pub(crate) fn xtream_get_user_streams(cfg: &Config, target_name: &str, cluster: &XtreamCluster,
+                                      user: &UserCredentials) -> (Vec<Value>, Vec<Value>) {
+    let (categories, streams) = xtream_iter_user_streams(cfg, target_name, cluster, user);
+    (categories, streams.collect())
+}
+
+/// Returns the categories and streams of the users bouquet, the streams are read while they are iterated.
+pub(crate) fn xtream_iter_user_streams(cfg: &Config, target_name: &str, cluster: &XtreamCluster,
+                                       user: &UserCredentials) -> (Vec<Value>, Box<dyn Iterator<Item=Value>>) {
+    let (category_collection, collection) = match cluster {
+        XtreamCluster::Live => (COL_CAT_LIVE, COL_LIVE),
+        XtreamCluster::Video => (COL_CAT_VOD, COL_VOD),
+        XtreamCluster::Series => (COL_CAT_SERIES, COL_SERIES),
+    };
+    let user_category_ids = xtream_get_user_category_ids(cfg, target_name, category_collection, user);
+    let is_user_category = move |document: &Value| user_category_ids.as_ref()
+        .is_none_or(|ids| document.get("category_id").and_then(get_category_id).is_some_and(|id| ids.contains(&id)));
+    let categories = xtream_load_collection(cfg, target_name, category_collection).into_iter()
+        .filter(|category| is_user_category(category)).collect();
+    let streams = xtream_iter_collection(cfg, target_name, collection)
+        .filter(move |stream| is_user_category(stream));
+    (categories, Box::new(streams))
+}
+
+/// Returns true if the user is not restricted to groups or the stream is in a category of the users bouquet.
+pub(crate) fn xtream_is_user_stream(cfg: &Config, target_name: &str, clusters: &[XtreamCluster], stream_id: i32,
+                                    user: &UserCredentials) -> bool {
+    if user.groups.is_none() {
+        return true;
+    }
+    let stream_id = stream_id.to_string();
+    clusters.iter().any(|cluster| {
+        let id_field = if *cluster == XtreamCluster::Series { "series_id" } else { "stream_id" };
+        let (_, mut streams) = xtream_iter_user_streams(cfg, target_name, cluster, user);
+        streams.any(|stream| stream.get(id_field).and_then(get_category_id).is_some_and(|id| id == stream_id))
+    })
+}
+
 fn load_index(path: &Path) -> Option<IndexTree> {
     match fs::read(path) {
         Ok(encoded) => {
@@ -351,7 +754,11 @@ pub(crate) async fn xtream_get_stored_stream_info(
     let cache_info = target_input.options.as_ref()
         .map(|o| o.xtream_info_cache).unwrap_or(false);
     if cache_info {
-        if let Some(path) = get_xtream_storage_path(&app_state.config, target_name) {
+        let config = app_state.get_config();
+        if let Some(path) = get_xtream_storage_path(&config, target_name) {
+            if config.storage == StorageType::Sqlite {
+                return sqlite_repository::get_stream_info(&path, &cluster.to_string(), stream_id).ok_or(());
+            }
             let (col_path, idx_path) = get_info_collection_and_idx_path(&path, cluster);
             let lock = app_state.shared_locks.get_lock(target_name);
             let shared_lock = lock.read().unwrap();
@@ -380,8 +787,16 @@ pub(crate) async fn xtream_persist_stream_info(
     cluster: &XtreamCluster, target_input: &ConfigInput, content: &str) {
     let cache_info = target_input.options.as_ref()
         .map(|o| o.xtream_info_cache).unwrap_or(false);
-    if cache_info {
-        if let Some(path) = get_xtream_storage_path(&app_state.config, target_name) {
+    let config = app_state.get_config();
+    // the collections of the target are rewritten by a running job
+    if cache_info && !jobs::is_target_locked(&config.working_dir, target_name) {
+        if let Some(path) = get_xtream_storage_path(&config, target_name) {
+            if config.storage == StorageType::Sqlite {
+                if let Err(err) = sqlite_repository::put_stream_info(&path, &cluster.to_string(), stream_id, content) {
+                    error!("{}", err);
+                }
+                return;
+            }
             let lock = app_state.shared_locks.get_lock(target_name);
             let shared_lock = lock.write().unwrap();
             let mut index_tree = {
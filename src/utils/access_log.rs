@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use log::error;
+use rusqlite::{Connection, params};
+use serde::{Deserialize, Serialize};
+
+use crate::model::config::{AccessLogConfig, Config};
+use crate::model::model_config::AccessLogStorage;
+use crate::utils::file_utils;
+
+const ACCESS_LOG_FILE: &str = "access_log.jsonl";
+const ACCESS_LOG_DB: &str = "access_log.sqlite";
+const BYTES_PER_MB: u64 = 1024 * 1024;
+/// seconds between the removals of expired sqlite entries
+const CLEANUP_INTERVAL: i64 = 3600;
+const MAX_QUERY_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub(crate) struct AccessLogEntry {
+    pub timestamp: i64,
+    pub username: String,
+    pub ip: String,
+    /// the endpoint, like `live`, `movie`, `m3u`, `xmltv` or `player_api:get_live_streams`
+    pub action: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_id: Option<String>,
+    pub status: u16,
+    pub bytes_sent: u64,
+    /// milliseconds until the response was sent completely or the client disconnected
+    pub duration: u64,
+}
+
+#[derive(Debug, Default, Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct AccessLogQuery {
+    pub username: Option<String>,
+    pub action: Option<String>,
+    pub stream_id: Option<String>,
+    /// timestamp of the oldest entry
+    pub since: Option<i64>,
+    pub limit: Option<usize>,
+    /// only the entries of these users, set for an operator or viewer with targets
+    #[serde(skip)]
+    #[param(ignore)]
+    pub usernames: Option<Vec<String>>,
+}
+
+impl AccessLogQuery {
+    fn matches(&self, entry: &AccessLogEntry) -> bool {
+        self.username.as_ref().is_none_or(|username| &entry.username == username)
+            && self.usernames.as_ref().is_none_or(|usernames| usernames.contains(&entry.username))
+            && self.action.as_ref().is_none_or(|action| entry.action.starts_with(action.as_str()))
+            && self.stream_id.as_ref().is_none_or(|stream_id| entry.stream_id.as_ref() == Some(stream_id))
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+    }
+
+    fn get_limit(&self) -> usize {
+        self.limit.unwrap_or(100).clamp(1, MAX_QUERY_LIMIT)
+    }
+}
+
+/// The last cleanup of the sqlite storage per working dir.
+fn cleanups() -> &'static Mutex<HashMap<String, i64>> {
+    static CLEANUPS: OnceLock<Mutex<HashMap<String, i64>>> = OnceLock::new();
+    CLEANUPS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Writes of the file storage are serialized, so the rotation doesn't interleave with appends.
+fn file_lock() -> &'static Mutex<()> {
+    static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+    LOCK.get_or_init(|| Mutex::new(()))
+}
+
+fn get_path(cfg: &Config, file_name: &str) -> Option<PathBuf> {
+    file_utils::get_file_path(&cfg.working_dir, Some(PathBuf::from(file_name)))
+}
+
+fn get_rotated_path(path: &Path, index: u16) -> PathBuf {
+    path.with_extension(format!("jsonl.{}", index))
+}
+
+/// `access_log.jsonl` is renamed to `access_log.jsonl.1`, the older files are shifted and the oldest is removed.
+fn rotate(path: &Path, max_files: u16) {
+    if max_files == 0 {
+        let _ = std::fs::remove_file(path);
+        return;
+    }
+    let _ = std::fs::remove_file(get_rotated_path(path, max_files));
+    for index in (1..max_files).rev() {
+        let _ = std::fs::rename(get_rotated_path(path, index), get_rotated_path(path, index + 1));
+    }
+    let _ = std::fs::rename(path, get_rotated_path(path, 1));
+}
+
+fn append_to_file(path: &Path, access_log: &AccessLogConfig, entry: &AccessLogEntry) -> std::io::Result<()> {
+    let _guard = file_lock().lock().unwrap();
+    if access_log.max_size > 0 && std::fs::metadata(path).is_ok_and(|md| md.len() >= access_log.max_size * BYTES_PER_MB) {
+        rotate(path, access_log.max_files);
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry).unwrap_or_default())
+}
+
+fn open_db(path: &Path) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(Duration::from_secs(5))?;
+    conn.execute_batch("PRAGMA journal_mode=WAL;
+        CREATE TABLE IF NOT EXISTS access_log (
+            timestamp INTEGER NOT NULL,
+            username TEXT NOT NULL,
+            ip TEXT NOT NULL,
+            action TEXT NOT NULL,
+            stream_id TEXT,
+            status INTEGER NOT NULL,
+            bytes_sent INTEGER NOT NULL,
+            duration INTEGER NOT NULL);
+        CREATE INDEX IF NOT EXISTS idx_access_log_timestamp ON access_log (timestamp);
+        CREATE INDEX IF NOT EXISTS idx_access_log_username ON access_log (username, timestamp);")?;
+    Ok(conn)
+}
+
+fn insert_into_db(path: &Path, working_dir: &str, access_log: &AccessLogConfig, entry: &AccessLogEntry) -> rusqlite::Result<()> {
+    let conn = open_db(path)?;
+    conn.execute("INSERT INTO access_log (timestamp, username, ip, action, stream_id, status, bytes_sent, duration) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                 params![entry.timestamp, entry.username, entry.ip, entry.action, entry.stream_id, entry.status, entry.bytes_sent as i64, entry.duration as i64])?;
+    let cleanup_due = {
+        let mut last_cleanups = cleanups().lock().unwrap();
+        let last_cleanup = last_cleanups.entry(working_dir.to_string()).or_insert(0);
+        if entry.timestamp - *last_cleanup >= CLEANUP_INTERVAL {
+            *last_cleanup = entry.timestamp;
+            true
+        } else {
+            false
+        }
+    };
+    if cleanup_due {
+        conn.execute("DELETE FROM access_log WHERE timestamp < ?1", params![entry.timestamp - i64::from(access_log.max_days) * 86_400])?;
+    }
+    Ok(())
+}
+
+/// Writes the entry if `api.access_log` is configured, tenants log into their working dir.
+pub(crate) fn write_entry(cfg: &Config, entry: &AccessLogEntry) {
+    let access_log = match &cfg.api.access_log {
+        Some(access_log) => access_log,
+        None => return,
+    };
+    let result = match access_log.storage {
+        AccessLogStorage::File => get_path(cfg, ACCESS_LOG_FILE)
+            .map(|path| append_to_file(&path, access_log, entry).map_err(|err| err.to_string())),
+        AccessLogStorage::Sqlite => get_path(cfg, ACCESS_LOG_DB)
+            .map(|path| insert_into_db(&path, &cfg.working_dir, access_log, entry).map_err(|err| err.to_string())),
+    };
+    if let Some(Err(err)) = result {
+        error!("cant write access log: {}", err);
+    }
+}
+
+type QueuedEntry = (Arc<Config>, AccessLogEntry);
+
+/// The entries are written by a background thread, the responses don't wait for the file or database.
+fn writer() -> &'static Mutex<Sender<QueuedEntry>> {
+    static WRITER: OnceLock<Mutex<Sender<QueuedEntry>>> = OnceLock::new();
+    WRITER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<QueuedEntry>();
+        if let Err(err) = std::thread::Builder::new().name(String::from("access-log")).spawn(move || {
+            for (cfg, entry) in receiver {
+                write_entry(&cfg, &entry);
+            }
+        }) {
+            error!("cant start access log writer: {}", err);
+        }
+        Mutex::new(sender)
+    })
+}
+
+/// Queues the entry for the background writer.
+pub(crate) fn record(cfg: Arc<Config>, entry: AccessLogEntry) {
+    if cfg.api.access_log.is_some() && writer().lock().unwrap().send((cfg, entry)).is_err() {
+        error!("cant write access log: writer stopped");
+    }
+}
+
+fn query_file(path: &Path, access_log: &AccessLogConfig, query: &AccessLogQuery) -> Vec<AccessLogEntry> {
+    let limit = query.get_limit();
+    let mut result = vec![];
+    let files = std::iter::once(path.to_path_buf()).chain((1..=access_log.max_files).map(|index| get_rotated_path(path, index)));
+    for file_path in files {
+        let file = match std::fs::File::open(&file_path) {
+            Ok(file) => file,
+            Err(_) => break,
+        };
+        let mut entries: Vec<AccessLogEntry> = BufReader::new(file).lines().map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<AccessLogEntry>(&line).ok())
+            .filter(|entry| query.matches(entry))
+            .collect();
+        // the newest entries are at the end of the file
+        entries.reverse();
+        result.extend(entries.into_iter().take(limit - result.len()));
+        if result.len() >= limit {
+            break;
+        }
+    }
+    result
+}
+
+fn query_db(path: &Path, query: &AccessLogQuery) -> rusqlite::Result<Vec<AccessLogEntry>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let conn = open_db(path)?;
+    let mut stmt = conn.prepare("SELECT timestamp, username, ip, action, stream_id, status, bytes_sent, duration FROM access_log
+        WHERE (?1 IS NULL OR username = ?1) AND (?2 IS NULL OR action LIKE ?2 || '%') AND (?3 IS NULL OR stream_id = ?3) AND (?4 IS NULL OR timestamp >= ?4)
+        AND (?6 IS NULL OR username IN (SELECT value FROM json_each(?6)))
+        ORDER BY timestamp DESC, rowid DESC LIMIT ?5")?;
+    let usernames = query.usernames.as_ref().map(|usernames| serde_json::to_string(usernames).unwrap_or_default());
+    let result = stmt.query_map(params![query.username, query.action, query.stream_id, query.since, query.get_limit() as i64, usernames], |row| Ok(AccessLogEntry {
+        timestamp: row.get(0)?,
+        username: row.get(1)?,
+        ip: row.get(2)?,
+        action: row.get(3)?,
+        stream_id: row.get(4)?,
+        status: row.get(5)?,
+        bytes_sent: row.get::<_, i64>(6)? as u64,
+        duration: row.get::<_, i64>(7)? as u64,
+    }))?.collect();
+    result
+}
+
+/// Returns the matching entries, newest first.
+/// The log files can be large, the api reads them on a blocking thread.
+pub(crate) fn query(cfg: &Config, query: &AccessLogQuery) -> Result<Vec<AccessLogEntry>, String> {
+    let access_log = match &cfg.api.access_log {
+        Some(access_log) => access_log,
+        None => return Err(String::from("access log is not enabled")),
+    };
+    match access_log.storage {
+        AccessLogStorage::File => Ok(get_path(cfg, ACCESS_LOG_FILE)
+            .map_or_else(Vec::new, |path| query_file(&path, access_log, query))),
+        AccessLogStorage::Sqlite => match get_path(cfg, ACCESS_LOG_DB) {
+            Some(path) => query_db(&path, query).map_err(|err| err.to_string()),
+            None => Ok(vec![]),
+        },
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, OnceLock};
+
+use log::{debug, info};
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::messaging::{send_message, MsgKind};
+use crate::model::config::{AccountCheckConfig, Config, ConfigInput, InputType};
+use crate::utils::request_utils;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Account state of an xtream input as reported by the `player_api` of the provider.
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub(crate) struct InputAccountStatus {
+    pub input_id: u16,
+    pub name: String,
+    /// `Active`, `Expired`, `Banned` or `Disabled` from the provider, empty if the request failed,
+    /// `Unknown` if the provider could not be reached
+    pub status: String,
+    /// not set if the provider could not be reached, the other fields are the ones of the last check then
+    pub auth: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp_date: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_cons: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<i64>,
+    pub is_trial: bool,
+    pub checked_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// the authorization of the last check which reached the provider, the changes are notified against it
+    #[serde(skip)]
+    pub known_auth: bool,
+}
+
+impl InputAccountStatus {
+    fn get_days_left(&self, now: i64) -> Option<i64> {
+        self.exp_date.map(|exp_date| (exp_date - now).div_euclid(SECONDS_PER_DAY))
+    }
+
+    fn is_expiring(&self, now: i64, expiry_days: u16) -> bool {
+        self.get_days_left(now).is_some_and(|days| days <= i64::from(expiry_days))
+    }
+}
+
+/// Each working dir has its own status list, like the ones of tenants.
+fn stores() -> &'static Mutex<HashMap<String, BTreeMap<u16, InputAccountStatus>>> {
+    static STORES: OnceLock<Mutex<HashMap<String, BTreeMap<u16, InputAccountStatus>>>> = OnceLock::new();
+    STORES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Providers send the numbers as string or number, the expiry date is `null` for unlimited accounts.
+fn get_number(user_info: &Value, field: &str) -> Option<i64> {
+    match user_info.get(field)? {
+        Value::Number(value) => value.as_i64(),
+        Value::String(value) => value.trim().parse::<i64>().ok(),
+        _ => None,
+    }
+}
+
+fn get_input_name(input: &ConfigInput) -> String {
+    // the url can contain credentials, unnamed inputs are reported by id
+    input.name.clone().unwrap_or_else(|| format!("input_{}", input.id))
+}
+
+/// A failed check. Network errors and server errors of the provider say nothing about the account.
+enum CheckError {
+    Failed(String),
+    Unknown(String),
+}
+
+/// The credentials are encoded, they can contain characters like `&` or `#`.
+pub(crate) fn get_user_info_url(input: &ConfigInput) -> Option<url::Url> {
+    let mut url = url::Url::parse(&format!("{}/player_api.php", input.url.trim_end_matches('/'))).ok()?;
+    url.query_pairs_mut()
+        .append_pair("username", input.username.as_deref().unwrap_or_default())
+        .append_pair("password", input.password.as_deref().unwrap_or_default());
+    Some(url)
+}
+
+/// Timeouts, rate limits and server errors are temporary, the account can't be checked right now.
+pub(crate) fn is_temporary_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::REQUEST_TIMEOUT
+}
+
+async fn request_user_info(input: &ConfigInput) -> Result<Value, CheckError> {
+    let url = get_user_info_url(input).ok_or_else(|| CheckError::Failed(String::from("malformed url")))?;
+    let request = request_utils::get_client_request(input, url, None).map_err(CheckError::Failed)?;
+    let response = request.send().await.map_err(|err| CheckError::Unknown(err.without_url().to_string()))?;
+    if !response.status().is_success() {
+        let message = format!("provider responded with status {}", response.status());
+        return Err(if is_temporary_status(response.status()) { CheckError::Unknown(message) } else { CheckError::Failed(message) });
+    }
+    let content: Value = response.json().await.map_err(|err| if err.is_decode() {
+        CheckError::Failed(err.without_url().to_string())
+    } else {
+        CheckError::Unknown(err.without_url().to_string())
+    })?;
+    content.get("user_info").cloned().ok_or_else(|| CheckError::Failed(String::from("response has no user_info")))
+}
+
+async fn check_account(input: &ConfigInput, now: i64) -> InputAccountStatus {
+    let mut status = InputAccountStatus {
+        input_id: input.id,
+        name: get_input_name(input),
+        status: String::new(),
+        auth: Some(false),
+        exp_date: None,
+        active_cons: None,
+        max_connections: None,
+        is_trial: false,
+        checked_at: now,
+        error: None,
+        known_auth: false,
+    };
+    match request_user_info(input).await {
+        Ok(user_info) => {
+            status.auth = Some(get_number(&user_info, "auth") == Some(1));
+            status.status = user_info.get("status").and_then(Value::as_str).unwrap_or_default().to_string();
+            status.exp_date = get_number(&user_info, "exp_date");
+            status.active_cons = get_number(&user_info, "active_cons");
+            status.max_connections = get_number(&user_info, "max_connections");
+            status.is_trial = get_number(&user_info, "is_trial") == Some(1);
+        }
+        Err(CheckError::Failed(err)) => status.error = Some(err),
+        Err(CheckError::Unknown(err)) => {
+            status.status = String::from("Unknown");
+            status.auth = None;
+            status.error = Some(err);
+        }
+    }
+    status
+}
+
+/// Returns the message for a change of the account, the messages are only sent once for each change.
+fn get_change_message(previous: Option<&InputAccountStatus>, current: &InputAccountStatus, account_check: &AccountCheckConfig) -> Option<String> {
+    let previous_auth = previous.is_none_or(|status| status.known_auth);
+    if !current.known_auth {
+        return if previous_auth {
+            Some(format!("Provider account of {} failed: {}", &current.name,
+                         current.error.as_deref().unwrap_or(if current.status.is_empty() { "not authorized" } else { &current.status })))
+        } else {
+            None
+        };
+    }
+    if !previous_auth {
+        return Some(format!("Provider account of {} is {} again", &current.name, &current.status));
+    }
+    let now = current.checked_at;
+    let was_expiring = previous.is_some_and(|status| status.is_expiring(now, account_check.expiry_days));
+    if current.is_expiring(now, account_check.expiry_days) && !was_expiring {
+        let expiry = current.exp_date.and_then(|exp_date| chrono::DateTime::from_timestamp(exp_date, 0))
+            .map(|date| date.format("%Y-%m-%d").to_string()).unwrap_or_default();
+        return Some(format!("Provider account of {} expires in {} days on {}", &current.name,
+                            current.get_days_left(now).unwrap_or_default().max(0), expiry));
+    }
+    None
+}
+
+/// Stores the result of a check and returns the message for its change.
+/// If the provider could not be reached, the values of the last check are kept and nothing is notified.
+pub(crate) fn update_status(store: &mut BTreeMap<u16, InputAccountStatus>, mut status: InputAccountStatus,
+                            account_check: &AccountCheckConfig) -> Option<String> {
+    let previous = store.get(&status.input_id);
+    let message = match status.auth {
+        Some(auth) => {
+            status.known_auth = auth;
+            get_change_message(previous, &status, account_check)
+        }
+        None => {
+            status.known_auth = previous.is_none_or(|previous| previous.known_auth);
+            if let Some(previous) = previous {
+                status.exp_date = previous.exp_date;
+                status.active_cons = previous.active_cons;
+                status.max_connections = previous.max_connections;
+                status.is_trial = previous.is_trial;
+            }
+            None
+        }
+    };
+    store.insert(status.input_id, status);
+    message
+}
+
+/// Requests the account info of all enabled xtream inputs and notifies about failed, restored and expiring accounts.
+pub(crate) async fn check_accounts(cfg: &Config) {
+    let account_check = match &cfg.account_check {
+        Some(account_check) => account_check,
+        None => return,
+    };
+    let now = chrono::Local::now().timestamp();
+    let inputs = cfg.sources.iter().flat_map(|source| &source.inputs)
+        .filter(|input| input.enabled && input.input_type == InputType::Xtream && !request_utils::is_local_url(&input.url));
+    for input in inputs {
+        let status = check_account(input, now).await;
+        debug!("Provider account of {}: {} auth {:?}", &status.name, &status.status, status.auth);
+        let message = {
+            let mut guard = stores().lock().unwrap();
+            update_status(guard.entry(cfg.working_dir.to_string()).or_default(), status, account_check)
+        };
+        if let Some(msg) = message {
+            info!("{}", msg);
+            send_message(&MsgKind::Account, &cfg.messaging, &msg);
+        }
+    }
+}
+
+/// Returns the result of the last check of each xtream input, inputs which are no longer configured are left out.
+pub(crate) fn get_account_status(cfg: &Config) -> Vec<InputAccountStatus> {
+    let guard = stores().lock().unwrap();
+    guard.get(&cfg.working_dir).map_or(vec![], |store| cfg.sources.iter().flat_map(|source| &source.inputs)
+        .filter_map(|input| store.get(&input.id).cloned())
+        .collect())
+}
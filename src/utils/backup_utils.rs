@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, NaiveDateTime, TimeZone};
+use log::{error, info};
+
+use crate::create_m3u_filter_error_result;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{BackupConfig, Config};
+
+const BACKUP_TIMESTAMP_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub(crate) struct BackupEntry {
+    pub id: String,
+    pub file: String,
+    pub timestamp: i64,
+    pub size: u64,
+}
+
+/// Backups are named `<filename>_<timestamp>`.
+fn parse_backup_name(name: &str) -> Option<(String, i64)> {
+    // the timestamp has the fixed length of the format 20240101_120000
+    if name.len() <= 16 || !name.is_char_boundary(name.len() - 16) {
+        return None;
+    }
+    let (file, suffix) = name.split_at(name.len() - 16);
+    let date_time = NaiveDateTime::parse_from_str(suffix.strip_prefix('_')?, BACKUP_TIMESTAMP_FORMAT).ok()?;
+    let timestamp = Local.from_local_datetime(&date_time).earliest()?.timestamp();
+    Some((file.to_string(), timestamp))
+}
+
+pub(crate) fn get_backup_path(backup_dir: &str, filename: &str) -> PathBuf {
+    PathBuf::from(backup_dir).join(format!("{}_{}", filename, Local::now().format(BACKUP_TIMESTAMP_FORMAT)))
+}
+
+/// Lists the backups, newest first.
+pub(crate) fn list_backups(backup_dir: &str) -> Vec<BackupEntry> {
+    let mut backups = vec![];
+    if let Ok(entries) = std::fs::read_dir(backup_dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some((file, timestamp)) = parse_backup_name(&name) {
+                let size = entry.metadata().map_or(0, |m| m.len());
+                backups.push(BackupEntry { id: name, file, timestamp, size });
+            }
+        }
+    }
+    backups.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| b.id.cmp(&a.id)));
+    backups
+}
+
+/// Deletes the backups exceeding `max_count` per file or older than `max_age` days.
+pub(crate) fn rotate_backups(backup_dir: &str, retention: &BackupConfig) {
+    let min_timestamp = retention.max_age.map(|days| Local::now().timestamp() - i64::from(days) * 86_400);
+    let mut counts = std::collections::HashMap::<String, usize>::new();
+    for backup in list_backups(backup_dir) {
+        let count = counts.entry(backup.file.clone()).or_insert(0);
+        *count += 1;
+        let expired = min_timestamp.is_some_and(|min| backup.timestamp < min);
+        let exceeded = retention.max_count.is_some_and(|max| *count > max);
+        if expired || exceeded {
+            let path = Path::new(backup_dir).join(&backup.id);
+            match std::fs::remove_file(&path) {
+                Ok(_) => info!("Removed backup {}", backup.id),
+                Err(err) => error!("Could not remove backup {}: {}", path.to_str().unwrap_or("?"), err),
+            }
+        }
+    }
+}
+
+fn get_config_file_paths(cfg: &Config) -> Vec<String> {
+    let mut paths = vec![cfg._config_file_path.clone(), cfg._sources_file_path.clone(), cfg._mapping_file_path.clone()];
+    if let Some(api_proxy) = cfg._api_proxy.read().unwrap().as_ref() {
+        paths.push(api_proxy._file_path.clone());
+    }
+    paths
+}
+
+/// Replaces the config file of the backup with the backup content. The current file is backed up before.
+pub(crate) fn restore_backup(cfg: &Config, id: &str) -> Result<String, M3uFilterError> {
+    let backup_dir = cfg.backup_dir.as_ref().unwrap();
+    let backup = match list_backups(backup_dir).into_iter().find(|b| b.id == id) {
+        Some(backup) => backup,
+        None => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Backup not found: {}", id),
+    };
+    let target_path = match get_config_file_paths(cfg).into_iter()
+        .map(PathBuf::from)
+        .find(|path| path.file_name().is_some_and(|name| name.to_string_lossy() == backup.file)) {
+        Some(path) => path,
+        None => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "No config file for backup: {}", id),
+    };
+    let backup_path = Path::new(backup_dir).join(&backup.id);
+    if target_path.exists() {
+        if let Err(err) = std::fs::copy(&target_path, get_backup_path(backup_dir, &backup.file)) {
+            error!("Could not backup file {}: {}", target_path.to_str().unwrap_or("?"), err);
+        }
+    }
+    // copy to a temporary file first, the rename replaces the config file atomically
+    let tmp_path = target_path.with_file_name(format!("{}.tmp", backup.file));
+    match std::fs::copy(&backup_path, &tmp_path).and_then(|_| std::fs::rename(&tmp_path, &target_path)) {
+        Ok(_) => {
+            info!("Restored {} from backup {}", target_path.to_str().unwrap_or("?"), id);
+            if let Some(retention) = &cfg.backup {
+                rotate_backups(backup_dir, retention);
+            }
+            Ok(target_path.to_string_lossy().to_string())
+        }
+        Err(err) => {
+            let _ = std::fs::remove_file(&tmp_path);
+            create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Could not restore backup {}: {}", id, err)
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::model::config::ConfigApiSecurity;
+use crate::utils::file_utils;
+
+const BANS_FILE: &str = "bans.json";
+/// seconds of the rate limit window
+const RATE_LIMIT_WINDOW: i64 = 60;
+/// seconds between the removal of idle clients and expired bans
+const CLEANUP_INTERVAL: i64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub(crate) struct Ban {
+    pub ip: String,
+    pub reason: String,
+    pub created_at: i64,
+    /// a ban without expiry lasts until it is removed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+}
+
+impl Ban {
+    fn is_active(&self, now: i64) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+pub(crate) enum ClientRejection {
+    Banned,
+    RateLimited,
+}
+
+#[derive(Default)]
+struct ClientActivity {
+    window_start: i64,
+    requests: u32,
+    failed_logins: Vec<i64>,
+}
+
+struct BanStore {
+    file_path: Option<PathBuf>,
+    bans: BTreeMap<String, Ban>,
+    clients: HashMap<String, ClientActivity>,
+    last_cleanup: i64,
+    /// seconds of `failed_login_window` of the last failed login, older failed logins are removed by the cleanup
+    failed_login_window: u64,
+}
+
+/// Each working dir has its own ban list, like the ones of tenants.
+fn stores() -> &'static Mutex<HashMap<String, BanStore>> {
+    static STORES: OnceLock<Mutex<HashMap<String, BanStore>>> = OnceLock::new();
+    STORES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn load_store(working_dir: &String) -> BanStore {
+    let file_path = file_utils::get_file_path(working_dir, Some(PathBuf::from(BANS_FILE)));
+    let bans = file_path.as_ref()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str::<Vec<Ban>>(&content).ok())
+        .unwrap_or_default();
+    BanStore {
+        file_path,
+        bans: bans.into_iter().map(|ban| (ban.ip.clone(), ban)).collect(),
+        clients: HashMap::new(),
+        last_cleanup: 0,
+        failed_login_window: 0,
+    }
+}
+
+fn save_store(store: &BanStore) {
+    if let Some(path) = &store.file_path {
+        match serde_json::to_string(&store.bans.values().collect::<Vec<&Ban>>()) {
+            Ok(content) => if let Err(err) = std::fs::write(path, content) {
+                error!("cant write ban list {}: {}", path.to_str().unwrap_or("?"), err);
+            },
+            Err(err) => error!("cant serialize ban list: {}", err),
+        }
+    }
+}
+
+fn with_store<F, R>(working_dir: &String, func: F) -> R
+    where F: FnOnce(&mut BanStore, i64) -> R {
+    let now = chrono::Local::now().timestamp();
+    let mut guard = stores().lock().unwrap();
+    let store = guard.entry(working_dir.to_string()).or_insert_with(|| load_store(working_dir));
+    if now - store.last_cleanup >= CLEANUP_INTERVAL {
+        store.last_cleanup = now;
+        let failed_login_start = now.saturating_sub_unsigned(store.failed_login_window);
+        store.clients.retain(|_, activity| {
+            activity.failed_logins.retain(|timestamp| *timestamp > failed_login_start);
+            now - activity.window_start < RATE_LIMIT_WINDOW || !activity.failed_logins.is_empty()
+        });
+        let ban_count = store.bans.len();
+        store.bans.retain(|_, ban| ban.is_active(now));
+        if store.bans.len() != ban_count {
+            save_store(store);
+        }
+    }
+    func(store, now)
+}
+
+fn insert_ban(store: &mut BanStore, ip: &str, reason: &str, duration: u64, now: i64) -> Ban {
+    let ban = Ban {
+        ip: ip.to_string(),
+        reason: reason.to_string(),
+        created_at: now,
+        expires_at: if duration > 0 { Some(now.saturating_add_unsigned(duration)) } else { None },
+    };
+    store.bans.insert(ip.to_string(), ban.clone());
+    save_store(store);
+    ban
+}
+
+/// Rejects banned clients and, if `count_request` is set, clients which exceed the rate limit.
+pub(crate) fn check_client(working_dir: &String, security: &ConfigApiSecurity, ip: &str, count_request: bool) -> Result<(), ClientRejection> {
+    with_store(working_dir, |store, now| {
+        if store.bans.get(ip).is_some_and(|ban| ban.is_active(now)) {
+            return Err(ClientRejection::Banned);
+        }
+        if count_request && security.rate_limit > 0 {
+            let activity = store.clients.entry(ip.to_string()).or_default();
+            if now - activity.window_start >= RATE_LIMIT_WINDOW {
+                activity.window_start = now;
+                activity.requests = 0;
+            }
+            activity.requests += 1;
+            if activity.requests > security.rate_limit {
+                return Err(ClientRejection::RateLimited);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Bans the client when it reaches `max_failed_logins` inside the window.
+pub(crate) fn record_failed_login(working_dir: &String, security: &ConfigApiSecurity, ip: &str) {
+    if security.max_failed_logins == 0 {
+        return;
+    }
+    with_store(working_dir, |store, now| {
+        store.failed_login_window = security.failed_login_window;
+        let window_start = now.saturating_sub_unsigned(security.failed_login_window);
+        let activity = store.clients.entry(ip.to_string()).or_default();
+        activity.failed_logins.retain(|timestamp| *timestamp > window_start);
+        activity.failed_logins.push(now);
+        if activity.failed_logins.len() >= usize::from(security.max_failed_logins) {
+            activity.failed_logins.clear();
+            warn!("Banned {} after {} failed logins", ip, security.max_failed_logins);
+            insert_ban(store, ip, "failed logins", security.ban_duration, now);
+        }
+    });
+}
+
+pub(crate) fn get_bans(working_dir: &String) -> Vec<Ban> {
+    with_store(working_dir, |store, now| store.bans.values().filter(|ban| ban.is_active(now)).cloned().collect())
+}
+
+/// Bans the ip for `duration` seconds, 0 bans until the ban is removed. An existing ban is replaced.
+pub(crate) fn add_ban(working_dir: &String, ip: &str, reason: &str, duration: u64) -> Ban {
+    with_store(working_dir, |store, now| insert_ban(store, ip, reason, duration, now))
+}
+
+pub(crate) fn remove_ban(working_dir: &String, ip: &str) -> bool {
+    with_store(working_dir, |store, _| {
+        store.clients.remove(ip);
+        let removed = store.bans.remove(ip).is_some();
+        if removed {
+            save_store(store);
+        }
+        removed
+    })
+}
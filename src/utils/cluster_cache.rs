@@ -0,0 +1,64 @@
+use std::fs;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use log::{debug, error};
+
+use crate::model::config::ConfigInput;
+use crate::utils::{file_utils, series_cache};
+
+const CLUSTER_CACHE_DIR: &str = "cluster_cache";
+
+fn get_cluster_cache_path(working_dir: &String, input: &ConfigInput, action: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(CLUSTER_CACHE_DIR).join(series_cache::get_input_key(input)).join(format!("{}.json", action));
+    file_utils::get_file_path(working_dir, Some(path))
+}
+
+/// Returns the content of a player_api action from the last run in which its cluster was downloaded completely.
+pub(crate) fn get_cluster_content(working_dir: &String, input: &ConfigInput, action: &str) -> Option<String> {
+    let path = get_cluster_cache_path(working_dir, input, action)?;
+    let content = fs::read_to_string(path).ok()?;
+    debug!("using previous content of {}", action);
+    Some(content)
+}
+
+/// Opens the content of a player_api action from the last run in which its cluster was downloaded completely,
+/// large stream lists are parsed while reading.
+pub(crate) fn open_cluster_content(working_dir: &String, input: &ConfigInput, action: &str) -> Option<BufReader<fs::File>> {
+    let path = get_cluster_cache_path(working_dir, input, action)?;
+    let file = fs::File::open(path).ok()?;
+    debug!("using previous content of {}", action);
+    Some(BufReader::new(file))
+}
+
+/// The file the response of a player_api action is downloaded into, it replaces the content with `put_cluster_file`.
+pub(crate) fn get_cluster_download_path(working_dir: &String, input: &ConfigInput, action: &str) -> Option<PathBuf> {
+    get_cluster_cache_path(working_dir, input, action).map(|path| path.with_extension("download"))
+}
+
+/// Replaces the content of the action with the downloaded file, once its cluster was parsed.
+pub(crate) fn put_cluster_file(working_dir: &String, input: &ConfigInput, action: &str, download_path: &Path) {
+    if let Some(path) = get_cluster_cache_path(working_dir, input, action) {
+        if let Err(err) = fs::rename(download_path, &path) {
+            error!("cant write cluster cache file {}: {}", path.to_str().unwrap_or("?"), err);
+        }
+    }
+}
+
+pub(crate) fn put_cluster_content(working_dir: &String, input: &ConfigInput, action: &str, content: &str) {
+    let path = match get_cluster_cache_path(working_dir, input, action) {
+        Some(path) => path,
+        None => return,
+    };
+    if let Some(parent) = path.parent() {
+        if let Err(err) = fs::create_dir_all(parent) {
+            error!("cant create cluster cache dir {}: {}", parent.to_str().unwrap_or("?"), err);
+            return;
+        }
+    }
+    // a partially written file would replace the last good content
+    let tmp_path = path.with_extension("tmp");
+    if let Err(err) = fs::write(&tmp_path, content).and_then(|_| fs::rename(&tmp_path, &path)) {
+        error!("cant write cluster cache file {}: {}", path.to_str().unwrap_or("?"), err);
+    }
+}
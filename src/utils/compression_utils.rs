@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use crate::utils::{file_utils, string_utils};
+
+const COMPRESSED_DIR: &str = "compressed";
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+/// smaller responses are sent as they are
+pub(crate) const MIN_COMPRESS_SIZE: u64 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// The name used in the `Accept-Encoding` and `Content-Encoding` headers.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+        }
+    }
+}
+
+/// Returns the encoding of an `Accept-Encoding` header, `br` is preferred over `gzip`.
+/// Encodings with `q=0` are not accepted.
+pub(crate) fn get_accepted_encoding(accept_encoding: &str) -> Option<Encoding> {
+    let accepted: Vec<String> = accept_encoding.split(',').filter_map(|part| {
+        let mut params = part.split(';').map(str::trim);
+        let name = params.next()?.to_lowercase();
+        let rejected = params.any(|param| param.strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()).is_some_and(|q| q <= 0.0));
+        if rejected { None } else { Some(name) }
+    }).collect();
+    [Encoding::Brotli, Encoding::Gzip].iter().copied().find(|encoding| accepted.iter().any(|name| name == encoding.name()))
+}
+
+fn compress_to<R: Read, W: Write>(encoding: Encoding, reader: &mut R, writer: W) -> io::Result<W> {
+    match encoding {
+        Encoding::Brotli => {
+            let mut compressor = brotli::CompressorWriter::new(writer, BROTLI_BUFFER_SIZE, BROTLI_QUALITY, BROTLI_WINDOW);
+            io::copy(reader, &mut compressor)?;
+            Ok(compressor.into_inner())
+        }
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(writer, Compression::default());
+            io::copy(reader, &mut encoder)?;
+            encoder.finish()
+        }
+    }
+}
+
+/// The compressed copies of the served files are kept in the working dir, not next to the outputs.
+pub(crate) fn get_cache_dir(working_dir: &String) -> Option<PathBuf> {
+    file_utils::get_file_path(working_dir, Some(PathBuf::from(COMPRESSED_DIR)))
+}
+
+/// Each compressed copy is written once, a request waiting for a copy gets the written one.
+/// Copies of different files are written at the same time.
+fn copy_locks() -> &'static Mutex<HashMap<PathBuf, Arc<Mutex<()>>>> {
+    static LOCKS: OnceLock<Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>> = OnceLock::new();
+    LOCKS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The copies of a file start with the hash of its path, the version is the hash of its size and modification time.
+fn get_copy_prefix(path: &Path) -> String {
+    format!("{}-", string_utils::get_stable_hash(&[&path.to_string_lossy()]))
+}
+
+fn get_copy_name(path: &Path, len: u64, modified: SystemTime, encoding: Encoding) -> String {
+    let modified = modified.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |duration| duration.as_nanos());
+    format!("{}{}.{}", get_copy_prefix(path), string_utils::get_stable_hash(&[&len.to_string(), &modified.to_string()]), encoding.extension())
+}
+
+/// Removes the other copies of the file and the copy the versions before the cache dir kept next to it.
+fn remove_outdated_copies(cache_dir: &Path, path: &Path, compressed_path: &Path, encoding: Encoding) {
+    let prefix = get_copy_prefix(path);
+    let extension = format!(".{}", encoding.extension());
+    if let Ok(entries) = fs::read_dir(cache_dir) {
+        entries.flatten().map(|entry| entry.path())
+            .filter(|copy| copy != compressed_path && copy.file_name().and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&prefix) && name.ends_with(&extension)))
+            .for_each(|copy| { let _ = fs::remove_file(copy); });
+    }
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(&extension);
+    let _ = fs::remove_file(path.with_file_name(file_name));
+}
+
+/// Returns the compressed copy of the file in the cache dir.
+/// A changed file gets a new copy, a copy is only used for the file with the same size and modification time.
+pub(crate) fn get_compressed_file(cache_dir: &Path, path: &Path, encoding: Encoding) -> io::Result<PathBuf> {
+    let metadata = fs::metadata(path)?;
+    let compressed_path = cache_dir.join(get_copy_name(path, metadata.len(), metadata.modified()?, encoding));
+    if compressed_path.exists() {
+        return Ok(compressed_path);
+    }
+    let lock = Arc::clone(copy_locks().lock().unwrap().entry(compressed_path.clone()).or_default());
+    let guard = lock.lock().unwrap();
+    let result = write_compressed_file(cache_dir, path, &compressed_path, encoding);
+    drop(guard);
+    copy_locks().lock().unwrap().remove(&compressed_path);
+    result.map(|_| compressed_path)
+}
+
+fn write_compressed_file(cache_dir: &Path, path: &Path, compressed_path: &Path, encoding: Encoding) -> io::Result<()> {
+    // the copy was written while the request waited for the lock
+    if compressed_path.exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(cache_dir)?;
+    let tmp_path = compressed_path.with_extension(format!("{}.tmp", encoding.extension()));
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut writer = compress_to(encoding, &mut reader, BufWriter::new(File::create(&tmp_path)?))?;
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&tmp_path, compressed_path)?;
+    remove_outdated_copies(cache_dir, path, compressed_path, encoding);
+    Ok(())
+}
+
+enum Compressor {
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    Gzip(GzEncoder<Vec<u8>>),
+}
+
+/// Compresses a response which is sent in chunks, each chunk returns the compressed bytes available so far.
+pub(crate) struct StreamCompressor {
+    compressor: Compressor,
+}
+
+impl StreamCompressor {
+    pub(crate) fn new(encoding: Encoding) -> Self {
+        let compressor = match encoding {
+            Encoding::Brotli => Compressor::Brotli(Box::new(brotli::CompressorWriter::new(Vec::new(), BROTLI_BUFFER_SIZE, BROTLI_QUALITY, BROTLI_WINDOW))),
+            Encoding::Gzip => Compressor::Gzip(GzEncoder::new(Vec::new(), Compression::default())),
+        };
+        StreamCompressor { compressor }
+    }
+
+    pub(crate) fn compress(&mut self, chunk: &[u8]) -> io::Result<Vec<u8>> {
+        match &mut self.compressor {
+            Compressor::Brotli(writer) => {
+                writer.write_all(chunk)?;
+                Ok(std::mem::take(writer.get_mut()))
+            }
+            Compressor::Gzip(encoder) => {
+                encoder.write_all(chunk)?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+        }
+    }
+
+    pub(crate) fn finish(self) -> io::Result<Vec<u8>> {
+        match self.compressor {
+            Compressor::Brotli(writer) => Ok(writer.into_inner()),
+            Compressor::Gzip(encoder) => encoder.finish(),
+        }
+    }
+}
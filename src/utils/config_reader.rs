@@ -1,14 +1,16 @@
 use std::fs::{File};
 use std::path::{PathBuf};
-use chrono::Local;
+use std::time::SystemTime;
 use log::{debug, error, info, warn};
+use regex::Regex;
 use serde::Serialize;
 use crate::model::api_proxy::ApiProxyConfig;
-use crate::model::config::{Config, ConfigDto};
+use crate::model::config::{Config, ConfigDto, ConfigSource, ConfigTenant, SourcesConfigDto};
 use crate::model::mapping::Mappings;
 use crate::{create_m3u_filter_error_result, handle_m3u_filter_error_result};
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
-use crate::utils::{file_utils, multi_file_reader};
+use crate::utils::{backup_utils, file_utils, multi_file_reader};
+use crate::utils::secret_store::SecretStore;
 
 pub(crate) fn read_mappings(args_mapping: Option<String>, cfg: &mut Config) -> Result<(), M3uFilterError> {
     let mappings_file: String = args_mapping.unwrap_or(file_utils::get_default_mappings_path(cfg._config_path.as_str()));
@@ -16,6 +18,7 @@ pub(crate) fn read_mappings(args_mapping: Option<String>, cfg: &mut Config) -> R
     match read_mapping(mappings_file.as_str()) {
         Ok(mappings) => {
             info!("Mappings File: {}", &mappings_file);
+            cfg._mapping_file_path = mappings_file;
             if mappings.is_none() { debug!("no mapping loaded"); }
             handle_m3u_filter_error_result!(M3uFilterErrorKind::Info, cfg.set_mappings(mappings));
             Ok(())
@@ -26,7 +29,8 @@ pub(crate) fn read_mappings(args_mapping: Option<String>, cfg: &mut Config) -> R
 
 pub(crate) fn read_api_proxy_config(args_api_proxy_config: Option<String>, cfg: &mut Config) {
     let api_proxy_config_file: String = args_api_proxy_config.unwrap_or(file_utils::get_default_api_proxy_config_path(cfg._config_path.as_str()));
-    let api_proxy_config = read_api_proxy(api_proxy_config_file.as_str());
+    let api_proxy_config = read_api_proxy(cfg._config_path.as_str(), api_proxy_config_file.as_str());
+    cfg._api_proxy_file_path = api_proxy_config_file.to_string();
     match api_proxy_config {
         None => {
             warn!("cant read api_proxy_config file: {}", api_proxy_config_file.as_str());
@@ -39,17 +43,191 @@ pub(crate) fn read_api_proxy_config(args_api_proxy_config: Option<String>, cfg:
     }
 }
 
+fn resolve_placeholder(kind: &str, name: &str) -> Result<String, M3uFilterError> {
+    match kind {
+        "env" => std::env::var(name)
+            .map_err(|_| M3uFilterError::new(M3uFilterErrorKind::Info, format!("environment variable not set: {}", name))),
+        // secret files usually end with a newline
+        _ => std::fs::read_to_string(name).map(|content| content.trim_end_matches(['\r', '\n']).to_string())
+            .map_err(|err| M3uFilterError::new(M3uFilterErrorKind::Info, format!("cant read secret file {}: {}", name, err))),
+    }
+}
+
+const SECRET_PREFIX: &str = "!secret ";
+
+/// The secret store is opened when the first `!secret` value is found.
+struct SecretResolver<'a> {
+    config_path: &'a str,
+    store: Option<SecretStore>,
+}
+
+impl<'a> SecretResolver<'a> {
+    fn new(config_path: &'a str) -> Self {
+        SecretResolver { config_path, store: None }
+    }
+
+    fn get(&mut self, name: &str) -> Result<String, M3uFilterError> {
+        if self.store.is_none() {
+            self.store = Some(SecretStore::open(self.config_path)?);
+        }
+        self.store.as_ref().unwrap().get(name)
+    }
+}
+
+/// Replaces `${env:VAR}` and `${file:/path}` in all string values of the config
+/// and `!secret name` values with the secret from the secret store.
+fn interpolate_value(value: &mut serde_yaml::Value, secrets: &mut SecretResolver) -> Result<(), M3uFilterError> {
+    match value {
+        serde_yaml::Value::Tagged(tagged) if tagged.tag == "secret" => {
+            match tagged.value.as_str() {
+                Some(name) => *value = serde_yaml::Value::String(secrets.get(name.trim())?),
+                None => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "secret name expected for !secret"),
+            }
+        }
+        // the Web-UI saves secret references as quoted strings
+        serde_yaml::Value::String(text) if text.starts_with(SECRET_PREFIX) => {
+            *text = secrets.get(text[SECRET_PREFIX.len()..].trim())?;
+        }
+        serde_yaml::Value::String(text) if text.contains("${") => {
+            let placeholder_re = Regex::new(r"\$\{(env|file):([^}]+)}").unwrap();
+            let mut result = String::with_capacity(text.len());
+            let mut last = 0;
+            for captures in placeholder_re.captures_iter(text) {
+                let placeholder = captures.get(0).unwrap();
+                result.push_str(&text[last..placeholder.start()]);
+                result.push_str(&resolve_placeholder(&captures[1], captures[2].trim())?);
+                last = placeholder.end();
+            }
+            result.push_str(&text[last..]);
+            *text = result;
+        }
+        serde_yaml::Value::Sequence(list) => {
+            for item in list {
+                interpolate_value(item, secrets)?;
+            }
+        }
+        serde_yaml::Value::Mapping(map) => {
+            for (_, item) in map.iter_mut() {
+                interpolate_value(item, secrets)?;
+            }
+        }
+        serde_yaml::Value::Tagged(tagged) => interpolate_value(&mut tagged.value, secrets)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Interpolates the placeholders and secrets of a config file like it is done when the config is read.
+pub(crate) fn interpolate_config_value(value: &mut serde_yaml::Value, config_path: &str) -> Result<(), M3uFilterError> {
+    interpolate_value(value, &mut SecretResolver::new(config_path))
+}
+
+/// The values are written as yaml again, so that numbers are still accepted
+/// for string fields like they are when the files are read directly.
+fn from_yaml_value<T>(value: &serde_yaml::Value) -> Result<T, serde_yaml::Error>
+    where
+        T: serde::de::DeserializeOwned {
+    serde_yaml::to_string(value).and_then(|content| serde_yaml::from_str(&content))
+}
+
+fn deserialize_interpolated<T>(mut value: serde_yaml::Value, config_path: &str) -> Result<T, String>
+    where
+        T: serde::de::DeserializeOwned {
+    interpolate_value(&mut value, &mut SecretResolver::new(config_path)).map_err(|err| err.message)?;
+    from_yaml_value(&value).map_err(|err| err.to_string())
+}
+
+fn deserialize_config_value<T>(mut value: serde_yaml::Value, config_path: &str, resolve: bool) -> Result<T, String>
+    where
+        T: serde::de::DeserializeOwned {
+    if resolve {
+        deserialize_interpolated(value, config_path)
+    } else {
+        keep_secret_references(&mut value);
+        serde_yaml::from_value(value).map_err(|err| err.to_string())
+    }
+}
+
+/// Returns the `.yml` and `.yaml` files of the sources dir sorted by name, a relative dir is resolved against the dir of the config file.
+/// Hidden files are skipped, so editors can keep their temporary files next to the sources.
+pub(crate) fn get_sources_dir_files(config_file: &str, sources_dir: &str) -> Result<Vec<PathBuf>, M3uFilterError> {
+    let dir = PathBuf::from(config_file).parent().map_or_else(|| PathBuf::from(sources_dir), |parent| parent.join(sources_dir));
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant read sources_dir {}: {}", dir.to_str().unwrap_or("?"), err),
+    };
+    let mut files: Vec<PathBuf> = entries.filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file()
+            && !path.file_name().and_then(std::ffi::OsStr::to_str).unwrap_or(".").starts_with('.')
+            && path.extension().and_then(std::ffi::OsStr::to_str).is_some_and(|ext| ext == "yml" || ext == "yaml"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// The file names and modification times of the sources dir, a change triggers a reload in server mode.
+pub(crate) fn get_sources_dir_state(cfg: &Config) -> Vec<(PathBuf, Option<SystemTime>)> {
+    match &cfg.sources_dir {
+        Some(sources_dir) => get_sources_dir_files(&cfg._config_file_path, sources_dir).unwrap_or_default().into_iter()
+            .map(|path| {
+                let modified = std::fs::metadata(&path).and_then(|md| md.modified()).ok();
+                (path, modified)
+            }).collect(),
+        None => vec![],
+    }
+}
+
+/// Each file of the sources dir contains one source with `inputs` and `targets`,
+/// the sources are appended to the sources of the sources file in the order of the file names.
+fn read_sources_dir(cfg: &mut Config, resolve: bool) -> Result<(), M3uFilterError> {
+    let sources_dir = match &cfg.sources_dir {
+        Some(sources_dir) => sources_dir.to_string(),
+        None => return Ok(()),
+    };
+    for path in get_sources_dir_files(&cfg._config_file_path, &sources_dir)? {
+        let file_name = path.to_str().unwrap_or("?").to_string();
+        let source = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_yaml::from_str::<serde_yaml::Value>(&content).map_err(|err| err.to_string())
+                .and_then(|value| deserialize_config_value::<ConfigSource>(value, &cfg._config_path, resolve)),
+            Err(err) => Err(err.to_string()),
+        };
+        match source {
+            Ok(source) => {
+                debug!("Source file: {}", &file_name);
+                cfg.sources.push(source);
+            }
+            Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant read source file {}: {}", file_name, err),
+        }
+    }
+    Ok(())
+}
+
 pub(crate) fn read_config(config_path: &str, config_file: &str, sources_file: &str) -> Result<Config, M3uFilterError> {
+    read_config_files(config_path, config_file, sources_file, true)
+}
+
+/// Reads the config like `read_config` without resolving placeholders and secrets,
+/// the config api returns it so that the files can be saved again without losing them.
+pub(crate) fn read_unresolved_config(config_path: &str, config_file: &str, sources_file: &str) -> Result<Config, M3uFilterError> {
+    read_config_files(config_path, config_file, sources_file, false)
+}
+
+fn read_config_files(config_path: &str, config_file: &str, sources_file: &str, resolve: bool) -> Result<Config, M3uFilterError> {
     let files = vec![std::path::PathBuf::from(config_file), std::path::PathBuf::from(sources_file)];
     match multi_file_reader::MultiFileReader::new(&files) {
         Ok(file) => {
-            match serde_yaml::from_reader::<_, Config>(file) {
+            match serde_yaml::from_reader::<_, serde_yaml::Value>(file).map_err(|err| err.to_string())
+                .and_then(|value| deserialize_config_value::<Config>(value, config_path, resolve)) {
                 Ok(mut result) => {
                     result._config_path = config_path.to_string();
                     result._config_file_path = config_file.to_string();
                     result._sources_file_path = sources_file.to_string();
+                    read_sources_dir(&mut result, resolve)?;
                     match result.prepare() {
                         Ok(_) => Ok(result),
+                        // unresolved placeholders can fail the validation, they are only shown
+                        Err(_) if !resolve => Ok(result),
                         Err(err) => Err(err)
                     }
                 }
@@ -62,6 +240,41 @@ pub(crate) fn read_config(config_path: &str, config_file: &str, sources_file: &s
     }
 }
 
+/// Reads config, sources, mappings and api-proxy config again from the files the given config was loaded from.
+/// An api-proxy file which exists but can't be read fails the reload, otherwise all users would be rejected.
+pub(crate) fn reload_config(cfg: &Config) -> Result<Config, M3uFilterError> {
+    let mut result = read_config(cfg._config_path.as_str(), cfg._config_file_path.as_str(), cfg._sources_file_path.as_str())?;
+    read_mappings(Some(cfg._mapping_file_path.to_string()), &mut result)?;
+    let api_proxy_file = cfg._api_proxy_file_path.as_str();
+    result._api_proxy_file_path = api_proxy_file.to_string();
+    match read_api_proxy(cfg._config_path.as_str(), api_proxy_file) {
+        Some(mut api_proxy) => {
+            api_proxy._file_path = api_proxy_file.to_string();
+            result.set_api_proxy(Some(api_proxy));
+        }
+        None => {
+            if file_utils::path_exists(&PathBuf::from(api_proxy_file)) {
+                return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant read api-proxy-config file: {}", api_proxy_file);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Reads the config, sources, mappings and api-proxy config of a tenant from the default files in its config path.
+pub(crate) fn read_tenant_config(tenant: &ConfigTenant) -> Result<Config, M3uFilterError> {
+    let config_path = tenant.config_path.as_str();
+    let mut result = read_config(config_path, &file_utils::get_default_config_file_path(config_path),
+                                 &file_utils::get_default_sources_file_path(config_path))
+        .map_err(|err| M3uFilterError::new(M3uFilterErrorKind::Info, format!("tenant {}: {}", &tenant.name, err.message)))?;
+    if result.tenants.take().is_some() {
+        warn!("tenants of tenant {} are ignored", &tenant.name);
+    }
+    read_mappings(None, &mut result)?;
+    read_api_proxy_config(None, &mut result);
+    Ok(result)
+}
+
 pub(crate) fn read_mapping(mapping_file: &str) -> Result<Option<Mappings>, M3uFilterError> {
     let mapping_file = std::path::PathBuf::from(mapping_file);
     match file_utils::open_file(&mapping_file) {
@@ -85,10 +298,11 @@ pub(crate) fn read_mapping(mapping_file: &str) -> Result<Option<Mappings>, M3uFi
     }
 }
 
-pub(crate) fn read_api_proxy(api_proxy_file: &str) -> Option<ApiProxyConfig> {
+pub(crate) fn read_api_proxy(config_path: &str, api_proxy_file: &str) -> Option<ApiProxyConfig> {
     match file_utils::open_file(&std::path::PathBuf::from(api_proxy_file)) {
         Ok(file) => {
-            let mapping: Result<ApiProxyConfig, _> = serde_yaml::from_reader(file);
+            let mapping: Result<ApiProxyConfig, _> = serde_yaml::from_reader::<_, serde_yaml::Value>(file).map_err(|err| err.to_string())
+                .and_then(|value| deserialize_interpolated(value, config_path));
             match mapping {
                 Ok(mut result) => {
                     match result.prepare() {
@@ -109,32 +323,132 @@ pub(crate) fn read_api_proxy(api_proxy_file: &str) -> Option<ApiProxyConfig> {
     }
 }
 
-fn write_config_file<T>(file_path: &str, backup_dir: &str, config: &T, default_name: &str) -> Result<(), M3uFilterError>
+/// The config api returns the files unresolved, `!secret` values are kept as text
+/// so they are not lost when the config is saved.
+fn keep_secret_references(value: &mut serde_yaml::Value) {
+    match value {
+        serde_yaml::Value::Tagged(tagged) if tagged.tag == "secret" => {
+            if let Some(name) = tagged.value.as_str() {
+                *value = serde_yaml::Value::String(format!("{}{}", SECRET_PREFIX, name.trim()));
+            }
+        }
+        serde_yaml::Value::Sequence(list) => list.iter_mut().for_each(keep_secret_references),
+        serde_yaml::Value::Mapping(map) => map.iter_mut().for_each(|(_, item)| keep_secret_references(item)),
+        _ => {}
+    }
+}
+
+fn read_yaml_file<T>(file_path: &str) -> Result<T, M3uFilterError>
+    where
+        T: serde::de::DeserializeOwned {
+    match file_utils::open_file(&PathBuf::from(file_path)) {
+        Ok(file) => match serde_yaml::from_reader::<_, serde_yaml::Value>(file)
+            .and_then(|mut value| {
+                keep_secret_references(&mut value);
+                from_yaml_value::<T>(&value)
+            }) {
+            Ok(result) => Ok(result),
+            Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant read file {}: {}", file_path, err)
+        },
+        Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant read file {}: {}", file_path, err)
+    }
+}
+
+pub(crate) fn read_main_config_file(config_file: &str) -> Result<ConfigDto, M3uFilterError> {
+    read_yaml_file(config_file)
+}
+
+pub(crate) fn read_sources_file(sources_file: &str) -> Result<SourcesConfigDto, M3uFilterError> {
+    read_yaml_file(sources_file)
+}
+
+/// Validates the main config together with the sources like it is done on startup,
+/// the paths of the running config are used to resolve secrets and the sources dir.
+pub(crate) fn validate_config(current: &Config, main_config: &ConfigDto, sources: &SourcesConfigDto) -> Result<(), M3uFilterError> {
+    let config_path = current._config_path.as_str();
+    let content = match (serde_yaml::to_string(main_config), serde_yaml::to_string(sources)) {
+        (Ok(main_content), Ok(sources_content)) => format!("{}\n{}", main_content, sources_content),
+        (Err(err), _) | (_, Err(err)) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant serialize config: {}", err)
+    };
+    match serde_yaml::from_str::<serde_yaml::Value>(&content).map_err(|err| err.to_string())
+        .and_then(|value| deserialize_interpolated::<Config>(value, config_path)) {
+        Ok(mut cfg) => {
+            cfg._config_path = config_path.to_string();
+            cfg._config_file_path = current._config_file_path.to_string();
+            read_sources_dir(&mut cfg, true)?;
+            cfg.prepare()
+        }
+        Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "invalid config: {}", err)
+    }
+}
+
+/// Programs are only accepted from the config files, a config saved through the api can't add or change them.
+pub(crate) fn check_executables(current: &[String], executables: &[String]) -> Result<(), M3uFilterError> {
+    match executables.iter().find(|executable| !current.contains(executable)) {
+        Some(executable) => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "{} can only be set in the config file", executable),
+        None => Ok(()),
+    }
+}
+
+fn write_config_file<T>(file_path: &str, cfg: &Config, config: &T, default_name: &str) -> Result<(), M3uFilterError>
     where
         T: ?Sized + Serialize {
     let path = PathBuf::from(file_path);
     let filename = path.file_name().map_or(default_name.to_string(), |f| f.to_string_lossy().to_string());
-    let backup_path = PathBuf::from(backup_dir).join(format!("{}_{}", filename, Local::now().format("%Y%m%d_%H%M%S")));
-
+    let backup_dir = cfg.backup_dir.as_ref().unwrap();
+    let backup_path = backup_utils::get_backup_path(backup_dir, &filename);
 
     match std::fs::copy(&path, &backup_path) {
-        Ok(_) => {}
+        Ok(_) => {
+            if let Some(retention) = &cfg.backup {
+                backup_utils::rotate_backups(backup_dir, retention);
+            }
+        }
         Err(err) => { error!("Could not backup file {}:{}", &backup_path.to_str().unwrap_or("?"), err) }
     }
     info!("Saving file to {}", &path.to_str().unwrap_or("?"));
-    match File::create(&path) {
+    // write to a temporary file first, the rename replaces the config file atomically
+    let tmp_path = path.with_file_name(format!("{}.tmp", filename));
+    match File::create(&tmp_path) {
         Ok(f) => {
-            serde_yaml::to_writer(f, &config).unwrap();
-            Ok(())
+            if let Err(err) = serde_yaml::to_writer(f, &config) {
+                let _ = std::fs::remove_file(&tmp_path);
+                return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Could not write file {}: {}", &path.to_str().unwrap_or("?"), err);
+            }
+            match std::fs::rename(&tmp_path, &path) {
+                Ok(_) => Ok(()),
+                Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Could not write file {}: {}", &path.to_str().unwrap_or("?"), err)
+            }
         }
         Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "Could not write file {}: {}", &path.to_str().unwrap_or("?"), err)
     }
 }
 
-pub(crate) fn save_api_proxy(file_path: &str, backup_dir: &str, config: &ApiProxyConfig) -> Result<(), M3uFilterError> {
-    write_config_file(file_path, backup_dir, config, "api-proxy.yml")
+/// Reads the api-proxy config without resolving placeholders and secrets.
+pub(crate) fn read_api_proxy_file(file_path: &str) -> Result<ApiProxyConfig, M3uFilterError> {
+    let mut result: ApiProxyConfig = read_yaml_file(file_path)?;
+    result._file_path = file_path.to_string();
+    Ok(result)
+}
+
+/// Saves the unresolved api-proxy config and returns it resolved and prepared,
+/// an invalid config is not written.
+pub(crate) fn save_api_proxy(file_path: &str, cfg: &Config, config: &ApiProxyConfig) -> Result<ApiProxyConfig, M3uFilterError> {
+    let mut resolved: ApiProxyConfig = match serde_yaml::to_value(config).map_err(|err| err.to_string())
+        .and_then(|value| deserialize_interpolated(value, &cfg._config_path)) {
+        Ok(resolved) => resolved,
+        Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "invalid api-proxy-config: {}", err),
+    };
+    resolved.prepare()?;
+    resolved._file_path = file_path.to_string();
+    write_config_file(file_path, cfg, config, "api-proxy.yml")?;
+    Ok(resolved)
+}
+
+pub(crate) fn save_main_config(file_path: &str, cfg: &Config, config: &ConfigDto) -> Result<(), M3uFilterError> {
+    write_config_file(file_path, cfg, config, "config.yml")
 }
 
-pub(crate) fn save_main_config(file_path: &str, backup_dir: &str, config: &ConfigDto) -> Result<(), M3uFilterError> {
-    write_config_file(file_path, backup_dir, config, "config.yml")
+pub(crate) fn save_sources_config(file_path: &str, cfg: &Config, config: &SourcesConfigDto) -> Result<(), M3uFilterError> {
+    write_config_file(file_path, cfg, config, "source.yml")
 }
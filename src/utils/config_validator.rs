@@ -0,0 +1,555 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use serde::de::{DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde::{Deserialize, Deserializer, forward_to_deserialize_any};
+use serde_yaml::{Mapping, Value};
+
+use crate::filter::prepare_templates;
+use crate::model::config::{Config, ConfigInput, ConfigSource, ConfigTarget};
+use crate::utils::{config_reader, file_utils};
+
+/// A problem of a config file, the location is the line and column of the nearest key which could be found.
+#[derive(Debug, Clone)]
+pub(crate) struct ConfigProblem {
+    pub file: String,
+    pub path: String,
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Display for ConfigProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", &self.file)?;
+        if let Some((line, column)) = self.location {
+            write!(f, ":{}:{}", line, column)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, ": {}", &self.path)?;
+        }
+        write!(f, ": {}", &self.message)
+    }
+}
+
+/// A deserialization error, the path is the one of the innermost value which failed.
+#[derive(Debug)]
+struct ValueError {
+    path: Option<Vec<PathSegment>>,
+    message: String,
+}
+
+impl ValueError {
+    fn at(mut self, path: &[PathSegment]) -> Self {
+        if self.path.is_none() {
+            self.path = Some(path.to_vec());
+        }
+        self
+    }
+}
+
+impl Display for ValueError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", &self.message)
+    }
+}
+
+impl std::error::Error for ValueError {}
+
+impl serde::de::Error for ValueError {
+    fn custom<T: Display>(msg: T) -> Self {
+        ValueError { path: None, message: msg.to_string() }
+    }
+}
+
+/// The keys which were ignored by a struct, with the fields of the struct.
+type UnknownFields = RefCell<Vec<(Vec<PathSegment>, &'static [&'static str])>>;
+
+/// Deserializes a yaml value like serde_yaml does, but knows the path of every value.
+/// The schema of a config struct is its serde definition: the keys a struct ignores are collected as unknown fields.
+struct TrackedValue<'a> {
+    value: &'a Value,
+    path: Vec<PathSegment>,
+    /// the fields of the struct, if the value belongs to one of its keys
+    fields: Option<&'static [&'static str]>,
+    unknown: &'a UnknownFields,
+}
+
+impl<'a> TrackedValue<'a> {
+    fn child(&self, value: &'a Value, segment: PathSegment, fields: Option<&'static [&'static str]>) -> TrackedValue<'a> {
+        let mut path = self.path.clone();
+        path.push(segment);
+        TrackedValue { value, path, fields, unknown: self.unknown }
+    }
+}
+
+fn key_segment(key: &Value) -> PathSegment {
+    match key {
+        Value::String(key) => PathSegment::Key(key.clone()),
+        Value::Number(key) => PathSegment::Key(key.to_string()),
+        Value::Bool(key) => PathSegment::Key(key.to_string()),
+        _ => PathSegment::Key(String::from("?")),
+    }
+}
+
+impl<'de, 'a> serde::Deserializer<'de> for TrackedValue<'a> {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let result = match self.value {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(value) => visitor.visit_bool(*value),
+            Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+                (Some(value), _) => visitor.visit_u64(value),
+                (None, Some(value)) => visitor.visit_i64(value),
+                _ => visitor.visit_f64(number.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(value) => visitor.visit_str(value),
+            Value::Sequence(items) => visitor.visit_seq(TrackedSeq { parent: &self, items: items.iter().enumerate() }),
+            Value::Mapping(mapping) => visitor.visit_map(TrackedMap { parent: &self, entries: mapping.iter(), entry: None, fields: None }),
+            Value::Tagged(tagged) => {
+                let variant = tagged.tag.to_string().trim_start_matches('!').to_string();
+                visitor.visit_enum(TrackedEnum { parent: &self, variant, value: &tagged.value })
+            }
+        };
+        result.map_err(|err| err.at(&self.path))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Null => visitor.visit_none().map_err(|err: ValueError| err.at(&self.path)),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Mapping(mapping) => visitor.visit_map(TrackedMap { parent: &self, entries: mapping.iter(), entry: None, fields: Some(fields) })
+                .map_err(|err| err.at(&self.path)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        let result = match self.value {
+            Value::String(variant) => visitor.visit_enum(variant.as_str().into_deserializer()),
+            Value::Mapping(mapping) if mapping.len() == 1 => match mapping.iter().next() {
+                Some((Value::String(variant), value)) => visitor.visit_enum(TrackedEnum { parent: &self, variant: variant.clone(), value }),
+                _ => return self.deserialize_any(visitor),
+            },
+            _ => return self.deserialize_any(visitor),
+        };
+        result.map_err(|err| err.at(&self.path))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if let (Some(fields), Some(PathSegment::Key(_))) = (self.fields, self.path.last()) {
+            self.unknown.borrow_mut().push((self.path.clone(), fields));
+        }
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map identifier
+    }
+}
+
+struct TrackedSeq<'s, 'a> {
+    parent: &'s TrackedValue<'a>,
+    items: std::iter::Enumerate<std::slice::Iter<'a, Value>>,
+}
+
+impl<'de> SeqAccess<'de> for TrackedSeq<'_, '_> {
+    type Error = ValueError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error> {
+        match self.items.next() {
+            Some((idx, item)) => seed.deserialize(self.parent.child(item, PathSegment::Index(idx), None)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct TrackedMap<'s, 'a> {
+    parent: &'s TrackedValue<'a>,
+    entries: serde_yaml::mapping::Iter<'a>,
+    entry: Option<(&'a Value, &'a Value)>,
+    fields: Option<&'static [&'static str]>,
+}
+
+impl<'de> MapAccess<'de> for TrackedMap<'_, '_> {
+    type Error = ValueError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.entry = Some((key, value));
+                let key_value = TrackedValue { value: key, path: self.parent.path.clone(), fields: None, unknown: self.parent.unknown };
+                seed.deserialize(key_value).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error> {
+        match self.entry.take() {
+            Some((key, value)) => seed.deserialize(self.parent.child(value, key_segment(key), self.fields)),
+            None => Err(serde::de::Error::custom("value is missing")),
+        }
+    }
+}
+
+/// An enum variant of a string, a mapping with a single key or a tag.
+struct TrackedEnum<'s, 'a> {
+    parent: &'s TrackedValue<'a>,
+    variant: String,
+    value: &'a Value,
+}
+
+impl<'de> EnumAccess<'de> for TrackedEnum<'_, '_> {
+    type Error = ValueError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant = seed.deserialize(self.variant.as_str().into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for TrackedEnum<'_, '_> {
+    type Error = ValueError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        <()>::deserialize(self.parent.child(self.value, PathSegment::Key(self.variant.clone()), None))
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self.parent.child(self.value, PathSegment::Key(self.variant.clone()), None))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error> {
+        self.parent.child(self.value, PathSegment::Key(self.variant.clone()), None).deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        self.parent.child(self.value, PathSegment::Key(self.variant.clone()), None).deserialize_struct("", fields, visitor)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+fn format_path(path: &[PathSegment]) -> String {
+    let mut result = String::new();
+    for segment in path {
+        match segment {
+            PathSegment::Key(key) => {
+                if !result.is_empty() {
+                    result.push('.');
+                }
+                result.push_str(key);
+            }
+            PathSegment::Index(index) => result.push_str(&format!("[{}]", index)),
+        }
+    }
+    result
+}
+
+/// A key or a list item of a line, `-` items and keys on the same line have their own column.
+#[derive(Debug, PartialEq)]
+pub(crate) struct YamlToken {
+    pub line: usize,
+    pub column: usize,
+    pub key: Option<String>,
+    pub dash: bool,
+}
+
+pub(crate) fn tokenize(content: &str) -> Vec<YamlToken> {
+    let key_re = regex::Regex::new(r#"^["']?([^"':#{\[][^"':#]*?)["']?\s*:(\s|$)"#).unwrap();
+    let mut tokens = vec![];
+    for (line_idx, line) in content.lines().enumerate() {
+        let mut column = line.len() - line.trim_start().len();
+        let mut rest = line.trim_start();
+        if rest.is_empty() || rest.starts_with('#') || rest.starts_with("---") {
+            continue;
+        }
+        while rest == "-" || rest.starts_with("- ") {
+            tokens.push(YamlToken { line: line_idx + 1, column, key: None, dash: true });
+            let trimmed = rest[1..].trim_start();
+            column += rest.len() - trimmed.len();
+            rest = trimmed;
+        }
+        if !rest.is_empty() {
+            let key = key_re.captures(rest).map(|captures| captures[1].trim().to_string());
+            tokens.push(YamlToken { line: line_idx + 1, column, key, dash: false });
+        }
+    }
+    tokens
+}
+
+/// Follows the path through the block structure of the file, flow style values are not entered.
+/// Returns the line and column of the deepest segment which was found.
+fn locate_path(tokens: &[YamlToken], path: &[PathSegment]) -> Option<(usize, usize)> {
+    let mut scope = 0..tokens.len();
+    let mut location = None;
+    for segment in path {
+        let level = match tokens.get(scope.start).filter(|_| !scope.is_empty()) {
+            Some(token) => token.column,
+            None => return location,
+        };
+        let found = match segment {
+            PathSegment::Key(name) => scope.clone().find(|idx| tokens[*idx].column == level
+                && tokens[*idx].key.as_deref() == Some(name.as_str())),
+            PathSegment::Index(index) => scope.clone().filter(|idx| tokens[*idx].column == level && tokens[*idx].dash).nth(*index),
+        };
+        let idx = match found {
+            Some(idx) => idx,
+            None => return location,
+        };
+        let token = &tokens[idx];
+        location = Some((token.line, token.column + 1));
+        // list items of a key can start in the column of the key
+        let end = (idx + 1..scope.end).find(|next| {
+            let next_token = &tokens[*next];
+            next_token.column < token.column || (next_token.column == token.column && (token.dash || !next_token.dash))
+        }).unwrap_or(scope.end);
+        scope = idx + 1..end;
+    }
+    location
+}
+
+struct FileValidator<'a> {
+    file: &'a str,
+    tokens: Vec<YamlToken>,
+    problems: Vec<ConfigProblem>,
+}
+
+impl FileValidator<'_> {
+    fn add_problem(&mut self, path: &[PathSegment], message: &str) {
+        let location = locate_path(&self.tokens, path);
+        self.problems.push(ConfigProblem { file: self.file.to_string(), path: format_path(path), location, message: message.to_string() });
+    }
+
+    /// Deserializes the value like the config reader does and reports the unknown fields.
+    fn deserialize<T: DeserializeOwned>(&mut self, value: &Value, path: &[PathSegment]) -> Result<T, ValueError> {
+        let unknown = UnknownFields::default();
+        let result = T::deserialize(TrackedValue { value, path: path.to_vec(), fields: None, unknown: &unknown });
+        for (key_path, fields) in unknown.into_inner() {
+            let key = format_path(key_path.last().map(std::slice::from_ref).unwrap_or_default());
+            self.add_problem(&key_path, &format!("unknown field `{}`, expected one of {}", key,
+                                                 fields.iter().map(|field| format!("`{}`", field)).collect::<Vec<_>>().join(", ")));
+        }
+        result
+    }
+
+    fn add_error(&mut self, err: ValueError, path: &[PathSegment]) {
+        self.add_problem(err.path.as_deref().unwrap_or(path), &err.message);
+    }
+
+    fn check_value<T: DeserializeOwned>(&mut self, value: &Value, path: &[PathSegment]) {
+        if let Err(err) = self.deserialize::<T>(value, path) {
+            self.add_error(err, path);
+        }
+    }
+
+    fn check_list<T: DeserializeOwned>(&mut self, value: &Value, path: &[PathSegment]) {
+        match value.as_sequence() {
+            Some(items) => items.iter().enumerate().for_each(|(idx, item)| {
+                let mut item_path = path.to_vec();
+                item_path.push(PathSegment::Index(idx));
+                self.check_value::<T>(item, &item_path);
+            }),
+            None => self.check_value::<Vec<T>>(value, path),
+        }
+    }
+
+    /// Inputs, targets and their options are checked one by one.
+    fn check_sources(&mut self, value: &Value, path: &[PathSegment]) {
+        let sources = match value.as_sequence() {
+            Some(sources) => sources,
+            None => return self.check_value::<Vec<Mapping>>(value, path),
+        };
+        for (idx, source) in sources.iter().enumerate() {
+            let mut source_path = path.to_vec();
+            source_path.push(PathSegment::Index(idx));
+            self.check_source(source, &source_path);
+        }
+    }
+
+    /// The inputs and targets are checked one by one, the source itself is checked without them.
+    fn check_source(&mut self, source: &Value, source_path: &[PathSegment]) {
+        let mapping = match source.as_mapping() {
+            Some(mapping) => mapping,
+            None => return self.add_problem(source_path, "a source needs `inputs` and `targets`"),
+        };
+        let mut source_fields = mapping.clone();
+        for (key, check) in [("inputs", Self::check_list::<ConfigInput> as fn(&mut Self, &Value, &[PathSegment])),
+            ("targets", Self::check_list::<ConfigTarget>)] {
+            if let Some(list) = mapping.get(key) {
+                let mut list_path = source_path.to_vec();
+                list_path.push(PathSegment::Key(key.to_string()));
+                check(self, list, &list_path);
+                source_fields.insert(Value::from(key), Value::Sequence(vec![]));
+            }
+        }
+        self.check_value::<ConfigSource>(&Value::Mapping(source_fields), source_path);
+    }
+
+    /// The top level keys are checked one by one, so each section reports its own problem.
+    /// The fields which are missing are checked for the config and the sources file together.
+    fn check_document(&mut self, document: &Value) {
+        let mapping = match document.as_mapping() {
+            Some(mapping) => mapping,
+            None => return self.add_problem(&[], "the file has to contain a mapping"),
+        };
+        for (key, value) in mapping.iter() {
+            if key.as_str() == Some("sources") {
+                self.check_sources(value, &[PathSegment::Key(String::from("sources"))]);
+                continue;
+            }
+            let mut section = Mapping::new();
+            section.insert(key.clone(), value.clone());
+            match self.deserialize::<Config>(&Value::Mapping(section), &[]) {
+                Err(err) if err.path.as_ref().is_some_and(Vec::is_empty) && err.message.starts_with("missing field") => {}
+                Err(err) => self.add_error(err, &[]),
+                Ok(_) => {}
+            }
+        }
+    }
+
+    /// The templates, inputs and targets are prepared like on startup, but each input and target reports its own problem.
+    fn check_prepare(&mut self, mut cfg: Config) {
+        let templates = match cfg.templates.as_ref().map(prepare_templates).transpose() {
+            Ok(templates) => templates,
+            Err(_) => return,
+        };
+        if cfg.resolve_target_extends().is_err() {
+            return;
+        }
+        let input_names: HashSet<String> = cfg.sources.iter().flat_map(|source| &source.inputs)
+            .filter_map(|input| input.name.as_ref().map(|name| name.trim().to_lowercase())).collect();
+        let mut input_id = 1;
+        let mut target_id = 1;
+        for (source_idx, source) in cfg.sources.iter_mut().enumerate() {
+            let source_path = [PathSegment::Key(String::from("sources")), PathSegment::Index(source_idx)];
+            for (idx, input) in source.inputs.iter_mut().enumerate() {
+                if let Err(err) = input.prepare(input_id) {
+                    self.add_problem(&[&source_path[..], &[PathSegment::Key(String::from("inputs")), PathSegment::Index(idx)]].concat(), &err.message);
+                }
+                input_id += 1;
+            }
+            for (idx, target) in source.targets.iter_mut().enumerate() {
+                let result = target.prepare(target_id, templates.as_ref())
+                    .and_then(|_| target.condition.as_mut().map_or(Ok(()), |condition| condition.prepare(&input_names)));
+                if let Err(err) = result {
+                    self.add_problem(&[&source_path[..], &[PathSegment::Key(String::from("targets")), PathSegment::Index(idx)]].concat(), &err.message);
+                }
+                target_id += 1;
+            }
+        }
+    }
+}
+
+/// Reads the file, a syntax error is the only problem of the file.
+fn read_document(file: &str, config_path: &str, problems: &mut Vec<ConfigProblem>) -> Option<(Value, Vec<YamlToken>)> {
+    let content = match std::fs::read_to_string(file) {
+        Ok(content) => content,
+        Err(err) => {
+            problems.push(ConfigProblem { file: file.to_string(), path: String::new(), location: None, message: err.to_string() });
+            return None;
+        }
+    };
+    let mut document = match serde_yaml::from_str::<Value>(&content) {
+        Ok(document) => document,
+        Err(err) => {
+            let location = err.location().map(|location| (location.line(), location.column()));
+            let message = regex::Regex::new(r" at line \d+ column \d+$").unwrap().replace(&err.to_string(), "").to_string();
+            problems.push(ConfigProblem { file: file.to_string(), path: String::new(), location, message });
+            return None;
+        }
+    };
+    if let Err(err) = config_reader::interpolate_config_value(&mut document, config_path) {
+        problems.push(ConfigProblem { file: file.to_string(), path: String::new(), location: None, message: err.message });
+    }
+    Some((document, tokenize(&content)))
+}
+
+/// Checks the config, the sources file and the files of the sources dir and reports all problems which are found.
+/// Only if the files have the right structure, the values are validated like on startup,
+/// the inputs and targets are prepared one by one and report all their problems.
+/// The other checks of the startup, the mappings and the api-proxy config report their first problem.
+pub(crate) fn check_config_files(config_path: &str, config_file: &str, sources_file: &str,
+                                 mapping_file: Option<&str>, api_proxy_file: Option<&str>) -> Vec<ConfigProblem> {
+    let mut problems = vec![];
+    let mut combined = Mapping::new();
+    let mut sources_validator = None;
+    for file in [config_file, sources_file] {
+        if let Some((document, tokens)) = read_document(file, config_path, &mut problems) {
+            let mut validator = FileValidator { file, tokens, problems: vec![] };
+            validator.check_document(&document);
+            problems.append(&mut validator.problems);
+            if let Value::Mapping(mapping) = document {
+                if mapping.contains_key("sources") {
+                    sources_validator = Some(validator);
+                }
+                combined.extend(mapping);
+            }
+        }
+    }
+    if let Some(sources_dir) = combined.get("sources_dir").and_then(Value::as_str) {
+        match config_reader::get_sources_dir_files(config_file, sources_dir) {
+            Ok(files) => for path in files {
+                let file = path.to_str().unwrap_or("?");
+                if let Some((document, tokens)) = read_document(file, config_path, &mut problems) {
+                    let mut validator = FileValidator { file, tokens, problems: vec![] };
+                    validator.check_source(&document, &[]);
+                    problems.append(&mut validator.problems);
+                }
+            },
+            Err(err) => problems.push(ConfigProblem { file: config_file.to_string(), path: String::from("sources_dir"), location: None, message: err.message }),
+        }
+    }
+    if !problems.is_empty() {
+        return problems;
+    }
+    let mut validator = FileValidator { file: config_file, tokens: vec![], problems: vec![] };
+    let cfg = match validator.deserialize::<Config>(&Value::Mapping(combined), &[]) {
+        Ok(cfg) => cfg,
+        Err(err) => {
+            validator.add_error(err, &[]);
+            return validator.problems;
+        }
+    };
+    if let Some(mut validator) = sources_validator {
+        validator.check_prepare(cfg);
+        if !validator.problems.is_empty() {
+            return validator.problems;
+        }
+    }
+    let mut cfg = match config_reader::read_config(config_path, config_file, sources_file) {
+        Ok(cfg) => cfg,
+        Err(err) => return vec![ConfigProblem { file: config_file.to_string(), path: String::new(), location: None, message: err.message }],
+    };
+    let mapping_file = mapping_file.map_or_else(|| file_utils::get_default_mappings_path(config_path), String::from);
+    if let Err(err) = config_reader::read_mappings(Some(mapping_file.to_string()), &mut cfg) {
+        problems.push(ConfigProblem { file: mapping_file, path: String::new(), location: None, message: err.message });
+    }
+    let api_proxy_file = api_proxy_file.map_or_else(|| file_utils::get_default_api_proxy_config_path(config_path), String::from);
+    if Path::new(&api_proxy_file).exists() && config_reader::read_api_proxy(config_path, &api_proxy_file).is_none() {
+        problems.push(ConfigProblem { file: api_proxy_file, path: String::new(), location: None, message: String::from("invalid api-proxy config") });
+    }
+    problems
+}
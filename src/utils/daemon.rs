@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{debug, error, warn};
+
+/// Detaches the server from the terminal. The parent process exits, the child continues
+/// in a new session with stdin, stdout and stderr redirected to `/dev/null`.
+#[cfg(unix)]
+pub(crate) fn daemonize() -> Result<(), String> {
+    use std::os::unix::io::AsRawFd;
+    // the second fork leaves the session leader, the daemon can't get a controlling terminal again
+    for step in 0..2 {
+        // SAFETY: the threads of the server are started after the fork
+        match unsafe { libc::fork() } {
+            -1 => return Err(format!("fork failed: {}", std::io::Error::last_os_error())),
+            0 => {}
+            _ => std::process::exit(0),
+        }
+        // SAFETY: setsid has no preconditions, the child is not a process group leader
+        if step == 0 && unsafe { libc::setsid() } == -1 {
+            return Err(format!("setsid failed: {}", std::io::Error::last_os_error()));
+        }
+    }
+    let dev_null = fs::OpenOptions::new().read(true).write(true).open("/dev/null")
+        .map_err(|err| format!("cant open /dev/null: {}", err))?;
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        // SAFETY: both file descriptors are open
+        if unsafe { libc::dup2(dev_null.as_raw_fd(), fd) } == -1 {
+            return Err(format!("cant redirect output: {}", std::io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn daemonize() -> Result<(), String> {
+    Err(String::from("daemon mode is only supported on unix, use a service wrapper"))
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    // SAFETY: signal 0 only checks if the process exists
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    // a process of another user can't be signaled but exists
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    false
+}
+
+/// The file with the process id of the server, it is removed when the server stops.
+pub(crate) struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes the id of this process. Fails if the file belongs to a process which is still running,
+    /// the file of a process which was killed is replaced.
+    pub(crate) fn create(path: &Path) -> Result<PidFile, String> {
+        if let Some(pid) = fs::read_to_string(path).ok().and_then(|content| content.trim().parse::<u32>().ok()) {
+            if pid != std::process::id() && is_running(pid) {
+                return Err(format!("server is already running with pid {}, see {}", pid, path.to_string_lossy()));
+            }
+            warn!("Replacing stale pid file {} of pid {}", path.to_string_lossy(), pid);
+        }
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|err| format!("cant create directory of pid file {}: {}", path.to_string_lossy(), err))?;
+        }
+        fs::write(path, format!("{}\n", std::process::id()))
+            .map_err(|err| format!("cant write pid file {}: {}", path.to_string_lossy(), err))?;
+        debug!("Pid file written: {}", path.to_string_lossy());
+        Ok(PidFile { path: path.to_path_buf() })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            error!("cant remove pid file {}: {}", self.path.to_string_lossy(), err);
+        }
+    }
+}
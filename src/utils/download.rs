@@ -1,14 +1,20 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::BufReader;
 use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
 use std::sync::atomic::{AtomicU32};
-use std::thread::sleep;
-use log::debug;
-use crate::m3u_filter_error::M3uFilterError;
-use crate::model::config::{Config, ConfigInput};
+use std::time::{Duration, Instant, SystemTime};
+use futures::StreamExt;
+use log::{debug, error, warn};
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::model::config::{Config, ConfigInput, ConfigInputOptions, ConfigTargetOptions, InputType};
 use crate::model::model_playlist::{FetchedPlaylist, PlaylistGroup, PlaylistItem, PlaylistItemType, XtreamCluster};
 use crate::model::xmltv::TVGuide;
 use crate::processing::{m3u_parser, xmltv_parser, xtream_parser};
 use crate::processing::xtream_parser::parse_xtream_series_info;
-use crate::utils::{file_utils, request_utils};
+use crate::utils::{cluster_cache, file_utils, request_utils, series_cache};
 
 fn prepare_file_path(input: &ConfigInput, working_dir: &String, action: &str) -> Option<PathBuf> {
     let persist_file: Option<PathBuf> =
@@ -28,9 +34,42 @@ fn prepare_file_path(input: &ConfigInput, working_dir: &String, action: &str) ->
     }
 }
 
-pub(crate) async fn get_m3u_playlist(cfg: &Config, input: &ConfigInput, working_dir: &String) -> (Vec<PlaylistGroup>, Vec<M3uFilterError>) {
+/// Creates an m3u input for a playlist url or file which is not part of the config.
+pub(crate) fn create_config_input_for_url(url: &str) -> ConfigInput {
+    ConfigInput {
+        id: 0,
+        headers: Default::default(),
+        input_type: InputType::M3u,
+        user_agent: None,
+        strip_client_headers: false,
+        header_rewrite: vec![],
+        proxy: None,
+        _proxy: None,
+        url: String::from(url),
+        epg_url: None,
+        username: None,
+        password: None,
+        persist: None,
+        prefix: None,
+        suffix: None,
+        name: None,
+        enabled: true,
+        options: Some(ConfigInputOptions {
+            xtream_info_cache: false,
+            cache: None,
+            skip_unmodified: false,
+            max_download_rate: None,
+            stream_reconnect: None,
+            shared_stream: None,
+        }),
+        retry: None,
+    }
+}
+
+/// A dry run doesn't persist the downloaded playlist.
+pub(crate) async fn get_m3u_playlist(cfg: &Config, input: &ConfigInput, working_dir: &String, dry_run: bool) -> (Vec<PlaylistGroup>, Vec<M3uFilterError>) {
     let url = input.url.to_owned();
-    let persist_file_path = prepare_file_path(input, working_dir, "");
+    let persist_file_path = prepare_file_path(input, working_dir, "").filter(|_| !dry_run);
     match request_utils::get_input_text_content(input, working_dir, &url, persist_file_path).await {
         Ok(text) => {
             let lines = text.lines().map(String::from).collect();
@@ -40,50 +79,137 @@ pub(crate) async fn get_m3u_playlist(cfg: &Config, input: &ConfigInput, working_
     }
 }
 
-pub(crate) async fn get_xtream_playlist_series<'a>(fpl: &mut FetchedPlaylist<'a>, errors: &mut Vec<M3uFilterError>, resolve_delay: u16) -> Vec<PlaylistGroup> {
+fn get_series_last_modified(pli: &PlaylistItem) -> Option<String> {
+    let header = pli.header.borrow();
+    header.additional_properties.as_ref()
+        .and_then(|props| props.iter().find(|(key, _)| key == "last_modified"))
+        .and_then(|(_, value)| match value {
+            serde_json::Value::String(text) if !text.is_empty() => Some(text.to_string()),
+            serde_json::Value::Number(num) => Some(num.to_string()),
+            _ => None,
+        })
+}
+
+/// Hands out the start times of the provider requests, one request per interval.
+struct RequestBudget {
+    interval: Duration,
+    next: Cell<Instant>,
+}
+
+impl RequestBudget {
+    fn new(options: &ConfigTargetOptions) -> Self {
+        let interval = match options.xtream_resolve_series_rate {
+            Some(rate) if rate > 0.0 => Duration::from_secs_f64(1.0 / rate),
+            _ => Duration::from_secs(u64::from(options.xtream_resolve_series_delay)),
+        };
+        RequestBudget { interval, next: Cell::new(Instant::now()) }
+    }
+
+    async fn acquire(&self) {
+        let now = Instant::now();
+        let slot = self.next.get().max(now);
+        self.next.set(slot + self.interval);
+        if slot > now {
+            actix_rt::time::sleep(slot - now).await;
+        }
+    }
+}
+
+struct SeriesInfoRequest {
+    group_idx: usize,
+    group: String,
+    series_id: String,
+    url: String,
+    last_modified: Option<String>,
+}
+
+/// Fetches the series info, with `use_cache` unmodified series are read from the series cache.
+/// Series downloaded by an interrupted run are read from the resume directory, a dry run writes neither the cache nor resume files.
+async fn get_series_info_content(input: &ConfigInput, working_dir: &String, request: &SeriesInfoRequest,
+                                 use_cache: bool, dry_run: bool, budget: &RequestBudget) -> Result<serde_json::Value, M3uFilterError> {
+    let last_modified = request.last_modified.as_ref().filter(|_| use_cache);
+    if let Some(last_modified) = last_modified {
+        if let Some(content) = series_cache::get_cached_series_info(working_dir, input, &request.series_id, last_modified) {
+            return Ok(content);
+        }
+    }
+    if let Some(content) = series_cache::get_resumed_series_info(working_dir, input, &request.series_id) {
+        return Ok(content);
+    }
+    budget.acquire().await;
+    let content = request_utils::get_input_json_content(input, working_dir, &request.url, None).await?;
+    if !dry_run {
+        if let Some(last_modified) = last_modified {
+            series_cache::put_cached_series_info(working_dir, input, &request.series_id, last_modified, &content);
+        }
+        series_cache::put_resumed_series_info(working_dir, input, &request.series_id, &content);
+    }
+    Ok(content)
+}
+
+/// Resolves the series info entries of the playlist with `xtream_resolve_series_parallel` requests at the same time.
+/// The requests are limited by `xtream_resolve_series_rate` per second, or one request per `xtream_resolve_series_delay` seconds.
+pub(crate) async fn get_xtream_playlist_series<'a>(fpl: &mut FetchedPlaylist<'a>, working_dir: &String, errors: &mut Vec<M3uFilterError>,
+                                                   options: &ConfigTargetOptions, dry_run: bool) -> Vec<PlaylistGroup> {
     let input = fpl.input;
-    let mut result: Vec<PlaylistGroup> = vec![];
-    for plg in &mut fpl.playlist {
-        let mut group_series: Vec<PlaylistItem> = vec![];
+    let mut requests = vec![];
+    for (group_idx, plg) in fpl.playlist.iter().enumerate() {
         for pli in &plg.channels {
-            let (fetch_series, series_info_url) = {
-                let mut header = pli.header.borrow_mut();
-                let fetch_series = !header.series_fetched && header.item_type == PlaylistItemType::SeriesInfo;
-                if fetch_series {
-                    header.series_fetched = true;
-                }
-                (fetch_series, header.url.to_string())
+            let fetch_series = {
+                let header = pli.header.borrow();
+                !header.series_fetched && header.item_type == PlaylistItemType::SeriesInfo
             };
             if fetch_series {
-                match request_utils::get_input_json_content(fpl.input, series_info_url.as_str(), None).await {
-                    Ok(series_content) => {
-                        match parse_xtream_series_info(&series_content, pli.header.borrow().group.as_str(), input) {
-                            Ok(series_info) => {
-                                if let Some(mut series) = series_info {
-                                    series.drain(..).for_each(|item| group_series.push(item));
-                                }
-                            }
-                            Err(err) => errors.push(err),
-                        }
-                    }
-                    Err(err) => errors.push(err)
-                };
-                if resolve_delay > 0 {
-                    sleep(std::time::Duration::new(resolve_delay as u64, 0)); // 2 seconds between
-                }
+                let last_modified = get_series_last_modified(pli);
+                let mut header = pli.header.borrow_mut();
+                header.series_fetched = true;
+                requests.push(SeriesInfoRequest {
+                    group_idx,
+                    group: header.group.to_string(),
+                    series_id: header.id.to_string(),
+                    url: header.url.to_string(),
+                    last_modified,
+                });
             }
         }
-        if !group_series.is_empty() {
-            let group = PlaylistGroup {
-                id: plg.id,
-                title: plg.title.clone(),
-                channels: group_series,
-                xtream_cluster: XtreamCluster::Series,
-            };
-            result.push(group);
+    }
+    if requests.is_empty() {
+        return vec![];
+    }
+
+    let budget = RequestBudget::new(options);
+    let use_cache = options.xtream_resolve_series_cache;
+    let mut results: Vec<(usize, Result<serde_json::Value, M3uFilterError>)> = futures::stream::iter(requests.iter().enumerate())
+        .map(|(idx, request)| {
+            let budget = &budget;
+            async move { (idx, get_series_info_content(input, working_dir, request, use_cache, dry_run, budget).await) }
+        })
+        .buffer_unordered(usize::from(options.xtream_resolve_series_parallel.max(1)))
+        .collect().await;
+    results.sort_by_key(|(idx, _)| *idx);
+
+    let mut group_series: Vec<Vec<PlaylistItem>> = fpl.playlist.iter().map(|_| vec![]).collect();
+    for (idx, result) in results {
+        let request = &requests[idx];
+        match result.and_then(|content| parse_xtream_series_info(&content, &request.series_id, &request.group, input)) {
+            Ok(Some(mut series)) => group_series[request.group_idx].append(&mut series),
+            Ok(None) => {}
+            Err(err) => errors.push(err),
         }
     }
-    result
+    // only an interrupted run continues with the downloaded series, failed series are requested again by the next run
+    if !dry_run {
+        series_cache::clear_resumed_series_info(working_dir, input);
+    }
+    fpl.playlist.iter().zip(group_series)
+        .filter(|(_, channels)| !channels.is_empty())
+        .map(|(plg, channels)| PlaylistGroup {
+            id: plg.id,
+            title: plg.title.clone(),
+            channels,
+            xtream_cluster: XtreamCluster::Series,
+        })
+        .collect()
 }
 
 
@@ -92,54 +218,113 @@ const ACTIONS: [(XtreamCluster, &str, &str); 3] = [
     (XtreamCluster::Video, "get_vod_categories", "get_vod_streams"),
     (XtreamCluster::Series, "get_series_categories", "get_series")];
 
-pub(crate) async fn get_xtream_playlist(input: &ConfigInput, working_dir: &String) -> (Vec<PlaylistGroup>, Vec<M3uFilterError>) {
+/// Downloads the category and stream list of a cluster and keeps them for the next runs, a dry run doesn't keep them.
+/// The stream list can be very large, it is written to disk and parsed while reading.
+async fn download_xtream_cluster(input: &ConfigInput, working_dir: &String, category_id_cnt: &AtomicU32, xtream_cluster: &XtreamCluster,
+                                 actions: (&str, &str), urls: (&str, &str), dry_run: bool) -> Result<Option<Vec<PlaylistGroup>>, M3uFilterError> {
+    let (category, stream) = actions;
+    let category_file_path = prepare_file_path(input, working_dir, format!("{}_", category).as_str()).filter(|_| !dry_run);
+    let category_content = request_utils::get_input_json_content(input, working_dir, urls.0, category_file_path).await?;
+    let stream_path = match cluster_cache::get_cluster_download_path(working_dir, input, stream) {
+        Some(path) => path,
+        None => return Err(M3uFilterError::new(M3uFilterErrorKind::Notify, format!("cant download {}, invalid working dir", stream))),
+    };
+    request_utils::download_input_json_file(input, working_dir, urls.1, &stream_path).await?;
+    let parse_result = file_utils::open_file(&stream_path)
+        .map_err(|err| M3uFilterError::new(M3uFilterErrorKind::Notify, format!("cant read {}: {}", stream, err)))
+        .and_then(|file| xtream_parser::parse_xtream(category_id_cnt, xtream_cluster, &category_content, input, BufReader::new(file)));
+    if parse_result.is_err() || dry_run {
+        // the content of the last complete download is kept
+        let _ = fs::remove_file(&stream_path);
+        return parse_result;
+    }
+    if let Some(persist_path) = prepare_file_path(input, working_dir, format!("{}_", stream).as_str()) {
+        if let Err(err) = fs::copy(&stream_path, &persist_path) {
+            error!("cant persist to: {}  => {}", persist_path.to_str().unwrap_or("?"), err);
+        }
+    }
+    cluster_cache::put_cluster_content(working_dir, input, category, &category_content.to_string());
+    cluster_cache::put_cluster_file(working_dir, input, stream, &stream_path);
+    parse_result
+}
+
+/// Parses the content of the last run in which the cluster was downloaded completely.
+fn get_previous_xtream_cluster(input: &ConfigInput, working_dir: &String, category_id_cnt: &AtomicU32, xtream_cluster: &XtreamCluster,
+                               actions: (&str, &str)) -> Option<Vec<PlaylistGroup>> {
+    let category_content = cluster_cache::get_cluster_content(working_dir, input, actions.0)?;
+    let streams = cluster_cache::open_cluster_content(working_dir, input, actions.1)?;
+    let category = serde_json::from_str::<serde_json::Value>(&category_content).ok()?;
+    xtream_parser::parse_xtream(category_id_cnt, xtream_cluster, &category, input, streams).ok()
+        .map(Option::unwrap_or_default)
+}
+
+/// The clusters are processed independently, if one of them fails the others are kept.
+/// For a failed cluster of a provider the data of the previous run is used, the failed clusters are returned.
+pub(crate) async fn get_xtream_playlist(input: &ConfigInput, working_dir: &String, dry_run: bool) -> (Vec<PlaylistGroup>, Vec<M3uFilterError>, Vec<XtreamCluster>) {
     let mut playlist: Vec<PlaylistGroup> = Vec::new();
     let username = input.username.as_ref().map_or("", |v| v);
     let password = input.password.as_ref().map_or("", |v| v);
     let base_url = format!("{}/player_api.php?username={}&password={}", input.url, username, password);
+    // local inputs point to a directory with json dumps of the player_api actions
+    let dump_dir = request_utils::get_local_file_path(working_dir, &input.url);
 
     let mut errors = vec![];
+    let mut failed_clusters = vec![];
     let category_id_cnt = AtomicU32::new(0);
     for (xtream_cluster, category, stream) in &ACTIONS {
-        let category_url = format!("{}&action={}", base_url, category);
-        let stream_url = format!("{}&action={}", base_url, stream);
-        let category_file_path = prepare_file_path(input, working_dir, format!("{}_", category).as_str());
-        let stream_file_path = prepare_file_path(input, working_dir, format!("{}_", stream).as_str());
-
-        match request_utils::get_input_json_content(input, category_url.as_str(), category_file_path).await {
-            Ok(category_content) => {
-                match request_utils::get_input_json_content(input, stream_url.as_str(), stream_file_path).await {
-                    Ok(stream_content) => {
-                        match xtream_parser::parse_xtream(&category_id_cnt,
-                                                          xtream_cluster,
-                                                          &category_content,
-                                                          input,
-                                                          &stream_content,
-                        ) {
-                            Ok(sub_playlist_opt) => {
-                                if let Some(mut sub_playlist) = sub_playlist_opt {
-                                    sub_playlist.drain(..).for_each(|group| playlist.push(group));
-                                }
-                            }
-                            Err(err) => errors.push(err)
-                        }
+        let parse_result = match &dump_dir {
+            Some(dir) => {
+                let category_file_path = prepare_file_path(input, working_dir, format!("{}_", category).as_str()).filter(|_| !dry_run);
+                let stream_file_path = prepare_file_path(input, working_dir, format!("{}_", stream).as_str()).filter(|_| !dry_run);
+                let category_url = request_utils::get_xtream_dump_path(dir, category).to_string_lossy().to_string();
+                let stream_url = request_utils::get_xtream_dump_path(dir, stream).to_string_lossy().to_string();
+                // the streams can be very large, they are parsed while reading
+                request_utils::get_input_json_content(input, working_dir, category_url.as_str(), category_file_path).await
+                    .and_then(|category_content| request_utils::open_input_json_file(working_dir, stream_url.as_str(), stream_file_path)
+                        .and_then(|reader| xtream_parser::parse_xtream(&category_id_cnt, xtream_cluster, &category_content, input, reader)))
+            }
+            None => {
+                let category_url = format!("{}&action={}", base_url, category);
+                let stream_url = format!("{}&action={}", base_url, stream);
+                match download_xtream_cluster(input, working_dir, &category_id_cnt, xtream_cluster, (category, stream),
+                                              (category_url.as_str(), stream_url.as_str()), dry_run).await {
+                    Ok(sub_playlist) => Ok(sub_playlist),
+                    Err(err) => {
+                        let input_name = input.name.clone().unwrap_or_else(|| format!("input_{}", input.id));
+                        let previous = get_previous_xtream_cluster(input, working_dir, &category_id_cnt, xtream_cluster, (category, stream));
+                        let message = if previous.is_some() {
+                            format!("Input {}: {} failed, keeping the data of the previous run: {}", input_name, xtream_cluster, err.message)
+                        } else {
+                            format!("Input {}: {} failed, no data of a previous run: {}", input_name, xtream_cluster, err.message)
+                        };
+                        warn!("{}", message);
+                        errors.push(M3uFilterError::new(M3uFilterErrorKind::Notify, message));
+                        failed_clusters.push(xtream_cluster.clone());
+                        Ok(previous)
                     }
-                    Err(err) => errors.push(err)
+                }
+            }
+        };
+        match parse_result {
+            Ok(sub_playlist_opt) => {
+                if let Some(mut sub_playlist) = sub_playlist_opt {
+                    sub_playlist.drain(..).for_each(|group| playlist.push(group));
                 }
             }
             Err(err) => errors.push(err)
         }
     }
-    (playlist, errors)
+    (playlist, errors, failed_clusters)
 }
 
 
-pub(crate) async fn get_xmltv(_cfg: &Config, input: &ConfigInput, working_dir: &String) -> (Option<TVGuide>, Vec<M3uFilterError>) {
+pub(crate) async fn get_xmltv(_cfg: &Config, input: &ConfigInput, working_dir: &String, dry_run: bool) -> (Option<TVGuide>, Vec<M3uFilterError>) {
     match &input.epg_url {
         None => (None, vec![]),
         Some(url) => {
             debug!("Getting epg file path for url: {}", url);
-            let persist_file_path = prepare_file_path(input, working_dir, "").map(|path| file_utils::add_prefix_to_filename(&path, "epg_", Some("xml")));
+            let persist_file_path = prepare_file_path(input, working_dir, "").filter(|_| !dry_run)
+                .map(|path| file_utils::add_prefix_to_filename(&path, "epg_", Some("xml")));
             match request_utils::get_input_text_content(input, working_dir, url, persist_file_path).await {
                 Ok(xml_content) => {
                     (xmltv_parser::parse_tvguide(xml_content.as_str()), vec![])
@@ -148,4 +333,63 @@ pub(crate) async fn get_xmltv(_cfg: &Config, input: &ConfigInput, working_dir: &
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// file in the working dir with the modification times of the local inputs of the last successful run
+const LOCAL_INPUTS_FILE: &str = "local_inputs.json";
+
+/// The sources are processed in parallel, the updates of the file are serialized.
+fn local_inputs_lock() -> &'static Mutex<()> {
+    static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+    LOCK.get_or_init(|| Mutex::new(()))
+}
+
+fn get_local_input_key(input: &ConfigInput) -> String {
+    format!("{}|{}", input.url, input.epg_url.as_deref().unwrap_or_default())
+}
+
+/// The modification time in nanoseconds, stored as string to keep the precision in json.
+fn get_modified_value(modified: SystemTime) -> Option<String> {
+    modified.duration_since(SystemTime::UNIX_EPOCH).ok().map(|duration| duration.as_nanos().to_string())
+}
+
+fn load_local_inputs(working_dir: &String) -> (Option<PathBuf>, HashMap<String, String>) {
+    let path = file_utils::get_file_path(working_dir, Some(PathBuf::from(LOCAL_INPUTS_FILE)));
+    let modified = path.as_ref().and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok()).unwrap_or_default();
+    (path, modified)
+}
+
+/// Returns true if the input has `skip_unmodified` set and its local files
+/// did not change since the last successful processing.
+/// The modification times are stored in the working dir, a restart doesn't process unchanged inputs again.
+pub(crate) fn is_local_input_unmodified(input: &ConfigInput, working_dir: &String) -> bool {
+    if !input.options.as_ref().is_some_and(|o| o.skip_unmodified) {
+        return false;
+    }
+    match request_utils::get_local_modified(input, working_dir).and_then(get_modified_value) {
+        Some(modified) => {
+            let _guard = local_inputs_lock().lock().unwrap();
+            load_local_inputs(working_dir).1.get(&get_local_input_key(input)).is_some_and(|last| *last == modified)
+        }
+        None => false,
+    }
+}
+
+pub(crate) fn update_local_input_modified(input: &ConfigInput, working_dir: &String) {
+    if let Some(modified) = request_utils::get_local_modified(input, working_dir).and_then(get_modified_value) {
+        let _guard = local_inputs_lock().lock().unwrap();
+        let (path, mut local_inputs) = load_local_inputs(working_dir);
+        if local_inputs.get(&get_local_input_key(input)) == Some(&modified) {
+            return;
+        }
+        local_inputs.insert(get_local_input_key(input), modified);
+        if let Some(path) = path {
+            let tmp_path = path.with_extension("tmp");
+            if let Err(err) = fs::write(&tmp_path, serde_json::to_string(&local_inputs).unwrap_or_default())
+                .and_then(|_| fs::rename(&tmp_path, &path)) {
+                error!("cant write {}: {}", path.to_str().unwrap_or("?"), err);
+            }
+        }
+    }
+}
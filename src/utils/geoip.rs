@@ -0,0 +1,46 @@
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use log::error;
+use maxminddb::{geoip2, Reader};
+
+/// The database is read again when the file is replaced, like by `geoipupdate`.
+struct GeoIpDatabase {
+    path: String,
+    modified: Option<SystemTime>,
+    reader: Arc<Reader<Vec<u8>>>,
+}
+
+fn database() -> &'static Mutex<Option<GeoIpDatabase>> {
+    static DATABASE: OnceLock<Mutex<Option<GeoIpDatabase>>> = OnceLock::new();
+    DATABASE.get_or_init(|| Mutex::new(None))
+}
+
+fn get_reader(path: &str) -> Option<Arc<Reader<Vec<u8>>>> {
+    let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let mut guard = database().lock().unwrap();
+    if let Some(db) = guard.as_ref() {
+        if db.path == path && db.modified == modified {
+            return Some(Arc::clone(&db.reader));
+        }
+    }
+    match Reader::open_readfile(path) {
+        Ok(reader) => {
+            let reader = Arc::new(reader);
+            *guard = Some(GeoIpDatabase { path: path.to_string(), modified, reader: Arc::clone(&reader) });
+            Some(reader)
+        }
+        Err(err) => {
+            error!("cant open geoip database {}: {}", path, err);
+            None
+        }
+    }
+}
+
+/// Returns the ISO code of the country of the ip, like `DE`, from a MaxMind country or city database.
+pub(crate) fn lookup_country(db_path: &str, ip: IpAddr) -> Option<String> {
+    let reader = get_reader(db_path)?;
+    let country: geoip2::Country = reader.lookup(ip).ok()?;
+    country.country.and_then(|country| country.iso_code).map(str::to_string)
+}
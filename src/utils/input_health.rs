@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use log::error;
+
+use crate::model::config::{Config, ConfigInput};
+use crate::utils::{account_status, file_utils, series_cache};
+
+const INPUT_HEALTH_FILE: &str = "input_health.json";
+
+/// The result of the last download of each input, each working dir has its own list like the account status.
+/// The inputs are identified by url and user, the ids change with the order of the config.
+fn stores() -> &'static Mutex<HashMap<String, HashMap<String, bool>>> {
+    static STORES: OnceLock<Mutex<HashMap<String, HashMap<String, bool>>>> = OnceLock::new();
+    STORES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn get_input_health_path(cfg: &Config) -> Option<PathBuf> {
+    file_utils::get_file_path(&cfg.working_dir, Some(PathBuf::from(INPUT_HEALTH_FILE)))
+}
+
+/// The results are read from the working dir once, a restart keeps the state of the inputs.
+fn get_store<'a>(stores: &'a mut HashMap<String, HashMap<String, bool>>, cfg: &Config) -> &'a mut HashMap<String, bool> {
+    stores.entry(cfg.working_dir.to_string()).or_insert_with(|| get_input_health_path(cfg)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default())
+}
+
+/// Records whether the playlist of the input could be downloaded.
+pub(crate) fn record_download(cfg: &Config, input_id: u16, success: bool) {
+    let input = match cfg.get_input_by_id(&input_id) {
+        Some(input) => input,
+        None => return,
+    };
+    let mut stores = stores().lock().unwrap();
+    let store = get_store(&mut stores, cfg);
+    if store.insert(series_cache::get_input_key(&input), success) == Some(success) {
+        return;
+    }
+    if let Some(path) = get_input_health_path(cfg) {
+        let tmp_path = path.with_extension("tmp");
+        if let Err(err) = fs::write(&tmp_path, serde_json::to_string(store).unwrap_or_default())
+            .and_then(|_| fs::rename(&tmp_path, &path)) {
+            error!("cant write {}: {}", path.to_str().unwrap_or("?"), err);
+        }
+    }
+}
+
+/// An input is down if its last download failed or if the `account_check` reports the account as not authorized.
+/// A provider which could not be reached keeps the result of its last check.
+/// An input which was neither downloaded nor checked yet is up.
+pub(crate) fn is_input_up(cfg: &Config, input: &ConfigInput) -> bool {
+    let downloaded = get_store(&mut stores().lock().unwrap(), cfg)
+        .get(&series_cache::get_input_key(input)).copied().unwrap_or(true);
+    downloaded && account_status::get_account_status(cfg).iter()
+        .find(|status| status.input_id == input.id).is_none_or(|status| status.known_auth)
+}
+
+/// Returns the input with the name, the names are compared ignoring case.
+pub(crate) fn get_input_by_name<'a>(cfg: &'a Config, name: &str) -> Option<&'a ConfigInput> {
+    cfg.sources.iter().flat_map(|source| &source.inputs)
+        .find(|input| input.name.as_deref().is_some_and(|input_name| input_name.trim().eq_ignore_ascii_case(name.trim())))
+}
@@ -0,0 +1,174 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::utils::shutdown;
+
+/// number of jobs kept for status requests
+const MAX_JOBS: usize = 50;
+
+// The jobs and job slots are kept per working dir, tenants don't see or block the jobs of each other.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum JobStatus {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub(crate) struct Job {
+    #[serde(skip)]
+    pub working_dir: String,
+    pub id: String,
+    pub targets: Vec<String>,
+    pub status: JobStatus,
+    pub created: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished: Option<i64>,
+    pub sources_total: usize,
+    pub sources_processed: usize,
+    pub errors: Vec<String>,
+}
+
+fn jobs() -> &'static Mutex<VecDeque<Job>> {
+    static JOBS: OnceLock<Mutex<VecDeque<Job>>> = OnceLock::new();
+    JOBS.get_or_init(|| Mutex::new(VecDeque::new()))
+}
+
+fn update_job<F>(id: &str, update: F) where F: FnOnce(&mut Job) {
+    if let Some(job) = jobs().lock().unwrap().iter_mut().find(|job| job.id == id) {
+        update(job);
+    }
+}
+
+/// Registers a new queued job and returns its id.
+pub(crate) fn create_job(working_dir: &str, targets: Vec<String>, sources_total: usize) -> String {
+    let job = Job {
+        working_dir: working_dir.to_string(),
+        id: uuid::Uuid::new_v4().to_string(),
+        targets,
+        status: JobStatus::Queued,
+        created: chrono::Local::now().timestamp(),
+        started: None,
+        finished: None,
+        sources_total,
+        sources_processed: 0,
+        errors: vec![],
+    };
+    let id = job.id.clone();
+    let mut job_list = jobs().lock().unwrap();
+    while job_list.iter().filter(|job| job.working_dir == working_dir).count() >= MAX_JOBS {
+        // drop the oldest completed job, running jobs are kept
+        match job_list.iter().position(|job| job.working_dir == working_dir && matches!(job.status, JobStatus::Finished | JobStatus::Failed)) {
+            Some(idx) => { job_list.remove(idx); }
+            None => break,
+        }
+    }
+    job_list.push_back(job);
+    id
+}
+
+pub(crate) fn start_job(id: &str) {
+    update_job(id, |job| {
+        job.status = JobStatus::Running;
+        job.started = Some(chrono::Local::now().timestamp());
+    });
+}
+
+pub(crate) fn record_source_processed(id: &str) {
+    update_job(id, |job| job.sources_processed += 1);
+}
+
+/// Marks the job as finished, the job fails if there are errors.
+pub(crate) fn finish_job(id: &str, errors: Vec<String>) {
+    update_job(id, |job| {
+        job.status = if errors.is_empty() { JobStatus::Finished } else { JobStatus::Failed };
+        job.finished = Some(chrono::Local::now().timestamp());
+        job.errors = errors;
+    });
+}
+
+pub(crate) fn get_job(working_dir: &str, id: &str) -> Option<Job> {
+    jobs().lock().unwrap().iter().find(|job| job.working_dir == working_dir && job.id == id).cloned()
+}
+
+pub(crate) fn get_jobs(working_dir: &str) -> Vec<Job> {
+    jobs().lock().unwrap().iter().filter(|job| job.working_dir == working_dir).cloned().collect()
+}
+
+#[derive(Default)]
+struct JobSlots {
+    running: usize,
+    targets: HashSet<String>,
+}
+
+fn job_slots() -> &'static Mutex<HashMap<String, JobSlots>> {
+    static SLOTS: OnceLock<Mutex<HashMap<String, JobSlots>>> = OnceLock::new();
+    SLOTS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Holds a job slot and the locks of its targets until it is dropped.
+pub(crate) struct JobPermit {
+    working_dir: String,
+    targets: Vec<String>,
+}
+
+impl Drop for JobPermit {
+    fn drop(&mut self) {
+        let mut guard = job_slots().lock().unwrap();
+        if let Some(slots) = guard.get_mut(&self.working_dir) {
+            slots.running -= 1;
+            self.targets.iter().for_each(|name| { slots.targets.remove(name); });
+        }
+    }
+}
+
+fn try_acquire(working_dir: &str, targets: &[String], max_parallel: usize) -> Option<JobPermit> {
+    let mut guard = job_slots().lock().unwrap();
+    let slots = guard.entry(working_dir.to_string()).or_default();
+    if slots.running >= max_parallel || targets.iter().any(|name| slots.targets.contains(name)) {
+        return None;
+    }
+    slots.running += 1;
+    targets.iter().for_each(|name| { slots.targets.insert(name.to_string()); });
+    Some(JobPermit { working_dir: working_dir.to_string(), targets: targets.to_vec() })
+}
+
+/// Waits until less than `max_parallel` jobs are running and no other job processes one of the targets.
+/// Returns `None` if the server is shutting down, queued jobs are not started anymore.
+pub(crate) async fn acquire(working_dir: &str, targets: Vec<String>, max_parallel: u8) -> Option<JobPermit> {
+    let max_parallel = usize::from(max_parallel.max(1));
+    loop {
+        if shutdown::is_shutting_down() {
+            return None;
+        }
+        if let Some(permit) = try_acquire(working_dir, &targets, max_parallel) {
+            return Some(permit);
+        }
+        actix_rt::time::sleep(Duration::from_millis(500)).await;
+    }
+}
+
+/// Waits until the running jobs of all working dirs are finished, returns false if they are still running after the timeout.
+pub(crate) async fn wait_for_running_jobs(timeout: Duration) -> bool {
+    let start = std::time::Instant::now();
+    while job_slots().lock().unwrap().values().any(|slots| slots.running > 0) {
+        if start.elapsed() >= timeout {
+            return false;
+        }
+        actix_rt::time::sleep(Duration::from_millis(500)).await;
+    }
+    true
+}
+
+/// Returns true if a running job is writing the target.
+pub(crate) fn is_target_locked(working_dir: &str, target_name: &str) -> bool {
+    job_slots().lock().unwrap().get(working_dir).is_some_and(|slots| slots.targets.contains(target_name))
+}
@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 use std::fs::File;
 use serde::de::DeserializeOwned;
 use serde_json::{self, Deserializer};
@@ -61,30 +60,55 @@ pub(crate) fn iter_json_array<T: DeserializeOwned, R: Read>(
     std::iter::from_fn(move || yield_next_obj(&mut reader, &mut at_start).transpose())
 }
 
-pub(crate) fn filter_json_file(file_path: &Path, filter: &HashMap<&str, &str>) -> Vec<serde_json::Value> {
+pub(crate) fn filter_json_file_by<F>(file_path: &Path, predicate: F) -> Vec<serde_json::Value>
+    where F: Fn(&serde_json::Map<String, serde_json::Value>) -> bool {
     let mut filtered: Vec<serde_json::Value> = Vec::new();
     if file_path.exists() {
         if let Ok(file) = File::open(file_path) {
             let reader = BufReader::new(file);
             for entry in iter_json_array::<serde_json::Value, BufReader<File>>(reader).flatten() {
-                if let Some(item) = entry.as_object() {
-                    for (&key, &value) in filter {
-                        if let Some(field_value) = item.get(key) {
-                            if field_value.is_string() && field_value.eq(value) {
-                                filtered.push(entry.clone());
-                            } else if let Some(num_val) = field_value.as_i64() {
-                                if let Ok(filter_num_val) = value.parse::<i64>() {
-                                    if num_val == filter_num_val {
-                                        filtered.push(entry.clone());
-                                    }
-                                }
-                            }
-                        }
-                    }
+                if entry.as_object().is_some_and(&predicate) {
+                    filtered.push(entry);
                 }
             }
         }
     }
-
     filtered
 }
+
+/// Writes the values as json array in chunks of at least `chunk_size` bytes, the last chunk closes the array.
+/// An error ends the chunks without closing the array.
+pub(crate) fn to_json_array_chunks<I>(mut values: I, chunk_size: usize) -> impl Iterator<Item=io::Result<String>>
+    where I: Iterator<Item=io::Result<serde_json::Value>> {
+    let mut started = false;
+    let mut finished = false;
+    std::iter::from_fn(move || {
+        if finished {
+            return None;
+        }
+        let mut chunk = String::with_capacity(chunk_size);
+        if !started {
+            chunk.push('[');
+        }
+        for value in values.by_ref() {
+            let value = match value {
+                Ok(value) => value,
+                Err(err) => {
+                    finished = true;
+                    return Some(Err(err));
+                }
+            };
+            if started {
+                chunk.push(',');
+            }
+            started = true;
+            chunk.push_str(&value.to_string());
+            if chunk.len() >= chunk_size {
+                return Some(Ok(chunk));
+            }
+        }
+        chunk.push(']');
+        finished = true;
+        Some(Ok(chunk))
+    })
+}
@@ -0,0 +1,146 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{OnceLock, RwLock};
+
+use chrono::SecondsFormat;
+use env_logger::{Builder, Logger, Target, WriteStyle};
+use env_logger::fmt::Formatter;
+use log::{error, Log, Metadata, Record};
+
+use crate::model::config::LogConfig;
+use crate::model::model_config::LogFormat;
+
+const DEFAULT_LOG_LEVEL: &str = "info";
+
+/// The logger is installed before the config is read,
+/// the `log` section of the config replaces it afterwards.
+struct ReloadableLogger {
+    inner: RwLock<Logger>,
+}
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.read().unwrap().enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.inner.read().unwrap().log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.read().unwrap().flush();
+    }
+}
+
+static LOGGER: OnceLock<&'static ReloadableLogger> = OnceLock::new();
+
+/// Log file which is renamed to `<file>.1` when it reaches the max size,
+/// older files are shifted up to `<file>.<max_files>`.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: u16,
+}
+
+impl RotatingFile {
+    fn open(path: &str, max_size_mb: u64, max_files: u16) -> std::io::Result<RotatingFile> {
+        let path = PathBuf::from(path);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile { path, file, size, max_size: max_size_mb * 1_048_576, max_files })
+    }
+
+    fn get_rotated_path(&self, index: u16) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        if self.max_files > 0 {
+            for index in (1..self.max_files).rev() {
+                let from = self.get_rotated_path(index);
+                if from.exists() {
+                    std::fs::rename(from, self.get_rotated_path(index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.get_rotated_path(1))?;
+        } else {
+            std::fs::remove_file(&self.path)?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.max_size > 0 && self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn format_json(buf: &mut Formatter, record: &Record) -> std::io::Result<()> {
+    let entry = serde_json::json!({
+        "time": chrono::Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    });
+    writeln!(buf, "{}", entry)
+}
+
+/// The level can contain levels for modules like `RUST_LOG`, the `RUST_LOG` environment variable is applied first.
+fn build_logger(level: &str, log_config: Option<&LogConfig>) -> Result<Logger, String> {
+    let mut builder = Builder::from_default_env();
+    builder.parse_filters(level);
+    if let Some(log_config) = log_config {
+        if log_config.format == LogFormat::Json {
+            builder.format(format_json);
+        }
+        if let Some(file) = &log_config.file {
+            let writer = RotatingFile::open(file, log_config.max_size, log_config.max_files)
+                .map_err(|err| format!("cant open log file {}: {}", file, err))?;
+            builder.target(Target::Pipe(Box::new(writer)));
+            builder.write_style(WriteStyle::Never);
+        }
+    }
+    Ok(builder.build())
+}
+
+pub(crate) fn init_logger(level: Option<&str>) {
+    let logger = build_logger(level.unwrap_or(DEFAULT_LOG_LEVEL), None).unwrap_or_else(|_| Builder::new().build());
+    let max_level = logger.filter();
+    let logger = LOGGER.get_or_init(|| Box::leak(Box::new(ReloadableLogger { inner: RwLock::new(logger) })));
+    if log::set_logger(*logger).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Applies the `log` section of the config, a level given on the command line takes precedence.
+pub(crate) fn configure_logger(cli_level: Option<&str>, log_config: &LogConfig) {
+    let level = cli_level.or(log_config.level.as_deref()).unwrap_or(DEFAULT_LOG_LEVEL);
+    match build_logger(level, Some(log_config)) {
+        Ok(new_logger) => {
+            if let Some(logger) = LOGGER.get() {
+                let max_level = new_logger.filter();
+                *logger.inner.write().unwrap() = new_logger;
+                log::set_max_level(max_level);
+            }
+        }
+        Err(err) => error!("{}", err),
+    }
+}
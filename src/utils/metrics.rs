@@ -0,0 +1,155 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::model::model_playlist::{PlaylistGroup, XtreamCluster};
+
+#[derive(Debug, Clone, Default, Serialize, utoipa::ToSchema)]
+pub(crate) struct ProcessingStats {
+    pub runs: u64,
+    pub last_duration_ms: u128,
+    pub last_run: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, utoipa::ToSchema)]
+pub(crate) struct TargetStats {
+    pub groups: usize,
+    pub channels: usize,
+    pub group_channels: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    pub(crate) fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 { 0.0 } else { self.hits as f64 / total as f64 }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Metrics {
+    pub processing: ProcessingStats,
+    pub targets: BTreeMap<String, TargetStats>,
+    pub download_failures: BTreeMap<String, u64>,
+    /// the xtream clusters of an input which failed in the last run
+    pub partial_failures: BTreeMap<String, Vec<String>>,
+    pub cache: CacheStats,
+}
+
+/// The metrics are kept per working dir, so the tenants have their own.
+fn metrics() -> &'static Mutex<HashMap<String, Metrics>> {
+    static METRICS: OnceLock<Mutex<HashMap<String, Metrics>>> = OnceLock::new();
+    METRICS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn update_metrics<F>(working_dir: &str, update: F) where F: FnOnce(&mut Metrics) {
+    update(metrics().lock().unwrap().entry(working_dir.to_string()).or_default());
+}
+
+pub(crate) fn record_processing(working_dir: &str, duration: Duration) {
+    update_metrics(working_dir, |stats| {
+        stats.processing.runs += 1;
+        stats.processing.last_duration_ms = duration.as_millis();
+        stats.processing.last_run = chrono::Local::now().timestamp();
+    });
+}
+
+pub(crate) fn record_target(working_dir: &str, target_name: &str, playlist: &[PlaylistGroup]) {
+    let group_channels: BTreeMap<String, usize> = playlist.iter()
+        .map(|group| (group.title.to_string(), group.channels.len())).collect();
+    let target_stats = TargetStats {
+        groups: playlist.len(),
+        channels: group_channels.values().sum(),
+        group_channels,
+    };
+    update_metrics(working_dir, |stats| { stats.targets.insert(target_name.to_string(), target_stats); });
+}
+
+pub(crate) fn record_download_failure(working_dir: &str, input_name: &str) {
+    update_metrics(working_dir, |stats| *stats.download_failures.entry(input_name.to_string()).or_insert(0) += 1);
+}
+
+pub(crate) fn record_failed_clusters(working_dir: &str, input_name: &str, failed_clusters: &[XtreamCluster]) {
+    update_metrics(working_dir, |stats| {
+        if failed_clusters.is_empty() {
+            stats.partial_failures.remove(input_name);
+        } else {
+            stats.partial_failures.insert(input_name.to_string(), failed_clusters.iter().map(ToString::to_string).collect());
+        }
+    });
+}
+
+pub(crate) fn record_cache_lookup(working_dir: &str, hit: bool) {
+    update_metrics(working_dir, |stats| if hit { stats.cache.hits += 1 } else { stats.cache.misses += 1 });
+}
+
+pub(crate) fn get_metrics(working_dir: &str) -> Metrics {
+    metrics().lock().unwrap().get(working_dir).cloned().unwrap_or_default()
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Renders the metrics in the prometheus text exposition format.
+pub(crate) fn render_prometheus(working_dir: &str, active_streams: usize) -> String {
+    let stats = get_metrics(working_dir);
+    let mut out = String::new();
+    let _ = writeln!(out, "# HELP m3u_filter_processing_runs_total Number of playlist processing runs.");
+    let _ = writeln!(out, "# TYPE m3u_filter_processing_runs_total counter");
+    let _ = writeln!(out, "m3u_filter_processing_runs_total {}", stats.processing.runs);
+    let _ = writeln!(out, "# HELP m3u_filter_processing_duration_seconds Duration of the last playlist processing run.");
+    let _ = writeln!(out, "# TYPE m3u_filter_processing_duration_seconds gauge");
+    let _ = writeln!(out, "m3u_filter_processing_duration_seconds {}", stats.processing.last_duration_ms as f64 / 1000.0);
+
+    let _ = writeln!(out, "# HELP m3u_filter_target_channels Number of channels of a target.");
+    let _ = writeln!(out, "# TYPE m3u_filter_target_channels gauge");
+    for (name, target) in &stats.targets {
+        let _ = writeln!(out, "m3u_filter_target_channels{{target=\"{}\"}} {}", escape_label(name), target.channels);
+    }
+    let _ = writeln!(out, "# HELP m3u_filter_target_groups Number of groups of a target.");
+    let _ = writeln!(out, "# TYPE m3u_filter_target_groups gauge");
+    for (name, target) in &stats.targets {
+        let _ = writeln!(out, "m3u_filter_target_groups{{target=\"{}\"}} {}", escape_label(name), target.groups);
+    }
+    let _ = writeln!(out, "# HELP m3u_filter_group_channels Number of channels of a group.");
+    let _ = writeln!(out, "# TYPE m3u_filter_group_channels gauge");
+    for (name, target) in &stats.targets {
+        for (group, count) in &target.group_channels {
+            let _ = writeln!(out, "m3u_filter_group_channels{{target=\"{}\",group=\"{}\"}} {}", escape_label(name), escape_label(group), count);
+        }
+    }
+
+    let _ = writeln!(out, "# HELP m3u_filter_download_failures_total Failed provider downloads.");
+    let _ = writeln!(out, "# TYPE m3u_filter_download_failures_total counter");
+    for (input, count) in &stats.download_failures {
+        let _ = writeln!(out, "m3u_filter_download_failures_total{{input=\"{}\"}} {}", escape_label(input), count);
+    }
+
+    let _ = writeln!(out, "# HELP m3u_filter_input_failed_clusters Xtream clusters of an input which failed in the last run.");
+    let _ = writeln!(out, "# TYPE m3u_filter_input_failed_clusters gauge");
+    for (input, clusters) in &stats.partial_failures {
+        let _ = writeln!(out, "m3u_filter_input_failed_clusters{{input=\"{}\"}} {}", escape_label(input), clusters.len());
+    }
+
+    let _ = writeln!(out, "# HELP m3u_filter_active_streams Streams currently proxied.");
+    let _ = writeln!(out, "# TYPE m3u_filter_active_streams gauge");
+    let _ = writeln!(out, "m3u_filter_active_streams {}", active_streams);
+
+    let _ = writeln!(out, "# HELP m3u_filter_cache_requests_total Response cache lookups.");
+    let _ = writeln!(out, "# TYPE m3u_filter_cache_requests_total counter");
+    let _ = writeln!(out, "m3u_filter_cache_requests_total{{result=\"hit\"}} {}", stats.cache.hits);
+    let _ = writeln!(out, "m3u_filter_cache_requests_total{{result=\"miss\"}} {}", stats.cache.misses);
+    let _ = writeln!(out, "# HELP m3u_filter_cache_hit_ratio Ratio of response cache hits.");
+    let _ = writeln!(out, "# TYPE m3u_filter_cache_hit_ratio gauge");
+    let _ = writeln!(out, "m3u_filter_cache_hit_ratio {}", stats.cache.hit_rate());
+    out
+}
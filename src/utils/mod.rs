@@ -4,4 +4,29 @@ pub (crate) mod download;
 pub (crate) mod string_utils;
 pub (crate) mod json_utils;
 pub (crate) mod config_reader;
-pub (crate) mod multi_file_reader;
\ No newline at end of file
+pub (crate) mod config_validator;
+pub (crate) mod multi_file_reader;
+pub (crate) mod response_cache;
+pub (crate) mod metrics;
+pub (crate) mod backup_utils;
+pub (crate) mod series_cache;
+pub (crate) mod cluster_cache;
+pub (crate) mod user_stats;
+pub (crate) mod access_log;
+pub (crate) mod jobs;
+pub (crate) mod secret_store;
+pub (crate) mod logger;
+pub (crate) mod shutdown;
+pub (crate) mod daemon;
+pub (crate) mod tls_utils;
+pub (crate) mod compression_utils;
+
+pub (crate) mod ban_list;
+pub (crate) mod geoip;
+pub (crate) mod throttle;
+pub (crate) mod stream_reconnect;
+pub (crate) mod shared_stream;
+pub (crate) mod account_status;
+pub (crate) mod input_health;
+pub (crate) mod video_postprocess;
+pub (crate) mod video_search;
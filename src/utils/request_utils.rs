@@ -1,85 +1,256 @@
 use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::{Read};
-use std::path::{PathBuf};
-use log::{debug, error, Level, log_enabled};
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use log::{debug, error, Level, log_enabled, warn};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT};
 use crate::create_m3u_filter_error_result;
 use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
-use crate::model::config::{ConfigInput};
+use crate::model::config::{ConfigInput, ConfigInputRetry, InputType};
 use crate::utils::file_utils::{get_file_path, open_file, persist_file};
+use crate::utils::{metrics, response_cache};
 
 pub(crate) fn bytes_to_megabytes(bytes: u64) -> u64 {
     bytes / 1_048_576
 }
 
+/// Returns true if the url is not a remote url but a local file path or a `file://` url.
+/// A path has no scheme, any other url which can't be parsed is treated as remote and not read from the disk.
+pub(crate) fn is_local_url(url_str: &str) -> bool {
+    match url_str.parse::<url::Url>() {
+        // a single letter scheme is a windows drive letter
+        Ok(url) => url.scheme() == "file" || url.scheme().len() == 1,
+        Err(url::ParseError::RelativeUrlWithoutBase) => !url_str.contains("://"),
+        Err(_) => false,
+    }
+}
+
+/// Resolves a local file path or `file://` url, relative paths are resolved against the working directory.
+pub(crate) fn get_local_file_path(working_dir: &String, url_str: &str) -> Option<PathBuf> {
+    if !is_local_url(url_str) {
+        return None;
+    }
+    match url_str.parse::<url::Url>() {
+        Ok(url) if url.scheme() == "file" => url.to_file_path().ok(),
+        _ => get_file_path(working_dir, Some(PathBuf::from(url_str))),
+    }
+}
+
+fn read_local_file(filepath: &PathBuf, persist_filepath: Option<PathBuf>) -> Result<String, M3uFilterError> {
+    if let Some(persist_file_value) = persist_filepath {
+        let to_file = &persist_file_value;
+        if let Err(e) = fs::copy(filepath, to_file) {
+            error!("cant persist to: {}  => {}", to_file.to_str().unwrap_or("?"), e);
+            return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Failed to persist: {}  => {}", to_file.to_str().unwrap_or("?"), e);
+        }
+    };
+    let file_str = &filepath.to_str().unwrap_or("?");
+    match open_file(filepath) {
+        Ok(file) => {
+            let mut content = String::new();
+            match std::io::BufReader::new(file).read_to_string(&mut content) {
+                Ok(_) => Ok(content),
+                Err(err) => {
+                    error!("cant read file: {} {}", file_str, err);
+                    create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Cant open file : {}  => {}", file_str, err)
+                }
+            }
+        }
+        Err(err) => {
+            error!("cant read file: {} {}", file_str, err);
+            create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Cant open file : {}  => {}", file_str, err)
+        }
+    }
+}
+
+fn get_local_file_content(working_dir: &String, url_str: &str, persist_filepath: Option<PathBuf>) -> Result<String, M3uFilterError> {
+    match get_local_file_path(working_dir, url_str) {
+        Some(filepath) if filepath.exists() => read_local_file(&filepath, persist_filepath),
+        _ => {
+            let msg = format!("cant read input url: {:?}", url_str);
+            error!("{}", msg);
+            create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "{}", msg)
+        }
+    }
+}
+
+const XTREAM_DUMP_ACTIONS: [&str; 6] = ["get_live_categories", "get_live_streams",
+    "get_vod_categories", "get_vod_streams", "get_series_categories", "get_series"];
+
+pub(crate) fn get_xtream_dump_path(dump_dir: &Path, action: &str) -> PathBuf {
+    dump_dir.join(format!("{}.json", action))
+}
+
+/// Returns the latest modification time of the local files of an input.
+/// For xtream inputs the url is a directory with the json dumps of the `player_api` actions.
+pub(crate) fn get_local_modified(input: &ConfigInput, working_dir: &String) -> Option<SystemTime> {
+    let mut files = vec![];
+    if let Some(path) = get_local_file_path(working_dir, &input.url) {
+        match input.input_type {
+            InputType::M3u => files.push(path),
+            InputType::Xtream => XTREAM_DUMP_ACTIONS.iter().for_each(|action| files.push(get_xtream_dump_path(&path, action))),
+        }
+    } else {
+        return None;
+    }
+    if let Some(epg_url) = &input.epg_url {
+        if let Some(path) = get_local_file_path(working_dir, epg_url) {
+            files.push(path);
+        }
+    }
+    files.iter().filter_map(|path| fs::metadata(path).and_then(|m| m.modified()).ok()).max()
+}
+
 pub(crate) async fn get_input_text_content(input: &ConfigInput, working_dir: &String, url_str: &str, persist_filepath: Option<PathBuf>) -> Result<String, M3uFilterError> {
     if log_enabled!(Level::Debug) {
         debug!("getting input text content working_dir: {}, url: {}", working_dir, url_str);
     }
+    if is_local_url(url_str) {
+        return get_local_file_content(working_dir, url_str, persist_filepath);
+    }
     match url_str.parse::<url::Url>() {
-        Ok(url) => match download_text_content(input, url, persist_filepath).await {
+        Ok(url) => match download_text_content(input, working_dir, url, persist_filepath).await {
             Ok(content) => Ok(content),
             Err(e) => {
                 error!("cant download input url: {}  => {}", url_str, e);
                 create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Failed to download")
             }
         }
-        Err(_) => {
-            let result = match get_file_path(working_dir, Some(PathBuf::from(url_str))) {
-                Some(filepath) => {
-                    if filepath.exists() {
-                        if let Some(persist_file_value) = persist_filepath {
-                            let to_file = &persist_file_value;
-                            match fs::copy(&filepath, to_file) {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    error!("cant persist to: {}  => {}", to_file.to_str().unwrap_or("?"), e);
-                                    return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Failed to persist: {}  => {}", to_file.to_str().unwrap_or("?"), e);
-                                }
-                            }
-                        };
-                        match open_file(&filepath) {
-                            Ok(file) => {
-                                let mut content = String::new();
-                                match std::io::BufReader::new(file).read_to_string(&mut content) {
-                                    Ok(_) => Some(content),
-                                    Err(err) => {
-                                        let file_str = &filepath.to_str().unwrap_or("?");
-                                        error!("cant read file: {} {}", file_str,  err);
-                                        return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Cant open file : {}  => {}", file_str,  err);
-                                    }
-                                }
-                            }
-                            Err(err) => {
-                                let file_str = &filepath.to_str().unwrap_or("?");
-                                error!("cant read file: {} {}", file_str,  err);
-                                return create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Cant open file : {}  => {}", file_str,  err);
-                            }
-                        }
-                    } else {
-                        None
-                    }
-                }
-                None => None
-            };
-            match result {
-                Some(content) => Ok(content),
-                None => {
-                    let msg = format!("cant read input url: {:?}", url_str);
-                    error!("{}", msg);
-                    create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "{}", msg)
-                }
+        Err(_) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "malformed input url: {}", url_str)
+    }
+}
+
+fn get_retry_delay(retry: &ConfigInputRetry, attempt: u8) -> std::time::Duration {
+    let backoff = retry.backoff.saturating_mul(1u64 << u32::from(attempt.min(16)));
+    let jitter = if retry.jitter > 0 {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| u64::from(d.subsec_nanos()));
+        nanos % (retry.jitter + 1)
+    } else {
+        0
+    };
+    std::time::Duration::from_millis(backoff.saturating_add(jitter))
+}
+
+/// Sends the request and retries transient failures (timeouts, connection errors, 5xx and 429)
+/// with exponential backoff, if the input has `retry` settings.
+pub(crate) async fn send_with_retry(input: &ConfigInput, working_dir: &str, url: url::Url) -> Result<reqwest::Response, String> {
+    let max_attempts = input.retry.as_ref().map_or(1, |r| r.max_attempts.max(1));
+    let mut attempt: u8 = 0;
+    loop {
+        let result = get_client_request(input, url.clone(), None)?.send().await;
+        let transient = match &result {
+            Ok(response) => response.status().is_server_error() || response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS,
+            Err(err) => err.is_timeout() || err.is_connect() || err.is_request(),
+        };
+        attempt += 1;
+        if !transient || attempt >= max_attempts {
+            if !result.as_ref().is_ok_and(|response| response.status().is_success()) {
+                // the url can contain credentials, unnamed inputs are reported by id
+                metrics::record_download_failure(working_dir, &input.name.clone().unwrap_or_else(|| format!("input_{}", input.id)));
+            }
+            return result.map_err(|e| e.to_string());
+        }
+        if let Some(retry) = &input.retry {
+            let delay = get_retry_delay(retry, attempt - 1);
+            match &result {
+                Ok(response) => warn!("request failed with status {}, retry {}/{} in {}ms", response.status(), attempt, max_attempts - 1, delay.as_millis()),
+                Err(err) => warn!("request failed {}, retry {}/{} in {}ms", err, attempt, max_attempts - 1, delay.as_millis()),
+            }
+            actix_rt::time::sleep(delay).await;
+        }
+    }
+}
+
+fn get_max_download_rate(input: &ConfigInput) -> Option<u64> {
+    input.options.as_ref().and_then(|options| options.max_download_rate).filter(|rate| *rate > 0)
+}
+
+/// Reads the response body chunk by chunk. With a `max_download_rate` the download pauses
+/// whenever the average rate since the start is above the limit.
+async fn read_response_chunks<F>(input: &ConfigInput, mut response: reqwest::Response, mut consume: F) -> Result<(), String>
+where
+    F: FnMut(&[u8]) -> Result<(), String>,
+{
+    let bytes_per_second = get_max_download_rate(input).map(|rate| rate.saturating_mul(1024));
+    let start = Instant::now();
+    let mut size: u64 = 0;
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        consume(&chunk)?;
+        size += chunk.len() as u64;
+        if let Some(bytes_per_second) = bytes_per_second {
+            let expected = Duration::from_millis(size.saturating_mul(1000) / bytes_per_second);
+            let elapsed = start.elapsed();
+            if expected > elapsed {
+                actix_rt::time::sleep(expected - elapsed).await;
             }
         }
     }
+    Ok(())
 }
 
-pub(crate) fn get_client_request(input: &ConfigInput, url: url::Url, custom_headers: Option<&HashMap<&str, &[u8]>>) -> reqwest::RequestBuilder {
-    let mut request = reqwest::Client::new().get(url);
-    let headers = get_request_headers(&input.headers, custom_headers);
+async fn read_response_text(input: &ConfigInput, response: reqwest::Response) -> Result<String, String> {
+    if get_max_download_rate(input).is_none() {
+        return response.text_with_charset("utf8").await.map_err(|e| e.to_string());
+    }
+    let mut content = Vec::new();
+    read_response_chunks(input, response, |chunk| {
+        content.extend_from_slice(chunk);
+        Ok(())
+    }).await?;
+    Ok(String::from_utf8_lossy(&content).into_owned())
+}
+
+/// Headers which reveal the client, they are not forwarded with `strip_client_headers`.
+const CLIENT_HEADERS: [&str; 9] = ["forwarded", "x-forwarded-for", "x-forwarded-host", "x-forwarded-proto", "x-real-ip", "via", "referer", "origin", "cookie"];
+
+/// Applies the `user_agent` and the `header_rewrite` rules of the input, a missing header has an empty value.
+fn rewrite_request_headers(input: &ConfigInput, headers: &mut HeaderMap) {
+    if let Some(user_agent) = input.user_agent.as_ref().and_then(|user_agent| HeaderValue::from_str(user_agent.trim()).ok()) {
+        headers.insert(USER_AGENT, user_agent);
+    }
+    for rewrite in &input.header_rewrite {
+        let (re, name) = match (&rewrite.re, HeaderName::from_bytes(rewrite.header.trim().as_bytes())) {
+            (Some(re), Ok(name)) => (re, name),
+            _ => continue,
+        };
+        let value = headers.get(&name).map(|value| String::from_utf8_lossy(value.as_bytes()).to_string()).unwrap_or_default();
+        let rewritten = re.replace_all(&value, rewrite.replace.as_str());
+        if rewritten.is_empty() {
+            headers.remove(&name);
+        } else if let Ok(rewritten) = HeaderValue::from_str(&rewritten) {
+            headers.insert(name, rewritten);
+        }
+    }
+}
+
+/// Builds the client for requests to the provider with the `proxy`, a proxy which can't be used is an error.
+/// The requests are never sent without the configured proxy.
+pub(crate) fn build_client(builder: reqwest::ClientBuilder, proxy: Option<&str>) -> Result<reqwest::Client, String> {
+    let builder = match proxy {
+        // the url can contain credentials
+        Some(proxy) => builder.proxy(reqwest::Proxy::all(proxy).map_err(|err| format!("invalid proxy: {}", err.without_url()))?),
+        None => builder,
+    };
+    builder.build().map_err(|err| format!("cant create http client: {}", err.without_url()))
+}
+
+/// Requests to the provider go through the `proxy` of the input or the global one.
+fn get_client(input: &ConfigInput) -> Result<reqwest::Client, String> {
+    build_client(reqwest::Client::builder(), input._proxy.as_deref())
+        .map_err(|err| format!("{} for input {}", err, input.name.clone().unwrap_or_else(|| format!("input_{}", input.id))))
+}
+
+pub(crate) fn get_client_request(input: &ConfigInput, url: url::Url, custom_headers: Option<&HashMap<&str, &[u8]>>) -> Result<reqwest::RequestBuilder, String> {
+    let mut request = get_client(input)?.get(url);
+    let stripped_headers: Option<HashMap<&str, &[u8]>> = custom_headers.filter(|_| input.strip_client_headers)
+        .map(|custom| custom.iter()
+            .filter(|(key, _)| !CLIENT_HEADERS.contains(&key.to_lowercase().as_str()))
+            .map(|(key, value)| (*key, *value)).collect());
+    let mut headers = get_request_headers(&input.headers, stripped_headers.as_ref().or(custom_headers));
+    rewrite_request_headers(input, &mut headers);
     request = request.headers(headers);
-    request
+    Ok(request)
 }
 
 pub(crate) fn get_request_headers(defined_headers: &HashMap<String, String>, custom_headers: Option<&HashMap<&str, &[u8]>>) -> HeaderMap {
@@ -109,37 +280,103 @@ pub(crate) fn get_request_headers(defined_headers: &HashMap<String, String>, cus
     headers
 }
 
-async fn download_json_content(input: &ConfigInput, url: url::Url, persist_filepath: Option<PathBuf>) -> Result<serde_json::Value, String> {
+async fn download_json_content(input: &ConfigInput, working_dir: &str, url: url::Url, persist_filepath: Option<PathBuf>) -> Result<serde_json::Value, String> {
     if log_enabled!(Level::Debug) {
         debug!("downloading json content from {}", url.to_string());
     }
-    let request = get_client_request(input, url, None);
-    match request.send().await {
+    let content = download_api_text_content(input, working_dir, url).await?;
+    match serde_json::from_str::<serde_json::Value>(&content) {
+        Ok(json_content) => {
+            if persist_filepath.is_some() {
+                persist_file(persist_filepath, &content);
+            }
+            Ok(json_content)
+        }
+        Err(e) => Err(e.to_string())
+    }
+}
+
+/// Downloads the response of a `player_api` request of a client, the response is cached when the input has a `cache` option.
+/// The downloads of the processing always fetch the current content from the provider.
+pub(crate) async fn get_api_text_content(input: &ConfigInput, working_dir: &String, url: url::Url) -> Result<String, String> {
+    let url_str = url.to_string();
+    if let Some(content) = response_cache::get_cached_response(input, working_dir, &url_str) {
+        return Ok(content);
+    }
+    let content = download_api_text_content(input, working_dir, url).await?;
+    response_cache::put_cached_response(input, working_dir, &url_str, &content);
+    Ok(content)
+}
+
+async fn download_api_text_content(input: &ConfigInput, working_dir: &str, url: url::Url) -> Result<String, String> {
+    match send_with_retry(input, working_dir, url).await {
         Ok(response) => {
             if log_enabled!(Level::Debug) {
-                debug!("downloading json content response code: {}", response.status().as_str());
+                debug!("downloading content response code: {}", response.status().as_str());
             }
             if response.status().is_success() {
-                match response.json::<serde_json::Value>().await {
-                    Ok(content) => {
-                        if persist_filepath.is_some() {
-                            persist_file(persist_filepath, &serde_json::to_string(&content).unwrap());
-                        }
-                        Ok(content)
-                    }
-                    Err(e) => Err(e.to_string())
-                }
+                read_response_text(input, response).await
             } else {
                 Err(format!("Request failed: {}", response.status()))
             }
         }
-        Err(e) => Err(e.to_string())
+        Err(e) => Err(e)
+    }
+}
+
+/// Opens a local json file for reading it in chunks, the file is copied to `persist_filepath`.
+pub(crate) fn open_input_json_file(working_dir: &String, url_str: &str, persist_filepath: Option<PathBuf>) -> Result<BufReader<fs::File>, M3uFilterError> {
+    match get_local_file_path(working_dir, url_str) {
+        Some(filepath) if filepath.exists() => {
+            if let Some(to_file) = persist_filepath {
+                if let Err(e) = fs::copy(&filepath, &to_file) {
+                    error!("cant persist to: {}  => {}", to_file.to_str().unwrap_or("?"), e);
+                }
+            }
+            match open_file(&filepath) {
+                Ok(file) => Ok(BufReader::new(file)),
+                Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "Cant open file : {}  => {}", filepath.to_str().unwrap_or("?"), err)
+            }
+        }
+        _ => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "cant read input url: {:?}", url_str)
+    }
+}
+
+/// Downloads the json response into the file without parsing it, large responses are parsed from the file in chunks
+/// and are never held in memory.
+pub(crate) async fn download_input_json_file(input: &ConfigInput, working_dir: &str, url_str: &str, file_path: &Path) -> Result<(), M3uFilterError> {
+    match url_str.parse::<url::Url>() {
+        Ok(url) => match download_to_file(input, working_dir, url, file_path).await {
+            Ok(()) => Ok(()),
+            Err(e) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "cant download input url: {}  => {}", url_str, e)
+        },
+        Err(_) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "malformed input url: {}", url_str)
     }
 }
 
-pub(crate) async fn get_input_json_content(input: &ConfigInput, url_str: &str, persist_filepath: Option<PathBuf>) -> Result<serde_json::Value, M3uFilterError> {
+async fn download_to_file(input: &ConfigInput, working_dir: &str, url: url::Url, file_path: &Path) -> Result<(), String> {
+    let response = send_with_retry(input, working_dir, url).await?;
+    if !response.status().is_success() {
+        return Err(format!("Request failed: {}", response.status()));
+    }
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut writer = BufWriter::new(fs::File::create(file_path).map_err(|e| e.to_string())?);
+    read_response_chunks(input, response, |chunk| writer.write_all(chunk).map_err(|e| e.to_string())).await?;
+    writer.flush().map_err(|e| e.to_string())
+}
+
+pub(crate) async fn get_input_json_content(input: &ConfigInput, working_dir: &String, url_str: &str, persist_filepath: Option<PathBuf>) -> Result<serde_json::Value, M3uFilterError> {
+    if is_local_url(url_str) {
+        let content = get_local_file_content(working_dir, url_str, persist_filepath)?;
+        return match serde_json::from_str::<serde_json::Value>(&content) {
+            Ok(json_content) => Ok(json_content),
+            Err(e) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "cant parse input file: {}  => {}", url_str, e)
+        };
+    }
     match url_str.parse::<url::Url>() {
-        Ok(url) => match download_json_content(input, url, persist_filepath).await {
+        Ok(url) => match download_json_content(input, working_dir, url, persist_filepath).await {
             Ok(content) => Ok(content),
             Err(e) => create_m3u_filter_error_result!(M3uFilterErrorKind::Notify, "cant download input url: {}  => {}", url_str, e)
         },
@@ -147,24 +384,23 @@ pub(crate) async fn get_input_json_content(input: &ConfigInput, url_str: &str, p
     }
 }
 
-async fn download_text_content(input: &ConfigInput, url: url::Url, persist_filepath: Option<PathBuf>) -> Result<String, String> {
-    let request = get_client_request(input, url, None);
-    match request.send().await {
+async fn download_text_content(input: &ConfigInput, working_dir: &str, url: url::Url, persist_filepath: Option<PathBuf>) -> Result<String, String> {
+    match send_with_retry(input, working_dir, url).await {
         Ok(response) => {
             if response.status().is_success() {
-                match response.text_with_charset("utf8").await {
+                match read_response_text(input, response).await {
                     Ok(content) => {
                         if persist_filepath.is_some() {
                             persist_file(persist_filepath, &content);
                         }
                         Ok(content)
                     }
-                    Err(e) => Err(e.to_string())
+                    Err(e) => Err(e)
                 }
             } else {
                 Err(format!("Request failed: {}", response.status()))
             }
         }
-        Err(e) => Err(e.to_string())
+        Err(e) => Err(e)
     }
 }
\ No newline at end of file
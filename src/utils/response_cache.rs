@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use log::{debug, error};
+
+use crate::model::config::{ConfigInput, ConfigInputCache};
+use crate::utils::{file_utils, metrics, series_cache, string_utils};
+
+const CACHE_DIR: &str = "cache";
+
+struct CacheEntry {
+    expires: SystemTime,
+    content: String,
+}
+
+fn memory_cache() -> &'static Mutex<HashMap<String, CacheEntry>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, CacheEntry>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn get_cache_config(input: &ConfigInput) -> Option<&ConfigInputCache> {
+    input.options.as_ref().and_then(|o| o.cache.as_ref()).filter(|c| c.ttl > 0)
+}
+
+/// Each input has its own directory, its entries are evicted with the ttl of the input.
+fn get_cache_file_path(working_dir: &String, input: &ConfigInput, url: &str) -> Option<PathBuf> {
+    let filename = format!("{}.json", string_utils::get_stable_hash(&[url]));
+    file_utils::get_file_path(working_dir, Some(PathBuf::from(CACHE_DIR).join(series_cache::get_input_key(input)).join(filename)))
+}
+
+fn is_expired(path: &Path, ttl: u64, now: SystemTime) -> bool {
+    fs::metadata(path).and_then(|m| m.modified())
+        .map_or(true, |modified| modified + Duration::from_secs(ttl) <= now)
+}
+
+/// Removes the expired entries of the input and the files of the flat layout used before the input directories.
+fn evict_disk_entries(input_dir: &Path, cache_dir: &Path, ttl: u64, now: SystemTime) {
+    for (dir, legacy) in [(input_dir, false), (cache_dir, true)] {
+        if let Ok(entries) = fs::read_dir(dir) {
+            for path in entries.flatten().map(|entry| entry.path()).filter(|path| path.is_file()) {
+                if legacy || is_expired(&path, ttl, now) {
+                    if let Err(err) = fs::remove_file(&path) {
+                        error!("cant remove cache file {}: {}", path.to_str().unwrap_or("?"), err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the cached response for the url if caching is enabled for the input and the entry is not expired.
+pub(crate) fn get_cached_response(input: &ConfigInput, working_dir: &String, url: &str) -> Option<String> {
+    let cache_cfg = get_cache_config(input)?;
+    let result = lookup_cached_response(input, cache_cfg, working_dir, url);
+    metrics::record_cache_lookup(working_dir, result.is_some());
+    result
+}
+
+fn lookup_cached_response(input: &ConfigInput, cache_cfg: &ConfigInputCache, working_dir: &String, url: &str) -> Option<String> {
+    let now = SystemTime::now();
+    if cache_cfg.memory {
+        if let Some(entry) = memory_cache().lock().unwrap().get(url) {
+            if entry.expires > now {
+                debug!("response cache memory hit for input {}", input.id);
+                return Some(entry.content.clone());
+            }
+        }
+    }
+    if cache_cfg.disk {
+        if let Some(path) = get_cache_file_path(working_dir, input, url) {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            let expires = modified + Duration::from_secs(cache_cfg.ttl);
+            if expires > now {
+                if let Ok(content) = fs::read_to_string(&path) {
+                    debug!("response cache disk hit for input {}", input.id);
+                    if cache_cfg.memory {
+                        memory_cache().lock().unwrap().insert(url.to_string(), CacheEntry { expires, content: content.clone() });
+                    }
+                    return Some(content);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Stores the response for the url if caching is enabled for the input.
+pub(crate) fn put_cached_response(input: &ConfigInput, working_dir: &String, url: &str, content: &str) {
+    if let Some(cache_cfg) = get_cache_config(input) {
+        let now = SystemTime::now();
+        if cache_cfg.memory {
+            let mut cache = memory_cache().lock().unwrap();
+            cache.retain(|_, entry| entry.expires > now);
+            cache.insert(url.to_string(), CacheEntry { expires: now + Duration::from_secs(cache_cfg.ttl), content: content.to_string() });
+        }
+        if cache_cfg.disk {
+            if let Some(path) = get_cache_file_path(working_dir, input, url) {
+                if let Some(parent) = path.parent() {
+                    if let Err(err) = fs::create_dir_all(parent) {
+                        error!("cant create cache directory {}: {}", parent.to_str().unwrap_or("?"), err);
+                        return;
+                    }
+                    if let Some(cache_dir) = parent.parent() {
+                        evict_disk_entries(parent, cache_dir, cache_cfg.ttl, now);
+                    }
+                }
+                if let Err(err) = fs::write(&path, content) {
+                    error!("cant write cache file {}: {}", path.to_str().unwrap_or("?"), err);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce};
+use aes_gcm::aead::{Aead, AeadCore, OsRng};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+use crate::create_m3u_filter_error_result;
+use crate::m3u_filter_error::{M3uFilterError, M3uFilterErrorKind};
+use crate::utils::file_utils;
+
+const SECRETS_FILE: &str = "secrets.yml";
+const KEY_FILE: &str = "secret.key";
+/// base64 encoded key, if set the key file is not used
+const ENV_SECRET_KEY: &str = "M3U_FILTER_SECRET_KEY";
+const NONCE_SIZE: usize = 12;
+
+/// Secrets encrypted with AES-256-GCM, stored as base64 of nonce and ciphertext by name.
+pub(crate) struct SecretStore {
+    path: PathBuf,
+    key_path: PathBuf,
+    key: Key<Aes256Gcm>,
+    secrets: BTreeMap<String, String>,
+}
+
+fn decode_key(encoded: &str) -> Result<Key<Aes256Gcm>, M3uFilterError> {
+    match STANDARD.decode(encoded.trim()) {
+        Ok(bytes) if bytes.len() == 32 => Ok(*Key::<Aes256Gcm>::from_slice(&bytes)),
+        _ => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "invalid secret key, expected 32 bytes base64 encoded"),
+    }
+}
+
+fn write_private_file(path: &Path, content: &str) -> Result<(), M3uFilterError> {
+    let tmp_path = path.with_extension("tmp");
+    let result = std::fs::write(&tmp_path, content).and_then(|_| {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        std::fs::rename(&tmp_path, path)
+    });
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant write file {}: {}", path.to_str().unwrap_or("?"), err),
+    }
+}
+
+impl SecretStore {
+    fn get_paths(config_path: &str) -> (PathBuf, PathBuf) {
+        let path = PathBuf::from(config_path);
+        (path.join(SECRETS_FILE), path.join(KEY_FILE))
+    }
+
+    fn is_env_key() -> bool {
+        std::env::var(ENV_SECRET_KEY).is_ok_and(|key| !key.trim().is_empty())
+    }
+
+    /// Creates the key file, an existing key is kept.
+    pub(crate) fn init(config_path: &str) -> Result<bool, M3uFilterError> {
+        let (_, key_path) = Self::get_paths(config_path);
+        if Self::is_env_key() || key_path.exists() {
+            return Ok(false);
+        }
+        write_private_file(&key_path, &STANDARD.encode(Aes256Gcm::generate_key(OsRng)))?;
+        Ok(true)
+    }
+
+    pub(crate) fn open(config_path: &str) -> Result<SecretStore, M3uFilterError> {
+        let (path, key_path) = Self::get_paths(config_path);
+        let key = if Self::is_env_key() {
+            decode_key(&std::env::var(ENV_SECRET_KEY).unwrap_or_default())?
+        } else {
+            match std::fs::read_to_string(&key_path) {
+                Ok(content) => decode_key(&content)?,
+                Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant read secret key {}: {}", key_path.to_str().unwrap_or("?"), err),
+            }
+        };
+        let secrets = if path.exists() {
+            match file_utils::open_file(&path).map_err(|err| err.to_string())
+                .and_then(|file| serde_yaml::from_reader(file).map_err(|err| err.to_string())) {
+                Ok(secrets) => secrets,
+                Err(err) => return create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant read secrets {}: {}", path.to_str().unwrap_or("?"), err),
+            }
+        } else {
+            BTreeMap::new()
+        };
+        Ok(SecretStore { path, key_path, key, secrets })
+    }
+
+    fn encrypt(key: &Key<Aes256Gcm>, value: &str) -> Result<String, M3uFilterError> {
+        let nonce = Aes256Gcm::generate_nonce(OsRng);
+        match Aes256Gcm::new(key).encrypt(&nonce, value.as_bytes()) {
+            Ok(ciphertext) => Ok(STANDARD.encode([nonce.as_slice(), &ciphertext].concat())),
+            Err(_) => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "failed to encrypt secret"),
+        }
+    }
+
+    fn decrypt(key: &Key<Aes256Gcm>, name: &str, encrypted: &str) -> Result<String, M3uFilterError> {
+        let data = STANDARD.decode(encrypted).unwrap_or_default();
+        if data.len() > NONCE_SIZE {
+            let (nonce, ciphertext) = data.split_at(NONCE_SIZE);
+            if let Ok(plain) = Aes256Gcm::new(key).decrypt(Nonce::from_slice(nonce), ciphertext) {
+                if let Ok(value) = String::from_utf8(plain) {
+                    return Ok(value);
+                }
+            }
+        }
+        create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant decrypt secret {}, wrong key?", name)
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Result<String, M3uFilterError> {
+        match self.secrets.get(name) {
+            Some(encrypted) => Self::decrypt(&self.key, name, encrypted),
+            None => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "unknown secret: {}", name),
+        }
+    }
+
+    pub(crate) fn set(&mut self, name: &str, value: &str) -> Result<(), M3uFilterError> {
+        let encrypted = Self::encrypt(&self.key, value)?;
+        self.secrets.insert(name.to_string(), encrypted);
+        Ok(())
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) -> bool {
+        self.secrets.remove(name).is_some()
+    }
+
+    pub(crate) fn names(&self) -> Vec<&String> {
+        self.secrets.keys().collect()
+    }
+
+    /// Encrypts all secrets with a new key. The new key is written to the key file,
+    /// a key from the environment can't be changed and is returned instead.
+    pub(crate) fn rotate(&mut self) -> Result<Option<String>, M3uFilterError> {
+        let new_key = Aes256Gcm::generate_key(OsRng);
+        let mut secrets = BTreeMap::new();
+        for (name, encrypted) in &self.secrets {
+            let value = Self::decrypt(&self.key, name, encrypted)?;
+            secrets.insert(name.to_string(), Self::encrypt(&new_key, &value)?);
+        }
+        let encoded_key = STANDARD.encode(new_key);
+        let env_key = Self::is_env_key();
+        // the new key is written first, a failed rotation leaves both keys
+        let new_key_path = self.key_path.with_extension("key.new");
+        if !env_key {
+            write_private_file(&new_key_path, &encoded_key)?;
+        }
+        self.key = new_key;
+        self.secrets = secrets;
+        self.save()?;
+        if env_key {
+            return Ok(Some(encoded_key));
+        }
+        match std::fs::rename(&new_key_path, &self.key_path) {
+            Ok(_) => Ok(None),
+            Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant replace secret key with {}: {}", new_key_path.to_str().unwrap_or("?"), err),
+        }
+    }
+
+    pub(crate) fn save(&self) -> Result<(), M3uFilterError> {
+        match serde_yaml::to_string(&self.secrets) {
+            Ok(content) => write_private_file(&self.path, &content),
+            Err(err) => create_m3u_filter_error_result!(M3uFilterErrorKind::Info, "cant serialize secrets: {}", err),
+        }
+    }
+}
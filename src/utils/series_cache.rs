@@ -0,0 +1,117 @@
+use std::fs;
+use std::path::PathBuf;
+
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::model::config::ConfigInput;
+use crate::utils::{file_utils, string_utils};
+
+const SERIES_CACHE_DIR: &str = "series_cache";
+const SERIES_RESUME_DIR: &str = "resume";
+/// series info of an interrupted run is resumed only for this time
+const SERIES_RESUME_MAX_AGE_SECS: i64 = 86_400;
+
+#[derive(Serialize, Deserialize)]
+struct SeriesCacheEntry {
+    last_modified: String,
+    info: Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SeriesResumeEntry {
+    /// unix timestamp of the download
+    downloaded: i64,
+    info: Value,
+}
+
+/// The provider url and user identify the input across config changes.
+pub(crate) fn get_input_key(input: &ConfigInput) -> String {
+    string_utils::get_stable_hash(&[&input.url, input.username.as_deref().unwrap_or_default()])
+}
+
+/// Each input has its own directory.
+fn get_input_dir(input: &ConfigInput) -> PathBuf {
+    PathBuf::from(SERIES_CACHE_DIR).join(get_input_key(input))
+}
+
+fn get_series_filename(series_id: &str) -> String {
+    format!("{}.json", series_id.replace(|c: char| !c.is_ascii_alphanumeric(), "_"))
+}
+
+fn get_series_cache_path(working_dir: &String, input: &ConfigInput, series_id: &str) -> Option<PathBuf> {
+    file_utils::get_file_path(working_dir, Some(get_input_dir(input).join(get_series_filename(series_id))))
+}
+
+fn get_series_resume_path(working_dir: &String, input: &ConfigInput, series_id: &str) -> Option<PathBuf> {
+    file_utils::get_file_path(working_dir, Some(get_input_dir(input).join(SERIES_RESUME_DIR).join(get_series_filename(series_id))))
+}
+
+fn write_series_file(path: &PathBuf, content: &str) {
+    if let Some(parent) = path.parent() {
+        if let Err(err) = fs::create_dir_all(parent) {
+            error!("cant create series cache dir {}: {}", parent.to_str().unwrap_or("?"), err);
+            return;
+        }
+    }
+    if let Err(err) = fs::write(path, content) {
+        error!("cant write series cache file {}: {}", path.to_str().unwrap_or("?"), err);
+    }
+}
+
+/// Returns the cached series info if the series was not modified since it was cached.
+pub(crate) fn get_cached_series_info(working_dir: &String, input: &ConfigInput, series_id: &str, last_modified: &str) -> Option<Value> {
+    let path = get_series_cache_path(working_dir, input, series_id)?;
+    let content = fs::read_to_string(path).ok()?;
+    match serde_json::from_str::<SeriesCacheEntry>(&content) {
+        Ok(entry) if entry.last_modified == last_modified => {
+            debug!("series info cache hit for series {}", series_id);
+            Some(entry.info)
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn put_cached_series_info(working_dir: &String, input: &ConfigInput, series_id: &str, last_modified: &str, info: &Value) {
+    if let Some(path) = get_series_cache_path(working_dir, input, series_id) {
+        let entry = SeriesCacheEntry { last_modified: last_modified.to_string(), info: info.clone() };
+        match serde_json::to_string(&entry) {
+            Ok(content) => write_series_file(&path, &content),
+            Err(err) => error!("cant serialize series info {}: {}", series_id, err),
+        }
+    }
+}
+
+/// Returns the series info downloaded by a previous run which was interrupted, older downloads are not used.
+pub(crate) fn get_resumed_series_info(working_dir: &String, input: &ConfigInput, series_id: &str) -> Option<Value> {
+    let path = get_series_resume_path(working_dir, input, series_id)?;
+    let content = fs::read_to_string(path).ok()?;
+    let entry = serde_json::from_str::<SeriesResumeEntry>(&content).ok()?;
+    if chrono::Utc::now().timestamp() - entry.downloaded > SERIES_RESUME_MAX_AGE_SECS {
+        return None;
+    }
+    debug!("series info resumed for series {}", series_id);
+    Some(entry.info)
+}
+
+/// Keeps the downloaded series info until the run is completed.
+pub(crate) fn put_resumed_series_info(working_dir: &String, input: &ConfigInput, series_id: &str, info: &Value) {
+    if let Some(path) = get_series_resume_path(working_dir, input, series_id) {
+        let entry = SeriesResumeEntry { downloaded: chrono::Utc::now().timestamp(), info: info.clone() };
+        match serde_json::to_string(&entry) {
+            Ok(content) => write_series_file(&path, &content),
+            Err(err) => error!("cant serialize series info {}: {}", series_id, err),
+        }
+    }
+}
+
+pub(crate) fn clear_resumed_series_info(working_dir: &String, input: &ConfigInput) {
+    if let Some(path) = file_utils::get_file_path(working_dir, Some(get_input_dir(input).join(SERIES_RESUME_DIR))) {
+        if path.exists() {
+            if let Err(err) = fs::remove_dir_all(&path) {
+                error!("cant remove series resume dir {}: {}", path.to_str().unwrap_or("?"), err);
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+
+use actix_web::web::Bytes;
+use futures::{Stream, StreamExt};
+use log::{debug, info};
+use reqwest::header::{CONTENT_LENGTH, HeaderMap};
+
+use crate::model::config::ConfigInput;
+
+const BYTES_PER_KB: usize = 1024;
+const TS_PACKET_SIZE: usize = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+
+/// The last chunks of the upstream, `next_seq` is the sequence number of the next pushed chunk.
+struct RingBuffer {
+    chunks: VecDeque<Bytes>,
+    size: usize,
+    next_seq: u64,
+    closed: bool,
+    error: Option<String>,
+    wakers: Vec<Waker>,
+}
+
+impl RingBuffer {
+    fn oldest_seq(&self) -> u64 {
+        self.next_seq - self.chunks.len() as u64
+    }
+}
+
+/// One upstream connection of a live channel which is relayed to all clients watching it.
+struct SharedStream {
+    headers: HeaderMap,
+    buffer_size: usize,
+    buffer: Mutex<RingBuffer>,
+    /// changed with the lock of the registry held
+    clients: Mutex<usize>,
+}
+
+impl SharedStream {
+    fn push(&self, chunk: Bytes) {
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.size += chunk.len();
+        buffer.chunks.push_back(chunk);
+        buffer.next_seq += 1;
+        // the newest chunk is kept even if it is larger than the buffer
+        while buffer.size > self.buffer_size && buffer.chunks.len() > 1 {
+            if let Some(chunk) = buffer.chunks.pop_front() {
+                buffer.size -= chunk.len();
+            }
+        }
+        buffer.wakers.drain(..).for_each(Waker::wake);
+    }
+
+    fn close(&self, error: Option<String>) {
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.closed = true;
+        buffer.error = error;
+        buffer.wakers.drain(..).for_each(Waker::wake);
+    }
+
+    fn subscribe(self: &Arc<Self>) -> SharedStreamClient {
+        *self.clients.lock().unwrap() += 1;
+        // a new client starts with the buffered chunks, the player can start without waiting for the upstream
+        let next = self.buffer.lock().unwrap().oldest_seq();
+        SharedStreamClient { shared: Arc::clone(self), next, aligned: false, done: false }
+    }
+}
+
+/// The offset of the first transport stream packet in the chunk, a packet starts with the sync byte
+/// and is followed by the next one. A chunk without a packet start is passed on from its beginning.
+fn get_packet_offset(chunk: &[u8]) -> usize {
+    (0..chunk.len().min(TS_PACKET_SIZE))
+        .find(|idx| chunk[*idx] == TS_SYNC_BYTE && chunk.get(idx + TS_PACKET_SIZE).is_none_or(|byte| *byte == TS_SYNC_BYTE))
+        .unwrap_or(0)
+}
+
+/// A client of a shared stream. A client which is slower than the upstream skips the chunks
+/// which were already dropped from the buffer.
+/// After joining or skipping, the client starts with a whole transport stream packet, the chunks are cut anywhere.
+pub(crate) struct SharedStreamClient {
+    shared: Arc<SharedStream>,
+    next: u64,
+    aligned: bool,
+    done: bool,
+}
+
+impl SharedStreamClient {
+    pub(crate) fn get_headers(&self) -> &HeaderMap {
+        &self.shared.headers
+    }
+}
+
+impl Stream for SharedStreamClient {
+    type Item = Result<Bytes, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        let mut buffer = this.shared.buffer.lock().unwrap();
+        let oldest = buffer.oldest_seq();
+        if this.next < oldest {
+            debug!("Shared stream client skipped {} chunks", oldest - this.next);
+            this.next = oldest;
+            this.aligned = false;
+        }
+        if this.next < buffer.next_seq {
+            let mut chunk = buffer.chunks[(this.next - oldest) as usize].clone();
+            this.next += 1;
+            if !this.aligned {
+                this.aligned = true;
+                chunk = chunk.slice(get_packet_offset(&chunk)..);
+            }
+            return Poll::Ready(Some(Ok(chunk)));
+        }
+        if buffer.closed {
+            this.done = true;
+            return Poll::Ready(buffer.error.as_ref().map(|err| Err(io::Error::other(err.to_string()))));
+        }
+        buffer.wakers.push(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for SharedStreamClient {
+    fn drop(&mut self) {
+        let _registry = streams().lock().unwrap();
+        let mut clients = self.shared.clients.lock().unwrap();
+        *clients = clients.saturating_sub(1);
+    }
+}
+
+/// The shared streams by provider url.
+fn streams() -> &'static Mutex<HashMap<String, Arc<SharedStream>>> {
+    static STREAMS: OnceLock<Mutex<HashMap<String, Arc<SharedStream>>>> = OnceLock::new();
+    STREAMS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Returns the buffer size in bytes if the input has `shared_stream` in its options.
+pub(crate) fn get_buffer_size(input: &ConfigInput) -> Option<usize> {
+    input.options.as_ref().and_then(|options| options.shared_stream.as_ref())
+        .map(|shared_stream| (shared_stream.buffer_size as usize).saturating_mul(BYTES_PER_KB).max(BYTES_PER_KB))
+}
+
+/// Joins the running upstream connection of the url.
+pub(crate) fn subscribe(url: &str) -> Option<SharedStreamClient> {
+    let registry = streams().lock().unwrap();
+    registry.get(url).map(SharedStream::subscribe)
+}
+
+/// Removes the stream from the registry, if it has no clients or is forced to.
+fn unregister(url: &str, shared: &Arc<SharedStream>, force: bool) -> bool {
+    let mut registry = streams().lock().unwrap();
+    if !force && *shared.clients.lock().unwrap() > 0 {
+        return false;
+    }
+    if registry.get(url).is_some_and(|registered| Arc::ptr_eq(registered, shared)) {
+        registry.remove(url);
+    }
+    true
+}
+
+/// Relays the upstream to all clients of the url. The upstream is read until it ends
+/// or the last client disconnected. If another client opened the url in the meantime,
+/// its upstream is joined and the given one is dropped.
+pub(crate) fn share<S>(url: &str, buffer_size: usize, mut headers: HeaderMap, upstream: S) -> SharedStreamClient
+    where S: Stream<Item=reqwest::Result<Bytes>> + 'static {
+    // clients join at any position of the stream
+    headers.remove(CONTENT_LENGTH);
+    let (client, shared) = {
+        let mut registry = streams().lock().unwrap();
+        if let Some(shared) = registry.get(url) {
+            return shared.subscribe();
+        }
+        let shared = Arc::new(SharedStream {
+            headers,
+            buffer_size,
+            buffer: Mutex::new(RingBuffer { chunks: VecDeque::new(), size: 0, next_seq: 0, closed: false, error: None, wakers: vec![] }),
+            clients: Mutex::new(0),
+        });
+        registry.insert(url.to_string(), Arc::clone(&shared));
+        (shared.subscribe(), shared)
+    };
+    let url = url.to_string();
+    actix_rt::spawn(async move {
+        info!("Opened shared stream");
+        let mut upstream = Box::pin(upstream);
+        let mut error = None;
+        while let Some(chunk) = upstream.next().await {
+            match chunk {
+                Ok(bytes) => shared.push(bytes),
+                Err(err) => {
+                    error = Some(err.without_url().to_string());
+                    break;
+                }
+            }
+            if unregister(&url, &shared, false) {
+                break;
+            }
+        }
+        unregister(&url, &shared, true);
+        shared.close(error);
+        info!("Closed shared stream");
+    });
+    client
+}
@@ -0,0 +1,66 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use futures::{future, Stream, StreamExt};
+use log::{debug, info};
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn is_shutting_down() -> bool {
+    SHUTDOWN.load(Ordering::Relaxed)
+}
+
+pub(crate) fn begin_shutdown() {
+    SHUTDOWN.store(true, Ordering::Relaxed);
+}
+
+/// Ends a proxied stream with the next chunk after the shutdown started,
+/// the client gets a complete response instead of a dropped connection.
+pub(crate) fn until_shutdown<S: Stream>(stream: S) -> impl Stream<Item=S::Item> {
+    stream.take_while(|_| future::ready(!is_shutting_down()))
+}
+
+/// Waits for SIGTERM or Ctrl-C.
+pub(crate) async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use actix_rt::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                future::select(Box::pin(sigterm.recv()), Box::pin(actix_rt::signal::ctrl_c())).await;
+            }
+            Err(_) => { let _ = actix_rt::signal::ctrl_c().await; }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = actix_rt::signal::ctrl_c().await;
+    }
+    info!("Shutdown requested");
+}
+
+/// Sends a state like `READY=1` to systemd, if the service is started with `Type=notify`.
+pub(crate) fn notify_systemd(state: &str) {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::{SocketAddr, UnixDatagram};
+        let socket_path = match std::env::var("NOTIFY_SOCKET") {
+            Ok(path) if !path.is_empty() => path,
+            _ => return,
+        };
+        // a leading @ is a socket in the abstract namespace
+        let address = match socket_path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name.as_bytes()),
+            None => SocketAddr::from_pathname(&socket_path),
+        };
+        let result = address.and_then(|address| UnixDatagram::unbound()
+            .and_then(|socket| socket.send_to_addr(state.as_bytes(), &address)));
+        if let Err(err) = result {
+            debug!("failed to notify systemd {}: {}", &socket_path, err);
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = state;
+    }
+}
@@ -0,0 +1,135 @@
+use std::pin::Pin;
+use std::time::Duration;
+
+use actix_web::web::Bytes;
+use futures::{Stream, StreamExt};
+use log::{debug, info};
+use reqwest::header::{HeaderMap, HeaderValue, RANGE};
+use reqwest::StatusCode;
+
+use crate::model::config::{ConfigInput, ConfigStreamReconnect};
+use crate::utils::shutdown;
+
+type ByteStream = Pin<Box<dyn Stream<Item=reqwest::Result<Bytes>>>>;
+
+struct ReconnectState {
+    stream: ByteStream,
+    request: Option<reqwest::RequestBuilder>,
+    reconnect: Option<ConfigStreamReconnect>,
+    live: bool,
+    /// first and last byte of the requested range, vod streams are resumed behind the received bytes
+    range: (u64, Option<u64>),
+    received: u64,
+    attempts: u8,
+    done: bool,
+}
+
+/// Parses a `Range` header like `bytes=100-` or `bytes=100-199`, suffix ranges can't be resumed.
+pub(crate) fn parse_range(range: &str) -> Option<(u64, Option<u64>)> {
+    let (start, end) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let start = start.trim().parse::<u64>().ok()?;
+    let end = match end.trim() {
+        "" => None,
+        end => Some(end.parse::<u64>().ok()?),
+    };
+    Some((start, end))
+}
+
+/// The delay before the next attempt, doubled after each failed attempt up to `max_backoff`.
+pub(crate) fn get_delay(reconnect: &ConfigStreamReconnect, attempts: u8) -> Duration {
+    let backoff = reconnect.backoff.saturating_mul(1u64 << u32::from(attempts.min(16)));
+    Duration::from_millis(backoff.min(reconnect.max_backoff))
+}
+
+impl ReconnectState {
+    fn get_request(&self) -> Option<reqwest::RequestBuilder> {
+        let request = self.request.as_ref()?.try_clone()?;
+        if self.live {
+            return Some(request);
+        }
+        let start = self.range.0 + self.received;
+        let range = match self.range.1 {
+            Some(end) => format!("bytes={}-{}", start, end),
+            None => format!("bytes={}-", start),
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(RANGE, HeaderValue::from_str(&range).ok()?);
+        // replaces the range of the client
+        Some(request.headers(headers))
+    }
+
+    /// Live streams are reopened, vod streams only continue with a partial response.
+    async fn reopen(&mut self) -> Option<ByteStream> {
+        let reconnect = self.reconnect.clone()?;
+        while self.attempts < reconnect.max_attempts && !shutdown::is_shutting_down() {
+            let delay = get_delay(&reconnect, self.attempts);
+            self.attempts += 1;
+            info!("Stream dropped by provider, reconnect {}/{} in {}ms", self.attempts, reconnect.max_attempts, delay.as_millis());
+            actix_rt::time::sleep(delay).await;
+            let request = self.get_request()?;
+            match request.send().await {
+                Ok(response) if (self.live && response.status().is_success()) || response.status() == StatusCode::PARTIAL_CONTENT => {
+                    return Some(Box::pin(response.bytes_stream()));
+                }
+                Ok(response) => debug!("Failed to reconnect stream got status {}", response.status()),
+                Err(err) => debug!("Failed to reconnect stream {}", err.without_url()),
+            }
+        }
+        None
+    }
+}
+
+/// Returns the body of the provider response. With `stream_reconnect` in the input options
+/// the stream is reopened when the connection drops, live streams also when the provider ends them.
+/// After the last failed attempt the error is passed on and the client connection is closed.
+pub(crate) fn get_stream(input: &ConfigInput, request: Option<reqwest::RequestBuilder>, response: reqwest::Response,
+                         live: bool, client_range: Option<&str>) -> impl Stream<Item=reqwest::Result<Bytes>> {
+    let mut reconnect = input.options.as_ref().and_then(|options| options.stream_reconnect.clone());
+    let range = if response.status() == StatusCode::PARTIAL_CONTENT {
+        match client_range.and_then(parse_range) {
+            Some(range) => range,
+            None => {
+                if !live {
+                    reconnect = None;
+                }
+                (0, None)
+            }
+        }
+    } else {
+        (0, None)
+    };
+    let state = ReconnectState {
+        stream: Box::pin(response.bytes_stream()),
+        request,
+        reconnect,
+        live,
+        range,
+        received: 0,
+        attempts: 0,
+        done: false,
+    };
+    futures::stream::unfold(state, |mut state| async move {
+        if state.done {
+            return None;
+        }
+        loop {
+            let error = match state.stream.next().await {
+                Some(Ok(bytes)) => {
+                    state.received += bytes.len() as u64;
+                    state.attempts = 0;
+                    return Some((Ok(bytes), state));
+                }
+                Some(Err(err)) => Some(err),
+                None if state.live => None,
+                None => return None,
+            };
+            match state.reopen().await {
+                Some(stream) => state.stream = stream,
+                None => {
+                    state.done = true;
+                    return error.map(|err| (Err(err), state));
+                }
+            }
+        }
+    })
+}
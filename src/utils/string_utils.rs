@@ -1,3 +1,11 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use openssl::sha::Sha256;
+
 // other implementations like calculating text_distance on all titles took too much time
 // we keep it now as simple as possible and less memory intensive.
 pub (crate) fn get_title_group(text: &str) -> String {
@@ -12,3 +20,80 @@ pub (crate) fn get_title_group(text: &str) -> String {
     }
     text.to_string()
 }
+
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        number.push(c);
+    }
+    number
+}
+
+/// Compares the numbers inside the strings by their value, `Channel 2` is before `Channel 10`.
+/// Other characters are compared like a plain sort, so the case matters.
+pub(crate) fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut chars_a = a.chars().peekable();
+    let mut chars_b = b.chars().peekable();
+    // leading zeros decide only between otherwise equal strings, `1a` is before `01b`
+    let mut zeros_ordering = Ordering::Equal;
+    loop {
+        let ordering = match (chars_a.peek(), chars_b.peek()) {
+            (None, None) => return zeros_ordering,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(char_a), Some(char_b)) if char_a.is_ascii_digit() && char_b.is_ascii_digit() => {
+                let number_a = take_number(&mut chars_a);
+                let number_b = take_number(&mut chars_b);
+                let digits_a = number_a.trim_start_matches('0');
+                let digits_b = number_b.trim_start_matches('0');
+                if zeros_ordering == Ordering::Equal {
+                    zeros_ordering = number_a.len().cmp(&number_b.len());
+                }
+                // the longer number is the greater one
+                digits_a.len().cmp(&digits_b.len()).then_with(|| digits_a.cmp(digits_b))
+            }
+            (Some(char_a), Some(char_b)) => {
+                let ordering = char_a.cmp(char_b);
+                chars_a.next();
+                chars_b.next();
+                ordering
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn interned() -> &'static Mutex<HashSet<Arc<str>>> {
+    static INTERNED: OnceLock<Mutex<HashSet<Arc<str>>>> = OnceLock::new();
+    INTERNED.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+/// Group and category names repeat for every channel of the group, all channels share one string.
+pub(crate) fn intern(text: &str) -> Arc<str> {
+    let mut strings = interned().lock().unwrap();
+    match strings.get(text) {
+        Some(value) => Arc::clone(value),
+        None => {
+            let value: Arc<str> = Arc::from(text);
+            strings.insert(Arc::clone(&value));
+            value
+        }
+    }
+}
+
+/// Returns a hex digest of the values, unlike `DefaultHasher` it is the same for every build and can be persisted.
+pub(crate) fn get_stable_hash(values: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for value in values {
+        hasher.update(value.as_bytes());
+        hasher.update(&[0]);
+    }
+    hasher.finish()[..16].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Drops the strings which are no longer used by any playlist.
+pub(crate) fn release_interned() {
+    interned().lock().unwrap().retain(|value| Arc::strong_count(value) > 1);
+}
@@ -0,0 +1,27 @@
+use std::time::{Duration, Instant};
+
+use futures::{Stream, StreamExt};
+
+/// Limits the transfer rate of a proxied stream to `max_bitrate_kbps` kilobits per second, `None` doesn't limit it.
+/// A chunk is passed on when the average rate since the start is below the limit.
+pub(crate) fn throttle<S, T, E>(stream: S, max_bitrate_kbps: Option<u32>) -> impl Stream<Item=Result<T, E>>
+    where S: Stream<Item=Result<T, E>>,
+          T: AsRef<[u8]> {
+    let bytes_per_second = max_bitrate_kbps.filter(|kbps| *kbps > 0).map(|kbps| (u64::from(kbps) * 1000 / 8).max(1));
+    let start = Instant::now();
+    let mut transferred: u64 = 0;
+    stream.then(move |chunk| {
+        let delay = bytes_per_second.and_then(|bytes_per_second| {
+            if let Ok(bytes) = &chunk {
+                transferred += bytes.as_ref().len() as u64;
+            }
+            Duration::from_millis(transferred.saturating_mul(1000) / bytes_per_second).checked_sub(start.elapsed())
+        });
+        async move {
+            if let Some(delay) = delay {
+                actix_rt::time::sleep(delay).await;
+            }
+            chunk
+        }
+    })
+}
@@ -0,0 +1,72 @@
+use std::net::IpAddr;
+use std::path::Path;
+
+use log::info;
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::ssl::{SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod};
+use openssl::x509::{X509, X509NameBuilder};
+use openssl::x509::extension::SubjectAlternativeName;
+
+use crate::model::config::ConfigApiTls;
+
+fn to_io_error(err: ErrorStack) -> std::io::Error {
+    std::io::Error::other(err.to_string())
+}
+
+/// Certificate for `localhost` and the api host, valid for 10 years.
+fn create_self_signed_certificate(host: &str) -> Result<(Vec<u8>, Vec<u8>), ErrorStack> {
+    let key = PKey::from_rsa(Rsa::generate(2048)?)?;
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, "m3u-filter")?;
+    let name = name.build();
+
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+    let serial = serial.to_asn1_integer()?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    let not_after = Asn1Time::days_from_now(3650)?;
+    let mut builder = X509::builder()?;
+    builder.set_version(2)?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(&name)?;
+    builder.set_issuer_name(&name)?;
+    builder.set_pubkey(&key)?;
+    builder.set_not_before(&not_before)?;
+    builder.set_not_after(&not_after)?;
+
+    let mut alt_names = SubjectAlternativeName::new();
+    alt_names.dns("localhost");
+    match host.parse::<IpAddr>() {
+        Ok(ip) if !ip.is_unspecified() => { alt_names.ip(host); }
+        Ok(_) => {}
+        Err(_) if host != "localhost" => { alt_names.dns(host); }
+        Err(_) => {}
+    }
+    let alt_names = alt_names.build(&builder.x509v3_context(None, None))?;
+    builder.append_extension(alt_names)?;
+    builder.sign(&key, MessageDigest::sha256())?;
+    Ok((builder.build().to_pem()?, key.private_key_to_pem_pkcs8()?))
+}
+
+/// Loads the certificate and key, with `self_signed` they are created first if they don't exist.
+pub(crate) fn create_ssl_acceptor(tls: &ConfigApiTls, host: &str) -> std::io::Result<SslAcceptorBuilder> {
+    if tls.self_signed && !(Path::new(&tls.cert).exists() && Path::new(&tls.key).exists()) {
+        let (cert, key) = create_self_signed_certificate(host).map_err(to_io_error)?;
+        std::fs::write(&tls.cert, cert)?;
+        std::fs::write(&tls.key, key)?;
+        info!("Created self signed certificate {}", &tls.cert);
+    }
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls()).map_err(to_io_error)?;
+    builder.set_private_key_file(&tls.key, SslFiletype::PEM)
+        .map_err(|err| std::io::Error::other(format!("cant load tls key {}: {}", &tls.key, err)))?;
+    builder.set_certificate_chain_file(&tls.cert)
+        .map_err(|err| std::io::Error::other(format!("cant load tls certificate {}: {}", &tls.cert, err)))?;
+    builder.check_private_key().map_err(to_io_error)?;
+    Ok(builder)
+}
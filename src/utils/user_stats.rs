@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::file_utils;
+
+const USER_STATS_FILE: &str = "user_stats.json";
+/// time between writes of the stats file
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, utoipa::ToSchema)]
+pub(crate) struct UserStats {
+    pub requests: u64,
+    pub bytes_streamed: u64,
+    pub last_seen: i64,
+}
+
+struct UserStatsStore {
+    file_path: Option<PathBuf>,
+    users: HashMap<String, UserStats>,
+    /// the stats have changed since the last write
+    changed: bool,
+}
+
+/// The stats are stored in the working dir, tenants have their own.
+fn stores() -> &'static Mutex<HashMap<String, UserStatsStore>> {
+    static STORES: OnceLock<Mutex<HashMap<String, UserStatsStore>>> = OnceLock::new();
+    STORES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn load_store(working_dir: &String) -> UserStatsStore {
+    let file_path = file_utils::get_file_path(working_dir, Some(PathBuf::from(USER_STATS_FILE)));
+    let users = file_path.as_ref()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str::<HashMap<String, UserStats>>(&content).ok())
+        .unwrap_or_default();
+    UserStatsStore { file_path, users, changed: false }
+}
+
+/// A partially written file would lose the stats, the file is replaced by the rename.
+fn save_stats(path: &Path, content: &str) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, content)?;
+    std::fs::rename(&tmp_path, path)
+}
+
+fn update_user<F>(working_dir: &String, username: &str, update: F)
+    where F: FnOnce(&mut UserStats) {
+    let mut guard = stores().lock().unwrap();
+    let store = guard.entry(working_dir.to_string()).or_insert_with(|| load_store(working_dir));
+    let stats = store.users.entry(username.to_string()).or_default();
+    update(stats);
+    stats.last_seen = chrono::Local::now().timestamp();
+    store.changed = true;
+}
+
+/// Counts an authenticated api request of the user.
+pub(crate) fn record_request(working_dir: &String, username: &str) {
+    update_user(working_dir, username, |stats| stats.requests += 1);
+}
+
+pub(crate) fn record_bytes(working_dir: &String, username: &str, bytes: u64) {
+    update_user(working_dir, username, |stats| stats.bytes_streamed += bytes);
+}
+
+/// bytes of a stream which are collected before they are added to the stats
+const STREAMED_BYTES_STEP: u64 = 1024 * 1024;
+
+/// Counts the bytes of a stream, they are added to the stats of the user in steps and when the stream ends.
+pub(crate) struct StreamedBytes {
+    working_dir: String,
+    username: String,
+    pending: u64,
+}
+
+impl StreamedBytes {
+    pub(crate) fn new(working_dir: String, username: String) -> Self {
+        StreamedBytes { working_dir, username, pending: 0 }
+    }
+
+    pub(crate) fn add(&mut self, bytes: u64) {
+        self.pending += bytes;
+        if self.pending >= STREAMED_BYTES_STEP {
+            record_bytes(&self.working_dir, &self.username, self.pending);
+            self.pending = 0;
+        }
+    }
+}
+
+impl Drop for StreamedBytes {
+    fn drop(&mut self) {
+        if self.pending > 0 {
+            record_bytes(&self.working_dir, &self.username, self.pending);
+        }
+    }
+}
+
+/// Writes the changed stats, the files are written without holding the lock.
+pub(crate) fn flush() {
+    let changed: Vec<(PathBuf, String)> = stores().lock().unwrap().values_mut()
+        .filter(|store| store.changed)
+        .filter_map(|store| {
+            store.changed = false;
+            let path = store.file_path.clone()?;
+            match serde_json::to_string(&store.users) {
+                Ok(content) => Some((path, content)),
+                Err(err) => {
+                    error!("cant serialize user stats: {}", err);
+                    None
+                }
+            }
+        }).collect();
+    for (path, content) in changed {
+        if let Err(err) = save_stats(&path, &content) {
+            error!("cant write user stats {}: {}", path.to_str().unwrap_or("?"), err);
+        }
+    }
+}
+
+/// Writes the changed stats every `SAVE_INTERVAL`, requests and streams only update the stats in memory.
+pub(crate) fn start_flush_task() {
+    actix_rt::spawn(async {
+        loop {
+            actix_rt::time::sleep(SAVE_INTERVAL).await;
+            let _ = actix_rt::task::spawn_blocking(flush).await;
+        }
+    });
+}
+
+pub(crate) fn get_user_stats(working_dir: &String, username: &str) -> UserStats {
+    let mut guard = stores().lock().unwrap();
+    let store = guard.entry(working_dir.to_string()).or_insert_with(|| load_store(working_dir));
+    store.users.get(username).cloned().unwrap_or_default()
+}
@@ -0,0 +1,148 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::model::config::{VideoDownloadConfig, VideoPostProcessingConfig};
+use crate::model::model_config::RemuxContainer;
+
+/// Returns the title of the file stem, the episode suffix matched by `episode_pattern` is removed.
+pub(crate) fn get_download_title(download_cfg: &VideoDownloadConfig, filestem: &str) -> String {
+    let mut file_stem = filestem;
+    if let Some(re) = &download_cfg._re_episode_pattern {
+        if let Some(captures) = re.captures(file_stem) {
+            if let Some(episode) = captures.name("episode") {
+                if !episode.as_str().is_empty() {
+                    file_stem = &file_stem[..episode.start()];
+                }
+            }
+        }
+    }
+    let re_ending = download_cfg._re_remove_filename_ending.as_ref().unwrap();
+    re_ending.replace(file_stem, "").to_string()
+}
+
+/// Copies the streams into the new container, the original file is removed on success.
+/// Without ffmpeg the file is kept as it is, ffmpeg is killed after `timeout` seconds.
+async fn remux(post_processing: &VideoPostProcessingConfig, file_path: &Path, container: &RemuxContainer) -> Result<PathBuf, String> {
+    if file_path.extension().and_then(OsStr::to_str).is_some_and(|ext| ext.eq_ignore_ascii_case(container.extension())) {
+        return Ok(file_path.to_path_buf());
+    }
+    let output_path = file_path.with_extension(container.extension());
+    let child = tokio::process::Command::new(&post_processing.ffmpeg)
+        .args([OsStr::new("-v"), OsStr::new("error"), OsStr::new("-y"), OsStr::new("-i"), file_path.as_os_str(),
+            OsStr::new("-map"), OsStr::new("0"), OsStr::new("-c"), OsStr::new("copy"), output_path.as_os_str()])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+    let child = match child {
+        Ok(child) => child,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            warn!("ffmpeg not found, {} is not remuxed", file_path.to_str().unwrap_or("?"));
+            return Ok(file_path.to_path_buf());
+        }
+        Err(err) => return Err(format!("failed to execute ffmpeg: {}", err)),
+    };
+    // the child is killed when the timeout drops it
+    match tokio::time::timeout(Duration::from_secs(u64::from(post_processing.timeout)), child.wait_with_output()).await {
+        Ok(Ok(output)) if output.status.success() => {
+            info!("Remuxed {}", output_path.to_str().unwrap_or("?"));
+            fs::remove_file(file_path).map_err(|err| format!("failed to remove {}: {}", file_path.to_str().unwrap_or("?"), err))?;
+            Ok(output_path)
+        }
+        Ok(Ok(output)) => {
+            let _ = fs::remove_file(&output_path);
+            Err(format!("ffmpeg failed: {}", String::from_utf8_lossy(&output.stderr).trim()))
+        }
+        Ok(Err(err)) => {
+            let _ = fs::remove_file(&output_path);
+            Err(format!("failed to execute ffmpeg: {}", err))
+        }
+        Err(_) => {
+            let _ = fs::remove_file(&output_path);
+            Err(format!("ffmpeg was killed after {} seconds", post_processing.timeout))
+        }
+    }
+}
+
+/// Replaces `{title}`, `{filename}` and the named groups of `episode_pattern` like `{episode}` in the template.
+/// Empty path segments are removed, the extension of the file is appended.
+fn get_renamed_path(download_cfg: &VideoDownloadConfig, template: &str, file_path: &Path) -> PathBuf {
+    let file_stem = file_path.file_stem().and_then(OsStr::to_str).unwrap_or_default();
+    let mut path = template.replace("{title}", &get_download_title(download_cfg, file_stem))
+        .replace("{filename}", file_stem);
+    if let Some(re) = &download_cfg._re_episode_pattern {
+        let captures = re.captures(file_stem);
+        for name in re.capture_names().flatten() {
+            let value = captures.as_ref().and_then(|caps| caps.name(name)).map_or("", |value| value.as_str());
+            path = path.replace(&format!("{{{}}}", name), value);
+        }
+    }
+    let mut renamed: PathBuf = path.split('/').map(str::trim).filter(|segment| !segment.is_empty() && *segment != "..").collect();
+    if let Some(ext) = file_path.extension() {
+        let mut file_name = renamed.file_name().map(OsStr::to_os_string).unwrap_or_default();
+        file_name.push(".");
+        file_name.push(ext);
+        renamed.set_file_name(file_name);
+    }
+    renamed
+}
+
+/// Appends a number to the file name while the file exists, like the downloads do.
+fn get_free_path(path: PathBuf) -> PathBuf {
+    let file_stem = path.file_stem().and_then(OsStr::to_str).unwrap_or_default().to_string();
+    let file_ext = path.extension().and_then(OsStr::to_str).map(|ext| format!(".{}", ext)).unwrap_or_default();
+    let mut free_path = path.clone();
+    let mut x: usize = 1;
+    while free_path.exists() {
+        free_path.set_file_name(format!("{}_{}{}", file_stem, x, file_ext));
+        x += 1;
+    }
+    free_path
+}
+
+/// Renames the file or copies it if the target is on another file system.
+fn move_file(source: &Path, target: &Path) -> Result<(), String> {
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir).map_err(|err| format!("failed to create directory {}: {}", dir.to_str().unwrap_or("?"), err))?;
+    }
+    if fs::rename(source, target).is_ok() {
+        return Ok(());
+    }
+    fs::copy(source, target)
+        .and_then(|_| fs::remove_file(source))
+        .map_err(|err| format!("failed to move {} to {}: {}", source.to_str().unwrap_or("?"), target.to_str().unwrap_or("?"), err))
+}
+
+/// Remuxes, renames and moves the downloaded file, returns the final path.
+pub(crate) async fn post_process(download_cfg: &VideoDownloadConfig, post_processing: &VideoPostProcessingConfig, file_path: &Path) -> Result<PathBuf, String> {
+    let mut path = match &post_processing.remux {
+        Some(container) => remux(post_processing, file_path, container).await?,
+        None => file_path.to_path_buf(),
+    };
+    let download_dir = PathBuf::from(download_cfg.directory.as_deref().unwrap_or_default());
+    let library_dir = post_processing.library_dir.as_ref().map_or_else(|| download_dir.clone(), PathBuf::from);
+    let relative_path = match &post_processing.rename {
+        Some(template) => get_renamed_path(download_cfg, template, &path),
+        // without a template the directories of `organize_into_directories` are kept
+        None => path.strip_prefix(&download_dir).map_or_else(|_| PathBuf::from(path.file_name().unwrap_or_default()), Path::to_path_buf),
+    };
+    let target_path = library_dir.join(relative_path);
+    if target_path != path {
+        let target_path = get_free_path(target_path);
+        move_file(&path, &target_path)?;
+        info!("Moved download to {}", target_path.to_str().unwrap_or("?"));
+        // the directory of `organize_into_directories` is removed if it is empty now
+        if let Some(dir) = path.parent().filter(|dir| *dir != download_dir) {
+            let _ = fs::remove_dir(dir);
+        }
+        path = target_path;
+    }
+    Ok(path)
+}
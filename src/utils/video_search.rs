@@ -0,0 +1,137 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::model::config::{VideoConfig, VideoSearchConfig};
+use crate::model::model_config::VideoSearchProvider;
+use crate::utils::video_postprocess;
+
+const TMDB_SEARCH_URL: &str = "https://api.themoviedb.org/3/search/multi?query={}";
+pub(crate) const TMDB_POSTER_URL: &str = "https://image.tmdb.org/t/p/w342";
+const IMDB_SEARCH_URL: &str = "https://v3.sg.media-imdb.com/suggestion/x/{}.json";
+const MAX_RESULTS: usize = 20;
+
+/// A search result of any provider.
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub(crate) struct VideoSearchResult {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poster: Option<String>,
+    /// page of the title at the provider
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+fn year_suffix_regex() -> &'static regex::Regex {
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    RE.get_or_init(|| regex::Regex::new(r"^(.*?)\s*[(\[](\d{4})[)\]]\s*$").unwrap())
+}
+
+/// Splits a year suffix like `(2020)` from the title.
+pub(crate) fn split_year_suffix(title: &str) -> (String, Option<u16>) {
+    match year_suffix_regex().captures(title.trim()) {
+        Some(captures) => (captures[1].to_string(), captures[2].parse::<u16>().ok()),
+        None => (title.trim().to_string(), None),
+    }
+}
+
+/// Removes the episode suffix and a year suffix from the title, the year is returned separately.
+pub(crate) fn get_search_query(video_cfg: &VideoConfig, title: &str) -> (String, Option<u16>) {
+    match &video_cfg.download {
+        Some(download_cfg) => split_year_suffix(&video_postprocess::get_download_title(download_cfg, title.trim())),
+        None => split_year_suffix(title),
+    }
+}
+
+/// Providers send numbers as string or number.
+fn get_text(item: &Value, fields: &[&str]) -> Option<String> {
+    fields.iter().find_map(|field| match item.get(field)? {
+        Value::String(value) if !value.trim().is_empty() => Some(value.trim().to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    })
+}
+
+/// Takes the year of values like `2020` or `2020-05-01`.
+fn get_year(item: &Value, fields: &[&str]) -> Option<u16> {
+    get_text(item, fields).and_then(|value| value.get(..4).and_then(|year| year.parse::<u16>().ok()))
+}
+
+fn get_search_url(search_cfg: &VideoSearchConfig, query: &str) -> Result<url::Url, String> {
+    let template = search_cfg.url.as_deref().unwrap_or(match search_cfg.provider {
+        VideoSearchProvider::Tmdb => TMDB_SEARCH_URL,
+        VideoSearchProvider::Imdb | VideoSearchProvider::Custom => IMDB_SEARCH_URL,
+    });
+    // spaces are encoded as `%20`, the imdb query is part of the path
+    let encoded = url::form_urlencoded::byte_serialize(query.as_bytes()).collect::<String>().replace('+', "%20");
+    let mut url = url::Url::parse(&template.replace("{}", &encoded)).map_err(|_| format!("malformed search url {}", template))?;
+    if search_cfg.provider == VideoSearchProvider::Tmdb {
+        let mut pairs = url.query_pairs_mut();
+        pairs.append_pair("api_key", search_cfg.api_key.as_deref().unwrap_or_default());
+        if let Some(language) = &search_cfg.language {
+            pairs.append_pair("language", language);
+        }
+    }
+    Ok(url)
+}
+
+fn parse_tmdb(content: &Value) -> Vec<VideoSearchResult> {
+    content.get("results").and_then(Value::as_array).map_or(vec![], |items| items.iter()
+        .filter(|item| item.get("media_type").and_then(Value::as_str) != Some("person"))
+        .filter_map(|item| Some(VideoSearchResult {
+            title: get_text(item, &["title", "name"])?,
+            year: get_year(item, &["release_date", "first_air_date"]),
+            poster: get_text(item, &["poster_path"]).map(|path| format!("{}{}", TMDB_POSTER_URL, path)),
+            url: item.get("media_type").and_then(Value::as_str).zip(get_text(item, &["id"]))
+                .map(|(media_type, id)| format!("https://www.themoviedb.org/{}/{}", media_type, id)),
+        })).collect())
+}
+
+fn parse_imdb(content: &Value) -> Vec<VideoSearchResult> {
+    content.get("d").and_then(Value::as_array).map_or(vec![], |items| items.iter()
+        // names of persons have `nm` ids
+        .filter(|item| get_text(item, &["id"]).is_some_and(|id| id.starts_with("tt")))
+        .filter_map(|item| Some(VideoSearchResult {
+            title: get_text(item, &["l"])?,
+            year: get_year(item, &["y"]),
+            poster: item.get("i").and_then(|image| get_text(image, &["imageUrl"])),
+            url: get_text(item, &["id"]).map(|id| format!("https://www.imdb.com/title/{}/", id)),
+        })).collect())
+}
+
+/// A custom api returns a list or an object with `results`, the items have `title`, `year`, `poster` and `url`.
+fn parse_custom(content: &Value) -> Vec<VideoSearchResult> {
+    let items = content.as_array().or_else(|| content.get("results").and_then(Value::as_array));
+    items.map_or(vec![], |items| items.iter()
+        .filter_map(|item| Some(VideoSearchResult {
+            title: get_text(item, &["title", "name"])?,
+            year: get_year(item, &["year", "release_date"]),
+            poster: get_text(item, &["poster", "image"]),
+            url: get_text(item, &["url"]),
+        })).collect())
+}
+
+/// Searches the title with the configured provider, results of the given year come first.
+pub(crate) async fn search(search_cfg: &VideoSearchConfig, query: &str, year: Option<u16>) -> Result<Vec<VideoSearchResult>, String> {
+    let url = get_search_url(search_cfg, query)?;
+    let client = reqwest::Client::builder().timeout(Duration::from_secs(10)).build().map_err(|err| err.to_string())?;
+    let response = client.get(url).send().await.map_err(|err| err.without_url().to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("search provider responded with status {}", response.status()));
+    }
+    let content: Value = response.json().await.map_err(|err| err.without_url().to_string())?;
+    let mut results = match search_cfg.provider {
+        VideoSearchProvider::Tmdb => parse_tmdb(&content),
+        VideoSearchProvider::Imdb => parse_imdb(&content),
+        VideoSearchProvider::Custom => parse_custom(&content),
+    };
+    if year.is_some() {
+        results.sort_by_key(|result| result.year != year);
+    }
+    results.truncate(MAX_RESULTS);
+    Ok(results)
+}